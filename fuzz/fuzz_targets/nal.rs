@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svt::rtp::hevc::nal_units;
+
+fuzz_target!(|data: &[u8]| {
+    for nal in nal_units(data).map_while(Result::ok) {
+        let _ = (nal.nal_type(), nal.layer_id(), nal.temporal_id());
+        assert!(nal.as_bytes().len() >= 2);
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svt::av1::obu::{obus, SequenceHeader};
+
+fuzz_target!(|data: &[u8]| {
+    for obu in obus(data).map_while(Result::ok) {
+        let _ = (obu.obu_type(), obu.temporal_id(), obu.spatial_id());
+        assert_eq!(obu.payload().len(), obu.size());
+
+        // Sequence headers are parsed from the payload alone.
+        let _ = SequenceHeader::parse(obu.payload());
+    }
+
+    let _ = SequenceHeader::find(data);
+});
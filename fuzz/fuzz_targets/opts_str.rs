@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svt::av1::Av1EncoderConfig;
+
+fuzz_target!(|opts: &str| {
+    let _ = Av1EncoderConfig::from_opts_str(opts);
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use svt::rtp::{av1, hevc};
+
+fuzz_target!(|input: (u16, &[u8])| {
+    let (max_payload_size, data) = input;
+    let max_payload_size = max_payload_size as usize;
+
+    if let Ok(payloads) = av1::Payloader::new(max_payload_size).payload(data) {
+        assert!(payloads.iter().all(|p| p.len() <= max_payload_size));
+    }
+
+    if let Ok(payloads) = hevc::Payloader::new(max_payload_size).payload(data) {
+        assert!(payloads.iter().all(|p| p.len() <= max_payload_size));
+    }
+});
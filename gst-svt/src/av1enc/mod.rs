@@ -0,0 +1,18 @@
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct Av1Enc(ObjectSubclass<imp::Av1Enc>)
+        @extends gst_video::VideoEncoder, gst::Element, gst::Object;
+}
+
+pub(crate) fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "svtav1enc-rs",
+        gst::Rank::SECONDARY,
+        Av1Enc::static_type(),
+    )
+}
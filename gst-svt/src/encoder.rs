@@ -0,0 +1,134 @@
+use std::sync::LazyLock;
+
+use gst_video::prelude::*;
+use svt::{Encoder, FrameTypeRequest, Packet};
+
+use crate::frame::FramePicture;
+
+pub(crate) static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "svtenc-rs",
+        gst::DebugColorFlags::empty(),
+        Some("SVT encoders"),
+    )
+});
+
+/// Returns the bit depth and framerate of the input, as the encoders expect
+/// them. The framerate is `None` for variable framerate streams.
+pub(crate) fn input_format(info: &gst_video::VideoInfo) -> (u32, Option<(u32, u32)>) {
+    let fps = info.fps();
+    let framerate = (fps.numer() > 0).then(|| (fps.numer() as u32, fps.denom() as u32));
+    (info.format_info().depth()[0], framerate)
+}
+
+/// The state of a running encoder, shared by both elements.
+pub(crate) struct State<E> {
+    encoder: E,
+    info: gst_video::VideoInfo,
+    /// Packets which don't complete an input frame, like AV1 alt-ref frames,
+    /// are held back and output together with the next frame.
+    pending: Vec<u8>,
+}
+
+impl<E: Encoder> State<E> {
+    pub(crate) fn new(encoder: E, info: gst_video::VideoInfo) -> Self {
+        Self {
+            encoder,
+            info,
+            pending: Vec::new(),
+        }
+    }
+
+    /// The format of the input frames.
+    pub(crate) fn info(&self) -> &gst_video::VideoInfo {
+        &self.info
+    }
+
+    /// Sends an input frame to the encoder. The system frame number is used
+    /// as the pts, so that the frame can be found again when its packet is
+    /// output.
+    pub(crate) fn send_frame(
+        &self,
+        element: &gst_video::VideoEncoder,
+        frame: &gst_video::VideoCodecFrame<'_>,
+    ) -> Result<(), gst::FlowError> {
+        let buffer = frame.input_buffer().ok_or(gst::FlowError::Error)?;
+        let mapped = gst_video::VideoFrameRef::from_buffer_ref_readable(buffer, &self.info)
+            .map_err(|_| {
+                gst::element_error!(
+                    element,
+                    gst::CoreError::Failed,
+                    ["Failed to map input buffer"]
+                );
+                gst::FlowError::Error
+            })?;
+
+        let frame_type = if frame
+            .flags()
+            .contains(gst_video::VideoCodecFrameFlags::FORCE_KEYFRAME)
+        {
+            FrameTypeRequest::Key
+        } else {
+            FrameTypeRequest::Auto
+        };
+
+        let pts = frame.system_frame_number() as i64;
+        self.encoder
+            .send_picture(&FramePicture::new(&mapped), pts, frame_type)
+            .map_err(|e| {
+                gst::element_error!(
+                    element,
+                    gst::LibraryError::Encode,
+                    ["Failed to send picture: {}", e]
+                );
+                gst::FlowError::Error
+            })
+    }
+
+    /// Outputs the packets the encoder has ready. If `eos` is set, this
+    /// finishes the stream and waits for the remaining packets.
+    pub(crate) fn drain(
+        &mut self,
+        element: &gst_video::VideoEncoder,
+        eos: bool,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let encode_error = |e: svt::Error| {
+            gst::element_error!(
+                element,
+                gst::LibraryError::Encode,
+                ["Failed to encode: {}", e]
+            );
+            gst::FlowError::Error
+        };
+
+        if eos {
+            self.encoder.finish().map_err(encode_error)?;
+        }
+
+        while let Some(packet) = self.encoder.get_packet(eos).map_err(encode_error)? {
+            if packet.is_eos() {
+                break;
+            }
+
+            self.pending.extend_from_slice(packet.as_bytes());
+            let Some(frame_number) = packet.completed_pts() else {
+                continue;
+            };
+
+            let Some(mut frame) = element.frame(frame_number as i32) else {
+                gst::warning!(CAT, obj = element, "No pending frame {}", frame_number);
+                continue;
+            };
+
+            if packet.keyframe_reason().is_some() {
+                frame.set_flags(gst_video::VideoCodecFrameFlags::SYNC_POINT);
+            }
+
+            let data = std::mem::take(&mut self.pending);
+            frame.set_output_buffer(gst::Buffer::from_mut_slice(data));
+            element.finish_frame(Some(frame))?;
+        }
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
@@ -0,0 +1,45 @@
+use svt::{Picture, Plane};
+
+/// A mapped input frame, passed to the encoder without copying.
+pub(crate) struct FramePicture<'a> {
+    frame: &'a gst_video::VideoFrameRef<&'a gst::BufferRef>,
+}
+
+impl<'a> FramePicture<'a> {
+    pub(crate) fn new(frame: &'a gst_video::VideoFrameRef<&'a gst::BufferRef>) -> Self {
+        Self { frame }
+    }
+}
+
+impl Picture for FramePicture<'_> {
+    fn width(&self) -> u32 {
+        self.frame.width()
+    }
+
+    fn height(&self) -> u32 {
+        self.frame.height()
+    }
+
+    fn as_slice(&self, plane: Plane) -> &[u8] {
+        let index = plane as u32;
+        let data = self.frame.plane_data(index).unwrap_or(&[]);
+
+        // The mapped plane may run on into padding or the next plane, but the
+        // encoder expects exactly `stride` bytes per row.
+        let rows = self.frame.comp_height(index) as usize;
+        let len = self.stride(plane) as usize * rows;
+        &data[..len.min(data.len())]
+    }
+
+    fn stride(&self, plane: Plane) -> u32 {
+        self.frame.plane_stride()[plane as usize] as u32
+    }
+
+    fn bytes_per_sample(&self) -> u32 {
+        if self.frame.format_info().depth()[0] > 8 {
+            2
+        } else {
+            1
+        }
+    }
+}
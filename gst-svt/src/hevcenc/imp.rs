@@ -0,0 +1,265 @@
+use std::sync::{LazyLock, Mutex};
+
+use gst::glib;
+use gst::subclass::prelude::*;
+use gst_video::prelude::*;
+use gst_video::subclass::prelude::*;
+use svt::hevc::{HevcEncoder, HevcEncoderConfig, HevcSettings, IntraPeriod, RateControlMode};
+use svt::{HevcQp, SubsamplingFormat};
+
+use crate::encoder::{input_format, State, CAT};
+
+const DEFAULT_PRESET: u32 = 9;
+const DEFAULT_BITRATE: u32 = 0;
+const DEFAULT_QP: u32 = 32;
+const DEFAULT_KEYINT: i32 = -2;
+
+/// The element properties.
+#[derive(Debug, Copy, Clone)]
+struct Props {
+    preset: u32,
+    bitrate: u32,
+    qp: u32,
+    keyint: i32,
+}
+
+impl Default for Props {
+    fn default() -> Self {
+        Self {
+            preset: DEFAULT_PRESET,
+            bitrate: DEFAULT_BITRATE,
+            qp: DEFAULT_QP,
+            keyint: DEFAULT_KEYINT,
+        }
+    }
+}
+
+impl Props {
+    fn to_settings(self, info: &gst_video::VideoInfo) -> HevcSettings {
+        let (bit_depth, framerate) = input_format(info);
+        let rate_control = match self.bitrate {
+            0 => RateControlMode::ConstantQp,
+            _ => RateControlMode::VariableBitrate,
+        };
+
+        let intra_period = match self.keyint {
+            -1 => IntraPeriod::None,
+            frames if frames < 0 => IntraPeriod::Auto,
+            frames => IntraPeriod::Fixed(frames as u32),
+        };
+
+        HevcSettings {
+            preset: Some(self.preset as u8),
+            framerate,
+            bit_depth: Some(bit_depth),
+            intra_period: Some(intra_period),
+            rate_control: Some(rate_control),
+            qp: Some(HevcQp::new(self.qp).expect("range checked by the property")),
+            target_bitrate: (self.bitrate > 0).then(|| self.bitrate.saturating_mul(1000)),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct HevcEnc {
+    props: Mutex<Props>,
+    state: Mutex<Option<State<HevcEncoder>>>,
+}
+
+impl HevcEnc {
+    fn start_encoder(&self, info: &gst_video::VideoInfo) -> Result<(), gst::LoggableError> {
+        let settings = self.props.lock().unwrap().to_settings(info);
+        gst::debug!(CAT, imp = self, "Creating encoder with {:?}", settings);
+
+        let encoder = HevcEncoderConfig::from(settings)
+            .create_encoder(info.width(), info.height(), SubsamplingFormat::Yuv420)
+            .map_err(|e| gst::loggable_error!(CAT, "Failed to create encoder: {}", e))?;
+
+        *self.state.lock().unwrap() = Some(State::new(encoder, info.clone()));
+        Ok(())
+    }
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for HevcEnc {
+    const NAME: &'static str = "GstSvtHevcEncRs";
+    type Type = super::HevcEnc;
+    type ParentType = gst_video::VideoEncoder;
+}
+
+impl ObjectImpl for HevcEnc {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![
+                glib::ParamSpecUInt::builder("preset")
+                    .nick("Preset")
+                    .blurb("Encoder preset, from slowest (0) to fastest (11)")
+                    .maximum(11)
+                    .default_value(DEFAULT_PRESET)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("bitrate")
+                    .nick("Bitrate")
+                    .blurb("Target bitrate in kbit/s, or 0 for constant quality")
+                    .default_value(DEFAULT_BITRATE)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecUInt::builder("qp")
+                    .nick("QP")
+                    .blurb("Constant quantization parameter, used if bitrate is 0")
+                    .maximum(51)
+                    .default_value(DEFAULT_QP)
+                    .mutable_ready()
+                    .build(),
+                glib::ParamSpecInt::builder("keyint")
+                    .nick("Keyframe interval")
+                    .blurb("Frames between keyframes, -1 for none, or -2 for automatic")
+                    .minimum(-2)
+                    .default_value(DEFAULT_KEYINT)
+                    .mutable_ready()
+                    .build(),
+            ]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        let mut props = self.props.lock().unwrap();
+        match pspec.name() {
+            "preset" => props.preset = value.get().expect("type checked upstream"),
+            "bitrate" => props.bitrate = value.get().expect("type checked upstream"),
+            "qp" => props.qp = value.get().expect("type checked upstream"),
+            "keyint" => props.keyint = value.get().expect("type checked upstream"),
+            _ => unreachable!("unknown property {}", pspec.name()),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        let props = self.props.lock().unwrap();
+        match pspec.name() {
+            "preset" => props.preset.to_value(),
+            "bitrate" => props.bitrate.to_value(),
+            "qp" => props.qp.to_value(),
+            "keyint" => props.keyint.to_value(),
+            _ => unreachable!("unknown property {}", pspec.name()),
+        }
+    }
+}
+
+impl GstObjectImpl for HevcEnc {}
+
+impl ElementImpl for HevcEnc {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "SVT-HEVC encoder",
+                "Encoder/Video",
+                "Encodes video to H.265 with SVT-HEVC",
+                "Colin Marc <hi@colinmarc.com>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let sink_caps = gst_video::VideoCapsBuilder::new()
+                .format_list([
+                    gst_video::VideoFormat::I420,
+                    gst_video::VideoFormat::I42010le,
+                ])
+                .build();
+            let sink_pad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &sink_caps,
+            )
+            .unwrap();
+
+            let src_caps = gst::Caps::builder("video/x-h265")
+                .field("stream-format", "byte-stream")
+                .field("alignment", "au")
+                .build();
+            let src_pad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &src_caps,
+            )
+            .unwrap();
+
+            vec![src_pad_template, sink_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+impl VideoEncoderImpl for HevcEnc {
+    fn stop(&self) -> Result<(), gst::ErrorMessage> {
+        *self.state.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn set_format(
+        &self,
+        state: &gst_video::VideoCodecState<'static, gst_video::video_codec_state::Readable>,
+    ) -> Result<(), gst::LoggableError> {
+        // Output anything encoded with the previous format first.
+        self.finish()
+            .map_err(|_| gst::loggable_error!(CAT, "Failed to drain encoder"))?;
+
+        self.start_encoder(state.info())?;
+
+        let caps = gst::Caps::builder("video/x-h265")
+            .field("stream-format", "byte-stream")
+            .field("alignment", "au")
+            .build();
+        let output_state = self
+            .obj()
+            .set_output_state(caps, Some(state))
+            .map_err(|_| gst::loggable_error!(CAT, "Failed to set output state"))?;
+        self.obj()
+            .negotiate(output_state)
+            .map_err(|_| gst::loggable_error!(CAT, "Failed to negotiate"))?;
+
+        self.parent_set_format(state)
+    }
+
+    fn flush(&self) -> bool {
+        // The library can't discard the pictures it holds, so the encoder is
+        // recreated instead.
+        let info = match self.state.lock().unwrap().take() {
+            Some(state) => state.info().clone(),
+            None => return true,
+        };
+
+        self.start_encoder(&info).is_ok()
+    }
+
+    fn finish(&self) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let mut state = self.state.lock().unwrap();
+        match state.as_mut() {
+            Some(state) => state.drain(self.obj().upcast_ref(), true),
+            None => Ok(gst::FlowSuccess::Ok),
+        }
+    }
+
+    fn handle_frame(
+        &self,
+        frame: gst_video::VideoCodecFrame,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let mut state = self.state.lock().unwrap();
+        let state = state.as_mut().ok_or(gst::FlowError::NotNegotiated)?;
+
+        let element = self.obj();
+        state.send_frame(element.upcast_ref(), &frame)?;
+        drop(frame);
+
+        state.drain(element.upcast_ref(), false)
+    }
+}
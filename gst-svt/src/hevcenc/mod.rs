@@ -0,0 +1,18 @@
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct HevcEnc(ObjectSubclass<imp::HevcEnc>)
+        @extends gst_video::VideoEncoder, gst::Element, gst::Object;
+}
+
+pub(crate) fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "svthevcenc-rs",
+        gst::Rank::SECONDARY,
+        HevcEnc::static_type(),
+    )
+}
@@ -0,0 +1,27 @@
+//! GStreamer elements wrapping the encoders from the `svt` crate:
+//! `svtav1enc-rs` and `svthevcenc-rs`.
+
+use gst::glib;
+
+mod av1enc;
+mod encoder;
+mod frame;
+mod hevcenc;
+
+fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    av1enc::register(plugin)?;
+    hevcenc::register(plugin)?;
+    Ok(())
+}
+
+gst::plugin_define!(
+    svt,
+    env!("CARGO_PKG_DESCRIPTION"),
+    plugin_init,
+    concat!(env!("CARGO_PKG_VERSION"), "-", env!("COMMIT_ID")),
+    "MIT/X11",
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_REPOSITORY"),
+    env!("BUILD_REL_DATE")
+);
@@ -5,9 +5,48 @@ use std::path::{Path, PathBuf};
 fn main() -> anyhow::Result<()> {
     println!("cargo:rerun-if-changed=svt-av1.h");
 
+    let out_path = PathBuf::from(env::var("OUT_DIR")?);
+
+    let include_path = match env_path("SVT_AV1_LIB_DIR") {
+        Some(lib_dir) => link_prebuilt(lib_dir),
+        #[cfg(feature = "system")]
+        None => link_system()?,
+        #[cfg(not(feature = "system"))]
+        None => build_vendored(&out_path)?,
+    };
+
+    // Export the library version, so that dependents can gate features on it.
+    let (major, minor, patch) = detect_version(&include_path)?;
+    println!("cargo:version_major={}", major);
+    println!("cargo:version_minor={}", minor);
+    println!("cargo:version_patch={}", patch);
+
+    // Generate bindings.
+    let bindings = bindgen::Builder::default()
+        .clang_args([format!("-I{}", include_path.display())])
+        .header("svt-av1.h")
+        .allowlist_item("E[Bb].*")
+        .allowlist_item("svt_av1_.*")
+        .allowlist_item("Svt.*")
+        .derive_default(true)
+        .generate_comments(false)
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .context("failed to generate bindings")?;
+
+    bindings
+        .write_to_file(out_path.join("bindings.rs"))
+        .context("failed to generate bindings")?;
+
+    Ok(())
+}
+
+/// Builds and links the vendored library, returning the include path for
+/// the headers.
+#[cfg(not(feature = "system"))]
+fn build_vendored(out_path: &Path) -> anyhow::Result<PathBuf> {
     let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
     let source_path = manifest_dir.join("SVT-AV1");
-    let out_path = PathBuf::from(env::var("OUT_DIR")?);
 
     let mut cmake_build = cmake::Config::new(source_path);
     cmake_build
@@ -29,7 +68,7 @@ fn main() -> anyhow::Result<()> {
         .context("failed to apply logging patch")?;
 
         // Insert the header.
-        cmake_build.cflag(format!("-include{}", patched_header.display()));
+        cmake_build.cflag(force_include(&patched_header));
     } else {
         // Disable logging.
         cmake_build.define("SVT_LOG_QUIET", "1");
@@ -44,29 +83,70 @@ fn main() -> anyhow::Result<()> {
     );
 
     println!("cargo:rustc-link-lib=static=SvtAv1Enc");
-    println!("cargo:rustc-link-lib=pthread");
-    println!("cargo:rustc-link-lib=m");
+    link_runtime_deps();
 
-    // Generate bindings.
-    let bindings = bindgen::Builder::default()
-        .clang_args([format!("-I{}/include/svt-av1", compile_path.display())])
-        .header("svt-av1.h")
-        .allowlist_item("E[Bb].*")
-        .allowlist_item("svt_av1_.*")
-        .allowlist_item("Svt.*")
-        .derive_default(true)
-        .generate_comments(false)
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
-        .generate()
-        .context("failed to generate bindings")?;
+    Ok(compile_path.join("include/svt-av1"))
+}
 
-    bindings
-        .write_to_file(out_path.join("bindings.rs"))
-        .context("failed to generate bindings")?;
+/// Links a prebuilt static library from `lib_dir`, and returns the include
+/// path for the headers. The headers are found in `SVT_AV1_INCLUDE_DIR`, or
+/// otherwise in `include/svt-av1` next to the library directory.
+fn link_prebuilt(lib_dir: PathBuf) -> PathBuf {
+    let include_path =
+        env_path("SVT_AV1_INCLUDE_DIR").unwrap_or_else(|| lib_dir.join("../include/svt-av1"));
 
-    Ok(())
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib=static=SvtAv1Enc");
+    link_runtime_deps();
+
+    include_path
+}
+
+/// Links a library installed on the system, using pkg-config, and returns
+/// the include path for the headers.
+#[cfg(feature = "system")]
+fn link_system() -> anyhow::Result<PathBuf> {
+    let library = pkg_config::Config::new()
+        .atleast_version("2.0")
+        .probe("SvtAv1Enc")
+        .context("failed to find SvtAv1Enc with pkg-config")?;
+
+    library
+        .include_paths
+        .into_iter()
+        .find(|path| path.join("EbSvtAv1Enc.h").exists())
+        .context("failed to find EbSvtAv1Enc.h")
+}
+
+fn detect_version(include_path: &Path) -> anyhow::Result<(u32, u32, u32)> {
+    let mut major = None;
+    let mut minor = None;
+    let mut patch = None;
+
+    for entry in std::fs::read_dir(include_path).context("failed to read include dir")? {
+        let src = std::fs::read_to_string(entry?.path())?;
+        for line in src.lines() {
+            let mut parts = line.split_whitespace();
+            if parts.next() != Some("#define") {
+                continue;
+            }
+
+            match (parts.next(), parts.next().map(str::parse::<u32>)) {
+                (Some("SVT_AV1_VERSION_MAJOR"), Some(Ok(v))) => major = Some(v),
+                (Some("SVT_AV1_VERSION_MINOR"), Some(Ok(v))) => minor = Some(v),
+                (Some("SVT_AV1_VERSION_PATCHLEVEL"), Some(Ok(v))) => patch = Some(v),
+                _ => (),
+            }
+        }
+    }
+
+    match (major, minor) {
+        (Some(major), Some(minor)) => Ok((major, minor, patch.unwrap_or(0))),
+        _ => anyhow::bail!("failed to find SVT_AV1_VERSION_MAJOR and SVT_AV1_VERSION_MINOR"),
+    }
 }
 
+#[cfg(not(feature = "system"))]
 fn apply_patch(
     in_file: impl AsRef<Path>,
     out_file: impl AsRef<Path>,
@@ -83,3 +163,33 @@ fn apply_patch(
     std::io::Write::write_all(&mut dst, patched.as_bytes())?;
     Ok(())
 }
+
+fn env_path(key: &str) -> Option<PathBuf> {
+    println!("cargo:rerun-if-env-changed={}", key);
+    env::var_os(key).map(PathBuf::from)
+}
+
+/// Links the libraries the encoder depends on. On MSVC, threading and math
+/// are provided by the C runtime, which rustc links anyway.
+fn link_runtime_deps() {
+    if is_msvc() {
+        return;
+    }
+
+    println!("cargo:rustc-link-lib=pthread");
+    println!("cargo:rustc-link-lib=m");
+}
+
+fn is_msvc() -> bool {
+    env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|v| v == "msvc")
+}
+
+/// The compiler flag to include a header before each source file.
+#[cfg(not(feature = "system"))]
+fn force_include(header: &Path) -> String {
+    if is_msvc() {
+        format!("/FI{}", header.display())
+    } else {
+        format!("-include{}", header.display())
+    }
+}
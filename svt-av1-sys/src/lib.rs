@@ -3,6 +3,127 @@
 #![allow(non_snake_case)]
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+#[cfg(feature = "log")]
+thread_local! {
+    static CAPTURED_ERRORS: std::cell::RefCell<Option<Vec<String>>> =
+        const { std::cell::RefCell::new(None) };
+    static LOG_SINK: std::cell::RefCell<Option<LogSink>> =
+        const { std::cell::RefCell::new(None) };
+    static CALLBACK_PANIC: std::cell::RefCell<Option<String>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// A message logged by the library.
+#[cfg(feature = "log")]
+#[derive(Debug, Clone, Copy)]
+pub struct LogMessage<'a> {
+    /// The severity of the message.
+    pub level: log::Level,
+    /// The module path the message is logged under, derived from the
+    /// library's log tag. For example, messages tagged `SvtMalloc` are
+    /// logged under `svt_av1::malloc`.
+    pub target: &'a str,
+    /// The message, without a trailing newline.
+    pub message: &'a str,
+}
+
+/// A destination for log messages. See [`with_log_sink`].
+#[cfg(feature = "log")]
+pub type LogSink = std::sync::Arc<dyn Fn(&LogMessage<'_>) + Send + Sync>;
+
+/// Calls `f`, passing any messages logged by the library on the current
+/// thread in the meantime to `sink`, instead of the logger.
+///
+/// Messages logged by the library's worker threads still go to the logger.
+#[cfg(feature = "log")]
+pub fn with_log_sink<T>(sink: LogSink, f: impl FnOnce() -> T) -> T {
+    let _guard = ScopedLocal::set(&LOG_SINK, sink);
+    f()
+}
+
+/// Maps a log tag, like `SvtMalloc`, to a module path, like
+/// `svt_av1::malloc`.
+#[cfg(feature = "log")]
+fn log_target(tag: Option<&str>) -> String {
+    match tag.map(|t| t.strip_prefix("Svt").unwrap_or(t)) {
+        Some(tag) if !tag.is_empty() => format!("svt_av1::{}", tag.to_lowercase()),
+        _ => "svt_av1".to_owned(),
+    }
+}
+
+/// Calls `f`, collecting any error messages logged by the library on the
+/// current thread in the meantime. The messages are still passed on to the
+/// logger as usual.
+#[cfg(feature = "log")]
+pub fn capture_errors<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let guard = ScopedLocal::set(&CAPTURED_ERRORS, Vec::new());
+    let v = f();
+    (v, guard.restore().unwrap_or_default())
+}
+
+/// Sets a thread-local for the lifetime of the guard, restoring the previous
+/// value when it's dropped, even if the closure it was set for panics.
+#[cfg(feature = "log")]
+struct ScopedLocal<T: 'static> {
+    key: &'static std::thread::LocalKey<std::cell::RefCell<Option<T>>>,
+    prev: Option<Option<T>>,
+}
+
+#[cfg(feature = "log")]
+impl<T> ScopedLocal<T> {
+    fn set(key: &'static std::thread::LocalKey<std::cell::RefCell<Option<T>>>, value: T) -> Self {
+        let prev = key.with(|v| v.replace(Some(value)));
+        Self {
+            key,
+            prev: Some(prev),
+        }
+    }
+
+    /// Restores the previous value, returning the current one.
+    fn restore(mut self) -> Option<T> {
+        let prev = self.prev.take().flatten();
+        self.key.with(|v| v.replace(prev))
+    }
+}
+
+#[cfg(feature = "log")]
+impl<T> Drop for ScopedLocal<T> {
+    fn drop(&mut self) {
+        if let Some(prev) = self.prev.take() {
+            // The thread-local may already be gone if the thread is exiting.
+            let _ = self.key.try_with(|v| v.replace(prev));
+        }
+    }
+}
+
+/// Returns the message of the last panic caught in the log callback on the
+/// current thread, if there was one since the last call.
+///
+/// Unwinding into the library is undefined behavior, so panics in a log sink
+/// or the logger are caught at the boundary instead. If that happens on the
+/// calling thread, the library's state can't be trusted afterwards. Panics on
+/// the library's worker threads are caught and discarded.
+#[cfg(feature = "log")]
+pub fn take_callback_panic() -> Option<String> {
+    CALLBACK_PANIC.with(|p| p.take())
+}
+
+/// Runs the body of a callback from the library, catching any panic.
+#[cfg(feature = "log")]
+fn catch_callback_panic(f: impl FnOnce()) {
+    if let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        let msg = match payload.downcast::<String>() {
+            Ok(msg) => *msg,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(msg) => (*msg).to_owned(),
+                Err(_) => "unknown panic".to_owned(),
+            },
+        };
+
+        CALLBACK_PANIC.with(|p| p.replace(Some(msg)));
+    }
+}
+
 #[no_mangle]
 #[cfg(feature = "log")]
 extern "C" fn __svt_av1_rust_log_callback(
@@ -10,6 +131,11 @@ extern "C" fn __svt_av1_rust_log_callback(
     tag: *const std::ffi::c_char,
     msg: *const std::ffi::c_char,
 ) {
+    catch_callback_panic(|| log_message(level, tag, msg))
+}
+
+#[cfg(feature = "log")]
+fn log_message(level: std::ffi::c_int, tag: *const std::ffi::c_char, msg: *const std::ffi::c_char) {
     let level = match level {
         0 | 1 => log::Level::Error,
         2 => log::Level::Warn,
@@ -18,21 +144,79 @@ extern "C" fn __svt_av1_rust_log_callback(
         _ => return,
     };
 
-    let tag = unsafe { std::ffi::CStr::from_ptr(tag).to_str().unwrap() };
-    let msg = unsafe {
-        std::ffi::CStr::from_ptr(msg)
-            .to_str()
-            .unwrap()
-            .trim_end_matches('\n')
+    // Untagged messages have a null tag.
+    let tag = if tag.is_null() {
+        None
+    } else {
+        unsafe { std::ffi::CStr::from_ptr(tag).to_str().ok() }
     };
 
-    log::log!(level, "{}: {}", tag, msg);
+    let msg = unsafe { std::ffi::CStr::from_ptr(msg).to_string_lossy() };
+    let msg = msg.trim_end_matches('\n');
+
+    if level == log::Level::Error {
+        CAPTURED_ERRORS.with(|c| {
+            if let Some(captured) = c.borrow_mut().as_mut() {
+                captured.push(msg.to_owned());
+            }
+        });
+    }
+
+    let target = log_target(tag);
+    match LOG_SINK.with(|s| s.borrow().clone()) {
+        Some(sink) => sink(&LogMessage {
+            level,
+            target: &target,
+            message: msg,
+        }),
+        None => log::log!(target: &target, level, "{}", msg),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    #[cfg(feature = "log")]
+    fn restore_after_panic() {
+        let sink: LogSink = std::sync::Arc::new(|_| ());
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_log_sink(sink, || capture_errors(|| panic!("closure failed")))
+        }));
+
+        assert!(res.is_err());
+        assert!(LOG_SINK.with(|s| s.borrow().is_none()));
+        assert!(CAPTURED_ERRORS.with(|c| c.borrow().is_none()));
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn log_targets() {
+        assert_eq!(log_target(Some("SvtMalloc")), "svt_av1::malloc");
+        assert_eq!(log_target(Some("Svt")), "svt_av1");
+        assert_eq!(log_target(None), "svt_av1");
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn callback_panic() {
+        let sink: LogSink = std::sync::Arc::new(|_| panic!("sink failed"));
+        let msg = c"\xffnot utf-8\n";
+        with_log_sink(sink, || {
+            __svt_av1_rust_log_callback(2, std::ptr::null(), msg.as_ptr())
+        });
+
+        assert_eq!(take_callback_panic().as_deref(), Some("sink failed"));
+        assert_eq!(take_callback_panic(), None);
+
+        // Invalid UTF-8 doesn't panic.
+        let (_, errors) =
+            capture_errors(|| __svt_av1_rust_log_callback(1, std::ptr::null(), msg.as_ptr()));
+        assert_eq!(errors, ["\u{fffd}not utf-8"]);
+        assert_eq!(take_callback_panic(), None);
+    }
+
     #[test]
     fn create_handle() {
         simple_logger::init_with_env().ok();
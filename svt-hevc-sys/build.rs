@@ -1,13 +1,46 @@
 use anyhow::Context;
 use std::env;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+
+#[cfg(not(feature = "system"))]
+use std::path::Path;
 
 fn main() -> anyhow::Result<()> {
     println!("cargo:rerun-if-changed=svt-hevc.h");
 
+    let out_path = PathBuf::from(env::var("OUT_DIR")?);
+
+    let include_path = match env_path("SVT_HEVC_LIB_DIR") {
+        Some(lib_dir) => link_prebuilt(lib_dir),
+        #[cfg(feature = "system")]
+        None => link_system()?,
+        #[cfg(not(feature = "system"))]
+        None => build_vendored(&out_path)?,
+    };
+
+    // Generate bindings.
+    let bindings = bindgen::Builder::default()
+        .clang_args([format!("-I{}", include_path.display())])
+        .header("svt-hevc.h")
+        .allowlist_item("E[Bb].*")
+        .derive_default(true)
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .context("failed to generate bindings")?;
+
+    bindings
+        .write_to_file(out_path.join("bindings.rs"))
+        .context("failed to generate bindings")?;
+
+    Ok(())
+}
+
+/// Builds and links the vendored library, returning the include path for
+/// the headers.
+#[cfg(not(feature = "system"))]
+fn build_vendored(out_path: &Path) -> anyhow::Result<PathBuf> {
     let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
     let source_path = manifest_dir.join("SVT-HEVC");
-    let out_path = PathBuf::from(env::var("OUT_DIR")?);
 
     // Patch the version file. CMake usually does this *in the source tree* the
     // first time it's run, but that's fragile.
@@ -40,8 +73,8 @@ fn main() -> anyhow::Result<()> {
         // by CMake yet.
         .cflag(format!("-I{}", out_path.display()))
         .cflag(format!("-I{}/Source/API", source_path.display()))
-        .cflag(format!("-include{}", patched_version_header.display()))
-        .cflag(format!("-include{}", patched_logging_header.display()))
+        .cflag(force_include(&patched_version_header))
+        .cflag(force_include(&patched_logging_header))
         .build();
 
     println!(
@@ -49,25 +82,42 @@ fn main() -> anyhow::Result<()> {
         compile_path.display()
     );
     println!("cargo:rustc-link-lib=static=SvtHevcEnc");
-    println!("cargo:rustc-link-lib=pthread");
+    link_runtime_deps();
 
-    // Generate bindings.
-    let bindings = bindgen::Builder::default()
-        .clang_args([format!("-I{}/include/svt-hevc", compile_path.display())])
-        .header("svt-hevc.h")
-        .allowlist_item("E[Bb].*")
-        .derive_default(true)
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
-        .generate()
-        .context("failed to generate bindings")?;
+    Ok(compile_path.join("include/svt-hevc"))
+}
 
-    bindings
-        .write_to_file(out_path.join("bindings.rs"))
-        .context("failed to generate bindings")?;
+/// Links a prebuilt static library from `lib_dir`, and returns the include
+/// path for the headers. The headers are found in `SVT_HEVC_INCLUDE_DIR`, or
+/// otherwise in `include/svt-hevc` next to the library directory.
+fn link_prebuilt(lib_dir: PathBuf) -> PathBuf {
+    let include_path =
+        env_path("SVT_HEVC_INCLUDE_DIR").unwrap_or_else(|| lib_dir.join("../include/svt-hevc"));
 
-    Ok(())
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib=static=SvtHevcEnc");
+    link_runtime_deps();
+
+    include_path
 }
 
+/// Links a library installed on the system, using pkg-config, and returns
+/// the include path for the headers.
+#[cfg(feature = "system")]
+fn link_system() -> anyhow::Result<PathBuf> {
+    let library = pkg_config::Config::new()
+        .atleast_version("1.5")
+        .probe("SvtHevcEnc")
+        .context("failed to find SvtHevcEnc with pkg-config")?;
+
+    library
+        .include_paths
+        .into_iter()
+        .find(|path| path.join("EbApi.h").exists())
+        .context("failed to find EbApi.h")
+}
+
+#[cfg(not(feature = "system"))]
 fn apply_patch(
     in_file: impl AsRef<Path>,
     out_file: impl AsRef<Path>,
@@ -84,3 +134,32 @@ fn apply_patch(
     std::io::Write::write_all(&mut dst, patched.as_bytes())?;
     Ok(())
 }
+
+fn env_path(key: &str) -> Option<PathBuf> {
+    println!("cargo:rerun-if-env-changed={}", key);
+    env::var_os(key).map(PathBuf::from)
+}
+
+/// Links the libraries the encoder depends on. On MSVC, threading and math
+/// are provided by the C runtime, which rustc links anyway.
+fn link_runtime_deps() {
+    if is_msvc() {
+        return;
+    }
+
+    println!("cargo:rustc-link-lib=pthread");
+}
+
+fn is_msvc() -> bool {
+    env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|v| v == "msvc")
+}
+
+/// The compiler flag to include a header before each source file.
+#[cfg(not(feature = "system"))]
+fn force_include(header: &Path) -> String {
+    if is_msvc() {
+        format!("/FI{}", header.display())
+    } else {
+        format!("-include{}", header.display())
+    }
+}
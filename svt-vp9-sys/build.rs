@@ -0,0 +1,58 @@
+use anyhow::Context;
+use std::env;
+use std::path::PathBuf;
+
+fn main() -> anyhow::Result<()> {
+    println!("cargo:rerun-if-changed=svt-vp9.h");
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let source_path = manifest_dir.join("SVT-VP9");
+    let out_path = PathBuf::from(env::var("OUT_DIR")?);
+
+    // Build the library.
+    let compile_path = cmake::Config::new(&source_path)
+        .define("BUILD_SHARED_LIBS", "OFF")
+        .define("BUILD_APP", "OFF")
+        // The encoder does an awful lot of printf() in debug mode.
+        .profile("Release")
+        .build();
+
+    println!(
+        "cargo:rustc-link-search=native={}/lib",
+        compile_path.display()
+    );
+    println!("cargo:rustc-link-lib=static=SvtVp9Enc");
+    link_runtime_deps();
+
+    // Generate bindings.
+    let bindings = bindgen::Builder::default()
+        .clang_args([format!("-I{}/include/svt-vp9", compile_path.display())])
+        .header("svt-vp9.h")
+        .allowlist_item("E[Bb].*")
+        .allowlist_item("eb_vp9_.*")
+        .derive_default(true)
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .context("failed to generate bindings")?;
+
+    bindings
+        .write_to_file(out_path.join("bindings.rs"))
+        .context("failed to generate bindings")?;
+
+    Ok(())
+}
+
+/// Links the libraries the encoder depends on. On MSVC, threading and math
+/// are provided by the C runtime, which rustc links anyway.
+fn link_runtime_deps() {
+    if is_msvc() {
+        return;
+    }
+
+    println!("cargo:rustc-link-lib=pthread");
+    println!("cargo:rustc-link-lib=m");
+}
+
+fn is_msvc() -> bool {
+    env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|v| v == "msvc")
+}
@@ -0,0 +1,28 @@
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_handle() {
+        simple_logger::init_with_env().ok();
+
+        unsafe {
+            let mut foo: *mut EbComponentType = std::ptr::null_mut();
+            let mut cfg = EbSvtVp9EncConfiguration {
+                ..std::mem::zeroed()
+            };
+
+            let res = eb_vp9_svt_init_handle(&mut foo as *mut _, std::ptr::null_mut(), &mut cfg);
+
+            assert_eq!(0, res);
+            assert_eq!(9, cfg.enc_mode);
+
+            eb_vp9_deinit_handle(foo);
+        }
+    }
+}
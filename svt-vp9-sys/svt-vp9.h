@@ -0,0 +1,2 @@
+#include "EbSvtVp9Enc.h"
+#include "EbSvtVp9ErrorCodes.h"
\ No newline at end of file
@@ -0,0 +1,175 @@
+//! Encodes a moving zone plate with each enabled codec, across a few presets
+//! and resolutions, to catch performance regressions in the wrapper. Run with,
+//! for example:
+//!
+//!     cargo bench --features av1,hevc,vp9
+//!
+//! Each configuration is benchmarked twice: once sending pre-generated
+//! pictures directly, and once decoding and copying them from an in-memory
+//! y4m stream, like a typical application would. Throughput is reported in
+//! frames per second, and the average output size per frame is printed before
+//! each configuration runs.
+
+// Without any codecs enabled, there's nothing to benchmark.
+#![cfg_attr(
+    not(any(feature = "av1", feature = "hevc", feature = "vp9")),
+    allow(dead_code, unused_imports, unused_variables)
+)]
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use svt::testsrc::Pattern;
+use svt::y4m::{ChromaSiting, Y4mHeader, Y4mReader, Y4mWriter};
+use svt::{Encoder, EncoderConfig, Packet, RateControl, Rational, SubsamplingFormat, YUVBuffer};
+
+const FRAMES: u64 = 30;
+const RESOLUTIONS: &[(u32, u32)] = &[(640, 360), (1280, 720)];
+
+fn encode_pictures(encoder: &impl Encoder, pictures: &[YUVBuffer]) -> usize {
+    let mut bytes = 0;
+    for (pts, picture) in pictures.iter().enumerate() {
+        encoder
+            .send_picture(picture, pts as i64, false)
+            .expect("failed to send picture");
+        bytes += drain(encoder, false);
+    }
+
+    encoder.finish().expect("failed to finish");
+    bytes + drain(encoder, true)
+}
+
+fn encode_y4m(encoder: &impl Encoder, y4m: &[u8], width: u32, height: u32) -> usize {
+    let mut reader = Y4mReader::new(y4m).expect("failed to read y4m header");
+    let mut buf = YUVBuffer::new(width, height, SubsamplingFormat::Yuv420);
+    let mut bytes = 0;
+    let mut pts = 0;
+
+    while reader
+        .read_frame(&mut buf)
+        .expect("failed to read y4m frame")
+    {
+        encoder
+            .send_picture(&buf, pts, false)
+            .expect("failed to send picture");
+        bytes += drain(encoder, false);
+        pts += 1;
+    }
+
+    encoder.finish().expect("failed to finish");
+    bytes + drain(encoder, true)
+}
+
+/// Retrieves packets until the queue is empty or, if `wait` is set, until
+/// the end of the stream. Returns the total size of the packets.
+fn drain(encoder: &impl Encoder, wait: bool) -> usize {
+    let mut bytes = 0;
+    while let Some(packet) = encoder.get_packet(wait).expect("failed to get packet") {
+        bytes += packet.as_bytes().len();
+        if packet.is_eos() {
+            break;
+        }
+    }
+
+    bytes
+}
+
+fn to_y4m(pictures: &[YUVBuffer], width: u32, height: u32) -> Vec<u8> {
+    let header = Y4mHeader {
+        width,
+        height,
+        framerate: Rational::new(30, 1),
+        subsampling_format: SubsamplingFormat::Yuv420,
+        bit_depth: 8,
+        chroma_siting: ChromaSiting::Center,
+    };
+
+    let mut writer = Y4mWriter::new(Vec::new(), header).expect("failed to write y4m header");
+    for picture in pictures {
+        writer
+            .write_frame(picture)
+            .expect("failed to write y4m frame");
+    }
+
+    writer.into_inner()
+}
+
+fn bench_codec<C: EncoderConfig>(
+    c: &mut Criterion,
+    codec: &str,
+    config: fn() -> C,
+    presets: &[u8],
+) {
+    let mut group = c.benchmark_group(codec);
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(FRAMES));
+
+    for &(width, height) in RESOLUTIONS {
+        let pictures: Vec<_> = (0..FRAMES)
+            .map(|n| {
+                let mut buf = YUVBuffer::new(width, height, SubsamplingFormat::Yuv420);
+                Pattern::ZonePlate.fill(&mut buf, n);
+                buf
+            })
+            .collect();
+        let y4m = to_y4m(&pictures, width, height);
+
+        for &preset in presets {
+            let create = || {
+                config()
+                    .preset(preset)
+                    .framerate(30, 1)
+                    .rate_control(RateControl::ConstantQp(32))
+                    .create_encoder(width, height, SubsamplingFormat::Yuv420)
+                    .expect("failed to create encoder")
+            };
+
+            let param = format!("{}x{}/preset {}", width, height, preset);
+            let bytes = encode_pictures(&create(), &pictures);
+            println!(
+                "{}/{}: {:.0} bytes/frame",
+                codec,
+                param,
+                bytes as f64 / FRAMES as f64
+            );
+
+            // Creating and tearing down the encoder is excluded from the
+            // measurement.
+            group.bench_function(BenchmarkId::new("synthetic", &param), |b| {
+                b.iter_batched(
+                    create,
+                    |encoder| {
+                        encode_pictures(&encoder, &pictures);
+                        encoder
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+
+            group.bench_function(BenchmarkId::new("y4m", &param), |b| {
+                b.iter_batched(
+                    create,
+                    |encoder| {
+                        encode_y4m(&encoder, &y4m, width, height);
+                        encoder
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    #[cfg(feature = "av1")]
+    bench_codec(c, "av1", svt::av1::Av1EncoderConfig::default, &[8, 12]);
+
+    #[cfg(feature = "hevc")]
+    bench_codec(c, "hevc", svt::hevc::HevcEncoderConfig::default, &[7, 11]);
+
+    #[cfg(feature = "vp9")]
+    bench_codec(c, "vp9", svt::vp9::Vp9EncoderConfig::default, &[7, 9]);
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -0,0 +1,50 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    println!("cargo:rustc-check-cfg=cfg(svt_av1_avif)");
+    println!("cargo:rustc-check-cfg=cfg(svt_av1_luminance_qp_bias)");
+    println!("cargo:rustc-check-cfg=cfg(svt_av1_tf_strength)");
+    println!("cargo:rustc-check-cfg=cfg(svt_av1_variance_boost)");
+    println!("cargo:rustc-check-cfg=cfg(svt_av1_logical_processors)");
+    println!("cargo:rustc-check-cfg=cfg(svt_av1_level_of_parallelism)");
+    println!("cargo:rustc-check-cfg=cfg(svt_av1_rate_change)");
+
+    // Set by svt-av1-sys, if the av1 feature is enabled.
+    let version = |key| {
+        env::var(format!("DEP_SVTAV1ENC_VERSION_{}", key))
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+    };
+
+    if let (Some(major), Some(minor)) = (version("MAJOR"), version("MINOR")) {
+        let patch = version("PATCH").unwrap_or(0);
+
+        // Exposed as av1::library_version.
+        let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+        std::fs::write(
+            out_path.join("svt_av1_version.rs"),
+            format!(
+                "const SVT_AV1_VERSION: (u32, u32, u32) = ({}, {}, {});\n",
+                major, minor, patch
+            ),
+        )
+        .unwrap();
+
+        if (major, minor) >= (2, 0) {
+            println!("cargo:rustc-cfg=svt_av1_avif");
+            println!("cargo:rustc-cfg=svt_av1_variance_boost");
+            println!("cargo:rustc-cfg=svt_av1_rate_change");
+        }
+
+        if (major, minor) < (3, 0) {
+            println!("cargo:rustc-cfg=svt_av1_logical_processors");
+        }
+
+        if (major, minor) >= (3, 0) {
+            println!("cargo:rustc-cfg=svt_av1_level_of_parallelism");
+            println!("cargo:rustc-cfg=svt_av1_luminance_qp_bias");
+            println!("cargo:rustc-cfg=svt_av1_tf_strength");
+        }
+    }
+}
@@ -0,0 +1,457 @@
+//! A command-line encoder, reading y4m input and writing an IVF, Annex B, or
+//! raw elementary stream. It exercises most of the common configuration
+//! options, for each of the enabled codecs.
+//!
+//! You can run it with, for example:
+//!
+//!     ffmpeg -loglevel error -i video.mp4 -f yuv4mpegpipe - \
+//!         | cargo run --release --example svt-encode --features av1,y4m -- \
+//!             --codec av1 --preset 8 --crf 30 -o out.ivf
+//!
+//! Run with `--help` for the full list of options.
+
+#![cfg_attr(
+    not(any(feature = "av1", feature = "hevc", feature = "vp9")),
+    allow(dead_code, unreachable_code, unused_imports, unused_variables)
+)]
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::{Parser, ValueEnum};
+use svt::y4m::{Y4mHeader, Y4mReader};
+use svt::{Encoder, EncoderConfig, Packet, Picture, Plane, RateControl, YUVBuffer, YUVBuffer16};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Codec {
+    Av1,
+    Hevc,
+    Vp9,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Container {
+    /// IVF, for AV1 and VP9.
+    Ivf,
+    /// A length-delimited AV1 Annex B stream, or an HEVC Annex B byte stream.
+    Annexb,
+    /// The packets as output by the encoder, concatenated.
+    Raw,
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Encodes y4m input with an SVT encoder.")]
+struct Args {
+    /// The y4m file to read, or `-` for stdin.
+    #[arg(default_value = "-")]
+    input: PathBuf,
+
+    /// The file to write, or `-` for stdout.
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+
+    /// The codec to encode with.
+    #[arg(short, long, value_enum, default_value_t = Codec::Av1)]
+    codec: Codec,
+
+    /// The output container. Defaults to IVF for AV1 and VP9, and Annex B for
+    /// HEVC.
+    #[arg(long, value_enum)]
+    container: Option<Container>,
+
+    /// The encoder preset. Lower values are slower and produce higher quality.
+    #[arg(short, long, default_value_t = 8)]
+    preset: u8,
+
+    /// Use a constant QP.
+    #[arg(long, group = "rate_control")]
+    qp: Option<u32>,
+
+    /// Use a constant rate factor (AV1 only).
+    #[arg(long, group = "rate_control")]
+    crf: Option<u32>,
+
+    /// Target a bitrate, in kbit/s.
+    #[arg(long, group = "rate_control")]
+    bitrate: Option<u32>,
+
+    /// With --bitrate, use constant rather than variable bitrate (AV1 and VP9
+    /// only).
+    #[arg(long, requires = "bitrate")]
+    cbr: bool,
+
+    /// The maximum number of frames between keyframes.
+    #[arg(long)]
+    keyint: Option<u32>,
+
+    /// The number of tile columns; the base-2 logarithm for AV1.
+    #[arg(long, default_value_t = 0)]
+    tile_columns: u32,
+
+    /// The number of tile rows; the base-2 logarithm for AV1.
+    #[arg(long, default_value_t = 0)]
+    tile_rows: u32,
+
+    /// The bit depth to encode at, if different from the input. Input samples
+    /// are shifted to match.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(8..=10))]
+    bit_depth: Option<u32>,
+
+    /// Stop after this many frames.
+    #[arg(long)]
+    frames: Option<u64>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let input: Box<dyn Read> = if args.input.as_os_str() == "-" {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(&args.input)?)
+    };
+
+    let output: Box<dyn Write> = if args.output.as_os_str() == "-" {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(&args.output)?)
+    };
+
+    let reader = Y4mReader::new(BufReader::new(input))?;
+    let header = reader.header();
+    let bit_depth = args.bit_depth.unwrap_or(header.bit_depth);
+
+    let container = args.container.unwrap_or(match args.codec {
+        Codec::Av1 | Codec::Vp9 => Container::Ivf,
+        Codec::Hevc => Container::Annexb,
+    });
+
+    let output = Output::new(BufWriter::new(output), container, args.codec, &header)?;
+    let start = Instant::now();
+
+    let frames: u64 = match args.codec {
+        #[cfg(feature = "av1")]
+        Codec::Av1 => {
+            use svt::av1::{Av1EncoderConfig, RateControlMode, TilingMode};
+            use svt::Av1Qp;
+
+            let mut config = common(Av1EncoderConfig::default(), &args, &header)
+                .bit_depth(bit_depth)
+                .chroma_sample_position(header.chroma_siting.into());
+
+            if let Some(crf) = args.crf {
+                config =
+                    config.rate_control_mode(RateControlMode::ConstantRateFactor(Av1Qp::new(crf)?));
+            } else if let (Some(kbps), true) = (args.bitrate, args.cbr) {
+                config = config.rate_control_mode(RateControlMode::ConstantBitrate(kbps * 1000));
+            }
+
+            if args.tile_columns > 0 || args.tile_rows > 0 {
+                config = config.tiling_mode(TilingMode::Multi {
+                    columns: args.tile_columns,
+                    rows: args.tile_rows,
+                });
+            }
+
+            let encoder =
+                config.create_encoder(header.width, header.height, header.subsampling_format)?;
+            run(&encoder, reader, output, &args, bit_depth, |p| p.pts())?
+        }
+        #[cfg(feature = "hevc")]
+        Codec::Hevc => {
+            use svt::hevc::{HevcEncoderConfig, TilingMode};
+
+            if args.crf.is_some() || args.cbr {
+                return Err("HEVC supports only --qp and variable --bitrate".into());
+            }
+
+            let mut config =
+                common(HevcEncoderConfig::default(), &args, &header).encoder_bit_depth(bit_depth);
+
+            if args.tile_columns > 0 || args.tile_rows > 0 {
+                config = config.tiling(TilingMode::Multi {
+                    columns: args.tile_columns.max(1).try_into()?,
+                    rows: args.tile_rows.max(1).try_into()?,
+                });
+            }
+
+            let encoder =
+                config.create_encoder(header.width, header.height, header.subsampling_format)?;
+            run(&encoder, reader, output, &args, bit_depth, |p| p.pts())?
+        }
+        #[cfg(feature = "vp9")]
+        Codec::Vp9 => {
+            use svt::vp9::{RateControlMode, Vp9EncoderConfig};
+
+            if args.crf.is_some() || bit_depth != 8 {
+                return Err("VP9 doesn't support --crf or high bit depth".into());
+            } else if args.tile_columns > 0 || args.tile_rows > 0 {
+                return Err("VP9 doesn't support tiles".into());
+            }
+
+            let mut config = common(Vp9EncoderConfig::default(), &args, &header);
+            if args.cbr {
+                config = config.rate_control_mode(RateControlMode::ConstantBitrate);
+            }
+
+            let encoder =
+                config.create_encoder(header.width, header.height, header.subsampling_format)?;
+            run(&encoder, reader, output, &args, bit_depth, |p| p.pts())?
+        }
+        #[allow(unreachable_patterns)]
+        codec => {
+            return Err(format!("the {:?} feature isn't enabled", codec).into());
+        }
+    };
+
+    let elapsed = start.elapsed().as_secs_f64();
+    eprintln!(
+        "encoded {} frames in {:.2}s ({:.1} fps)",
+        frames,
+        elapsed,
+        frames as f64 / elapsed
+    );
+
+    Ok(())
+}
+
+/// Applies the options shared by all codecs.
+fn common<C: EncoderConfig>(config: C, args: &Args, header: &Y4mHeader) -> C {
+    let mut config = config
+        .preset(args.preset)
+        .framerate(header.framerate.num, header.framerate.den)
+        .gop(args.keyint);
+
+    if let Some(qp) = args.qp {
+        config = config.rate_control(RateControl::ConstantQp(qp));
+    } else if let Some(kbps) = args.bitrate {
+        config = config.rate_control(RateControl::VariableBitrate(kbps * 1000));
+    }
+
+    config
+}
+
+/// Encodes every picture from `reader`, returning the number of frames.
+fn run<E: Encoder, R: Read, W: Write>(
+    encoder: &E,
+    mut reader: Y4mReader<R>,
+    mut output: Output<W>,
+    args: &Args,
+    bit_depth: u32,
+    pts: impl Fn(&E::Packet) -> i64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let header = reader.header();
+    let (width, height, format) = (header.width, header.height, header.subsampling_format);
+    let mut buf = YUVBuffer::new(width, height, format);
+    let mut buf16 = YUVBuffer16::new(width, height, format);
+
+    let mut frames = 0;
+    while args.frames.is_none_or(|n| frames < n) {
+        // Read at the input bit depth, then convert to the encoder's.
+        let more = if header.bit_depth == 8 {
+            reader.read_frame(&mut buf)?
+        } else {
+            reader.read_frame16(&mut buf16)?
+        };
+
+        if !more {
+            break;
+        }
+
+        match (header.bit_depth, bit_depth) {
+            (8, 8) => encoder.send_picture(&buf, frames as i64, false)?,
+            (8, _) => {
+                shift(&buf, &mut buf16, bit_depth - 8);
+                encoder.send_picture(&buf16, frames as i64, false)?
+            }
+            (input, 8) => {
+                for plane in [Plane::Y, Plane::U, Plane::V] {
+                    let samples = svt::Picture16::as_slice(&buf16, plane);
+                    for (dst, src) in buf.as_mut_slice(plane).iter_mut().zip(samples) {
+                        *dst = (src >> (input - 8)) as u8;
+                    }
+                }
+
+                encoder.send_picture(&buf, frames as i64, false)?
+            }
+            (input, _) => {
+                if input != bit_depth {
+                    for plane in [Plane::Y, Plane::U, Plane::V] {
+                        for v in buf16.as_mut_slice(plane) {
+                            *v = ((*v as u32 * (1 << bit_depth)) >> input) as u16;
+                        }
+                    }
+                }
+
+                encoder.send_picture(&buf16, frames as i64, false)?
+            }
+        }
+
+        frames += 1;
+        while let Some(packet) = encoder.get_packet(false)? {
+            output.write_packet(packet.as_bytes(), pts(&packet))?;
+        }
+    }
+
+    encoder.finish()?;
+    while let Some(packet) = encoder.get_packet(true)? {
+        output.write_packet(packet.as_bytes(), pts(&packet))?;
+        if packet.is_eos() {
+            break;
+        }
+    }
+
+    output.finish()?;
+    Ok(frames)
+}
+
+fn shift(src: &YUVBuffer, dst: &mut YUVBuffer16, bits: u32) {
+    for plane in [Plane::Y, Plane::U, Plane::V] {
+        for (dst, &src) in dst.as_mut_slice(plane).iter_mut().zip(src.as_slice(plane)) {
+            *dst = (src as u16) << bits;
+        }
+    }
+}
+
+/// Writes packets to the output container.
+struct Output<W> {
+    writer: W,
+    container: Container,
+    codec: Codec,
+}
+
+impl<W: Write> Output<W> {
+    fn new(
+        mut writer: W,
+        container: Container,
+        codec: Codec,
+        header: &Y4mHeader,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if container == Container::Ivf {
+            let fourcc = match codec {
+                Codec::Av1 => b"AV01",
+                Codec::Vp9 => b"VP90",
+                Codec::Hevc => return Err("IVF isn't supported for HEVC".into()),
+            };
+
+            // Timestamps are counted in frames. The frame count is left
+            // unset, since we don't know it up front.
+            writer.write_all(b"DKIF")?;
+            writer.write_all(&0u16.to_le_bytes())?;
+            writer.write_all(&32u16.to_le_bytes())?;
+            writer.write_all(fourcc)?;
+            writer.write_all(&u16::try_from(header.width)?.to_le_bytes())?;
+            writer.write_all(&u16::try_from(header.height)?.to_le_bytes())?;
+            writer.write_all(&header.framerate.num.to_le_bytes())?;
+            writer.write_all(&header.framerate.den.to_le_bytes())?;
+            writer.write_all(&[0; 8])?;
+        } else if container == Container::Annexb && codec == Codec::Vp9 {
+            return Err("Annex B isn't supported for VP9".into());
+        }
+
+        Ok(Self {
+            writer,
+            container,
+            codec,
+        })
+    }
+
+    fn write_packet(&mut self, data: &[u8], pts: i64) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        match (self.container, self.codec) {
+            (Container::Ivf, _) => {
+                self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+                self.writer.write_all(&pts.to_le_bytes())?;
+                self.writer.write_all(data)
+            }
+            (Container::Annexb, Codec::Av1) => self.writer.write_all(&av1_annexb(data)?),
+            _ => self.writer.write_all(data),
+        }
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Converts a temporal unit of AV1 OBUs, as output by the encoder, into the
+/// length-delimited format from Annex B of the AV1 specification. Each frame
+/// header starts a new frame unit. The OBUs keep their size fields, which
+/// Annex B allows.
+fn av1_annexb(tu: &[u8]) -> io::Result<Vec<u8>> {
+    const OBU_FRAME_HEADER: u8 = 3;
+    const OBU_FRAME: u8 = 6;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed OBU");
+
+    let mut frame_units: Vec<Vec<u8>> = vec![Vec::new()];
+    let mut has_frame = false;
+    let mut rest = tu;
+    while !rest.is_empty() {
+        let obu_type = (rest[0] >> 3) & 0xf;
+        let header_len = if rest[0] & 0b100 != 0 { 2 } else { 1 };
+        let obu_len = if rest[0] & 0b10 != 0 {
+            let (size, leb_len) =
+                read_leb128(rest.get(header_len..).ok_or_else(invalid)?).ok_or_else(invalid)?;
+            header_len + leb_len + size as usize
+        } else {
+            rest.len()
+        };
+
+        let obu = rest.get(..obu_len).ok_or_else(invalid)?;
+        rest = &rest[obu_len..];
+
+        if matches!(obu_type, OBU_FRAME_HEADER | OBU_FRAME) {
+            if has_frame {
+                frame_units.push(Vec::new());
+            }
+
+            has_frame = true;
+        }
+
+        let unit = frame_units.last_mut().unwrap();
+        write_leb128(unit, obu.len() as u64);
+        unit.extend_from_slice(obu);
+    }
+
+    let mut tu_payload = Vec::new();
+    for unit in frame_units {
+        write_leb128(&mut tu_payload, unit.len() as u64);
+        tu_payload.extend(unit);
+    }
+
+    let mut out = Vec::with_capacity(tu_payload.len() + 8);
+    write_leb128(&mut out, tu_payload.len() as u64);
+    out.extend(tu_payload);
+    Ok(out)
+}
+
+fn read_leb128(data: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as u64) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+
+    None
+}
+
+fn write_leb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+
+        out.push(byte | 0x80);
+    }
+}
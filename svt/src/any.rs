@@ -0,0 +1,415 @@
+//! Codec-independent encoders, for selecting the codec at runtime.
+
+use crate::{Encoder, EncoderConfig, EncoderState, Error, FrameTypeRequest, KeyframeReason};
+use crate::{Orientation, Packet, Picture, PictureRequirements, RateControl, Rational, Stats};
+use crate::{SubsamplingFormat, Timestamp};
+
+#[cfg(feature = "av1")]
+use crate::av1::{Av1Encoder, Av1EncoderConfig, Av1Packet};
+#[cfg(feature = "hevc")]
+use crate::hevc::{HevcEncoder, HevcEncoderConfig, HevcPacket};
+
+/// A video codec supported by this crate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Codec {
+    /// AV1, using SVT-AV1.
+    #[cfg(feature = "av1")]
+    Av1,
+    /// HEVC (H.265), using SVT-HEVC.
+    #[cfg(feature = "hevc")]
+    Hevc,
+}
+
+impl Codec {
+    /// Parses a codec name, like `"av1"` or `"hevc"`, or an RFC 6381 codecs
+    /// string like `"av01.0.08M.08"` or `"hvc1.1.6.L93.B0"`. Returns `None` if
+    /// the codec is unknown or the corresponding feature isn't enabled.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.split('.').next().unwrap_or_default();
+        match name.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "av1")]
+            "av1" | "av01" => Some(Codec::Av1),
+            #[cfg(feature = "hevc")]
+            "hevc" | "h265" | "hvc1" | "hev1" => Some(Codec::Hevc),
+            _ => None,
+        }
+    }
+}
+
+/// Configuration common to all codecs, for use with [`create_encoder`].
+/// Settings left as `None` use the codec's defaults.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CommonConfig {
+    /// The encoder preset. See [`EncoderConfig::preset`].
+    pub preset: Option<u8>,
+    /// The framerate, as a numerator and denominator.
+    pub framerate: Option<(u32, u32)>,
+    /// The rate control mode.
+    pub rate_control: Option<RateControl>,
+    /// The number of frames between intra refreshes.
+    pub gop: Option<u32>,
+}
+
+impl CommonConfig {
+    fn apply<C: EncoderConfig>(&self, mut config: C) -> C {
+        if let Some(preset) = self.preset {
+            config = config.preset(preset);
+        }
+
+        if let Some((numerator, denominator)) = self.framerate {
+            config = config.framerate(numerator, denominator);
+        }
+
+        if let Some(rate_control) = self.rate_control {
+            config = config.rate_control(rate_control);
+        }
+
+        config.gop(self.gop)
+    }
+}
+
+/// Creates an encoder for the given codec.
+pub fn create_encoder(
+    codec: Codec,
+    config: &CommonConfig,
+    width: u32,
+    height: u32,
+    subsampling_format: SubsamplingFormat,
+) -> Result<AnyEncoder, Error> {
+    match codec {
+        #[cfg(feature = "av1")]
+        Codec::Av1 => Ok(AnyEncoder::Av1(
+            config.apply(Av1EncoderConfig::default()).create_encoder(
+                width,
+                height,
+                subsampling_format,
+            )?,
+        )),
+        #[cfg(feature = "hevc")]
+        Codec::Hevc => Ok(AnyEncoder::Hevc(
+            config.apply(HevcEncoderConfig::default()).create_encoder(
+                width,
+                height,
+                subsampling_format,
+            )?,
+        )),
+    }
+}
+
+/// An encoder for any supported codec. See [`create_encoder`].
+#[derive(Debug)]
+pub enum AnyEncoder {
+    /// An AV1 encoder.
+    #[cfg(feature = "av1")]
+    Av1(Av1Encoder),
+    /// An HEVC encoder.
+    #[cfg(feature = "hevc")]
+    Hevc(HevcEncoder),
+}
+
+impl AnyEncoder {
+    /// The codec used by the encoder.
+    pub fn codec(&self) -> Codec {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(_) => Codec::Av1,
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(_) => Codec::Hevc,
+        }
+    }
+
+    /// Returns statistics about the encode so far.
+    pub fn stats(&self) -> Stats {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.stats(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.stats(),
+        }
+    }
+
+    /// The display orientation set on the encoder config, if any.
+    pub fn display_orientation(&self) -> Option<Orientation> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.display_orientation(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.display_orientation(),
+        }
+    }
+
+    /// The size of the encoded pictures, which may be larger than the
+    /// pictures sent to the encoder.
+    pub fn coded_size(&self) -> (u32, u32) {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.coded_size(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.coded_size(),
+        }
+    }
+
+    /// The dimensions and format of the pictures the encoder accepts.
+    pub fn picture_requirements(&self) -> PictureRequirements {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.picture_requirements(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.picture_requirements(),
+        }
+    }
+
+    /// The timebase of the presentation timestamps passed to the encoder.
+    pub fn timebase(&self) -> Rational {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.timebase(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.timebase(),
+        }
+    }
+
+    /// Sends an input picture to the encoder, with a timestamp in any
+    /// timebase.
+    pub fn send_picture_at(
+        &self,
+        picture: &impl Picture,
+        timestamp: Timestamp,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.send_picture_at(picture, timestamp, frame_type),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.send_picture_at(picture, timestamp, frame_type),
+        }
+    }
+
+    /// The maximum number of frames by which output is delayed because of
+    /// frame reordering. See [`TimestampReorderer`](crate::TimestampReorderer).
+    pub fn reorder_depth(&self) -> u32 {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.reorder_depth(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.reorder_depth(),
+        }
+    }
+
+    /// Sends an input picture to the encoder, unless the configured limit on
+    /// pending frames has been reached, in which case [`Error::WouldBlock`] is
+    /// returned.
+    pub fn try_send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.try_send_picture(picture, pts, frame_type),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.try_send_picture(picture, pts, frame_type),
+        }
+    }
+
+    /// The number of pictures that can be submitted without blocking, if
+    /// limited.
+    pub fn available_input_buffers(&self) -> Option<u32> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.available_input_buffers(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.available_input_buffers(),
+        }
+    }
+
+    /// Generates a packet containing the stream headers.
+    pub fn code_headers(&self) -> Result<AnyPacket, Error> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.code_headers().map(AnyPacket::Av1),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.code_headers().map(AnyPacket::Hevc),
+        }
+    }
+}
+
+impl Encoder for AnyEncoder {
+    type Packet = AnyPacket;
+
+    fn send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.send_picture(picture, pts, frame_type),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.send_picture(picture, pts, frame_type),
+        }
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.finish(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.finish(),
+        }
+    }
+
+    fn get_packet(&self, wait: bool) -> Result<Option<AnyPacket>, Error> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => Ok(enc.get_packet(wait)?.map(AnyPacket::Av1)),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => Ok(enc.get_packet(wait)?.map(AnyPacket::Hevc)),
+        }
+    }
+
+    fn state(&self) -> EncoderState {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.state(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.state(),
+        }
+    }
+
+    fn in_flight(&self) -> usize {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyEncoder::Av1(enc) => enc.in_flight(),
+            #[cfg(feature = "hevc")]
+            AnyEncoder::Hevc(enc) => enc.in_flight(),
+        }
+    }
+}
+
+/// A packet output by an [`AnyEncoder`].
+#[derive(Debug)]
+pub enum AnyPacket {
+    /// An AV1 packet.
+    #[cfg(feature = "av1")]
+    Av1(Av1Packet),
+    /// An HEVC packet.
+    #[cfg(feature = "hevc")]
+    Hevc(HevcPacket),
+}
+
+impl AnyPacket {
+    /// The presentation timestamp of the frame, in the encoder's timebase.
+    /// This is `None` for the stream headers.
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyPacket::Av1(p) => p.timestamp(),
+            #[cfg(feature = "hevc")]
+            AnyPacket::Hevc(p) => p.timestamp(),
+        }
+    }
+}
+
+impl Packet for AnyPacket {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyPacket::Av1(p) => p.as_bytes(),
+            #[cfg(feature = "hevc")]
+            AnyPacket::Hevc(p) => p.as_bytes(),
+        }
+    }
+
+    fn is_eos(&self) -> bool {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyPacket::Av1(p) => p.is_eos(),
+            #[cfg(feature = "hevc")]
+            AnyPacket::Hevc(p) => p.is_eos(),
+        }
+    }
+
+    fn keyframe_reason(&self) -> Option<KeyframeReason> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyPacket::Av1(p) => p.keyframe_reason(),
+            #[cfg(feature = "hevc")]
+            AnyPacket::Hevc(p) => p.keyframe_reason(),
+        }
+    }
+
+    fn completed_pts(&self) -> Option<i64> {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyPacket::Av1(p) => p.completed_pts(),
+            #[cfg(feature = "hevc")]
+            AnyPacket::Hevc(p) => p.completed_pts(),
+        }
+    }
+
+    fn is_frame_complete(&self) -> bool {
+        match self {
+            #[cfg(feature = "av1")]
+            AnyPacket::Av1(p) => p.is_frame_complete(),
+            #[cfg(feature = "hevc")]
+            AnyPacket::Hevc(p) => p.is_frame_complete(),
+        }
+    }
+}
+
+impl AsRef<[u8]> for AnyPacket {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::YUVBuffer;
+
+    use super::*;
+
+    #[test]
+    fn codec_from_name() {
+        #[cfg(feature = "av1")]
+        assert_eq!(Codec::from_name("av01.0.08M.08"), Some(Codec::Av1));
+        #[cfg(feature = "hevc")]
+        assert_eq!(Codec::from_name("HEVC"), Some(Codec::Hevc));
+        assert_eq!(Codec::from_name("vp9"), None);
+    }
+
+    #[test]
+    fn encode_frame() {
+        simple_logger::init_with_env().ok();
+
+        let config = CommonConfig {
+            framerate: Some((30, 1)),
+            rate_control: Some(RateControl::ConstantQp(30)),
+            ..Default::default()
+        };
+
+        #[cfg(feature = "av1")]
+        let codec = Codec::Av1;
+        #[cfg(not(feature = "av1"))]
+        let codec = Codec::Hevc;
+
+        let enc = create_encoder(codec, &config, 800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.codec(), codec);
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, true)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+    }
+}
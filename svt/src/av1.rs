@@ -3,7 +3,7 @@
 //!
 //! # Example
 //! ```
-//! # use svt::{Encoder, Packet, YUVBuffer, SubsamplingFormat};
+//! # use svt::{Av1Qp, Encoder, Packet, YUVBuffer, SubsamplingFormat};
 //! # use svt::av1::{Av1EncoderConfig, RateControlMode};
 //! # fn copy_frame(_: &mut YUVBuffer)
 //! #     -> Result<i64, Box<dyn std::error::Error>> { Ok(0) }
@@ -13,7 +13,7 @@
 //! # let colorspace = SubsamplingFormat::Yuv420;
 //! let encoder = Av1EncoderConfig::default()
 //!     .preset(8)
-//!     .rate_control_mode(RateControlMode::ConstantRateFactor(30))
+//!     .rate_control_mode(RateControlMode::ConstantRateFactor(Av1Qp::new(30)?))
 //!     .create_encoder(width, height, colorspace)?;
 //!
 //! let mut buffer = YUVBuffer::new(width, height, colorspace);
@@ -48,13 +48,75 @@
 
 use svt_av1_sys::*;
 
-use crate::{Encoder, Error, Picture, Plane, SubsamplingFormat};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::buffer::{coded_size, EdgePadding, NeutralChroma, PlaneLayout};
+use crate::gop::GopTracker;
+use crate::instrument::queue_wait;
+use crate::shutdown;
+use crate::state::StateTracker;
+use crate::stats::StatsTracker;
+use crate::{
+    Encoder, EncoderCall, EncoderState, Error, FrameTypeRequest, Packet, Picture,
+    PictureRequirements,
+};
+use crate::{
+    LibraryError, Orientation, OwnedPacket, Rational, Stats, SubsamplingFormat, Timestamp,
+};
 
 mod config;
+#[cfg(feature = "scale")]
+mod ladder;
+mod level;
+mod metadata;
 mod packet;
+mod realtime;
+mod recon;
+mod recovery;
+mod reference;
+#[cfg(svt_av1_rate_change)]
+mod schedule;
+mod temporal_unit;
 
 pub use config::*;
+#[cfg(feature = "scale")]
+pub use ladder::*;
+pub use level::Level;
+pub use metadata::*;
 pub use packet::*;
+pub use realtime::{RealtimeController, Speed};
+pub use recon::*;
+pub use recovery::RecoveryFrame;
+pub use reference::{LossRecovery, ReferenceTracker};
+#[cfg(svt_av1_rate_change)]
+pub use schedule::RateSchedule;
+
+/// Parsing of the OBUs in encoded packets, for muxing, packetization, and
+/// debugging.
+///
+/// ```
+/// # use svt::av1::obu::{obus, ObuType, SequenceHeader};
+/// # fn example(packet: &[u8]) -> Result<(), svt::av1::obu::ObuError> {
+/// for obu in obus(packet) {
+///     let obu = obu?;
+///     println!("{:?}: {} bytes", obu.obu_type(), obu.size());
+/// }
+///
+/// if let Some(header) = SequenceHeader::find(packet)? {
+///     println!("{}x{}", header.max_width, header.max_height);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub mod obu {
+    pub use crate::obu::{obus, Obu, ObuError, ObuType, Obus, SequenceHeader};
+}
+
+/// The raw library configuration, for use with [`Av1EncoderConfig::with_raw`].
+pub use svt_av1_sys::EbSvtAv1EncConfiguration;
 
 struct LibraryHandle(*mut EbComponentType);
 
@@ -74,10 +136,144 @@ impl Drop for LibraryHandle {
 
 unsafe impl Send for LibraryHandle {}
 
+// The library allows pictures to be sent and packets to be retrieved
+// concurrently. The encoder serializes calls of each kind.
+unsafe impl Sync for LibraryHandle {}
+
+/// An initialized library instance. The buffers of the packets it outputs
+/// belong to it, so packets hold on to it, and it's only deinitialized once
+/// the encoder has replaced or dropped it and all of its packets have been
+/// dropped.
+struct Library {
+    handle: LibraryHandle,
+    initialized: AtomicBool,
+}
+
+impl Library {
+    fn new(handle: LibraryHandle) -> Arc<Self> {
+        Arc::new(Self {
+            handle,
+            initialized: AtomicBool::new(true),
+        })
+    }
+
+    fn as_ptr(&self) -> *mut EbComponentType {
+        self.handle.as_ptr()
+    }
+
+    /// Deinitializes the library instance, if it hasn't been already.
+    fn deinit(&self) {
+        if self.initialized.swap(false, Ordering::AcqRel) {
+            unsafe {
+                svt_av1_enc_deinit(self.as_ptr());
+            }
+        }
+    }
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        self.deinit();
+    }
+}
+
+/// The maximum number of hierarchical levels supported by the library.
+const MAX_HIERARCHICAL_LEVELS: u32 = 5;
+
+include!(concat!(env!("OUT_DIR"), "/svt_av1_version.rs"));
+
+/// The version of SVT-AV1 the crate was built against, as a major, minor,
+/// and patch number. Builder methods which aren't supported by this version
+/// are omitted.
+pub fn library_version() -> (u32, u32, u32) {
+    SVT_AV1_VERSION
+}
+
+/// A copy of the configuration an encoder was created with, used to
+/// re-initialize it.
+struct SavedConfig(EbSvtAv1EncConfiguration);
+
+// The configuration contains pointers to caller-owned arrays (for example,
+// resize events), which are only read by the library when the encoder is
+// (re-)initialized.
+unsafe impl Send for SavedConfig {}
+unsafe impl Sync for SavedConfig {}
+
+/// The format of the frames being encoded, as configured.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct StreamFormat {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) subsampling_format: SubsamplingFormat,
+    pub(crate) bit_depth: u32,
+    pub(crate) stat_report: bool,
+}
+
+impl StreamFormat {
+    fn from_cfg(cfg: &EbSvtAv1EncConfiguration) -> Self {
+        #[allow(non_upper_case_globals)]
+        let subsampling_format = match cfg.encoder_color_format {
+            EbColorFormat_EB_YUV400 => SubsamplingFormat::Yuv400,
+            EbColorFormat_EB_YUV420 => SubsamplingFormat::Yuv420,
+            EbColorFormat_EB_YUV422 => SubsamplingFormat::Yuv422,
+            EbColorFormat_EB_YUV444 => SubsamplingFormat::Yuv444,
+            _ => panic!("unsupported color format"),
+        };
+
+        StreamFormat {
+            width: cfg.source_width,
+            height: cfg.source_height,
+            subsampling_format,
+            bit_depth: cfg.encoder_bit_depth,
+            stat_report: cfg.stat_report != 0,
+        }
+    }
+}
+
+/// The parameters the encoder settled on after initialization, as returned
+/// by [`Av1Encoder::effective_config`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Av1EffectiveConfig {
+    /// The sequence header the encoder writes, which records the profile,
+    /// level, and tier chosen by the library.
+    pub sequence_header: obu::SequenceHeader,
+    /// The number of tile columns in each frame.
+    pub tile_columns: u32,
+    /// The number of tile rows in each frame.
+    pub tile_rows: u32,
+    /// The intra period, as configured. The library doesn't report the
+    /// period it picks for [`IntraPeriod::Auto`].
+    pub intra_period: IntraPeriod,
+    /// The look-ahead distance, in frames, or `None` if the library chooses
+    /// it.
+    pub look_ahead_distance: Option<u32>,
+}
+
 /// An encoder instance.
 pub struct Av1Encoder {
-    handle: LibraryHandle,
-    subsampling_format: SubsamplingFormat,
+    handle: Arc<Library>,
+    cfg: Box<SavedConfig>,
+    pub(crate) format: StreamFormat,
+    pending_resolution: Mutex<Option<SvtAv1InputPicDef>>,
+    #[cfg(svt_av1_rate_change)]
+    pub(crate) rate: Mutex<schedule::RateTracker>,
+    stats: Mutex<StatsTracker>,
+    gop: Mutex<GopTracker>,
+    pub(crate) recovery: Mutex<recovery::RecoveryTracker>,
+    pub(crate) temporal_units: Option<Mutex<temporal_unit::TemporalUnits>>,
+    metadata_pts: Mutex<HashSet<i64>>,
+    alt_ref_pts: Mutex<Option<i64>>,
+    max_pending_frames: Option<u32>,
+    drop_timeout: Duration,
+    padding: Option<EdgePadding>,
+    monochrome: Option<Mutex<NeutralChroma>>,
+    orientation: Option<Orientation>,
+    timebase: Rational,
+    #[cfg(feature = "log")]
+    log_sink: Option<LogSink>,
+    state: StateTracker,
+    send_lock: Mutex<()>,
+    recv_lock: Mutex<()>,
 }
 
 impl std::fmt::Debug for Av1Encoder {
@@ -95,80 +291,55 @@ impl Encoder for Av1Encoder {
         &self,
         picture: &impl Picture,
         pts: i64,
-        force_keyframe: bool,
+        frame_type: impl Into<FrameTypeRequest>,
     ) -> Result<(), Error> {
-        let y = picture.as_slice(Plane::Y);
-        let u = picture.as_slice(Plane::U);
-        let v = picture.as_slice(Plane::V);
+        self.send_input(picture, pts, frame_type.into(), &[])
+    }
 
-        let y_stride = picture.stride(Plane::Y);
-        let u_stride = picture.stride(Plane::U);
-        let v_stride = picture.stride(Plane::V);
+    fn get_packet(&self, wait: bool) -> Result<Option<Av1Packet>, Error> {
+        let Some(units) = &self.temporal_units else {
+            return self.get_frame(wait);
+        };
 
-        assert_eq!(y.len(), (y_stride * picture.height()) as usize);
-        match self.subsampling_format {
-            SubsamplingFormat::Yuv400 => {
-                assert_eq!(u.len(), 0);
-                assert_eq!(v.len(), 0);
-            }
-            SubsamplingFormat::Yuv420 => {
-                assert_eq!(u.len(), (u_stride * picture.height() / 2) as usize);
-                assert_eq!(v.len(), (v_stride * picture.height() / 2) as usize);
+        let mut units = units.lock().unwrap();
+        loop {
+            if let Some(packet) = units.pop() {
+                return Ok(Some(packet));
             }
-            SubsamplingFormat::Yuv422 | SubsamplingFormat::Yuv444 => {
-                assert_eq!(u.len(), (u_stride * picture.height()) as usize);
-                assert_eq!(v.len(), (v_stride * picture.height()) as usize);
+
+            match self.get_frame(wait)? {
+                Some(packet) => units.push(packet),
+                None => return Ok(None),
             }
         }
+    }
 
-        let mut input_pic = EbSvtIOFormat {
-            luma: picture.as_slice(Plane::Y).as_ptr() as *mut _,
-            cb: picture.as_slice(Plane::U).as_ptr() as *mut _,
-            cr: picture.as_slice(Plane::V).as_ptr() as *mut _,
-            y_stride,
-            cr_stride: u_stride,
-            cb_stride: v_stride,
-            ..Default::default()
-        };
-
-        let pic_type = if force_keyframe {
-            EbAv1PictureType_EB_AV1_KEY_PICTURE
-        } else {
-            EbAv1PictureType_EB_AV1_INVALID_PICTURE
-        };
-
+    fn finish(&self) -> Result<(), Error> {
         let mut input = EbBufferHeaderType {
-            size: size_of::<EbBufferHeaderType>() as u32,
-            p_buffer: &mut input_pic as *mut _ as *mut u8,
-            n_filled_len: (y.len() + u.len() + v.len()) as u32,
-            pts,
-            pic_type,
+            flags: EB_BUFFERFLAG_EOS,
             ..Default::default()
         };
 
-        unsafe { result(svt_av1_enc_send_picture(self.handle.as_ptr(), &mut input)) }
-    }
+        let _guard = self.state.lock(&self.send_lock)?;
+        if !self.state.should_finish()? {
+            return Ok(());
+        }
 
-    fn get_packet(&self, wait: bool) -> Result<Option<Av1Packet>, Error> {
-        let mut p = std::ptr::null_mut();
         unsafe {
-            #[allow(non_upper_case_globals)]
-            match svt_av1_enc_get_packet(self.handle.as_ptr(), &mut p, wait as u8) {
-                EbErrorType_EB_NoErrorEmptyQueue => return Ok(None),
-                code => result(code)?,
-            }
-
-            Ok(Some(Av1Packet::new(p)))
+            result(self.logged(|| svt_av1_enc_send_picture(self.handle.as_ptr(), &mut input)))
+                .map_err(self.in_call(EncoderCall::Finish, None))?;
         }
+
+        self.state.set_draining();
+        Ok(())
     }
 
-    fn finish(&self) -> Result<(), Error> {
-        let mut input = EbBufferHeaderType {
-            flags: EB_BUFFERFLAG_EOS,
-            ..Default::default()
-        };
+    fn state(&self) -> EncoderState {
+        self.state.get()
+    }
 
-        unsafe { result(svt_av1_enc_send_picture(self.handle.as_ptr(), &mut input)) }
+    fn in_flight(&self) -> usize {
+        self.stats.lock().unwrap().pending() as usize
     }
 }
 
@@ -183,100 +354,2710 @@ impl Av1Encoder {
         handle: *mut EbComponentType,
         cfg: *mut EbSvtAv1EncConfiguration,
     ) -> Self {
-        #[allow(non_upper_case_globals)]
-        let subsampling_format = match (*cfg).encoder_color_format {
-            EbColorFormat_EB_YUV400 => SubsamplingFormat::Yuv400,
-            EbColorFormat_EB_YUV420 => SubsamplingFormat::Yuv420,
-            EbColorFormat_EB_YUV422 => SubsamplingFormat::Yuv422,
-            EbColorFormat_EB_YUV444 => SubsamplingFormat::Yuv444,
-            _ => panic!("unsupported color format"),
-        };
+        Self::new(LibraryHandle(handle), &*cfg)
+    }
 
+    fn new(handle: LibraryHandle, cfg: &EbSvtAv1EncConfiguration) -> Self {
         Av1Encoder {
-            handle: LibraryHandle(handle),
-            subsampling_format,
+            handle: Library::new(handle),
+            cfg: Box::new(SavedConfig(*cfg)),
+            format: StreamFormat::from_cfg(cfg),
+            pending_resolution: Mutex::new(None),
+            stats: Mutex::new(StatsTracker::new(
+                cfg.frame_rate_numerator,
+                cfg.frame_rate_denominator,
+            )),
+            gop: Mutex::new(GopTracker::new(
+                cfg.intra_period_length,
+                cfg.scene_change_detection != 0,
+            )),
+            #[cfg(svt_av1_rate_change)]
+            rate: Mutex::new(schedule::RateTracker::new(cfg.target_bit_rate, None)),
+            recovery: Mutex::new(recovery::RecoveryTracker::default()),
+            temporal_units: None,
+            metadata_pts: Mutex::new(HashSet::new()),
+            alt_ref_pts: Mutex::new(None),
+            max_pending_frames: None,
+            drop_timeout: shutdown::DEFAULT_DROP_TIMEOUT,
+            padding: None,
+            monochrome: None,
+            orientation: None,
+            timebase: Rational::from_framerate(
+                cfg.frame_rate_numerator.max(1),
+                cfg.frame_rate_denominator.max(1),
+            ),
+            #[cfg(feature = "log")]
+            log_sink: None,
+            state: StateTracker::new(),
+            send_lock: Mutex::new(()),
+            recv_lock: Mutex::new(()),
         }
     }
 
-    /// Generates a Sequence Header OBU.
-    ///
-    /// This is not generally necessary, as the encoder will automatically
-    /// generate headers as needed.
-    pub fn code_headers(&self) -> Result<Av1Packet, Error> {
+    /// Retrieves the next buffer output by the library.
+    fn get_frame(&self, wait: bool) -> Result<Option<Av1Packet>, Error> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "get_packet",
+            codec = "av1",
+            wait,
+            pts = tracing::field::Empty,
+            size = tracing::field::Empty,
+        )
+        .entered();
+
+        let _guard = self.state.lock(&self.recv_lock)?;
+        self.state.check_poisoned()?;
+        if self.state.get() == EncoderState::Finished {
+            return Ok(None);
+        }
+
         let mut p = std::ptr::null_mut();
         unsafe {
-            result(svt_av1_enc_stream_header(self.handle.as_ptr(), &mut p))?;
+            let code = queue_wait(|| {
+                self.logged(|| svt_av1_enc_get_packet(self.handle.as_ptr(), &mut p, wait as u8))
+            });
+
+            #[allow(non_upper_case_globals)]
+            match code {
+                EbErrorType_EB_NoErrorEmptyQueue => return Ok(None),
+                code => result(code).map_err(self.in_call(EncoderCall::GetPacket, None))?,
+            }
+
+            let mut packet = Av1Packet::new(p, self.format, self.handle.clone());
+            if packet.is_eos() {
+                self.state.set_finished();
+            }
+
+            packet.timebase = Some(self.timebase);
+            packet.has_metadata = self.metadata_pts.lock().unwrap().remove(&packet.pts());
+
+            // Overlay frames are coded from the same source picture as the
+            // preceding alt-ref frame.
+            let mut alt_ref_pts = self.alt_ref_pts.lock().unwrap();
+            if packet.frame_type() == FrameType::AltRef {
+                *alt_ref_pts = Some(packet.pts());
+            } else if *alt_ref_pts == Some(packet.pts()) {
+                packet.is_overlay = true;
+                *alt_ref_pts = None;
+            }
+
+            // Switch frames are reported as inter frames.
+            if self.cfg.0.sframe_dist > 0 && packet.frame_type() == FrameType::Inter {
+                packet.is_switch =
+                    crate::obu::frame_type(packet.as_bytes()) == Some(crate::obu::SWITCH_FRAME);
+            }
+
+            if !packet.as_bytes().is_empty() {
+                let keyframe = packet.frame_type() == FrameType::Key;
+                packet.output = self
+                    .gop
+                    .lock()
+                    .unwrap()
+                    .record_output(packet.pts(), keyframe);
+
+                let intra = matches!(packet.frame_type(), FrameType::Key | FrameType::IntraOnly);
+                if intra {
+                    self.recovery.lock().unwrap().record_intra(packet.pts());
+                }
+
+                let mut stats = self.stats.lock().unwrap();
+                stats.record_output(packet.as_bytes().len(), packet.qp(), intra);
+                if packet.completed_pts().is_some() {
+                    stats.record_completed();
+                }
+            }
+
+            #[cfg(feature = "tracing")]
+            {
+                span.record("pts", packet.pts());
+                span.record("size", packet.as_bytes().len());
+            }
 
-            Ok(Av1Packet::new_headers(p))
+            Ok(Some(packet))
         }
     }
-}
 
-impl Drop for Av1Encoder {
-    fn drop(&mut self) {
-        unsafe {
-            svt_av1_enc_deinit(self.handle.as_ptr());
+    /// Sends an input picture to the encoder, like [`Encoder::send_picture`],
+    /// and attaches the given metadata OBUs to the resulting frame.
+    pub fn send_picture_with_metadata(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+        metadata: &[Metadata],
+    ) -> Result<(), Error> {
+        self.send_input(picture, pts, frame_type.into(), metadata)
+    }
+
+    fn send_input(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: FrameTypeRequest,
+        metadata: &[Metadata],
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("send_picture", codec = "av1", pts).entered();
+
+        let mut layout = PlaneLayout::new(picture, &self.picture_requirements())?;
+
+        // Keep the padded luma and neutral chroma locked until the library
+        // has copied them.
+        let _luma = self
+            .padding
+            .as_ref()
+            .map(|padding| padding.pad(picture, &mut layout));
+        let _chroma = self.monochrome.as_ref().map(|chroma| {
+            let mut chroma = chroma.lock().unwrap();
+            chroma.fill(&mut layout, self.format.width, self.format.height);
+            chroma
+        });
+
+        let mut input_pic = EbSvtIOFormat {
+            luma: layout.luma as *mut _,
+            cb: layout.cb as *mut _,
+            cr: layout.cr as *mut _,
+            y_stride: layout.y_stride,
+            cb_stride: layout.cb_stride,
+            cr_stride: layout.cr_stride,
+            ..Default::default()
+        };
+
+        // Packets may be retrieved on another thread as soon as the picture
+        // is submitted.
+        let _guard = self.state.lock(&self.send_lock)?;
+        self.state.check_running()?;
+
+        // An outstanding recovery request applies to the next picture
+        // without an explicit frame type.
+        let frame_type = self.recovery.lock().unwrap().frame_type(frame_type);
+        let pic_type = match frame_type {
+            FrameTypeRequest::Auto => EbAv1PictureType_EB_AV1_INVALID_PICTURE,
+            FrameTypeRequest::Key => EbAv1PictureType_EB_AV1_KEY_PICTURE,
+            FrameTypeRequest::IntraOnly => EbAv1PictureType_EB_AV1_INTRA_ONLY_PICTURE,
+            FrameTypeRequest::AltRef => EbAv1PictureType_EB_AV1_ALT_REF_PICTURE,
+            v @ (FrameTypeRequest::Idr | FrameTypeRequest::Cra) => {
+                return Err(Error::BadParameter {
+                    detail: Some(format!("unsupported frame type for AV1: {:?}", v)),
+                })
+            }
+        };
+
+        let mut input = EbBufferHeaderType {
+            size: size_of::<EbBufferHeaderType>() as u32,
+            p_buffer: &mut input_pic as *mut _ as *mut u8,
+            n_filled_len: layout.len as u32,
+            pts,
+            pic_type,
+            ..Default::default()
+        };
+
+        // The library copies the events when the picture is submitted.
+        let mut events = Vec::new();
+        let mut resolution = self.pending_resolution.lock().unwrap().take();
+        if let Some(def) = &mut resolution {
+            events.push(EbPrivDataNode {
+                node_type: PrivDataType_RES_CHANGE_EVENT,
+                data: <*mut _>::cast(def),
+                size: size_of::<SvtAv1InputPicDef>() as u32,
+                next: std::ptr::null_mut(),
+            });
+        }
+
+        #[cfg(svt_av1_rate_change)]
+        let (mut rate, rate_checkpoint) = {
+            let mut tracker = self.rate.lock().unwrap();
+            let checkpoint = tracker.checkpoint();
+            let rate = tracker.next(pts).map(|target_bit_rate| SvtAv1RateInfo {
+                seq_qp: 0,
+                target_bit_rate,
+            });
+
+            (rate, checkpoint)
+        };
+
+        #[cfg(svt_av1_rate_change)]
+        if let Some(info) = &mut rate {
+            events.push(EbPrivDataNode {
+                node_type: PrivDataType_RATE_CHANGE_EVENT,
+                data: <*mut _>::cast(info),
+                size: size_of::<SvtAv1RateInfo>() as u32,
+                next: std::ptr::null_mut(),
+            });
+        }
+
+        // Link the events into a list.
+        for i in 1..events.len() {
+            let next: *mut EbPrivDataNode = &mut events[i];
+            events[i - 1].next = next;
+        }
+
+        if let Some(node) = events.first_mut() {
+            input.p_app_private = <*mut _>::cast(node);
+        }
+
+        if !metadata.is_empty() {
+            self.metadata_pts.lock().unwrap().insert(pts);
+        }
+
+        // A resolution change always produces a keyframe.
+        let forced = frame_type == FrameTypeRequest::Key || resolution.is_some();
+        self.gop.lock().unwrap().record_submitted(pts, forced);
+
+        // The library copies the metadata when the picture is submitted, so
+        // it can be freed afterwards either way.
+        let res = metadata
+            .iter()
+            .try_for_each(|m| m.attach(&mut input))
+            .and_then(|_| unsafe {
+                result(queue_wait(|| {
+                    self.logged(|| svt_av1_enc_send_picture(self.handle.as_ptr(), &mut input))
+                }))
+            })
+            .map_err(self.in_call(EncoderCall::SendPicture, Some(pts)));
+
+        if !input.metadata.is_null() {
+            unsafe { svt_metadata_array_free(<*mut _>::cast(&mut input.metadata)) };
+        }
+
+        if res.is_err() {
+            self.metadata_pts.lock().unwrap().remove(&pts);
+            self.gop.lock().unwrap().cancel(pts);
+
+            // Retry the events with the next picture.
+            let mut pending = self.pending_resolution.lock().unwrap();
+            if pending.is_none() {
+                *pending = resolution;
+            }
+
+            #[cfg(svt_av1_rate_change)]
+            self.rate.lock().unwrap().restore(rate_checkpoint);
+            return res;
         }
+
+        self.recovery.lock().unwrap().submitted(pts, frame_type);
+        self.stats.lock().unwrap().record_submitted();
+        Ok(())
     }
-}
 
-#[allow(non_upper_case_globals)]
-pub(crate) fn result(code: EbErrorType) -> Result<(), Error> {
-    match code {
-        0 => Ok(()),
-        // These are used for decoding only.
-        // EbErrorType_EB_DecUnsupportedBitstream => Err(Error::UnsupportedBitstream),
-        // EbErrorType_EB_DecNoOutputPicture => Err(Error::NoOutputPicture),
-        // EbErrorType_EB_DecDecodingError => Err(Error::DecodingError),
-        // EbErrorType_EB_Corrupt_Frame => Err(Error::CorruptFrame),
-        EbErrorType_EB_ErrorInsufficientResources => Err(Error::InsufficientResources),
-        EbErrorType_EB_ErrorUndefined => Err(Error::Undefined),
-        EbErrorType_EB_ErrorInvalidComponent => Err(Error::InvalidComponent),
-        EbErrorType_EB_ErrorBadParameter => Err(Error::BadParameter),
-        EbErrorType_EB_ErrorDestroyThreadFailed => Err(Error::DestroyThreadFailed),
-        EbErrorType_EB_ErrorSemaphoreUnresponsive => Err(Error::SemaphoreUnresponsive),
-        EbErrorType_EB_ErrorDestroySemaphoreFailed => Err(Error::DestroySemaphoreFailed),
-        EbErrorType_EB_ErrorCreateMutexFailed => Err(Error::CreateMutexFailed),
-        EbErrorType_EB_ErrorMutexUnresponsive => Err(Error::MutexUnresponsive),
-        EbErrorType_EB_ErrorDestroyMutexFailed => Err(Error::DestroyMutexFailed),
-        _ => Err(Error::Unknown(code)),
+    /// The timebase of the presentation timestamps passed to the encoder and
+    /// reported on packets. See [`Av1EncoderConfig::timebase`].
+    pub fn timebase(&self) -> Rational {
+        self.timebase
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::YUVBuffer;
+    /// Sends an input picture to the encoder, like [`Encoder::send_picture`],
+    /// with a timestamp in any timebase. The timestamp is converted to the
+    /// encoder's timebase.
+    pub fn send_picture_at(
+        &self,
+        picture: &impl Picture,
+        timestamp: Timestamp,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        let pts = timestamp.rescale(self.timebase).value;
+        self.send_picture(picture, pts, frame_type)
+    }
 
-    use super::*;
+    /// Sends an input picture to the encoder, like [`Encoder::send_picture`],
+    /// unless the limit set with [`Av1EncoderConfig::max_pending_frames`] has been
+    /// reached, in which case [`Error::WouldBlock`] is returned. Real-time
+    /// applications can use this to drop frames rather than stall.
+    pub fn try_send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        if self.available_input_buffers() == Some(0) {
+            return Err(Error::WouldBlock);
+        }
 
-    #[test]
-    fn encode_frame() {
-        simple_logger::init_with_env().ok();
+        self.send_picture(picture, pts, frame_type)
+    }
 
-        let enc = Av1EncoderConfig::default()
-            .preset(7)
-            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
-            .expect("failed to create encoder");
+    /// The number of pictures that can be submitted before reaching the limit
+    /// set with [`Av1EncoderConfig::max_pending_frames`], or `None` if there is no
+    /// limit.
+    pub fn available_input_buffers(&self) -> Option<u32> {
+        let pending = self.stats.lock().unwrap().pending();
+        self.max_pending_frames
+            .map(|max| (max as u64).saturating_sub(pending) as u32)
+    }
 
-        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+    /// Requests a recovery frame for a receiver which lost packets, and
+    /// decoded the stream correctly up to the frame with `last_good_pts`.
+    /// The frame is coded with the next picture sent without an explicit
+    /// frame type, as configured with
+    /// [`Av1EncoderConfig::recovery_frame`].
+    ///
+    /// Returns `false` if the request is already covered, by an outstanding
+    /// request or by an intra frame after `last_good_pts`, so that requests
+    /// from several receivers after the same loss produce a single recovery
+    /// frame. This assumes a low-delay prediction structure, where frames
+    /// are coded in presentation order.
+    pub fn request_recovery(&self, last_good_pts: i64) -> bool {
+        self.recovery.lock().unwrap().request(last_good_pts)
+    }
 
-        enc.send_picture(&buf, 0, false)
-            .expect("failed to send picture");
+    /// Changes the target bitrate, in bits per second, starting with the next
+    /// picture sent. The encoder must use [`RateControlMode::VariableBitrate`]
+    /// or [`RateControlMode::ConstantBitrate`]. The new bitrate holds until
+    /// the next entry in the [`RateSchedule`], if any, is reached.
+    #[cfg(svt_av1_rate_change)]
+    pub fn change_bitrate(&self, bitrate: u32) -> Result<(), Error> {
+        if self.cfg.0.rate_control_mode == 0 {
+            return Err(Error::BadParameter {
+                detail: Some("changing the bitrate requires VBR or CBR".to_owned()),
+            });
+        }
 
-        let _packet = enc.get_packet(false).expect("failed to get packet");
+        self.rate.lock().unwrap().request(bitrate);
+        Ok(())
+    }
 
-        enc.finish().expect("failed to finish");
+    /// Changes the resolution of the input pictures, starting with the next
+    /// picture sent.
+    ///
+    /// With [`PredictionStructure::LowDelay`], the library handles the change
+    /// mid-stream, by inserting a new keyframe. The new resolution must not
+    /// exceed the size set with [`Av1EncoderConfig::set_forced_max_frame_size`],
+    /// if any.
+    ///
+    /// Otherwise, the encoder finishes the current stream, and is
+    /// re-initialized with the same configuration, like
+    /// [`Av1Encoder::change_speed`]. The packets for the pictures still being
+    /// encoded are returned, in decode order, and the next picture sent
+    /// starts a new stream with a keyframe.
+    ///
+    /// For adaptive bitrate ladders, it's usually better to run an encoder
+    /// per rendition, with switch frames enabled (see
+    /// [`Av1EncoderConfig::switch_frame_insertion`]) and the same forced
+    /// maximum frame size, so that clients can switch between renditions.
+    pub fn change_resolution(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<OwnedPacket>, Error> {
+        self.state.check_poisoned()?;
 
-        let _packet = enc.get_packet(true).expect("failed to get final packet");
+        let input_format = self.picture_requirements().subsampling_format;
+        let (coded_width, coded_height) = coded_size(width, height, input_format);
+
+        let mut cfg = self.cfg.0;
+        set_frame_size(&mut cfg, width, height, self.format.subsampling_format);
+
+        let mut packets = Vec::new();
+
+        // Low delay.
+        if cfg.pred_structure == 1 {
+            *self.pending_resolution.get_mut().unwrap() = Some(SvtAv1InputPicDef {
+                input_luma_width: coded_width,
+                input_luma_height: coded_height,
+                input_pad_bottom: 0,
+                input_pad_right: 0,
+            });
+
+            // A later re-initialization starts at the new size.
+            self.cfg.0 = cfg;
+        } else {
+            if self.in_flight() > 0 {
+                packets = self.drain_stream()?;
+            }
+
+            self.reinit(cfg)?;
+        }
+
+        self.format.width = coded_width;
+        self.format.height = coded_height;
+        self.padding = EdgePadding::new(width, height, input_format);
+        Ok(packets)
     }
 
-    #[test]
-    fn create_encoder_error() {
-        simple_logger::init_with_env().ok();
+    /// Changes the preset of a running encoder, keeping fast-decode mode as
+    /// it is. See [`Av1Encoder::change_speed`].
+    pub fn set_preset(&mut self, preset: i8) -> Result<Vec<OwnedPacket>, Error> {
+        let fast_decode = self.cfg.0.fast_decode != 0;
+        self.change_speed(Speed {
+            preset,
+            fast_decode,
+        })
+    }
 
-        let _enc = Av1EncoderConfig::default()
-            .bitrate_section_percentage(999, 999)
-            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
-            .expect_err("expected EB_BadParameter");
+    /// Changes the preset and fast-decode mode of a running encoder, for
+    /// example as directed by a [`RealtimeController`].
+    ///
+    /// No version of SVT-AV1 can change either of them mid-stream, so the
+    /// encoder finishes the current stream, and is re-initialized with the
+    /// new settings. The packets for the pictures still being encoded are
+    /// copied out of the old stream and returned, in decode order, so that
+    /// nothing is lost; the next picture sent starts a new stream with a
+    /// keyframe. The stats, timebase, and other settings carry over. With
+    /// [`PredictionStructure::LowDelay`], few pictures are in flight, so the
+    /// switch is quick enough for real-time use.
+    ///
+    /// Packets retrieved from the previous stream stay valid, and the old
+    /// library instance is shut down once they're dropped. If the speed is
+    /// unchanged, this does nothing.
+    pub fn change_speed(&mut self, speed: Speed) -> Result<Vec<OwnedPacket>, Error> {
+        self.state.check_poisoned()?;
+
+        let mut cfg = self.cfg.0;
+        if cfg.enc_mode == speed.preset && (cfg.fast_decode != 0) == speed.fast_decode {
+            return Ok(Vec::new());
+        }
+
+        let packets = if self.in_flight() > 0 {
+            self.drain_stream()?
+        } else {
+            Vec::new()
+        };
+
+        cfg.enc_mode = speed.preset;
+        cfg.fast_decode = speed.fast_decode.into();
+        self.reinit(cfg)?;
+        Ok(packets)
+    }
+
+    /// Finishes the stream, and copies the remaining packets out of the
+    /// encoder, so that they outlive the library instance.
+    fn drain_stream(&self) -> Result<Vec<OwnedPacket>, Error> {
+        self.finish()?;
+
+        let mut packets = Vec::new();
+        while let Some(packet) = self.get_packet(true)? {
+            if packet.is_eos() {
+                break;
+            }
+
+            if !packet.as_bytes().is_empty() {
+                let keyframe = packet.frame_type() == FrameType::Key;
+                let data = packet.as_bytes().to_vec();
+                packets.push(OwnedPacket::new(data, packet.pts(), keyframe));
+            }
+        }
+
+        Ok(packets)
+    }
+
+    /// Replaces the library instance with a new one using `cfg`, which starts
+    /// a new stream. Any pictures in flight should be drained first, with
+    /// [`Av1Encoder::drain_stream`].
+    fn reinit(&mut self, mut cfg: EbSvtAv1EncConfiguration) -> Result<(), Error> {
+        // Keep any bitrate changed since the encoder was created.
+        #[cfg(svt_av1_rate_change)]
+        if cfg.rate_control_mode != 0 {
+            cfg.target_bit_rate = self.rate.get_mut().unwrap().current();
+        }
+
+        unsafe {
+            let mut handle = std::ptr::null_mut();
+            let mut scratch_cfg = std::mem::zeroed();
+            result(svt_av1_enc_init_handle(
+                &mut handle,
+                std::ptr::null_mut(),
+                &mut scratch_cfg,
+            ))?;
+
+            let handle = LibraryHandle(handle);
+            self.logged(|| {
+                result_with_detail(|| svt_av1_enc_set_parameter(handle.as_ptr(), &mut cfg))?;
+                result_with_detail(|| svt_av1_enc_init(handle.as_ptr()))
+            })?;
+
+            let old = std::mem::replace(&mut self.handle, Library::new(handle));
+            self.release_library(old);
+        }
+
+        // Anything still tracked for the previous stream will never be
+        // output.
+        self.cfg.0 = cfg;
+        *self.gop.get_mut().unwrap() =
+            GopTracker::new(cfg.intra_period_length, cfg.scene_change_detection != 0);
+        *self.recovery.get_mut().unwrap() = recovery::RecoveryTracker::default();
+        *self.pending_resolution.get_mut().unwrap() = None;
+        *self.alt_ref_pts.get_mut().unwrap() = None;
+        self.metadata_pts.get_mut().unwrap().clear();
+        if let Some(units) = &mut self.temporal_units {
+            *units.get_mut().unwrap() = temporal_unit::TemporalUnits::default();
+        }
+
+        self.stats.get_mut().unwrap().discard_pending();
+        self.state.restart();
+        Ok(())
+    }
+
+    /// The maximum number of frames by which output is delayed because of
+    /// frame reordering. This is zero for [`PredictionStructure::LowDelay`],
+    /// and otherwise the number of hierarchical levels. See
+    /// [`TimestampReorderer`](crate::TimestampReorderer).
+    pub fn reorder_depth(&self) -> u32 {
+        match (self.cfg.0.pred_structure, self.cfg.0.hierarchical_levels) {
+            // Low delay.
+            (1, _) => 0,
+            // Let the library decide, up to the maximum.
+            (_, 0) => MAX_HIERARCHICAL_LEVELS,
+            (_, levels) => levels,
+        }
+    }
+
+    /// A rough estimate of the memory the library uses for this encoder, in
+    /// bytes, derived from the frame size and the settings which control how
+    /// many pictures are buffered. Actual usage varies with the preset and
+    /// content; this is meant for sizing machines and comparing settings.
+    /// See [`Av1EncoderConfig::memory_budget`].
+    pub fn approx_memory_usage(&self) -> u64 {
+        memory::approx_memory_usage(&self.cfg.0)
+    }
+
+    /// The display orientation set with
+    /// [`Av1EncoderConfig::display_orientation`], for a muxer to write into
+    /// the container.
+    pub fn display_orientation(&self) -> Option<Orientation> {
+        self.orientation
+    }
+
+    /// The size of the encoded frames. This is larger than the pictures sent
+    /// to the encoder if they have odd dimensions that the chroma subsampling
+    /// can't represent, in which case a container can crop the difference
+    /// (for example, with Matroska's `PixelCrop` elements).
+    pub fn coded_size(&self) -> (u32, u32) {
+        (self.format.width, self.format.height)
+    }
+
+    /// Finishes the stream, waits at most `timeout` for the remaining
+    /// packets, and shuts the encoder down. Any packets not yet retrieved are
+    /// discarded. Returns [`Error::TimedOut`] if the stream didn't finish in
+    /// time, in which case the encoder is deinitialized anyway.
+    ///
+    /// Dropping the encoder does the same, with the timeout set by
+    /// [`Av1EncoderConfig::drop_timeout`], but ignores any errors.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<(), Error> {
+        let res = shutdown::drain(&self, timeout);
+        self.drop_timeout = Duration::ZERO;
+        res
+    }
+
+    /// The dimensions and format of the pictures the encoder accepts. These
+    /// change with [`Av1Encoder::change_resolution`].
+    pub fn picture_requirements(&self) -> PictureRequirements {
+        let subsampling_format = match self.monochrome {
+            Some(_) => SubsamplingFormat::Yuv400,
+            None => self.format.subsampling_format,
+        };
+
+        let (width, height) = match &self.padding {
+            Some(padding) => (padding.width, padding.height),
+            None => (self.format.width, self.format.height),
+        };
+
+        PictureRequirements {
+            width,
+            height,
+            subsampling_format,
+            bytes_per_sample: if self.format.bit_depth > 8 { 2 } else { 1 },
+        }
+    }
+
+    /// Returns statistics about the encode so far.
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().snapshot()
+    }
+
+    /// Retrieves the next reconstructed frame, if one is available. The
+    /// encoder must have been configured with
+    /// [`Av1EncoderConfig::enable_recon`]; otherwise, this returns an error.
+    pub fn get_recon(&self) -> Result<Option<ReconPicture>, Error> {
+        let mut recon = ReconPicture::alloc(self.format);
+        let mut header = recon.header();
+
+        let _guard = self.state.lock(&self.recv_lock)?;
+        self.state.check_poisoned()?;
+        unsafe {
+            #[allow(non_upper_case_globals)]
+            match self.logged(|| svt_av1_get_recon(self.handle.as_ptr(), &mut header)) {
+                EbErrorType_EB_NoErrorEmptyQueue => return Ok(None),
+                code => result(code)?,
+            }
+        }
+
+        recon.set_pts(header.pts);
+        Ok(Some(recon))
+    }
+
+    /// Deinitializes a library instance the encoder no longer uses, unless
+    /// packets output by it are still alive, in which case the last of them
+    /// to be dropped does.
+    fn release_library(&self, library: Arc<Library>) {
+        if Arc::strong_count(&library) == 1 {
+            self.logged(|| library.deinit());
+        }
+    }
+
+    /// Attaches the context of a failed call to a library error.
+    fn in_call(&self, call: EncoderCall, pts: Option<i64>) -> impl FnOnce(Error) -> Error {
+        let channel_id = self.cfg.0.channel_id;
+        move |e| e.in_call(call, pts, Some(channel_id))
+    }
+
+    /// Calls `f`, passing any messages logged by the library on the current
+    /// thread in the meantime to the encoder's log sink, if one is set.
+    ///
+    /// If a panic was caught in the log callback, the encoder is poisoned.
+    fn logged<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "log")]
+        {
+            let v = match &self.log_sink {
+                Some(sink) => with_log_sink(sink.clone(), f),
+                None => f(),
+            };
+
+            // The panic hook has already reported the panic.
+            if take_callback_panic().is_some() {
+                self.state.set_poisoned();
+            }
+
+            v
+        }
+
+        #[cfg(not(feature = "log"))]
+        f()
+    }
+
+    /// Generates a Sequence Header OBU.
+    ///
+    /// This is not generally necessary, as the encoder will automatically
+    /// generate headers as needed.
+    pub fn code_headers(&self) -> Result<Av1Packet, Error> {
+        self.state.check_poisoned()?;
+
+        let mut p = std::ptr::null_mut();
+        unsafe {
+            result(self.logged(|| svt_av1_enc_stream_header(self.handle.as_ptr(), &mut p)))?;
+
+            Ok(Av1Packet::new_headers(p, self.handle.clone()))
+        }
+    }
+
+    /// Returns the parameters the encoder is actually using, which may
+    /// differ from the configuration when the library resolved automatic
+    /// settings or clamped values to the limits of the level.
+    ///
+    /// The level, tier, and profile are read back from the sequence header;
+    /// the tile layout is derived from the coded size and superblock size,
+    /// following the limits in the AV1 specification.
+    pub fn effective_config(&self) -> Result<Av1EffectiveConfig, Error> {
+        let headers = self.code_headers()?;
+        let sequence_header = match obu::SequenceHeader::find(headers.as_bytes()) {
+            Ok(Some(header)) => header,
+            _ => return Err(LibraryError::Undefined.into()),
+        };
+
+        let cfg = &self.cfg.0;
+        let (tile_columns, tile_rows) = tile_layout(
+            self.format.width,
+            self.format.height,
+            sequence_header.superblock_size,
+            cfg.tile_columns.max(0) as u32,
+            cfg.tile_rows.max(0) as u32,
+        );
+
+        let intra_period = match cfg.intra_period_length {
+            -1 => IntraPeriod::None,
+            frames if frames < 0 => IntraPeriod::Auto,
+            frames => IntraPeriod::Fixed(frames as u32),
+        };
+
+        let look_ahead_distance = match cfg.look_ahead_distance {
+            u32::MAX => None,
+            distance => Some(distance.min(MAX_LOOK_AHEAD_DISTANCE)),
+        };
+
+        Ok(Av1EffectiveConfig {
+            sequence_header,
+            tile_columns,
+            tile_rows,
+            intra_period,
+            look_ahead_distance,
+        })
+    }
+
+    /// Encodes a single picture as a keyframe, and finishes the stream. This
+    /// is meant for still images, with an encoder configured with
+    /// [`Av1EncoderConfig::still_picture`].
+    ///
+    /// The output is copied out of the encoder, so it can outlive it.
+    /// Afterwards, the encoder is finished, and sending another picture
+    /// returns [`Error::InvalidState`]. If the picture was sent after others,
+    /// their frames are included in the output too.
+    pub fn encode_single(&self, picture: &impl Picture) -> Result<OwnedPacket, Error> {
+        self.send_picture(picture, 0, FrameTypeRequest::Key)?;
+        self.finish()?;
+
+        let mut output: Option<OwnedPacket> = None;
+        while let Some(packet) = self.get_packet(true)? {
+            if !packet.as_bytes().is_empty() {
+                match &mut output {
+                    Some(output) => output.extend(packet.as_bytes()),
+                    None => {
+                        let keyframe = packet.frame_type() == FrameType::Key;
+                        let data = packet.as_bytes().to_vec();
+                        output = Some(OwnedPacket::new(data, packet.pts(), keyframe));
+                    }
+                }
+            }
+
+            if packet.is_eos() {
+                break;
+            }
+        }
+
+        output.ok_or(LibraryError::Undefined.into())
+    }
+}
+
+impl Drop for Av1Encoder {
+    fn drop(&mut self) {
+        // Deinitializing the library while pictures are in flight can hang.
+        // Drains can't be attempted while unwinding, since the locks may be
+        // poisoned.
+        if !std::thread::panicking() {
+            let _ = shutdown::drain(&*self, self.drop_timeout);
+        }
+
+        // Otherwise, the last packet still alive deinitializes the library.
+        if Arc::strong_count(&self.handle) == 1 {
+            self.logged(|| self.handle.deinit());
+        }
+    }
+}
+
+/// The largest look-ahead distance the library supports.
+const MAX_LOOK_AHEAD_DISTANCE: u32 = 120;
+
+/// Computes the number of tile columns and rows in a frame from the
+/// requested log2 counts, clamped to the limits in section 5.9.15 of the AV1
+/// specification, with uniform tile spacing.
+fn tile_layout(
+    width: u32,
+    height: u32,
+    sb_size: u32,
+    log2_cols: u32,
+    log2_rows: u32,
+) -> (u32, u32) {
+    const MAX_TILE_WIDTH: u32 = 4096;
+    const MAX_TILE_AREA: u32 = 4096 * 2304;
+    const MAX_TILE_COLS: u32 = 64;
+    const MAX_TILE_ROWS: u32 = 64;
+
+    fn tile_log2(blk_size: u32, target: u32) -> u32 {
+        let mut k = 0;
+        while (blk_size << k) < target {
+            k += 1;
+        }
+
+        k
+    }
+
+    let sb_shift = sb_size.trailing_zeros();
+    let sb_cols = width.div_ceil(sb_size);
+    let sb_rows = height.div_ceil(sb_size);
+
+    let max_tile_width_sb = MAX_TILE_WIDTH >> sb_shift;
+    let max_tile_area_sb = MAX_TILE_AREA >> (2 * sb_shift);
+    let min_log2_tile_cols = tile_log2(max_tile_width_sb, sb_cols);
+    let max_log2_tile_cols = tile_log2(1, sb_cols.min(MAX_TILE_COLS));
+    let max_log2_tile_rows = tile_log2(1, sb_rows.min(MAX_TILE_ROWS));
+    let min_log2_tiles = min_log2_tile_cols.max(tile_log2(max_tile_area_sb, sb_rows * sb_cols));
+
+    let log2_cols = log2_cols.clamp(
+        min_log2_tile_cols,
+        max_log2_tile_cols.max(min_log2_tile_cols),
+    );
+    let min_log2_tile_rows = min_log2_tiles.saturating_sub(log2_cols);
+    let log2_rows = log2_rows.clamp(
+        min_log2_tile_rows,
+        max_log2_tile_rows.max(min_log2_tile_rows),
+    );
+
+    let tile_width_sb = (sb_cols + (1 << log2_cols) - 1) >> log2_cols;
+    let tile_height_sb = (sb_rows + (1 << log2_rows) - 1) >> log2_rows;
+    (
+        sb_cols.div_ceil(tile_width_sb),
+        sb_rows.div_ceil(tile_height_sb),
+    )
+}
+
+pub(crate) fn result(code: EbErrorType) -> Result<(), Error> {
+    Error::check(code)
+}
+
+/// Wraps a log sink for records from the given channel into one accepted by
+/// the library.
+#[cfg(feature = "log")]
+fn channel_log_sink(sink: crate::logging::LogSink, channel_id: u32) -> LogSink {
+    Arc::new(move |m: &LogMessage<'_>| {
+        sink(&crate::LogRecord {
+            level: m.level,
+            target: m.target,
+            message: m.message,
+            channel_id,
+        })
+    })
+}
+
+/// Like [`result`], but attaches any errors logged by the library during `f`
+/// to a resulting [`Error::BadParameter`].
+pub(crate) fn result_with_detail(f: impl FnOnce() -> EbErrorType) -> Result<(), Error> {
+    #[cfg(feature = "log")]
+    {
+        let (code, messages) = capture_errors(f);
+        match result(code) {
+            Err(Error::BadParameter { .. }) if !messages.is_empty() => Err(Error::BadParameter {
+                detail: Some(messages.join("\n")),
+            }),
+            res => res,
+        }
+    }
+
+    #[cfg(not(feature = "log"))]
+    result(f())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Av1Qp, EncoderConfig, KeyframeReason, PicturePool, Plane, RateControl, YUVBuffer,
+        YUVBuffer16,
+    };
+
+    use super::*;
+
+    #[test]
+    fn encode_frame() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(7)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        assert_eq!(enc.stats().frames_submitted, 1);
+
+        let _packet = enc.get_packet(false).expect("failed to get packet");
+
+        enc.finish().expect("failed to finish");
+
+        let _packet = enc.get_packet(true).expect("failed to get final packet");
+    }
+
+    #[test]
+    fn invalid_picture() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .bit_depth(10)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let requirements = enc.picture_requirements();
+        assert_eq!(requirements.bytes_per_sample, 2);
+        assert_eq!((requirements.width, requirements.height), (800, 600));
+
+        // Wrong bit depth, then wrong dimensions.
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        let err = enc.send_picture(&buf, 0, false).unwrap_err();
+        assert!(matches!(err, Error::InvalidPicture { .. }));
+
+        let buf = YUVBuffer16::new(640, 480, SubsamplingFormat::Yuv420);
+        assert!(requirements.check(&buf).is_err());
+        let err = enc.send_picture(&buf, 0, false).unwrap_err();
+        assert!(matches!(err, Error::InvalidPicture { .. }));
+
+        assert_eq!(enc.stats().frames_submitted, 0);
+    }
+
+    #[test]
+    fn encode_frame_10bit() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(7)
+            .bit_depth(10)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let mut buf = YUVBuffer16::new(800, 600, SubsamplingFormat::Yuv420);
+        buf.as_mut_slice(Plane::Y).fill(512);
+
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+
+        enc.finish().expect("failed to finish");
+
+        let _packet = enc.get_packet(true).expect("failed to get final packet");
+    }
+
+    #[test]
+    fn create_encoder_with_profile() {
+        simple_logger::init_with_env().ok();
+
+        for profile in [
+            EncodingProfile::Screen,
+            EncodingProfile::LowLatencyStreaming,
+            EncodingProfile::VodFilm,
+            EncodingProfile::Archival,
+        ] {
+            let _enc = Av1EncoderConfig::default()
+                .apply_profile(profile)
+                .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+                .expect("failed to create encoder");
+        }
+    }
+
+    #[test]
+    fn stat_report() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .enable_stat_report(true)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, true)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let packet = enc.get_packet(true).expect("failed to get packet").unwrap();
+        assert!(packet.luma_psnr().is_some());
+        assert!(packet.cb_psnr().is_some());
+        assert!(packet.luma_ssim().is_some());
+    }
+
+    #[test]
+    fn get_recon() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .enable_recon(true)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, true)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let _packet = enc.get_packet(true).expect("failed to get packet");
+        let recon = enc
+            .get_recon()
+            .expect("failed to get recon")
+            .expect("no recon available");
+
+        assert_eq!(recon.width(), 800);
+        assert_eq!(recon.as_slice(Plane::U).len(), 400 * 300);
+    }
+
+    #[test]
+    fn force_frame_types() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, FrameTypeRequest::Key)
+            .expect("failed to send picture");
+        enc.send_picture(&buf, 1, FrameTypeRequest::IntraOnly)
+            .expect("failed to send picture");
+
+        let err = enc
+            .send_picture(&buf, 2, FrameTypeRequest::Idr)
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(err, Error::BadParameter { .. }));
+
+        enc.finish().expect("failed to finish");
+    }
+
+    #[test]
+    fn encode_frame_with_metadata() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        let metadata = [Metadata::itu_t_t35([0xb5, 0x00, 0x3c, 0x00, 0x01, 0x04])];
+        enc.send_picture_with_metadata(&buf, 0, true, &metadata)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let mut has_metadata = false;
+        loop {
+            let packet = enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet");
+            has_metadata |= packet.has_metadata();
+            if packet.is_eos() {
+                break;
+            }
+        }
+
+        assert!(has_metadata);
+    }
+
+    #[test]
+    fn set_raw_parameter() {
+        simple_logger::init_with_env().ok();
+
+        let _enc = Av1EncoderConfig::default()
+            .set_raw_parameter("preset", "12")
+            .expect("failed to set preset")
+            .set_raw_parameter("enable-qm", "1")
+            .expect("failed to set enable-qm")
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let err = Av1EncoderConfig::default()
+            .set_raw_parameter("not-a-real-parameter", "1")
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(err, Error::BadParameter { .. }));
+    }
+
+    #[test]
+    fn with_raw() {
+        simple_logger::init_with_env().ok();
+
+        let config = Av1EncoderConfig::default().preset(12).with_raw(|cfg| {
+            cfg.enable_qm = true;
+            cfg.startup_mg_size = 2;
+        });
+
+        assert!(config.raw().enable_qm);
+        assert_eq!(config.raw().startup_mg_size, 2);
+
+        let _enc = config
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    fn create_encoder_with_qm() {
+        simple_logger::init_with_env().ok();
+
+        let _enc = Av1EncoderConfig::default()
+            .preset(12)
+            .enable_qm(true)
+            .qm_levels(QuantizationMatrixRange { min: 4, max: 12 })
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let err = Av1EncoderConfig::default()
+            .qm_levels(QuantizationMatrixRange { min: 12, max: 4 })
+            .validate(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected an out of range error");
+        assert!(matches!(
+            err,
+            ConfigError::OutOfRange {
+                parameter: "min_qm_level",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn library_version_matches() {
+        let (major, minor, patch) = library_version();
+        let linked = unsafe { std::ffi::CStr::from_ptr(svt_av1_get_version()) };
+        assert!(linked
+            .to_str()
+            .unwrap()
+            .contains(&format!("{}.{}.{}", major, minor, patch)));
+    }
+
+    #[test]
+    #[cfg(svt_av1_variance_boost)]
+    fn create_encoder_with_variance_boost() {
+        simple_logger::init_with_env().ok();
+
+        let _enc = Av1EncoderConfig::default()
+            .preset(12)
+            .enable_variance_boost(true)
+            .variance_boost_strength(3)
+            .variance_octile(4)
+            .sharpness(2)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    fn create_encoder_with_detected_cpu_flags() {
+        simple_logger::init_with_env().ok();
+
+        let flags = CpuFlags::detect();
+        #[cfg(target_arch = "x86_64")]
+        assert!(flags.contains(CpuFlags::EB_CPU_FLAGS_SSE2));
+        #[cfg(target_arch = "aarch64")]
+        assert!(flags.contains(CpuFlags::EB_CPU_FLAGS_NEON));
+
+        let _enc = Av1EncoderConfig::default()
+            .preset(12)
+            .cpu_flags(flags)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    fn create_encoder_generic() {
+        simple_logger::init_with_env().ok();
+
+        fn create<C: EncoderConfig>(config: C) -> C::Encoder {
+            config
+                .preset(12)
+                .framerate(30, 1)
+                .rate_control(RateControl::VariableBitrate(1_000_000))
+                .gop(Some(60))
+                .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+                .expect("failed to create encoder")
+        }
+
+        let enc = create(Av1EncoderConfig::default());
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+    }
+
+    #[test]
+    fn send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Av1Encoder>();
+    }
+
+    #[test]
+    fn split_encoder() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let (sender, receiver) = enc.split();
+        let receiver = std::thread::spawn(move || {
+            let mut frames = 0;
+            loop {
+                let packet = receiver
+                    .get_packet(true)
+                    .expect("failed to get packet")
+                    .expect("no packet");
+                if !packet.as_bytes().is_empty() {
+                    frames += 1;
+                }
+
+                if packet.is_eos() {
+                    break frames;
+                }
+            }
+        });
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..60 {
+            sender
+                .send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        sender.finish().expect("failed to finish");
+        let frames = receiver.join().expect("receiver panicked");
+        assert!(frames >= 60);
+    }
+
+    #[test]
+    fn sender_panic_finishes_stream() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let (sender, receiver) = enc.split();
+        let shutdown = sender.shutdown_handle();
+        let producer = std::thread::spawn(move || {
+            let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+            sender
+                .send_picture(&buf, 0, false)
+                .expect("failed to send picture");
+            panic!("producer died");
+        });
+
+        assert!(producer.join().is_err());
+        assert!(shutdown.is_shutdown());
+
+        // The receiver sees the EOS, rather than blocking forever.
+        while !receiver
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+    }
+
+    #[test]
+    fn state_transitions() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.state(), EncoderState::Running);
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+
+        enc.finish().expect("failed to finish");
+        enc.finish().expect("failed to finish twice");
+        assert_eq!(enc.state(), EncoderState::Draining);
+
+        let err = enc.send_picture(&buf, 1, false).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidState {
+                state: EncoderState::Draining
+            }
+        ));
+
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+
+        // Waiting again returns immediately.
+        assert_eq!(enc.state(), EncoderState::Finished);
+        assert!(enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .is_none());
+    }
+
+    #[test]
+    fn in_flight() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..16 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        // Nothing counts as complete until the packets are retrieved.
+        assert_eq!(enc.in_flight(), 16);
+
+        enc.finish().expect("failed to finish");
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+
+        // Alt-ref frames are output in addition to the pictures they're
+        // coded from.
+        let stats = enc.stats();
+        assert_eq!(enc.in_flight(), 0);
+        assert_eq!(stats.pictures_completed, 16);
+        assert!(stats.frames_output >= 16);
+    }
+
+    #[test]
+    fn picture_pool() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let pool = PicturePool::new(320, 240, SubsamplingFormat::Yuv420, 64);
+        for pts in 0..16 {
+            let picture = pool.get().expect("pool is empty");
+            pool.send(&enc, picture, pts, false)
+                .expect("failed to send picture");
+        }
+
+        assert_eq!(pool.available(), 48);
+        enc.finish().expect("failed to finish");
+
+        // Every picture is completed before the EOS packet.
+        loop {
+            let packet = enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet");
+            if packet.is_eos() {
+                break;
+            }
+
+            pool.recycle(&packet);
+        }
+
+        assert_eq!(pool.available(), 64);
+    }
+
+    #[test]
+    fn get_packet_timeout() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let start = std::time::Instant::now();
+        let packet = enc
+            .get_packet_timeout(Duration::from_millis(50))
+            .expect("failed to get packet");
+        assert!(packet.is_none());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let packet = enc
+            .get_packet_timeout(Duration::from_secs(10))
+            .expect("failed to get packet");
+        assert!(packet.is_some());
+    }
+
+    #[test]
+    fn shutdown_mid_stream() {
+        simple_logger::init_with_env().ok();
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        for pts in 0..10 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        enc.shutdown(Duration::from_secs(30))
+            .expect("failed to shut down");
+
+        // Dropping mid-stream drains the encoder the same way.
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .drop_timeout(Duration::from_secs(30))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        for pts in 0..10 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        drop(enc);
+    }
+
+    #[test]
+    fn pipelined_encoder() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let pipeline = crate::PipelinedEncoder::new(enc);
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..10 {
+            pipeline
+                .send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        pipeline.finish().expect("failed to finish");
+
+        let packets = pipeline
+            .packets()
+            .iter()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to get packet");
+        assert!(packets.last().expect("no packets").is_eos());
+    }
+
+    #[test]
+    fn try_send_picture() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .max_pending_frames(2)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        assert_eq!(enc.available_input_buffers(), Some(2));
+        enc.try_send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        enc.try_send_picture(&buf, 1, false)
+            .expect("failed to send picture");
+
+        if enc.available_input_buffers() == Some(0) {
+            let err = enc
+                .try_send_picture(&buf, 2, false)
+                .expect_err("expected WouldBlock");
+            assert!(matches!(err, Error::WouldBlock));
+        }
+
+        enc.finish().expect("failed to finish");
+    }
+
+    #[test]
+    fn change_resolution() {
+        simple_logger::init_with_env().ok();
+
+        let mut enc = Av1EncoderConfig::default()
+            .preset(12)
+            .pred_structure(PredictionStructure::LowDelay)
+            .set_forced_max_frame_size(640, 480)
+            .create_encoder(640, 480, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(640, 480, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+
+        enc.change_resolution(320, 240)
+            .expect("failed to change resolution");
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 1, false)
+            .expect("failed to send picture");
+
+        enc.finish().expect("failed to finish");
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+    }
+
+    #[test]
+    fn change_resolution_random_access() {
+        simple_logger::init_with_env().ok();
+
+        let mut enc = Av1EncoderConfig::default()
+            .preset(12)
+            .pred_structure(PredictionStructure::RandomAccess)
+            .max_pending_frames(8)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..3 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        // The pictures in flight are drained from the old stream.
+        let packets = enc
+            .change_resolution(160, 120)
+            .expect("failed to change resolution");
+        let mut pts: Vec<i64> = packets.iter().map(|p| p.pts()).collect();
+        pts.sort();
+        assert_eq!(pts, [0, 1, 2]);
+        assert_eq!(enc.in_flight(), 0);
+        assert_eq!(enc.available_input_buffers(), Some(8));
+
+        let buf = YUVBuffer::new(160, 120, SubsamplingFormat::Yuv420);
+        enc.try_send_picture(&buf, 3, false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+
+        assert_eq!(enc.in_flight(), 0);
+    }
+
+    #[test]
+    fn change_resolution_then_speed() {
+        simple_logger::init_with_env().ok();
+
+        let mut enc = Av1EncoderConfig::default()
+            .preset(11)
+            .pred_structure(PredictionStructure::LowDelay)
+            .set_forced_max_frame_size(640, 480)
+            .create_encoder(640, 480, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        // The new stream starts at the changed size.
+        enc.change_resolution(320, 240)
+            .expect("failed to change resolution");
+        enc.set_preset(12).expect("failed to change preset");
+        assert_eq!(
+            (enc.cfg.0.source_width, enc.cfg.0.source_height),
+            (320, 240)
+        );
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+
+        enc.finish().expect("failed to finish");
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+    }
+
+    #[test]
+    fn change_speed() {
+        simple_logger::init_with_env().ok();
+
+        let mut enc = Av1EncoderConfig::default()
+            .preset(11)
+            .pred_structure(PredictionStructure::LowDelay)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..3 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        // The pictures in flight are carried over.
+        let packets = enc.set_preset(12).expect("failed to change preset");
+        let pts: Vec<i64> = packets.iter().map(|p| p.pts()).collect();
+        assert_eq!(pts, [0, 1, 2]);
+        assert!(enc
+            .set_preset(12)
+            .expect("failed to change preset")
+            .is_empty());
+
+        let packets = enc
+            .change_speed(Speed {
+                preset: 12,
+                fast_decode: true,
+            })
+            .expect("failed to change speed");
+        assert!(packets.is_empty());
+        assert_eq!(enc.state(), EncoderState::Running);
+
+        enc.send_picture(&buf, 3, false)
+            .expect("failed to send picture");
+        let packet = enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet");
+        assert_eq!(packet.frame_type(), FrameType::Key);
+        assert_eq!(packet.pts(), 3);
+
+        // A packet can be held across the switch, and even outlive the
+        // encoder.
+        let packets = enc.set_preset(11).expect("failed to change preset");
+        assert!(packets.is_empty());
+        drop(enc);
+        assert_eq!(packet.pts(), 3);
+        assert!(!packet.as_bytes().is_empty());
+    }
+
+    #[test]
+    #[cfg(svt_av1_rate_change)]
+    fn rate_schedule() {
+        simple_logger::init_with_env().ok();
+
+        let err = Av1EncoderConfig::default()
+            .rate_schedule(RateSchedule::new().at(2, 500_000))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect_err("expected a rate control error");
+        assert!(matches!(err, Error::BadParameter { .. }));
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .pred_structure(PredictionStructure::LowDelay)
+            .rate_control_mode(RateControlMode::ConstantBitrate(1_000_000))
+            .rate_schedule(RateSchedule::new().at(2, 500_000).at(4, 2_000_000))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..6 {
+            if pts == 5 {
+                enc.change_bitrate(750_000)
+                    .expect("failed to change bitrate");
+            }
+
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        enc.finish().expect("failed to finish");
+        let mut pts = Vec::new();
+        while let Some(packet) = enc.get_packet(true).expect("failed to get packet") {
+            if packet.is_eos() {
+                break;
+            }
+
+            pts.push(packet.pts());
+        }
+
+        assert_eq!(pts, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn request_recovery() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .pred_structure(PredictionStructure::LowDelay)
+            .recovery_frame(RecoveryFrame::IntraOnly)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..6 {
+            if pts == 3 {
+                assert!(enc.request_recovery(1));
+                assert!(!enc.request_recovery(2));
+            }
+
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        // The intra-only frame covers losses before it.
+        assert!(!enc.request_recovery(2));
+
+        enc.finish().expect("failed to finish");
+        let mut frame_types = Vec::new();
+        while let Some(packet) = enc.get_packet(true).expect("failed to get packet") {
+            if packet.is_eos() {
+                break;
+            }
+
+            frame_types.push((packet.pts(), packet.frame_type()));
+        }
+
+        assert_eq!(frame_types[0], (0, FrameType::Key));
+        assert!(frame_types.contains(&(3, FrameType::IntraOnly)));
+    }
+
+    #[test]
+    fn temporal_units() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .pred_structure(PredictionStructure::RandomAccess)
+            .output_mode(OutputMode::TemporalUnit)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let pictures = 24;
+        let mut units = 0;
+        let mut check = |packet: Av1Packet| {
+            let types: Vec<_> = crate::obu::obus(packet.as_bytes())
+                .map(|obu| obu.expect("invalid OBU").obu_type())
+                .collect();
+            assert_eq!(types.first(), Some(&crate::obu::ObuType::TemporalDelimiter));
+            let delimiters = types
+                .iter()
+                .filter(|&&t| t == crate::obu::ObuType::TemporalDelimiter)
+                .count();
+            assert_eq!(delimiters, 1);
+            units += 1;
+        };
+
+        for pts in 0..pictures {
+            let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+            while let Some(packet) = enc.get_packet(false).expect("failed to get packet") {
+                check(packet);
+            }
+        }
+
+        enc.finish().expect("failed to finish");
+        while let Some(packet) = enc.get_packet(true).expect("failed to get packet") {
+            if packet.is_eos() {
+                break;
+            }
+
+            check(packet);
+        }
+
+        // Every picture is shown in exactly one temporal unit.
+        assert_eq!(units, pictures);
+    }
+
+    #[test]
+    fn latency_modes() {
+        simple_logger::init_with_env().ok();
+
+        const PICTURES: i64 = 40;
+        let mut delays = Vec::new();
+        for mode in [
+            LatencyMode::UltraLow,
+            LatencyMode::Low,
+            LatencyMode::Normal,
+            LatencyMode::Throughput,
+        ] {
+            let enc = Av1EncoderConfig::default()
+                .preset(12)
+                .latency_mode(mode)
+                .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+                .expect("failed to create encoder");
+
+            let low_delay = matches!(mode, LatencyMode::UltraLow | LatencyMode::Low);
+            assert_eq!(enc.reorder_depth() == 0, low_delay);
+
+            // The number of pictures sent after the first one before its
+            // packet is output.
+            let mut delay = None;
+            for pts in 0..PICTURES {
+                let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+                enc.send_picture(&buf, pts, false)
+                    .expect("failed to send picture");
+
+                // In the low-delay modes, each packet comes out without any
+                // further input.
+                let packet = enc
+                    .get_packet(low_delay)
+                    .expect("failed to get packet")
+                    .map(|p| p.pts());
+                if low_delay {
+                    assert_eq!(packet, Some(pts));
+                }
+
+                if delay.is_none() && packet.is_some() {
+                    delay = Some(pts);
+                }
+
+                while enc
+                    .get_packet(false)
+                    .expect("failed to get packet")
+                    .is_some()
+                {}
+            }
+
+            // The throughput mode may buffer more pictures than were sent.
+            let frames = delay.unwrap_or(PICTURES);
+            if low_delay {
+                assert_eq!(frames, 0);
+            } else {
+                assert!(frames > 0);
+            }
+
+            enc.finish().expect("failed to finish");
+            delays.push(frames);
+        }
+
+        let [ultra_low, low, normal, throughput] = delays[..] else {
+            unreachable!()
+        };
+        assert!(ultra_low <= low);
+        assert!(low < normal);
+        assert!(normal <= throughput);
+    }
+
+    #[test]
+    fn create_encoder_error() {
+        simple_logger::init_with_env().ok();
+
+        let _enc = Av1EncoderConfig::default()
+            .bitrate_section_percentage(999, 999)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+    }
+
+    #[test]
+    fn level_exceeded() {
+        simple_logger::init_with_env().ok();
+
+        let err = Av1EncoderConfig::default()
+            .framerate(60, 1)
+            .level(Level::L4_0.into())
+            .create_encoder(1920, 1080, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(
+            err,
+            Error::BadParameter {
+                detail: Some(ref detail)
+            } if detail.contains("level 4.0")
+        ));
+
+        let _enc = Av1EncoderConfig::default()
+            .preset(12)
+            .framerate(60, 1)
+            .level(Level::for_stream(1920, 1080, 60.0, 0).into())
+            .create_encoder(1920, 1080, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn create_encoder_error_detail() {
+        simple_logger::init_with_env().ok();
+
+        let err = Av1EncoderConfig::default()
+            .bitrate_section_percentage(999, 999)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+
+        assert!(matches!(
+            err,
+            Error::BadParameter {
+                detail: Some(ref detail)
+            } if !detail.is_empty()
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn log_sink() {
+        simple_logger::init_with_env().ok();
+
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink_records = records.clone();
+        let _err = Av1EncoderConfig::default()
+            .channel_id(3)
+            .log_sink(move |r| {
+                sink_records
+                    .lock()
+                    .unwrap()
+                    .push((r.channel_id, r.level, r.target.to_owned()))
+            })
+            .bitrate_section_percentage(999, 999)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+
+        let records = records.lock().unwrap();
+        assert!(records.iter().any(|(id, level, target)| *id == 3
+            && *level == log::Level::Error
+            && target.starts_with("svt_av1")));
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn log_sink_panic() {
+        simple_logger::init_with_env().ok();
+
+        // The sink panics on the error the library logs while initializing.
+        let err = Av1EncoderConfig::default()
+            .log_sink(|_| panic!("sink failed"))
+            .bitrate_section_percentage(999, 999)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected a poisoned encoder");
+
+        assert!(matches!(err, Error::Poisoned));
+    }
+
+    #[test]
+    fn validate_error() {
+        simple_logger::init_with_env().ok();
+
+        let err = Av1EncoderConfig::default()
+            .bitrate_section_percentage(999, 999)
+            .validate(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected an out of range error");
+
+        assert!(matches!(
+            err,
+            ConfigError::OutOfRange {
+                parameter: "vbr_min_section_pct",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn encode_monochrome() {
+        simple_logger::init_with_env().ok();
+
+        for bit_depth in [8, 10] {
+            let enc = Av1EncoderConfig::default()
+                .preset(12)
+                .bit_depth(bit_depth)
+                .create_encoder(320, 240, SubsamplingFormat::Yuv400)
+                .expect("failed to create encoder");
+            assert_eq!(
+                enc.picture_requirements().subsampling_format,
+                SubsamplingFormat::Yuv400
+            );
+
+            for pts in 0..4 {
+                if bit_depth == 8 {
+                    let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv400);
+                    enc.send_picture(&buf, pts, false)
+                } else {
+                    let buf = YUVBuffer16::new(320, 240, SubsamplingFormat::Yuv400);
+                    enc.send_picture(&buf, pts, false)
+                }
+                .expect("failed to send picture");
+            }
+
+            enc.finish().expect("failed to finish");
+            while !enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet")
+                .is_eos()
+            {}
+
+            assert_eq!(enc.stats().pictures_completed, 4);
+        }
+    }
+
+    #[test]
+    fn av1c() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .bit_depth(10)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let headers = enc.code_headers().expect("failed to code headers");
+        let header = obu::SequenceHeader::find(headers.as_bytes())
+            .expect("invalid headers")
+            .expect("no sequence header");
+        assert_eq!((header.max_width, header.max_height), (320, 240));
+        assert_eq!(header.bit_depth, 10);
+
+        let av1c = headers.to_av1c().expect("failed to build av1C");
+        assert_eq!(av1c[0], 0x81);
+    }
+
+    #[test]
+    fn effective_config() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .intra_period_length(IntraPeriod::Fixed(30))
+            .look_ahead_distance(16)
+            .tiling_mode(TilingMode::Multi {
+                columns: 6,
+                rows: 1,
+            })
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let effective = enc.effective_config().expect("failed to read config");
+        assert_eq!(effective.sequence_header.max_width, 320);
+        assert_eq!(effective.intra_period, IntraPeriod::Fixed(30));
+        assert_eq!(effective.look_ahead_distance, Some(16));
+
+        // There aren't enough superblocks for 64 tile columns.
+        let sb = effective.sequence_header.superblock_size;
+        assert_eq!(effective.tile_columns, 320u32.div_ceil(sb));
+        assert_eq!(effective.tile_rows, 2);
+    }
+
+    #[test]
+    fn encode_single() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .still_picture(true)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let picture = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        let packet = enc.encode_single(&picture).expect("failed to encode");
+        assert!(packet.is_keyframe());
+        assert!(crate::mux::av1c(packet.as_bytes()).is_ok());
+
+        #[cfg(svt_av1_avif)]
+        {
+            let header = obu::SequenceHeader::find(packet.as_bytes())
+                .unwrap()
+                .unwrap();
+            assert!(header.still_picture);
+        }
+
+        assert!(matches!(
+            enc.send_picture(&picture, 1, false),
+            Err(Error::InvalidState { .. })
+        ));
+    }
+
+    #[test]
+    fn session() {
+        simple_logger::init_with_env().ok();
+
+        let mut session = crate::Session::new(Av1EncoderConfig::default().preset(12));
+        let picture = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+
+        let start = std::time::Instant::now();
+        let timestamps: Vec<_> = (0..10)
+            .map(|i| start + Duration::from_millis(i * 33))
+            .collect();
+
+        let mut packets = Vec::new();
+        for &timestamp in &timestamps {
+            session
+                .encode(&picture, timestamp)
+                .expect("failed to encode");
+            packets.extend(session.take_packets());
+        }
+
+        packets.extend(session.flush().expect("failed to flush"));
+        assert_eq!(packets.len(), timestamps.len());
+        assert!(packets[0].is_keyframe());
+
+        let mut output: Vec<_> = packets.iter().map(|p| p.timestamp().unwrap()).collect();
+        output.sort();
+        assert_eq!(output, timestamps);
+
+        assert!(session.encode(&picture, start).is_err());
+    }
+
+    #[test]
+    fn channel_group() {
+        simple_logger::init_with_env().ok();
+
+        let sizes = [(640, 480), (320, 240)];
+        let group = sizes
+            .iter()
+            .fold(crate::ChannelGroup::builder(), |builder, &(w, h)| {
+                builder.channel(Av1EncoderConfig::default(), w, h, SubsamplingFormat::Yuv420)
+            })
+            .shared(|config: Av1EncoderConfig| config.preset(12))
+            .build()
+            .expect("failed to create channels");
+        assert_eq!(group.len(), 2);
+
+        for pts in 0..5 {
+            for (channel, &(w, h)) in sizes.iter().enumerate() {
+                let picture = YUVBuffer::new(w, h, SubsamplingFormat::Yuv420);
+                group
+                    .send_picture(channel, &picture, pts, false)
+                    .expect("failed to send picture");
+            }
+        }
+
+        group.finish().expect("failed to finish");
+        for channel in 0..sizes.len() {
+            while let Some(packet) = group.get_packet(channel, true).unwrap() {
+                if packet.is_eos() {
+                    break;
+                }
+            }
+        }
+
+        assert!(group.get_packet(2, false).is_err());
+        assert_eq!(group.total_stats().frames_submitted, 10);
+    }
+
+    #[test]
+    #[cfg(feature = "scale")]
+    fn ladder_keyframes() {
+        simple_logger::init_with_env().ok();
+
+        let mut ladder = Ladder::builder(SubsamplingFormat::Yuv420)
+            .rendition(Av1EncoderConfig::default(), 320, 240)
+            .rendition(Av1EncoderConfig::default(), 160, 120)
+            .shared(|config| config.preset(12))
+            .keyframe_interval(4)
+            .build()
+            .expect("failed to create ladder");
+
+        let picture = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        let mut requested = Vec::new();
+        for pts in 0..10 {
+            if ladder
+                .send_picture(&picture, pts, pts == 6)
+                .expect("failed to send picture")
+            {
+                requested.push(pts);
+            }
+        }
+
+        assert_eq!(requested, [0, 4, 6]);
+        ladder.finish().expect("failed to finish");
+
+        for rendition in 0..ladder.len() {
+            let mut keyframes = Vec::new();
+            while let Some(packet) = ladder.get_packet(rendition, true).unwrap() {
+                if packet.is_eos() {
+                    break;
+                }
+
+                if let (Some(pts), Some(_)) = (packet.completed_pts(), packet.keyframe_reason()) {
+                    keyframes.push(pts);
+                }
+            }
+
+            keyframes.sort();
+            assert_eq!(keyframes, requested, "rendition {}", rendition);
+        }
+    }
+
+    #[test]
+    fn memory_budget() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .memory_budget(256)
+            .create_encoder(1280, 720, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        assert!(enc.approx_memory_usage() <= 256 << 20);
+    }
+
+    #[test]
+    fn tile_layout_limits() {
+        // Requests are clamped to the number of superblocks.
+        assert_eq!(tile_layout(320, 240, 64, 6, 6), (5, 4));
+        assert_eq!(tile_layout(1920, 1080, 64, 1, 0), (2, 1));
+        // Tiles can be at most 4096 pixels wide.
+        assert_eq!(tile_layout(8192, 2304, 64, 0, 0), (2, 1));
+        // ...and cover at most 4096x2304 pixels.
+        assert_eq!(tile_layout(7680, 4320, 128, 1, 0), (2, 2));
+        // Uniform spacing can leave fewer tiles than requested.
+        assert_eq!(tile_layout(320, 64, 64, 2, 0), (3, 1));
+    }
+
+    #[test]
+    fn encoding_profile_names() {
+        for profile in [
+            EncodingProfile::Screen,
+            EncodingProfile::LowLatencyStreaming,
+            EncodingProfile::VodFilm,
+            EncodingProfile::Archival,
+        ] {
+            assert_eq!(
+                profile.to_string().parse::<EncodingProfile>().ok(),
+                Some(profile)
+            );
+        }
+
+        assert_eq!(
+            "Low_Latency_Streaming".parse::<EncodingProfile>().ok(),
+            Some(EncodingProfile::LowLatencyStreaming)
+        );
+        assert!("fast".parse::<EncodingProfile>().is_err());
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Av1Settings {
+            profile: Some(EncodingProfile::LowLatencyStreaming),
+            preset: Some(8),
+            framerate: Some((30000, 1001)),
+            intra_period: Some(IntraPeriod::Fixed(60)),
+            rate_control: Some(RateControlMode::VariableBitrate(2_000_000)),
+            tiling: Some(TilingMode::Multi {
+                columns: 2,
+                rows: 1,
+            }),
+            ..Default::default()
+        };
+
+        let config = Av1EncoderConfig::from(settings);
+        let applied = config.settings();
+        assert_eq!(applied.preset, Some(8));
+        assert_eq!(applied.framerate, Some((30000, 1001)));
+        assert_eq!(applied.intra_period, Some(IntraPeriod::Fixed(60)));
+        assert_eq!(applied.rate_control, settings.rate_control);
+        assert_eq!(applied.tiling, settings.tiling);
+
+        // Set by the profile.
+        assert_eq!(applied.pred_structure, Some(PredictionStructure::LowDelay));
+        assert_eq!(applied.look_ahead_distance, Some(0));
+
+        let reapplied = Av1EncoderConfig::default().apply_settings(&applied);
+        assert_eq!(reapplied.settings(), applied);
+    }
+
+    #[test]
+    fn adaptive_quantization() {
+        simple_logger::init_with_env().ok();
+
+        let crf = RateControlMode::ConstantRateFactor(Av1Qp::new(30).unwrap());
+
+        // CRF leaves the library default.
+        let settings = Av1EncoderConfig::default()
+            .rate_control_mode(crf)
+            .settings();
+        assert_eq!(settings.adaptive_quantization, Some(AqMode::DeltaQ));
+        assert_eq!(settings.rate_control, Some(crf));
+
+        // The explicit mode holds, whichever order the builders are called.
+        for mode in [AqMode::DeltaQ, AqMode::Variance] {
+            let before = Av1EncoderConfig::default()
+                .adaptive_quantization(mode)
+                .rate_control_mode(crf)
+                .settings();
+            let after = Av1EncoderConfig::default()
+                .rate_control_mode(crf)
+                .adaptive_quantization(mode)
+                .settings();
+
+            for settings in [before, after] {
+                assert_eq!(settings.adaptive_quantization, Some(mode));
+                assert_eq!(settings.rate_control, Some(crf));
+            }
+        }
+
+        let settings = Av1EncoderConfig::default()
+            .rate_control_mode(RateControlMode::ConstantQp(Av1Qp::new(30).unwrap()))
+            .settings();
+        assert_eq!(settings.adaptive_quantization, Some(AqMode::Off));
+
+        let err = Av1EncoderConfig::default()
+            .adaptive_quantization(AqMode::Variance)
+            .rate_control_mode(RateControlMode::ConstantQp(Av1Qp::new(30).unwrap()))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(err, Error::BadParameter { .. }));
+
+        let err = Av1EncoderConfig::default()
+            .rate_control_mode(RateControlMode::ConstantRateFactor(Av1Qp::new(30).unwrap()))
+            .adaptive_quantization(AqMode::Off)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(err, Error::BadParameter { .. }));
+
+        Av1EncoderConfig::default()
+            .preset(12)
+            .rate_control_mode(RateControlMode::VariableBitrate(1_000_000))
+            .adaptive_quantization(AqMode::Off)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    fn qp_out_of_range() {
+        simple_logger::init_with_env().ok();
+
+        // The generic rate control checks the range when it's set, and the
+        // error is returned when the encoder is created.
+        let err = Av1EncoderConfig::default()
+            .rate_control(RateControl::ConstantQp(64))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(err, Error::BadParameter { .. }));
+
+        Av1EncoderConfig::default()
+            .preset(12)
+            .rate_control(RateControl::ConstantQp(63))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn settings_serde() {
+        let settings: Av1Settings = serde_json::from_str(
+            r#"{
+                "profile": "vod-film",
+                "preset": 4,
+                "intra_period": { "fixed": 240 },
+                "rate_control": { "constant-rate-factor": 30 },
+                "tune": "ssim"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.profile, Some(EncodingProfile::VodFilm));
+        assert_eq!(settings.intra_period, Some(IntraPeriod::Fixed(240)));
+        assert_eq!(
+            settings.rate_control,
+            Some(RateControlMode::ConstantRateFactor(Av1Qp::new(30).unwrap()))
+        );
+        assert_eq!(settings.tune, Some(Tune::Ssim));
+
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Av1Settings>(&json).unwrap(),
+            settings
+        );
+        assert!(serde_json::from_str::<Av1Settings>(r#"{"speed": 4}"#).is_err());
+    }
+
+    #[test]
+    fn opts_str() {
+        let config =
+            Av1EncoderConfig::from_opts_str("preset=8:crf=30:tile-columns=2:--enable-qm=1:")
+                .expect("failed to parse options");
+        let settings = config.settings();
+        assert_eq!(settings.preset, Some(8));
+        assert_eq!(
+            settings.rate_control,
+            Some(RateControlMode::ConstantRateFactor(Av1Qp::new(30).unwrap()))
+        );
+        assert_eq!(
+            settings.tiling,
+            Some(TilingMode::Multi {
+                columns: 2,
+                rows: 0
+            })
+        );
+        assert!(config.raw().enable_qm);
+
+        for opts in [
+            "preset",
+            "preset=fast",
+            "crf=-1",
+            "crf=64",
+            "qp=0",
+            "no-such-option=1",
+        ] {
+            assert!(
+                matches!(
+                    Av1EncoderConfig::from_opts_str(opts),
+                    Err(Error::BadParameter { .. })
+                ),
+                "{}",
+                opts
+            );
+        }
+    }
+
+    #[test]
+    fn encode_odd_dimensions() {
+        simple_logger::init_with_env().ok();
+
+        let mut enc = Av1EncoderConfig::default()
+            .preset(12)
+            .pred_structure(PredictionStructure::LowDelay)
+            .set_forced_max_frame_size(322, 242)
+            .create_encoder(321, 241, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.coded_size(), (322, 242));
+
+        let requirements = enc.picture_requirements();
+        assert_eq!((requirements.width, requirements.height), (321, 241));
+
+        let buf = YUVBuffer::new(321, 241, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+
+        enc.change_resolution(161, 121)
+            .expect("failed to change resolution");
+        assert_eq!(enc.coded_size(), (162, 122));
+
+        let buf = YUVBuffer::new(161, 121, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 1, false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+    }
+
+    #[test]
+    fn incompatible_profile() {
+        simple_logger::init_with_env().ok();
+
+        let err = Av1EncoderConfig::default()
+            .profile(Av1Profile::High)
+            .validate(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected an incompatible profile error");
+        assert!(matches!(
+            err,
+            ConfigError::IncompatibleProfile {
+                profile: Av1Profile::High,
+                bit_depth: 8,
+                subsampling_format: SubsamplingFormat::Yuv420,
+            }
+        ));
+
+        // The library can't encode 12-bit or 4:4:4 input with any profile.
+        let err = Av1EncoderConfig::default()
+            .profile(Av1Profile::Professional)
+            .bit_depth(12)
+            .validate(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected an unsupported format error");
+        assert!(matches!(
+            err,
+            ConfigError::UnsupportedFormat {
+                bit_depth: 12,
+                subsampling_format: SubsamplingFormat::Yuv420,
+            }
+        ));
+
+        let err = Av1EncoderConfig::default()
+            .profile(Av1Profile::High)
+            .validate(800, 600, SubsamplingFormat::Yuv444)
+            .expect_err("expected an unsupported format error");
+        assert!(matches!(err, ConfigError::UnsupportedFormat { .. }));
+
+        let err = Av1EncoderConfig::default()
+            .bit_depth(12)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(
+            err,
+            Error::BadParameter {
+                detail: Some(ref detail)
+            } if detail == "SVT-AV1 only encodes 8 or 10-bit 4:2:0, got 12-bit Yuv420"
+        ));
+    }
+
+    #[test]
+    fn super_resolution() {
+        simple_logger::init_with_env().ok();
+
+        let err = Av1EncoderConfig::default()
+            .super_resolution(SuperResolution::Fixed {
+                denom: 20,
+                kf_denom: 8,
+            })
+            .validate(800, 600, SubsamplingFormat::Yuv420)
+            .expect_err("expected an out of range error");
+
+        assert!(matches!(
+            err,
+            ConfigError::OutOfRange {
+                parameter: "superres_denom",
+                ..
+            }
+        ));
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .super_resolution(SuperResolution::Fixed {
+                denom: 16,
+                kf_denom: 16,
+            })
+            .resize_mode(ResizeMode::Fixed {
+                denom: 12,
+                kf_denom: 12,
+            })
+            .create_encoder(640, 480, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(640, 480, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+        while !enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet")
+            .is_eos()
+        {}
+    }
+
+    #[test]
+    fn overlays() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .enable_tf(true)
+            .enable_overlays(true)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let mut buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..32 {
+            buf.as_mut_slice(Plane::Y).fill((pts * 8) as u8);
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        enc.finish().expect("failed to finish");
+        let mut alt_ref_pts = Vec::new();
+        loop {
+            let packet = enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet");
+            if packet.is_eos() {
+                break;
+            }
+
+            match packet.frame_type() {
+                FrameType::AltRef => alt_ref_pts.push(packet.pts()),
+                FrameType::Overlay => assert!(alt_ref_pts.contains(&packet.pts())),
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn error_resilience_mfmv() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .error_resilience(true)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.cfg.0.enable_mfmv, 0);
+
+        // An explicit setting wins, regardless of the order.
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .enable_motion_field_motion_vector_control(Some(true))
+            .error_resilience(true)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.cfg.0.enable_mfmv, 1);
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .error_resilience(true)
+            .enable_motion_field_motion_vector_control(Some(true))
+            .error_resilience(false)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.cfg.0.enable_mfmv, 1);
+    }
+
+    #[test]
+    fn switch_frames() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .error_resilience(true)
+            .switch_frame_insertion(SwitchFrameInsertion::Nearest(8))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..32 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        enc.finish().expect("failed to finish");
+        let mut frame_types = Vec::new();
+        loop {
+            let packet = enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet");
+            if packet.is_eos() {
+                break;
+            }
+
+            assert_eq!(
+                packet.is_independently_decodable(),
+                matches!(packet.frame_type(), FrameType::Key | FrameType::IntraOnly)
+            );
+            frame_types.push(packet.frame_type());
+        }
+
+        assert_eq!(frame_types[0], FrameType::Key);
+        assert!(frame_types.contains(&FrameType::Switch));
+    }
+
+    #[test]
+    fn keyframe_reasons() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .intra_period_length(IntraPeriod::Fixed(15))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..20 {
+            enc.send_picture(&buf, pts, pts == 5)
+                .expect("failed to send picture");
+        }
+
+        enc.finish().expect("failed to finish");
+        let mut reasons = Vec::new();
+        loop {
+            let packet = enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet");
+            if packet.is_eos() {
+                break;
+            }
+
+            if let Some(reason) = packet.keyframe_reason() {
+                reasons.push((packet.pts(), reason));
+            }
+        }
+
+        assert_eq!(
+            reasons,
+            [
+                (0, KeyframeReason::StreamStart),
+                (5, KeyframeReason::Forced)
+            ]
+        );
+    }
+
+    #[test]
+    fn timebase() {
+        simple_logger::init_with_env().ok();
+
+        let mpegts = Rational::new(1, 90000);
+        let enc = Av1EncoderConfig::default()
+            .preset(12)
+            .framerate(30, 1)
+            .timebase(Rational::new(1, 1000))
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        enc.send_picture_at(&buf, Timestamp::new(3003, mpegts), false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let packet = enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet");
+        assert_eq!(
+            packet.timestamp(),
+            Some(Timestamp::new(33, Rational::new(1, 1000)))
+        );
     }
 }
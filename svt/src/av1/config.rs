@@ -1,14 +1,43 @@
+use std::ffi::CString;
+use std::sync::Mutex;
+use std::time::Duration;
+
 use svt_av1_sys::*;
 
-use crate::{Error, SubsamplingFormat};
+use crate::buffer::{coded_size, EdgePadding, NeutralChroma};
+use crate::hdr::{fixed, Chromaticity, ContentLightLevel, MasteringDisplay};
+use crate::{
+    Av1Qp, ChannelConfig, EncoderConfig, Error, Orientation, RateControl, Rational, Stats,
+    SubsamplingFormat,
+};
+
+use super::temporal_unit::TemporalUnits;
+use super::{result_with_detail, Av1Encoder, LibraryHandle, RecoveryFrame};
 
-use super::{result, Av1Encoder, LibraryHandle};
+// The color types are shared with HEVC, and re-exported here for
+// compatibility.
+pub use crate::{ChromaSamplePosition, ColorDescription, ColorRange};
 
 mod cpu_flags;
 pub use cpu_flags::CpuFlags;
 
+pub(crate) mod memory;
+
+mod opts;
+
+mod settings;
+pub use settings::Av1Settings;
+
+mod validate;
+pub use validate::ConfigError;
+
 /// How often (in frames) to insert an intra refresh.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum IntraPeriod {
     /// Automatically determine the intra period.
     Auto,
@@ -29,6 +58,11 @@ pub enum IntraRefreshType {
 
 /// The prediction structure for each GOP.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum PredictionStructure {
     /// Forward prediction only.
     LowDelay,
@@ -36,7 +70,20 @@ pub enum PredictionStructure {
     RandomAccess,
 }
 
-/// The AV1 bitstream profile.
+/// The AV1 bitstream profile, which limits the bit depth and chroma
+/// subsampling of the stream.
+///
+/// | Profile      | 8 and 10-bit | 12-bit                     |
+/// |--------------|--------------|----------------------------|
+/// | Main         | 4:0:0, 4:2:0 | -                          |
+/// | High         | 4:4:4        | -                          |
+/// | Professional | 4:2:2        | 4:0:0, 4:2:0, 4:2:2, 4:4:4 |
+///
+/// The combination is checked when the encoder is created, and by
+/// [`Av1EncoderConfig::validate`]. Released versions of SVT-AV1 only encode
+/// 8 and 10-bit 4:2:0 (or monochrome) input, though, so in practice only the
+/// main profile can be used; other formats are rejected with
+/// [`ConfigError::UnsupportedFormat`].
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Av1Profile {
     /// Main profile.
@@ -47,6 +94,20 @@ pub enum Av1Profile {
     Professional,
 }
 
+impl Av1Profile {
+    /// Whether the profile allows the subsampling format at 8 or 10-bit.
+    fn supports(self, subsampling_format: SubsamplingFormat) -> bool {
+        matches!(
+            (self, subsampling_format),
+            (
+                Av1Profile::Main,
+                SubsamplingFormat::Yuv400 | SubsamplingFormat::Yuv420
+            ) | (Av1Profile::High, SubsamplingFormat::Yuv444)
+                | (Av1Profile::Professional, SubsamplingFormat::Yuv422)
+        )
+    }
+}
+
 /// AV1 decoder tier.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Av1Tier {
@@ -56,59 +117,42 @@ pub enum Av1Tier {
     High,
 }
 
-/// Input/output color space, according to SO/IEC 23091-4/ITU-T H.273.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum ColorDescription {
-    /// Unspecified color description (CP_UNSPECIFIED, TC_UNSPECIFIED, MC_UNSPECIFIED).
-    Unspecified,
-    /// CP_BT_709 color primaries, TC_BT_709 transfer characteristics, and MC_BT_709 matrix coefficients. Standard for HD.
-    Bt709,
-    /// CP_BT_2020 color primaries, TC_SMPTE_2084 transfer characteristics, and MC_BT_2020_NCL matrix coefficients. Standard for the HDR10 media profile.
-    Bt2020Pq,
-    /// Some other combination. See the AV1 spec section 6.4.2 for details.
-    Other {
-        /// The color primaries.
-        primaries: u32,
-        /// The transfer characteristics.
-        transfer_characteristics: u32,
-        /// The matrix coefficients.
-        matrix_coefficients: u32,
-    },
-}
-
-/// Input/output color range.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum ColorRange {
-    /// Studio swing (16-235 for Y, 16-240 for U and V).
-    Limited,
-    /// Full swing (0-255 for Y, 0-255 for U and V).
-    Full,
-}
-
-/// Chroma sample position.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum ChromaSamplePosition {
-    /// Top left.
-    Colocated,
-    /// Left.
-    Vertical,
-    /// Unknown.
-    Unknown,
-}
-
 /// The rate control mode to use.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum RateControlMode {
-    /// Use a constant QP (1-63).
-    ConstantQp(u32),
-    /// Use a constant rate factor to hit a target QP (1-63).
-    ConstantRateFactor(u32),
+    /// Use a constant QP.
+    ConstantQp(Av1Qp),
+    /// Use a constant rate factor to hit a target QP.
+    ConstantRateFactor(Av1Qp),
     /// Use variable bitrate. The value is in bits per second.
     VariableBitrate(u32),
     /// Use a constant bitrate. The value is in bits per second.
     ConstantBitrate(u32),
 }
 
+/// The adaptive quantization mode, which varies the QP within a frame. See
+/// [`Av1EncoderConfig::adaptive_quantization`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum AqMode {
+    /// Use the same QP for every block.
+    Off,
+    /// Vary the QP by segment, based on the variance of each block.
+    Variance,
+    /// Vary the QP by superblock, based on how much later frames predict
+    /// from it. This is the library default.
+    DeltaQ,
+}
+
 /// The strength of the constrained directional enhancement filter.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum CdefLevel {
@@ -133,6 +177,11 @@ pub enum RestorationFilteringMode {
 
 /// The tiling mode.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum TilingMode {
     /// Use a single tile.
     Single,
@@ -162,6 +211,11 @@ pub enum RecodeLevel {
 
 /// The tuning metric.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum Tune {
     /// Visual quality.
     Vq,
@@ -182,6 +236,24 @@ pub enum SwitchFrameInsertion {
     Nearest(u32),
 }
 
+/// How the encoder groups its output into packets.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Each packet is a buffer as the library outputs it, with one coded
+    /// frame. A hidden alt-ref frame comes in a packet of its own, ahead of
+    /// the rest of its temporal unit, and a packet may end with a
+    /// show-existing frame, which is a temporal unit of its own. This has
+    /// the lowest latency.
+    #[default]
+    Frame,
+    /// Each packet is exactly one temporal unit, starting with a temporal
+    /// delimiter and containing exactly one shown frame, as expected by
+    /// most containers and by the AV1 RTP payload format. Hidden frames
+    /// are held back until the shown frame of their temporal unit is
+    /// output, and packets which combine several temporal units are split.
+    TemporalUnit,
+}
+
 /// Which socket(s) to use for encoding, on dual-socket systems.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TargetSocket {
@@ -193,6 +265,170 @@ pub enum TargetSocket {
     Both,
 }
 
+/// The super-resolution mode. With super-resolution, frames are encoded at
+/// a reduced width and upscaled by the decoder.
+///
+/// Denominators range from 8-16, where the frame width is scaled by 8 divided
+/// by the denominator; a denominator of 8 disables scaling.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SuperResolution {
+    /// Disable super-resolution.
+    Off,
+    /// Scale all frames by a fixed amount.
+    Fixed {
+        /// The denominator for non-key frames.
+        denom: u8,
+        /// The denominator for keyframes.
+        kf_denom: u8,
+    },
+    /// Pick a random denominator for each frame. This is intended for
+    /// testing.
+    Random,
+    /// Enable super-resolution for frames where the QP exceeds a threshold
+    /// (0-63).
+    QThreshold {
+        /// The threshold for non-key frames.
+        qthres: u8,
+        /// The threshold for keyframes.
+        kf_qthres: u8,
+    },
+    /// Search for the best denominator for each frame.
+    Auto(SuperResolutionSearch),
+}
+
+/// The search strategy for [`SuperResolution::Auto`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SuperResolutionSearch {
+    /// Encode with two candidate denominators, and pick the best.
+    Dual,
+    /// Encode with a single denominator, picked based on the QP.
+    Solo,
+    /// Encode with every denominator, and pick the best. This is very slow.
+    All,
+}
+
+/// The reference scaling mode. Unlike super-resolution, this scales frames
+/// in both dimensions.
+///
+/// Denominators range from 8-16, where the frame size is scaled by 8 divided
+/// by the denominator; a denominator of 8 disables scaling.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Disable reference scaling.
+    Off,
+    /// Scale all frames by a fixed amount.
+    Fixed {
+        /// The denominator for non-key frames.
+        denom: u8,
+        /// The denominator for keyframes.
+        kf_denom: u8,
+    },
+    /// Pick a random denominator for each frame. This is intended for
+    /// testing.
+    Random,
+    /// Scale frames dynamically based on the buffer level. Only supported
+    /// with [`RateControlMode::ConstantBitrate`] and
+    /// [`PredictionStructure::LowDelay`].
+    Dynamic,
+}
+
+/// A range of quantization matrix levels, from 0 (the strongest weighting)
+/// to 15 (flat). The encoder picks a level within the range for each frame,
+/// based on the QP.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QuantizationMatrixRange {
+    /// The minimum level.
+    pub min: u8,
+    /// The maximum level.
+    pub max: u8,
+}
+
+impl Default for QuantizationMatrixRange {
+    fn default() -> Self {
+        Self { min: 8, max: 15 }
+    }
+}
+
+/// A trade-off between latency and throughput. See
+/// [`Av1EncoderConfig::latency_mode`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// Each packet is output before the next picture is needed, and only
+    /// one frame is encoded at a time. This is the lowest latency in both
+    /// frames and wall-clock time, and the lowest throughput.
+    UltraLow,
+    /// Like [`LatencyMode::UltraLow`], but several frames are encoded in
+    /// parallel. The latency in frames is still zero, but each packet takes
+    /// longer to come out, because frames move through a deeper pipeline.
+    Low,
+    /// A random access prediction structure with a mini-GOP of 16 frames and
+    /// 15 frames of look-ahead, for good compression at a latency of about
+    /// half a second at 30 fps.
+    Normal,
+    /// The longest look-ahead and largest mini-GOP, with as many frames in
+    /// parallel as the library chooses, for offline encodes.
+    Throughput,
+}
+
+/// A curated bundle of settings for a common use case. See
+/// [`Av1EncoderConfig::apply_profile`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum EncodingProfile {
+    /// Screen content, like desktop capture or slides.
+    Screen,
+    /// Low-latency live streaming, with forward prediction only and no
+    /// look-ahead.
+    LowLatencyStreaming,
+    /// Video on demand for photographic content, with film grain synthesis.
+    VodFilm,
+    /// High quality archival encodes, where speed is not a concern.
+    Archival,
+}
+
+impl EncodingProfile {
+    /// The name of the profile, as accepted by [`str::parse`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            EncodingProfile::Screen => "screen",
+            EncodingProfile::LowLatencyStreaming => "low-latency-streaming",
+            EncodingProfile::VodFilm => "vod-film",
+            EncodingProfile::Archival => "archival",
+        }
+    }
+}
+
+impl std::fmt::Display for EncodingProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for EncodingProfile {
+    type Err = Error;
+
+    /// Parses a profile name, like `"vod-film"`. Names are case-insensitive,
+    /// and underscores can be used in place of hyphens.
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let name = s.to_ascii_lowercase().replace('_', "-");
+        [
+            EncodingProfile::Screen,
+            EncodingProfile::LowLatencyStreaming,
+            EncodingProfile::VodFilm,
+            EncodingProfile::Archival,
+        ]
+        .into_iter()
+        .find(|profile| profile.name() == name)
+        .ok_or_else(|| Error::BadParameter {
+            detail: Some(format!("unknown encoding profile: {:?}", s)),
+        })
+    }
+}
+
 /// A helper for building an encode configuration.
 ///
 /// For configuration options, see the upstream docs:
@@ -200,6 +436,22 @@ pub enum TargetSocket {
 /// <https://gitlab.com/AOMediaCodec/SVT-AV1/-/blob/master/Docs/Parameters.md?ref_type=heads>
 pub struct Av1EncoderConfig {
     handle: LibraryHandle,
+    max_pending_frames: Option<u32>,
+    drop_timeout: Option<Duration>,
+    memory_budget: Option<u64>,
+    timebase: Option<Rational>,
+    orientation: Option<Orientation>,
+    recovery_frame: RecoveryFrame,
+    output_mode: OutputMode,
+    aq_mode: Option<AqMode>,
+    error_resilience: bool,
+    /// An invalid QP passed to [`EncoderConfig::rate_control`], returned by
+    /// `create_encoder`.
+    rate_control_error: Option<Error>,
+    #[cfg(svt_av1_rate_change)]
+    rate_schedule: Option<super::RateSchedule>,
+    #[cfg(feature = "log")]
+    log_sink: Option<crate::logging::LogSink>,
     cfg: EbSvtAv1EncConfiguration,
 }
 
@@ -214,6 +466,20 @@ impl Default for Av1EncoderConfig {
 
             Av1EncoderConfig {
                 handle: LibraryHandle(handle),
+                max_pending_frames: None,
+                drop_timeout: None,
+                memory_budget: None,
+                timebase: None,
+                orientation: None,
+                recovery_frame: RecoveryFrame::default(),
+                output_mode: OutputMode::default(),
+                aq_mode: None,
+                error_resilience: false,
+                rate_control_error: None,
+                #[cfg(svt_av1_rate_change)]
+                rate_schedule: None,
+                #[cfg(feature = "log")]
+                log_sink: None,
                 cfg,
             }
         }
@@ -230,6 +496,12 @@ impl std::fmt::Debug for Av1EncoderConfig {
 
 impl Av1EncoderConfig {
     /// Creates a new encoder from the config.
+    ///
+    /// If the subsampling format requires even dimensions and `width` or
+    /// `height` is odd, the encoder pads each picture to the next even size by
+    /// repeating the last column or row. The stream has the padded size,
+    /// since SVT-AV1 doesn't expose the render size; see
+    /// [`Av1Encoder::coded_size`].
     pub fn create_encoder(
         mut self,
         width: u32,
@@ -237,36 +509,198 @@ impl Av1EncoderConfig {
         subsampling_format: SubsamplingFormat,
     ) -> Result<Av1Encoder, Error> {
         // Set the frame size.
-        self.cfg.source_width = width;
-        self.cfg.source_height = height;
-        self.cfg.encoder_color_format = match subsampling_format {
-            SubsamplingFormat::Yuv400 => 0,
-            SubsamplingFormat::Yuv420 => 1,
-            SubsamplingFormat::Yuv422 => 2,
-            SubsamplingFormat::Yuv444 => 3,
+        set_frame_size(&mut self.cfg, width, height, subsampling_format);
+
+        // The budget depends on the frame size, so it's applied last.
+        if let Some(budget) = self.memory_budget {
+            memory::fit_budget(&mut self.cfg, budget);
+        }
+
+        // Check the format, profile and level up front, so that the error
+        // names the offending combination.
+        validate::check_format(&self.cfg, subsampling_format)
+            .and_then(|_| validate::check_profile(&self.cfg, subsampling_format))
+            .and_then(|_| validate::check_level(&self.cfg))
+            .map_err(|e| Error::BadParameter {
+                detail: Some(e.to_string()),
+            })?;
+
+        if let Some(err) = self.rate_control_error.take() {
+            return Err(err);
+        }
+
+        if let Some(mode) = self.aq_mode {
+            apply_aq_mode(&mut self.cfg, mode).map_err(|e| Error::BadParameter {
+                detail: Some(e.to_owned()),
+            })?;
+        }
+
+        // Only override MFMV if it's left to the encoder.
+        if self.error_resilience && self.cfg.enable_mfmv == -1 {
+            self.cfg.enable_mfmv = 0;
+        }
+
+        #[cfg(svt_av1_rate_change)]
+        if self.rate_schedule.is_some() && self.cfg.rate_control_mode == 0 {
+            return Err(Error::BadParameter {
+                detail: Some("a rate schedule requires VBR or CBR".to_owned()),
+            });
+        }
+
+        #[cfg(feature = "log")]
+        let log_sink = self
+            .log_sink
+            .take()
+            .map(|sink| super::channel_log_sink(sink, self.cfg.channel_id));
+
+        // Copy config parameters onto the encoder handle, then create the
+        // encoder.
+        let mut init = || unsafe {
+            result_with_detail(|| svt_av1_enc_set_parameter(self.handle.as_ptr(), &mut self.cfg))?;
+            result_with_detail(|| svt_av1_enc_init(self.handle.as_ptr()))
+        };
+
+        #[cfg(feature = "log")]
+        {
+            let res = match &log_sink {
+                Some(sink) => with_log_sink(sink.clone(), init),
+                None => init(),
+            };
+
+            // The library's state can't be trusted if a panic in the log
+            // callback interrupted it.
+            if take_callback_panic().is_some() {
+                return Err(Error::Poisoned);
+            }
+
+            res?;
+        }
+
+        #[cfg(not(feature = "log"))]
+        init()?;
+
+        let mut encoder = Av1Encoder::new(self.handle, &self.cfg);
+        encoder.max_pending_frames = self.max_pending_frames;
+        if let Some(timeout) = self.drop_timeout {
+            encoder.drop_timeout = timeout;
+        }
+        encoder.padding = EdgePadding::new(width, height, subsampling_format);
+        if subsampling_format == SubsamplingFormat::Yuv400 {
+            let bytes_per_sample = if self.cfg.encoder_bit_depth > 8 { 2 } else { 1 };
+            encoder.monochrome = Some(Mutex::new(NeutralChroma::new(
+                self.cfg.encoder_bit_depth,
+                bytes_per_sample,
+            )));
+        }
+        #[cfg(feature = "log")]
+        {
+            encoder.log_sink = log_sink;
+        }
+        if let Some(timebase) = self.timebase {
+            encoder.timebase = timebase;
+        }
+        encoder.orientation = self.orientation;
+        encoder.recovery = Mutex::new(super::recovery::RecoveryTracker::new(self.recovery_frame));
+        if self.output_mode == OutputMode::TemporalUnit {
+            encoder.temporal_units = Some(Mutex::new(TemporalUnits::default()));
+        }
+        #[cfg(svt_av1_rate_change)]
+        {
+            encoder.rate = Mutex::new(super::schedule::RateTracker::new(
+                self.cfg.target_bit_rate,
+                self.rate_schedule,
+            ));
+        }
+
+        Ok(encoder)
+    }
+
+    /// Applies a curated bundle of settings for a common use case. This sets
+    /// the preset, tuning metric, temporal filtering, film grain, tiling, and
+    /// look-ahead to values that work well together.
+    ///
+    /// Any of the individual settings can be overridden by calling the
+    /// corresponding builder method after this one. Rate control is left
+    /// untouched.
+    pub fn apply_profile(self, profile: EncodingProfile) -> Self {
+        match profile {
+            EncodingProfile::Screen => self
+                .preset(10)
+                .tune(Tune::Psnr)
+                .enable_screen_content_mode(true)
+                .enable_tpl(true)
+                .enable_film_grain_synthesis(0)
+                .tiling_mode(TilingMode::Single),
+            EncodingProfile::LowLatencyStreaming => self
+                .preset(10)
+                .tune(Tune::Psnr)
+                .pred_structure(PredictionStructure::LowDelay)
+                .look_ahead_distance(0)
+                .enable_tpl(false)
+                .enable_film_grain_synthesis(0)
+                .enable_fast_decode(true)
+                .tiling_mode(TilingMode::Multi {
+                    columns: 1,
+                    rows: 0,
+                }),
+            EncodingProfile::VodFilm => self
+                .preset(6)
+                .tune(Tune::Vq)
+                .pred_structure(PredictionStructure::RandomAccess)
+                .look_ahead_distance(120)
+                .enable_tpl(true)
+                .enable_film_grain_synthesis(8)
+                .enable_film_grain_apply_denoise(false)
+                .tiling_mode(TilingMode::Single),
+            EncodingProfile::Archival => self
+                .preset(2)
+                .tune(Tune::Ssim)
+                .pred_structure(PredictionStructure::RandomAccess)
+                .look_ahead_distance(120)
+                .enable_tpl(true)
+                .enable_film_grain_synthesis(0)
+                .tiling_mode(TilingMode::Single),
+        }
+    }
+
+    /// Modifies the underlying library configuration directly. This is an
+    /// escape hatch for fields that don't have a builder method yet, such as
+    /// `enable_qm` or `startup_mg_size`.
+    ///
+    /// The frame size and subsampling format are overwritten by
+    /// [`Av1EncoderConfig::create_encoder`].
+    pub fn with_raw(mut self, f: impl FnOnce(&mut EbSvtAv1EncConfiguration)) -> Self {
+        f(&mut self.cfg);
+        self
+    }
+
+    /// Returns the underlying library configuration.
+    pub fn raw(&self) -> &EbSvtAv1EncConfiguration {
+        &self.cfg
+    }
+
+    /// Sets a parameter by name, using the same names and value syntax as the
+    /// `SvtAv1EncApp` command line (for example, `"enable-qm"` and `"1"`).
+    /// This covers every option the library supports, including ones without
+    /// a dedicated builder method.
+    ///
+    /// Returns [`Error::BadParameter`] if the name is unknown or the value
+    /// can't be parsed.
+    pub fn set_raw_parameter(mut self, name: &str, value: &str) -> Result<Self, Error> {
+        let invalid = |what: &str| Error::BadParameter {
+            detail: Some(format!("invalid parameter {}: {:?}", what, name)),
         };
 
-        // Copy config parameters onto the encoder handle.
+        let c_name = CString::new(name).map_err(|_| invalid("name"))?;
+        let c_value = CString::new(value).map_err(|_| invalid("value"))?;
+
         unsafe {
-            result(svt_av1_enc_set_parameter(
-                self.handle.as_ptr(),
-                &mut self.cfg,
-            ))?
+            result_with_detail(|| {
+                svt_av1_enc_parse_parameter(&mut self.cfg, c_name.as_ptr(), c_value.as_ptr())
+            })?
         }
 
-        // Create the encoder.
-        unsafe { result(svt_av1_enc_init(self.handle.as_ptr()))? }
-
-        Ok(Av1Encoder {
-            handle: self.handle,
-            subsampling_format: match self.cfg.encoder_color_format {
-                0 => SubsamplingFormat::Yuv400,
-                1 => SubsamplingFormat::Yuv420,
-                2 => SubsamplingFormat::Yuv422,
-                3 => SubsamplingFormat::Yuv444,
-                _ => unreachable!(),
-            },
-        })
+        Ok(self)
     }
 
     /// Sets the encoder preset, from 0-13, with 0 being the highest quality and
@@ -276,6 +710,45 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Limits the number of pictures that can be submitted to the encoder
+    /// before the corresponding packets are retrieved. Once the limit is
+    /// reached, [`Av1Encoder::try_send_picture`] returns [`Error::WouldBlock`].
+    ///
+    /// This should be no larger than the number of input buffers the library
+    /// allocates, which depends on the look-ahead distance and prediction
+    /// structure; otherwise, sending a picture may still block.
+    pub fn max_pending_frames(mut self, frames: u32) -> Self {
+        self.max_pending_frames = Some(frames);
+        self
+    }
+
+    /// Sets how long dropping the encoder waits for the stream to finish
+    /// before deinitializing the library, which can hang if pictures are
+    /// still being encoded. The default is five seconds. See
+    /// [`Av1Encoder::shutdown`].
+    pub fn drop_timeout(mut self, timeout: Duration) -> Self {
+        self.drop_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the timebase of the presentation timestamps passed to the
+    /// encoder. Timestamps passed to [`Av1Encoder::send_picture_at`] are
+    /// converted to this timebase. By default, timestamps are counted in
+    /// frames at the configured framerate.
+    pub fn timebase(mut self, timebase: Rational) -> Self {
+        self.timebase = Some(timebase);
+        self
+    }
+
+    /// Sets how decoded pictures should be oriented for display. AV1 can't
+    /// signal this in the bitstream, so the encoder only carries it, for a
+    /// muxer to write into the container. See
+    /// [`Av1Encoder::display_orientation`].
+    pub fn display_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = Some(orientation);
+        self
+    }
+
     /// Sets the intra refresh period.
     pub fn intra_period_length(mut self, intra_period_length: IntraPeriod) -> Self {
         self.cfg.intra_period_length = match intra_period_length {
@@ -329,7 +802,11 @@ impl Av1EncoderConfig {
         self
     }
 
-    /// Sets the input bit depth (8 or 10).
+    /// Sets the input bit depth, 8 or 10. 10-bit pictures are passed in as
+    /// [`Picture16`](crate::Picture16). No released version of SVT-AV1 can
+    /// encode 12-bit input, so [`Av1EncoderConfig::validate`] rejects it with
+    /// [`ConfigError::UnsupportedFormat`], and creating the encoder fails
+    /// with [`Error::BadParameter`].
     pub fn bit_depth(mut self, bit_depth: u32) -> Self {
         self.cfg.encoder_bit_depth = bit_depth;
         self
@@ -358,7 +835,11 @@ impl Av1EncoderConfig {
 
     /// Sets the AV1 level. A value of 0 configures the encoder to auto-detect
     /// the level. Any other value represents the level multiplied by ten - for
-    /// example, 31 for level 3.1.
+    /// example, 31 for level 3.1, or [`Level::L3_1`](super::Level::L3_1)
+    /// converted with `into()`.
+    ///
+    /// Creating the encoder fails if the stream doesn't fit within the level;
+    /// see [`Level::for_stream`](super::Level::for_stream).
     pub fn level(mut self, level: u32) -> Self {
         self.cfg.level = level;
         self
@@ -366,16 +847,7 @@ impl Av1EncoderConfig {
 
     /// Sets the color metadata, which is used to tag the bitstream.
     pub fn color_description(mut self, color_space: ColorDescription) -> Self {
-        let (cp, tc, mc) = match color_space {
-            ColorDescription::Unspecified => (2, 2, 2),
-            ColorDescription::Bt709 => (1, 1, 1),
-            ColorDescription::Bt2020Pq => (9, 16, 9),
-            ColorDescription::Other {
-                primaries,
-                transfer_characteristics,
-                matrix_coefficients,
-            } => (primaries, transfer_characteristics, matrix_coefficients),
-        };
+        let (cp, tc, mc) = color_space.code_points();
 
         self.cfg.color_primaries = cp;
         self.cfg.transfer_characteristics = tc;
@@ -384,6 +856,38 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Sets the mastering display color volume, which is signaled in a
+    /// metadata OBU.
+    pub fn mastering_display(mut self, display: MasteringDisplay) -> Self {
+        // Chromaticity is coded as 0.16 fixed point, the maximum luminance as
+        // 24.8 and the minimum luminance as 18.14.
+        let point = |c: Chromaticity| EbSvtAv1ChromaPoints {
+            x: fixed(c.x, 65536.0).min(u16::MAX as u32) as u16,
+            y: fixed(c.y, 65536.0).min(u16::MAX as u32) as u16,
+        };
+
+        self.cfg.mastering_display = EbSvtAv1MasteringDisplayInfo {
+            r: point(display.red),
+            g: point(display.green),
+            b: point(display.blue),
+            white_point: point(display.white_point),
+            max_luma: fixed(display.max_luminance, 256.0),
+            min_luma: fixed(display.min_luminance, 16384.0),
+        };
+
+        self
+    }
+
+    /// Sets the content light level, which is signaled in a metadata OBU.
+    pub fn content_light_level(mut self, level: ContentLightLevel) -> Self {
+        self.cfg.content_light_level = EbContentLightLevel {
+            max_cll: level.max_content_light_level,
+            max_fall: level.max_frame_average_light_level,
+        };
+
+        self
+    }
+
     /// Sets the color range to tag the bitstream with.
     pub fn color_range(mut self, color_range: ColorRange) -> Self {
         self.cfg.color_range = match color_range {
@@ -406,17 +910,25 @@ impl Av1EncoderConfig {
     }
 
     /// Sets the rate control mode.
+    ///
+    /// The library tells constant QP and constant rate factor apart by
+    /// whether adaptive quantization is enabled, so this also sets the
+    /// adaptive quantization mode: [`AqMode::Off`] for
+    /// [`RateControlMode::ConstantQp`], and the library default,
+    /// [`AqMode::DeltaQ`], for [`RateControlMode::ConstantRateFactor`]. A
+    /// mode set with [`Av1EncoderConfig::adaptive_quantization`] takes
+    /// precedence, whichever is called first.
     pub fn rate_control_mode(mut self, rate_control_mode: RateControlMode) -> Self {
         match rate_control_mode {
             RateControlMode::ConstantQp(qp) => {
                 self.cfg.rate_control_mode = 0;
                 self.cfg.enable_adaptive_quantization = 0;
-                self.cfg.qp = qp;
+                self.cfg.qp = qp.get();
             }
             RateControlMode::ConstantRateFactor(qp) => {
                 self.cfg.rate_control_mode = 0;
-                self.cfg.enable_adaptive_quantization = 1;
-                self.cfg.qp = qp;
+                self.cfg.enable_adaptive_quantization = aq_mode_value(AqMode::DeltaQ);
+                self.cfg.qp = qp.get();
             }
             RateControlMode::VariableBitrate(bitrate) => {
                 self.cfg.rate_control_mode = 1;
@@ -431,6 +943,17 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Sets the adaptive quantization mode, regardless of the order in which
+    /// it's set relative to [`Av1EncoderConfig::rate_control_mode`].
+    ///
+    /// Creating the encoder fails with [`Error::BadParameter`] if the mode
+    /// contradicts the rate control mode: constant QP requires
+    /// [`AqMode::Off`], and constant rate factor requires another mode.
+    pub fn adaptive_quantization(mut self, mode: AqMode) -> Self {
+        self.aq_mode = Some(mode);
+        self
+    }
+
     /// Sets the maximum bitrate in bits per second. Only applicable when using
     /// [`RateControlMode::ConstantQp`] or
     /// [`RateControlMode::ConstantRateFactor`].
@@ -439,12 +962,22 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Sets a schedule of target bitrates, which the encoder switches
+    /// between as the pictures with the scheduled pts are sent. Requires
+    /// [`RateControlMode::VariableBitrate`] or
+    /// [`RateControlMode::ConstantBitrate`], whose bitrate applies until the
+    /// first entry.
+    #[cfg(svt_av1_rate_change)]
+    pub fn rate_schedule(mut self, schedule: super::RateSchedule) -> Self {
+        self.rate_schedule = Some(schedule);
+        self
+    }
+
     /// Sets the range of QP values allowed when using
-    /// [`RateControlMode::VariableBitrate`]. The values must be in the range
-    /// 0-63.
-    pub fn qp_range(mut self, min_qp: u32, max_qp: u32) -> Self {
-        self.cfg.min_qp_allowed = min_qp;
-        self.cfg.max_qp_allowed = max_qp;
+    /// [`RateControlMode::VariableBitrate`].
+    pub fn qp_range(mut self, min_qp: Av1Qp, max_qp: Av1Qp) -> Self {
+        self.cfg.min_qp_allowed = min_qp.get();
+        self.cfg.max_qp_allowed = max_qp.get();
         self
     }
 
@@ -585,6 +1118,56 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Limits the number of frames the encoder may hold on to before
+    /// outputting the corresponding packet. This caps the look-ahead distance
+    /// and the size of each mini-GOP.
+    ///
+    /// A value of 0 configures the encoder for the lowest possible latency:
+    /// the low-delay prediction structure with TPL disabled and no frame
+    /// parallelism, so that each packet is output before the next picture is
+    /// needed.
+    pub fn max_frame_latency(self, frames: u32) -> Self {
+        if frames == 0 {
+            return self
+                .pred_structure(PredictionStructure::LowDelay)
+                .look_ahead_distance(0)
+                .enable_tpl(false)
+                .frame_parallelism(1);
+        }
+
+        // A random access mini-GOP of 2^n frames requires buffering 2^n - 1
+        // future frames.
+        let levels = (frames + 1).ilog2().min(5);
+        self.look_ahead_distance(frames).hierarchical_levels(levels)
+    }
+
+    /// Sets the prediction structure, look-ahead distance, TPL and level of
+    /// parallelism to a combination which trades latency for throughput, as
+    /// measured in the `latency_modes` test. Any of the individual settings
+    /// can be overridden by calling the corresponding builder method after
+    /// this one.
+    pub fn latency_mode(self, mode: LatencyMode) -> Self {
+        match mode {
+            LatencyMode::UltraLow => self.max_frame_latency(0),
+            LatencyMode::Low => self
+                .pred_structure(PredictionStructure::LowDelay)
+                .look_ahead_distance(0)
+                .enable_tpl(false)
+                .frame_parallelism(0),
+            LatencyMode::Normal => self
+                .pred_structure(PredictionStructure::RandomAccess)
+                .enable_tpl(true)
+                .frame_parallelism(0)
+                .max_frame_latency(15),
+            LatencyMode::Throughput => self
+                .pred_structure(PredictionStructure::RandomAccess)
+                .enable_tpl(true)
+                .frame_parallelism(0)
+                .hierarchical_levels(5)
+                .look_ahead_distance(120),
+        }
+    }
+
     /// Enables the Temporal Dependency Model (TPL for short).
     pub fn enable_tpl(mut self, v: bool) -> Self {
         self.cfg.enable_tpl_la = v.into();
@@ -604,18 +1187,151 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Enables quantization matrices, which weight quantization towards
+    /// frequencies the eye is less sensitive to.
+    pub fn enable_qm(mut self, v: bool) -> Self {
+        self.cfg.enable_qm = v;
+        self
+    }
+
+    /// Sets the range of quantization matrix levels to use, if quantization
+    /// matrices are enabled with [`Av1EncoderConfig::enable_qm`].
+    pub fn qm_levels(mut self, range: QuantizationMatrixRange) -> Self {
+        self.cfg.min_qm_level = range.min;
+        self.cfg.max_qm_level = range.max;
+        self
+    }
+
+    /// Enables variance boost, which lowers the QP of low-contrast blocks to
+    /// preserve detail in flat areas. Requires SVT-AV1 2.0 or later.
+    #[cfg(svt_av1_variance_boost)]
+    pub fn enable_variance_boost(mut self, v: bool) -> Self {
+        self.cfg.enable_variance_boost = v;
+        self
+    }
+
+    /// Sets the variance boost strength, from 1-4.
+    #[cfg(svt_av1_variance_boost)]
+    pub fn variance_boost_strength(mut self, strength: u8) -> Self {
+        self.cfg.variance_boost_strength = strength;
+        self
+    }
+
+    /// Sets the octile of a block's variance used for variance boost, from
+    /// 1-8. Lower values boost more blocks.
+    #[cfg(svt_av1_variance_boost)]
+    pub fn variance_octile(mut self, octile: u8) -> Self {
+        self.cfg.variance_octile = octile;
+        self
+    }
+
+    /// Sets the deblocking and rate-distortion sharpness bias, from -7 to 7.
+    /// Higher values preserve more detail.
+    pub fn sharpness(mut self, sharpness: i8) -> Self {
+        self.cfg.sharpness = sharpness;
+        self
+    }
+
+    /// Sets the strength of the QP bias applied to dark frames and blocks,
+    /// from 0-100. Requires SVT-AV1 3.0 or later.
+    #[cfg(svt_av1_luminance_qp_bias)]
+    pub fn luminance_qp_bias(mut self, bias: u8) -> Self {
+        self.cfg.luminance_qp_bias = bias;
+        self
+    }
+
+    /// Configures super-resolution.
+    pub fn super_resolution(mut self, mode: SuperResolution) -> Self {
+        self.cfg.superres_mode = match mode {
+            SuperResolution::Off => 0,
+            SuperResolution::Fixed { denom, kf_denom } => {
+                self.cfg.superres_denom = denom;
+                self.cfg.superres_kf_denom = kf_denom;
+                1
+            }
+            SuperResolution::Random => 2,
+            SuperResolution::QThreshold { qthres, kf_qthres } => {
+                self.cfg.superres_qthres = qthres;
+                self.cfg.superres_kf_qthres = kf_qthres;
+                3
+            }
+            SuperResolution::Auto(search) => {
+                self.cfg.superres_auto_search_type = match search {
+                    SuperResolutionSearch::Dual => 0,
+                    SuperResolutionSearch::Solo => 1,
+                    SuperResolutionSearch::All => 2,
+                };
+                4
+            }
+        };
+
+        self
+    }
+
+    /// Configures reference scaling.
+    pub fn resize_mode(mut self, mode: ResizeMode) -> Self {
+        self.cfg.resize_mode = match mode {
+            ResizeMode::Off => 0,
+            ResizeMode::Fixed { denom, kf_denom } => {
+                self.cfg.resize_denom = denom;
+                self.cfg.resize_kf_denom = kf_denom;
+                1
+            }
+            ResizeMode::Random => 2,
+            ResizeMode::Dynamic => 3,
+        };
+
+        self
+    }
+
     /// Enables screen content mode.
     pub fn enable_screen_content_mode(mut self, v: bool) -> Self {
         self.cfg.screen_content_mode = v.into();
         self
     }
 
+    /// Configures the encoder for still pictures, as in AVIF. Every picture
+    /// is coded as a keyframe. With SVT-AV1 2.0 or later, this also enables
+    /// the library's still-picture mode, which tunes the encoder for intra
+    /// coding and marks the sequence header as a still picture.
+    ///
+    /// To encode a single picture, see [`Av1Encoder::encode_single`].
+    pub fn still_picture(mut self, v: bool) -> Self {
+        #[cfg(svt_av1_avif)]
+        {
+            self.cfg.avif = v;
+        }
+
+        if v {
+            self.cfg.intra_period_length = 0;
+        }
+
+        self
+    }
+
     /// Enables the use of alt-ref (temporally filtered) frames.
     pub fn enable_tf(mut self, v: bool) -> Self {
         self.cfg.enable_tf = v.into();
         self
     }
 
+    /// Enables overlay frames, which are coded after each alt-ref frame to
+    /// refine it for display. Packets containing them report
+    /// [`FrameType::Overlay`](super::FrameType::Overlay).
+    pub fn enable_overlays(mut self, v: bool) -> Self {
+        self.cfg.enable_overlays = v;
+        self
+    }
+
+    /// Sets the strength of the temporal filtering used for alt-ref frames,
+    /// from 0-4. Higher values filter more strongly. Requires SVT-AV1 3.0 or
+    /// later.
+    #[cfg(svt_av1_tf_strength)]
+    pub fn tf_strength(mut self, strength: u8) -> Self {
+        self.cfg.tf_strength = strength;
+        self
+    }
+
     /// Sets the tuning metric.
     pub fn tune(mut self, tune: Tune) -> Self {
         self.cfg.tune = match tune {
@@ -626,13 +1342,37 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Enables computation of reconstruction quality statistics. When
+    /// enabled, packets report PSNR, SSIM and SSE for the encoded frame. See
+    /// [`Av1Packet::luma_psnr`].
+    pub fn enable_stat_report(mut self, v: bool) -> Self {
+        self.cfg.stat_report = v.into();
+        self
+    }
+
+    /// Enables output of reconstructed frames, which can be retrieved with
+    /// [`Av1Encoder::get_recon`].
+    pub fn enable_recon(mut self, v: bool) -> Self {
+        self.cfg.recon_enabled = v;
+        self
+    }
+
     /// Enables fast-decode mode.
     pub fn enable_fast_decode(mut self, v: bool) -> Self {
         self.cfg.fast_decode = v.into();
         self
     }
 
-    /// Configures the use of switch frames.
+    /// Sets how the encoder groups its output into packets. The default is
+    /// [`OutputMode::Frame`].
+    pub fn output_mode(mut self, mode: OutputMode) -> Self {
+        self.output_mode = mode;
+        self
+    }
+
+    /// Configures the use of switch frames. Switch frames are coded in
+    /// error resilient mode, and can be identified with
+    /// [`FrameType::Switch`](super::FrameType::Switch).
     pub fn switch_frame_insertion(mut self, mode: SwitchFrameInsertion) -> Self {
         match mode {
             SwitchFrameInsertion::Disabled => {
@@ -640,7 +1380,7 @@ impl Av1EncoderConfig {
             }
             SwitchFrameInsertion::Strict(interval) => {
                 self.cfg.sframe_dist = interval as i32;
-                self.cfg.sframe_mode = EbSFrameMode_SFRAME_NEAREST_BASE;
+                self.cfg.sframe_mode = EbSFrameMode_SFRAME_STRICT_BASE;
             }
             SwitchFrameInsertion::Nearest(interval) => {
                 self.cfg.sframe_dist = interval as i32;
@@ -651,25 +1391,111 @@ impl Av1EncoderConfig {
         self
     }
 
+    /// Sets the kind of frame coded for
+    /// [`Av1Encoder::request_recovery`]. The default is a keyframe.
+    pub fn recovery_frame(mut self, frame: RecoveryFrame) -> Self {
+        self.recovery_frame = frame;
+        self
+    }
+
+    /// Makes frames depend less on state carried over from earlier frames,
+    /// so that a stream sent over a lossy transport, like RTP, recovers
+    /// sooner after a lost packet. This disables the prediction of motion
+    /// vectors from the motion fields of reference frames, unless that's
+    /// been enabled explicitly with
+    /// [`Av1EncoderConfig::enable_motion_field_motion_vector_control`].
+    ///
+    /// The AV1 `error_resilient_mode` flag and the frame-parallel decoding
+    /// flags (`disable_cdf_update` and `disable_frame_end_update_cdf`) can't
+    /// be set, because SVT-AV1 doesn't expose them. To give decoders points to
+    /// resynchronize at, combine this with a short intra period or with
+    /// [`Av1EncoderConfig::switch_frame_insertion`], and check
+    /// [`Av1Packet::is_independently_decodable`](super::Av1Packet::is_independently_decodable)
+    /// for packets which don't depend on earlier ones.
+    pub fn error_resilience(mut self, v: bool) -> Self {
+        self.error_resilience = v;
+        self
+    }
+
     /// Sets the ID for the channel, if multiple channels are used.
     pub fn channel_id(mut self, id: u32) -> Self {
         self.cfg.channel_id = id;
         self
     }
 
+    /// Passes messages logged by the library on behalf of the encoder to
+    /// `sink`, instead of the logger. Each record carries the encoder's
+    /// [channel ID](Self::channel_id), so that the output of several encoders
+    /// can be told apart.
+    ///
+    /// Messages logged from the library's worker threads can't be attributed
+    /// to a specific encoder, and still go to the logger.
+    #[cfg(feature = "log")]
+    pub fn log_sink(
+        mut self,
+        sink: impl Fn(&crate::LogRecord<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.log_sink = Some(std::sync::Arc::new(sink));
+        self
+    }
+
     /// Sets the number of active channels.
     pub fn active_channel_count(mut self, count: u32) -> Self {
         self.cfg.active_channel_count = count;
         self
     }
 
-    /// Configures the number of logical processors to use.
+    /// Limits the memory the encoder uses to roughly the given number of
+    /// megabytes, for machines where the defaults would run out of memory.
+    ///
+    /// When the encoder is created, the settings which control how many
+    /// pictures the library buffers are reduced until its estimated usage
+    /// fits: first the look-ahead distance, then the level of parallelism,
+    /// and then the number of hierarchical levels. Settings which already
+    /// fit are left alone. If the budget can't be met, the encoder is
+    /// created with the smallest settings; compare
+    /// [`Av1Encoder::approx_memory_usage`] to the budget to check.
+    pub fn memory_budget(mut self, megabytes: u32) -> Self {
+        self.memory_budget = Some((megabytes as u64) << 20);
+        self
+    }
+
+    /// Limits the number of frames processed in parallel. Lower values reduce
+    /// latency and memory usage at the cost of throughput. 0 lets the encoder
+    /// decide. Requires SVT-AV1 3.0 or later.
+    #[cfg(svt_av1_level_of_parallelism)]
+    pub fn level_of_parallelism(mut self, level: u32) -> Self {
+        self.cfg.level_of_parallelism = level;
+        self
+    }
+
+    /// Limits frame parallelism with whichever setting the library has: the
+    /// level of parallelism, or before SVT-AV1 3.0, the number of logical
+    /// processors. 0 lets the encoder decide.
+    fn frame_parallelism(self, level: u32) -> Self {
+        #[cfg(svt_av1_level_of_parallelism)]
+        return self.level_of_parallelism(level);
+
+        #[cfg(not(svt_av1_level_of_parallelism))]
+        self.logical_processors(level)
+    }
+
+    /// Configures the number of logical processors to use. SVT-AV1 3.0
+    /// replaced this with [`Av1EncoderConfig::level_of_parallelism`].
+    #[cfg(svt_av1_logical_processors)]
     pub fn logical_processors(mut self, count: u32) -> Self {
         self.cfg.logical_processors = count;
         self
     }
 
-    /// Configures the encoder to pin execution to the cores specified by [`Av1EncoderConfig::logical_processors`].
+    /// Configures the encoder to pin its threads to the first logical
+    /// processors of the target socket, as many as it uses.
+    ///
+    /// Unlike SVT-HEVC, SVT-AV1 has no setting for the first logical
+    /// processor. To run an encoder on other cores, leave this and the target
+    /// socket unset, and create the encoder on a thread spawned with
+    /// [`ThreadOptions::affinity`](crate::ThreadOptions::affinity); the
+    /// library's threads inherit its affinity.
     pub fn enable_pinned_execution(mut self, v: bool) -> Self {
         self.cfg.pin_threads = v.into();
         self
@@ -686,9 +1512,107 @@ impl Av1EncoderConfig {
         self
     }
 
-    /// Configures the enabled assembly instruction sets.
+    /// Configures the enabled assembly instruction sets. See
+    /// [`CpuFlags::detect`].
     pub fn cpu_flags(mut self, flags: CpuFlags) -> Self {
         self.cfg.use_cpu_flags = flags.bits();
         self
     }
 }
+
+impl EncoderConfig for Av1EncoderConfig {
+    type Encoder = Av1Encoder;
+
+    fn preset(self, preset: u8) -> Self {
+        Av1EncoderConfig::preset(self, preset as i8)
+    }
+
+    fn framerate(self, numerator: u32, denominator: u32) -> Self {
+        Av1EncoderConfig::framerate(self, numerator, denominator)
+    }
+
+    fn rate_control(mut self, rate_control: RateControl) -> Self {
+        self.rate_control_error = None;
+        match rate_control {
+            // An invalid value is rejected by create_encoder.
+            RateControl::ConstantQp(qp) => match Av1Qp::new(qp) {
+                Ok(qp) => self.rate_control_mode(RateControlMode::ConstantQp(qp)),
+                Err(err) => {
+                    self.rate_control_error = Some(err);
+                    self
+                }
+            },
+            RateControl::VariableBitrate(bitrate) => {
+                self.rate_control_mode(RateControlMode::VariableBitrate(bitrate))
+            }
+        }
+    }
+
+    fn gop(self, intra_period: Option<u32>) -> Self {
+        self.intra_period_length(match intra_period {
+            Some(frames) => IntraPeriod::Fixed(frames),
+            None => IntraPeriod::Auto,
+        })
+    }
+
+    fn create_encoder(
+        self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<Av1Encoder, Error> {
+        Av1EncoderConfig::create_encoder(self, width, height, subsampling_format)
+    }
+}
+
+impl ChannelConfig for Av1EncoderConfig {
+    fn channel(self, id: u32, active_channel_count: u32) -> Self {
+        self.channel_id(id)
+            .active_channel_count(active_channel_count)
+    }
+
+    fn stats(encoder: &Av1Encoder) -> Stats {
+        encoder.stats()
+    }
+}
+
+pub(super) fn set_frame_size(
+    cfg: &mut EbSvtAv1EncConfiguration,
+    width: u32,
+    height: u32,
+    subsampling_format: SubsamplingFormat,
+) {
+    let (width, height) = coded_size(width, height, subsampling_format);
+    cfg.source_width = width;
+    cfg.source_height = height;
+    cfg.encoder_color_format = match subsampling_format {
+        // Monochrome input is encoded as 4:2:0, with neutral chroma.
+        SubsamplingFormat::Yuv400 | SubsamplingFormat::Yuv420 => 1,
+        SubsamplingFormat::Yuv422 => 2,
+        SubsamplingFormat::Yuv444 => 3,
+    };
+}
+
+/// Sets the adaptive quantization mode, unless it contradicts the rate
+/// control mode.
+fn apply_aq_mode(cfg: &mut EbSvtAv1EncConfiguration, mode: AqMode) -> Result<(), &'static str> {
+    if cfg.rate_control_mode == 0 {
+        let constant_qp = cfg.enable_adaptive_quantization == 0;
+        if constant_qp && mode != AqMode::Off {
+            return Err("constant QP requires adaptive quantization to be off");
+        } else if !constant_qp && mode == AqMode::Off {
+            return Err("constant rate factor requires adaptive quantization");
+        }
+    }
+
+    cfg.enable_adaptive_quantization = aq_mode_value(mode);
+    Ok(())
+}
+
+fn aq_mode_value(mode: AqMode) -> u8 {
+    match mode {
+        AqMode::Off => 0,
+        AqMode::Variance => 1,
+        AqMode::DeltaQ => 2,
+    }
+}
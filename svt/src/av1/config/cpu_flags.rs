@@ -35,7 +35,62 @@ bitflags! {
 bitflags! {
     impl CpuFlags: u64 {
         const EB_CPU_FLAGS_NEON = 1 << 0;
+        const EB_CPU_FLAGS_ARM_CRC32 = 1 << 1;
+        const EB_CPU_FLAGS_NEON_DOTPROD = 1 << 2;
+        const EB_CPU_FLAGS_NEON_I8MM = 1 << 3;
+        const EB_CPU_FLAGS_SVE = 1 << 4;
+        const EB_CPU_FLAGS_SVE2 = 1 << 5;
         const EB_CPU_FLAGS_INVALID = 1 << 63;
         const EB_CPU_FLAGS_ALL = u64::MAX;
     }
 }
+
+// The library has no optimizations for other architectures.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+bitflags! {
+    impl CpuFlags: u64 {
+        const EB_CPU_FLAGS_INVALID = 1 << 63;
+        const EB_CPU_FLAGS_ALL = u64::MAX;
+    }
+}
+
+impl CpuFlags {
+    /// Detects the instruction sets supported by the current CPU, at
+    /// runtime. The library does this itself by default; this is useful
+    /// as a starting point for disabling specific instruction sets.
+    pub fn detect() -> Self {
+        let mut flags = Self::empty();
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::is_x86_feature_detected as detected;
+            flags.set(Self::EB_CPU_FLAGS_MMX, detected!("mmx"));
+            flags.set(Self::EB_CPU_FLAGS_SSE, detected!("sse"));
+            flags.set(Self::EB_CPU_FLAGS_SSE2, detected!("sse2"));
+            flags.set(Self::EB_CPU_FLAGS_SSE3, detected!("sse3"));
+            flags.set(Self::EB_CPU_FLAGS_SSSE3, detected!("ssse3"));
+            flags.set(Self::EB_CPU_FLAGS_SSE41, detected!("sse4.1"));
+            flags.set(Self::EB_CPU_FLAGS_SSE42, detected!("sse4.2"));
+            flags.set(Self::EB_CPU_FLAGS_AVX, detected!("avx"));
+            flags.set(Self::EB_CPU_FLAGS_AVX2, detected!("avx2"));
+            flags.set(Self::EB_CPU_FLAGS_AVX512F, detected!("avx512f"));
+            flags.set(Self::EB_CPU_FLAGS_AVX512ICD, detected!("avx512cd"));
+            flags.set(Self::EB_CPU_FLAGS_AVX512DQ, detected!("avx512dq"));
+            flags.set(Self::EB_CPU_FLAGS_AVX512BW, detected!("avx512bw"));
+            flags.set(Self::EB_CPU_FLAGS_AVX512VL, detected!("avx512vl"));
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            use std::arch::is_aarch64_feature_detected as detected;
+            flags.set(Self::EB_CPU_FLAGS_NEON, detected!("neon"));
+            flags.set(Self::EB_CPU_FLAGS_ARM_CRC32, detected!("crc"));
+            flags.set(Self::EB_CPU_FLAGS_NEON_DOTPROD, detected!("dotprod"));
+            flags.set(Self::EB_CPU_FLAGS_NEON_I8MM, detected!("i8mm"));
+            flags.set(Self::EB_CPU_FLAGS_SVE, detected!("sve"));
+            flags.set(Self::EB_CPU_FLAGS_SVE2, detected!("sve2"));
+        }
+
+        flags
+    }
+}
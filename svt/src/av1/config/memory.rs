@@ -0,0 +1,215 @@
+use svt_av1_sys::*;
+
+use crate::av1::MAX_HIERARCHICAL_LEVELS;
+
+// A rough model of the library's memory use, dominated by picture buffers.
+// Every picture waiting in the look-ahead or the current mini-GOP is held as
+// a padded copy, with downscaled copies for motion estimation; each frame in
+// flight needs a padded reconstruction and scratch buffers for mode
+// decision; and a set of reference pictures is kept alongside.
+
+/// The padding the library adds around each stored picture, in pixels.
+const PICTURE_PADDING: u64 = 80;
+/// The number of reference pictures kept, not counting those being encoded.
+const REFERENCE_PICTURES: u64 = 8;
+/// Scratch buffers for each frame being encoded, as a multiple of the picture
+/// size.
+const SCRATCH_PER_FRAME: u64 = 3;
+/// Fixed overhead for tables, threads and queues.
+const BASE_OVERHEAD: u64 = 64 << 20;
+/// The library caps the look-ahead at this distance; larger values, like the
+/// default, let it choose.
+const MAX_LOOK_AHEAD: u32 = 120;
+/// The fewest hierarchical levels [`fit_budget`] will reduce to.
+const MIN_HIERARCHICAL_LEVELS: u32 = 2;
+
+/// The size of a padded picture in bytes.
+fn picture_bytes(cfg: &EbSvtAv1EncConfiguration) -> u64 {
+    let width = cfg.source_width as u64 + 2 * PICTURE_PADDING;
+    let height = cfg.source_height as u64 + 2 * PICTURE_PADDING;
+    let bytes_per_sample = if cfg.encoder_bit_depth > 8 { 2 } else { 1 };
+
+    // Chroma samples, in quarters of the luma samples.
+    #[allow(non_upper_case_globals)]
+    let chroma = match cfg.encoder_color_format {
+        EbColorFormat_EB_YUV400 => 0,
+        EbColorFormat_EB_YUV422 => 4,
+        EbColorFormat_EB_YUV444 => 8,
+        _ => 2,
+    };
+
+    width * height * (4 + chroma) / 4 * bytes_per_sample
+}
+
+/// The number of hierarchical levels, resolving the library's default.
+fn hierarchical_levels(cfg: &EbSvtAv1EncConfiguration) -> u32 {
+    match cfg.hierarchical_levels {
+        0 => MAX_HIERARCHICAL_LEVELS,
+        levels => levels.min(MAX_HIERARCHICAL_LEVELS),
+    }
+}
+
+/// The number of pictures in each mini-GOP. With the low-delay prediction
+/// structure, no future pictures are buffered.
+fn mini_gop_size(cfg: &EbSvtAv1EncConfiguration) -> u32 {
+    if cfg.pred_structure == 1 {
+        1
+    } else {
+        1 << hierarchical_levels(cfg)
+    }
+}
+
+/// The look-ahead distance, resolving the library's default.
+fn look_ahead(cfg: &EbSvtAv1EncConfiguration) -> u32 {
+    match cfg.look_ahead_distance {
+        distance if distance > MAX_LOOK_AHEAD => 2 * mini_gop_size(cfg),
+        distance => distance,
+    }
+}
+
+/// The setting which limits frame parallelism: the level of parallelism, or
+/// before SVT-AV1 3.0, the number of logical processors.
+fn parallelism(cfg: &EbSvtAv1EncConfiguration) -> u32 {
+    #[cfg(svt_av1_level_of_parallelism)]
+    return cfg.level_of_parallelism;
+
+    #[cfg(not(svt_av1_level_of_parallelism))]
+    cfg.logical_processors
+}
+
+fn set_parallelism(cfg: &mut EbSvtAv1EncConfiguration, level: u32) {
+    #[cfg(svt_av1_level_of_parallelism)]
+    {
+        cfg.level_of_parallelism = level;
+    }
+
+    #[cfg(not(svt_av1_level_of_parallelism))]
+    {
+        cfg.logical_processors = level;
+    }
+}
+
+/// The number of frames encoded in parallel, resolving the library's
+/// default of one per core.
+fn parallel_frames(cfg: &EbSvtAv1EncConfiguration) -> u32 {
+    match parallelism(cfg) {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+        level => level,
+    }
+}
+
+/// Estimates the memory the library uses for a configuration, in bytes. The
+/// frame size must already be set.
+pub(crate) fn approx_memory_usage(cfg: &EbSvtAv1EncConfiguration) -> u64 {
+    let picture = picture_bytes(cfg);
+    let parallel = parallel_frames(cfg) as u64;
+
+    let buffered = (mini_gop_size(cfg) + look_ahead(cfg)) as u64 + parallel;
+    // Motion estimation uses copies downscaled by 2 and 4 in each dimension.
+    let input = buffered * (picture + picture / 4 + picture / 16);
+    let references = (REFERENCE_PICTURES + parallel) * picture;
+    let scratch = parallel * SCRATCH_PER_FRAME * picture;
+
+    BASE_OVERHEAD + input + references + scratch
+}
+
+/// Reduces the settings which control buffering until the estimate fits the
+/// budget, in bytes, trading quality for memory as little as possible. The
+/// look-ahead is shortened first, then frame parallelism, and then the
+/// mini-GOP size. Settings are never raised, and if the budget can't be met,
+/// the smallest configuration is left in place.
+pub(super) fn fit_budget(cfg: &mut EbSvtAv1EncConfiguration, budget: u64) {
+    let fits = |cfg: &EbSvtAv1EncConfiguration| approx_memory_usage(cfg) <= budget;
+    if fits(cfg) {
+        return;
+    }
+
+    let mini_gop = mini_gop_size(cfg);
+    if look_ahead(cfg) > mini_gop {
+        cfg.look_ahead_distance = mini_gop;
+        if fits(cfg) {
+            return;
+        }
+    }
+
+    let mut parallel = parallel_frames(cfg);
+    while parallel > 1 {
+        parallel /= 2;
+        set_parallelism(cfg, parallel);
+        if fits(cfg) {
+            return;
+        }
+    }
+
+    if cfg.pred_structure == 1 {
+        return;
+    }
+
+    let mut levels = hierarchical_levels(cfg);
+    while levels > MIN_HIERARCHICAL_LEVELS {
+        levels -= 1;
+        cfg.hierarchical_levels = levels;
+        cfg.look_ahead_distance = cfg.look_ahead_distance.min(1 << levels);
+        if fits(cfg) {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(width: u32, height: u32) -> EbSvtAv1EncConfiguration {
+        let mut cfg: EbSvtAv1EncConfiguration = unsafe { std::mem::zeroed() };
+        cfg.source_width = width;
+        cfg.source_height = height;
+        cfg.encoder_bit_depth = 8;
+        cfg.encoder_color_format = EbColorFormat_EB_YUV420;
+        cfg.pred_structure = 2;
+        cfg.look_ahead_distance = u32::MAX;
+        set_parallelism(&mut cfg, 8);
+        cfg
+    }
+
+    #[test]
+    fn estimate() {
+        let hd = approx_memory_usage(&cfg(1920, 1080));
+        let uhd = approx_memory_usage(&cfg(3840, 2160));
+        assert!(uhd > 3 * hd);
+
+        let mut ten_bit = cfg(1920, 1080);
+        ten_bit.encoder_bit_depth = 10;
+        assert!(approx_memory_usage(&ten_bit) > hd);
+
+        let mut low_delay = cfg(1920, 1080);
+        low_delay.pred_structure = 1;
+        assert!(approx_memory_usage(&low_delay) < hd);
+    }
+
+    #[test]
+    fn fit() {
+        let mb = |bytes: u64| bytes >> 20;
+
+        // A generous budget changes nothing.
+        let mut generous = cfg(1920, 1080);
+        fit_budget(&mut generous, u64::MAX);
+        assert_eq!(generous.look_ahead_distance, u32::MAX);
+        assert_eq!(parallelism(&generous), 8);
+
+        let mut tight = cfg(3840, 2160);
+        let before = approx_memory_usage(&tight);
+        fit_budget(&mut tight, 1024 << 20);
+        let after = approx_memory_usage(&tight);
+        assert!(mb(after) <= 1024, "{} MB", mb(after));
+        assert!(after < before);
+        assert!(parallelism(&tight) < 8);
+
+        // An impossible budget leaves the smallest configuration.
+        let mut impossible = cfg(3840, 2160);
+        fit_budget(&mut impossible, 0);
+        assert_eq!(parallelism(&impossible), 1);
+        assert_eq!(impossible.hierarchical_levels, MIN_HIERARCHICAL_LEVELS);
+        assert_eq!(impossible.look_ahead_distance, 1 << MIN_HIERARCHICAL_LEVELS);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{Av1Qp, Error};
+
+use super::{Av1EncoderConfig, RateControlMode};
+
+impl Av1EncoderConfig {
+    /// Builds a configuration from an option string in the style of ffmpeg's
+    /// `-svtav1-params`, like `"preset=8:crf=30:tile-columns=2"`. Options are
+    /// separated by colons, and use the same names as the `SvtAv1EncApp`
+    /// command line, optionally with a leading `--`.
+    ///
+    /// `preset`, `crf`, `qp`, `lookahead`, `film-grain`, `fast-decode`,
+    /// `tile-columns` and `tile-rows` are mapped onto the corresponding
+    /// builder methods. Anything else is passed to
+    /// [`Av1EncoderConfig::set_raw_parameter`].
+    ///
+    /// Returns [`Error::BadParameter`] if an option is malformed, unknown, or
+    /// has an invalid value.
+    pub fn from_opts_str(opts: &str) -> Result<Self, Error> {
+        let mut config = Av1EncoderConfig::default();
+        for opt in opts.split(':').filter(|opt| !opt.is_empty()) {
+            let (key, value) = opt.split_once('=').ok_or_else(|| Error::BadParameter {
+                detail: Some(format!("expected key=value: {:?}", opt)),
+            })?;
+
+            let key = key.trim().trim_start_matches("--");
+            let value = value.trim();
+            config = match key {
+                "preset" => config.preset(parse(key, value)?),
+                "crf" => config
+                    .rate_control_mode(RateControlMode::ConstantRateFactor(parse_qp(key, value)?)),
+                "qp" => {
+                    config.rate_control_mode(RateControlMode::ConstantQp(parse_qp(key, value)?))
+                }
+                "lookahead" => config.look_ahead_distance(parse(key, value)?),
+                "film-grain" => config.enable_film_grain_synthesis(parse(key, value)?),
+                "fast-decode" => config.enable_fast_decode(parse::<u8>(key, value)? != 0),
+                "tile-columns" => {
+                    config.cfg.tile_columns = parse(key, value)?;
+                    config
+                }
+                "tile-rows" => {
+                    config.cfg.tile_rows = parse(key, value)?;
+                    config
+                }
+                _ => config.set_raw_parameter(key, value)?,
+            };
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_qp(key: &str, value: &str) -> Result<Av1Qp, Error> {
+    Av1Qp::new(parse(key, value)?)
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| Error::BadParameter {
+        detail: Some(format!("invalid value for {}: {:?}", key, value)),
+    })
+}
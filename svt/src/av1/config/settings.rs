@@ -0,0 +1,236 @@
+use crate::Av1Qp;
+
+use super::{
+    AqMode, Av1EncoderConfig, EncodingProfile, IntraPeriod, PredictionStructure, RateControlMode,
+    TilingMode, Tune,
+};
+
+/// Encoder settings as plain data, for loading from a configuration file or
+/// recording the settings used for an encode. With the `serde` feature, this
+/// implements `Serialize` and `Deserialize`.
+///
+/// Each field is optional, and unset fields keep the library default. The
+/// [`EncodingProfile`], if any, is applied first, so the other fields
+/// override it.
+///
+/// ```
+/// # use svt::av1::{Av1EncoderConfig, Av1Settings};
+/// let settings = Av1Settings {
+///     profile: Some("vod-film".parse()?),
+///     preset: Some(4),
+///     ..Default::default()
+/// };
+///
+/// let config = Av1EncoderConfig::default().apply_settings(&settings);
+/// assert_eq!(config.settings().preset, Some(4));
+/// # Ok::<(), svt::Error>(())
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct Av1Settings {
+    /// A bundle of settings for a common use case. See
+    /// [`Av1EncoderConfig::apply_profile`].
+    pub profile: Option<EncodingProfile>,
+    /// See [`Av1EncoderConfig::preset`].
+    pub preset: Option<i8>,
+    /// The framerate, as a numerator and denominator. See
+    /// [`Av1EncoderConfig::framerate`].
+    pub framerate: Option<(u32, u32)>,
+    /// See [`Av1EncoderConfig::bit_depth`].
+    pub bit_depth: Option<u32>,
+    /// See [`Av1EncoderConfig::intra_period_length`].
+    pub intra_period: Option<IntraPeriod>,
+    /// See [`Av1EncoderConfig::pred_structure`].
+    pub pred_structure: Option<PredictionStructure>,
+    /// See [`Av1EncoderConfig::hierarchical_levels`].
+    pub hierarchical_levels: Option<u32>,
+    /// See [`Av1EncoderConfig::rate_control_mode`].
+    pub rate_control: Option<RateControlMode>,
+    /// See [`Av1EncoderConfig::adaptive_quantization`].
+    pub adaptive_quantization: Option<AqMode>,
+    /// The minimum and maximum QP. See [`Av1EncoderConfig::qp_range`].
+    pub qp_range: Option<(Av1Qp, Av1Qp)>,
+    /// See [`Av1EncoderConfig::max_bitrate`].
+    pub max_bitrate: Option<u32>,
+    /// See [`Av1EncoderConfig::tiling_mode`].
+    pub tiling: Option<TilingMode>,
+    /// See [`Av1EncoderConfig::look_ahead_distance`].
+    pub look_ahead_distance: Option<u32>,
+    /// See [`Av1EncoderConfig::enable_tpl`].
+    pub enable_tpl: Option<bool>,
+    /// See [`Av1EncoderConfig::enable_film_grain_synthesis`].
+    pub film_grain: Option<u32>,
+    /// See [`Av1EncoderConfig::tune`].
+    pub tune: Option<Tune>,
+    /// See [`Av1EncoderConfig::enable_scene_change_detection`].
+    pub scene_change_detection: Option<bool>,
+    /// See [`Av1EncoderConfig::enable_fast_decode`].
+    pub fast_decode: Option<bool>,
+}
+
+impl From<Av1Settings> for Av1EncoderConfig {
+    fn from(settings: Av1Settings) -> Self {
+        Av1EncoderConfig::default().apply_settings(&settings)
+    }
+}
+
+impl Av1EncoderConfig {
+    /// Applies the fields of `settings` which are set, on top of the
+    /// current configuration.
+    pub fn apply_settings(mut self, settings: &Av1Settings) -> Self {
+        if let Some(profile) = settings.profile {
+            self = self.apply_profile(profile);
+        }
+
+        if let Some(preset) = settings.preset {
+            self = self.preset(preset);
+        }
+
+        if let Some((numerator, denominator)) = settings.framerate {
+            self = self.framerate(numerator, denominator);
+        }
+
+        if let Some(bit_depth) = settings.bit_depth {
+            self = self.bit_depth(bit_depth);
+        }
+
+        if let Some(intra_period) = settings.intra_period {
+            self = self.intra_period_length(intra_period);
+        }
+
+        if let Some(pred_structure) = settings.pred_structure {
+            self = self.pred_structure(pred_structure);
+        }
+
+        if let Some(levels) = settings.hierarchical_levels {
+            self = self.hierarchical_levels(levels);
+        }
+
+        if let Some(rate_control) = settings.rate_control {
+            self = self.rate_control_mode(rate_control);
+        }
+
+        if let Some(mode) = settings.adaptive_quantization {
+            self = self.adaptive_quantization(mode);
+        }
+
+        if let Some((min_qp, max_qp)) = settings.qp_range {
+            self = self.qp_range(min_qp, max_qp);
+        }
+
+        if let Some(max_bitrate) = settings.max_bitrate {
+            self = self.max_bitrate(max_bitrate);
+        }
+
+        if let Some(tiling) = settings.tiling {
+            self = self.tiling_mode(tiling);
+        }
+
+        if let Some(distance) = settings.look_ahead_distance {
+            self = self.look_ahead_distance(distance);
+        }
+
+        if let Some(v) = settings.enable_tpl {
+            self = self.enable_tpl(v);
+        }
+
+        if let Some(strength) = settings.film_grain {
+            self = self.enable_film_grain_synthesis(strength);
+        }
+
+        if let Some(tune) = settings.tune {
+            self = self.tune(tune);
+        }
+
+        if let Some(v) = settings.scene_change_detection {
+            self = self.enable_scene_change_detection(v);
+        }
+
+        if let Some(v) = settings.fast_decode {
+            self = self.enable_fast_decode(v);
+        }
+
+        self
+    }
+
+    /// Reads the current configuration back as [`Av1Settings`], for example
+    /// to log the settings used for an encode. Every field is set except
+    /// `profile`, since a profile is just a combination of the other
+    /// settings, and `look_ahead_distance`, if the library chooses it.
+    pub fn settings(&self) -> Av1Settings {
+        let cfg = &self.cfg;
+
+        let intra_period = match cfg.intra_period_length {
+            -1 => IntraPeriod::None,
+            frames if frames < 0 => IntraPeriod::Auto,
+            frames => IntraPeriod::Fixed(frames as u32),
+        };
+
+        let pred_structure = match cfg.pred_structure {
+            1 => Some(PredictionStructure::LowDelay),
+            2 => Some(PredictionStructure::RandomAccess),
+            _ => None,
+        };
+
+        let rate_control = match cfg.rate_control_mode {
+            0 if cfg.enable_adaptive_quantization == 0 => {
+                Av1Qp::new(cfg.qp).ok().map(RateControlMode::ConstantQp)
+            }
+            0 => Av1Qp::new(cfg.qp)
+                .ok()
+                .map(RateControlMode::ConstantRateFactor),
+            1 => Some(RateControlMode::VariableBitrate(cfg.target_bit_rate)),
+            2 => Some(RateControlMode::ConstantBitrate(cfg.target_bit_rate)),
+            _ => None,
+        };
+
+        let adaptive_quantization = match self
+            .aq_mode
+            .map_or(cfg.enable_adaptive_quantization, super::aq_mode_value)
+        {
+            0 => Some(AqMode::Off),
+            1 => Some(AqMode::Variance),
+            2 => Some(AqMode::DeltaQ),
+            _ => None,
+        };
+
+        let tiling = match (cfg.tile_columns, cfg.tile_rows) {
+            (0, 0) => TilingMode::Single,
+            (columns, rows) => TilingMode::Multi {
+                columns: columns.max(0) as u32,
+                rows: rows.max(0) as u32,
+            },
+        };
+
+        let tune = match cfg.tune {
+            0 => Some(Tune::Vq),
+            1 => Some(Tune::Psnr),
+            2 => Some(Tune::Ssim),
+            _ => None,
+        };
+
+        Av1Settings {
+            profile: None,
+            preset: Some(cfg.enc_mode),
+            framerate: Some((cfg.frame_rate_numerator, cfg.frame_rate_denominator)),
+            bit_depth: Some(cfg.encoder_bit_depth),
+            intra_period: Some(intra_period),
+            pred_structure,
+            hierarchical_levels: Some(cfg.hierarchical_levels),
+            rate_control,
+            adaptive_quantization,
+            qp_range: Av1Qp::new(cfg.min_qp_allowed)
+                .ok()
+                .zip(Av1Qp::new(cfg.max_qp_allowed).ok()),
+            max_bitrate: Some(cfg.max_bit_rate),
+            tiling: Some(tiling),
+            look_ahead_distance: Some(cfg.look_ahead_distance).filter(|&d| d != u32::MAX),
+            enable_tpl: Some(cfg.enable_tpl_la != 0),
+            film_grain: Some(cfg.film_grain_denoise_strength),
+            tune,
+            scene_change_detection: Some(cfg.scene_change_detection != 0),
+            fast_decode: Some(cfg.fast_decode != 0),
+        }
+    }
+}
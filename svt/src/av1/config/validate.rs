@@ -0,0 +1,336 @@
+use svt_av1_sys::*;
+
+use crate::{Error, SubsamplingFormat};
+
+use super::{set_frame_size, Av1EncoderConfig, Av1Profile};
+use crate::av1::{result, result_with_detail, Level, LibraryHandle};
+use crate::level::StreamParameters;
+
+/// An error describing an invalid encoder configuration, returned by
+/// [`Av1EncoderConfig::validate`].
+#[derive(Debug, Clone)]
+pub enum ConfigError {
+    /// A parameter is outside of its valid range. The parameter is named after
+    /// the corresponding field in `EbSvtAv1EncConfiguration`.
+    OutOfRange {
+        /// The name of the parameter.
+        parameter: &'static str,
+        /// The configured value.
+        value: i64,
+        /// The minimum allowed value.
+        min: i64,
+        /// The maximum allowed value.
+        max: i64,
+    },
+    /// The library can't encode the combination of bit depth and chroma
+    /// subsampling. Released versions of SVT-AV1 only encode 8 and 10-bit
+    /// 4:2:0 (or monochrome) input, whatever the profile.
+    UnsupportedFormat {
+        /// The configured bit depth.
+        bit_depth: u32,
+        /// The subsampling format of the input.
+        subsampling_format: SubsamplingFormat,
+    },
+    /// The profile doesn't allow the combination of bit depth and chroma
+    /// subsampling. See [`Av1Profile`].
+    IncompatibleProfile {
+        /// The configured profile.
+        profile: Av1Profile,
+        /// The configured bit depth.
+        bit_depth: u32,
+        /// The subsampling format of the input.
+        subsampling_format: SubsamplingFormat,
+    },
+    /// The configured level isn't one of the levels defined by the AV1
+    /// specification. See [`Level`].
+    UndefinedLevel(u32),
+    /// The stream exceeds the limits of the configured level.
+    ExceedsLevel {
+        /// The configured level.
+        level: Level,
+        /// Which limit is exceeded.
+        reason: String,
+    },
+    /// The library rejected the configuration for some other reason. The
+    /// library logs the specific problem.
+    Rejected(Error),
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Rejected(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::OutOfRange {
+                parameter,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{} must be in the range {}-{}, got {}",
+                parameter, min, max, value
+            ),
+            ConfigError::UnsupportedFormat {
+                bit_depth,
+                subsampling_format,
+            } => write!(
+                f,
+                "SVT-AV1 only encodes 8 or 10-bit 4:2:0, got {}-bit {:?}",
+                bit_depth, subsampling_format
+            ),
+            ConfigError::IncompatibleProfile {
+                profile,
+                bit_depth,
+                subsampling_format,
+            } => write!(
+                f,
+                "{:?} profile doesn't support {}-bit {:?}",
+                profile, bit_depth, subsampling_format
+            ),
+            ConfigError::UndefinedLevel(level) => write!(f, "undefined level {}", level),
+            ConfigError::ExceedsLevel { level, reason } => {
+                write!(f, "stream doesn't fit level {}: {}", level, reason)
+            }
+            ConfigError::Rejected(e) => write!(f, "invalid configuration: {}", e),
+        }
+    }
+}
+
+impl From<Error> for ConfigError {
+    fn from(e: Error) -> Self {
+        ConfigError::Rejected(e)
+    }
+}
+
+fn check_range(
+    parameter: &'static str,
+    value: impl Into<i64>,
+    min: i64,
+    max: i64,
+) -> Result<(), ConfigError> {
+    let value = value.into();
+    if value < min || value > max {
+        return Err(ConfigError::OutOfRange {
+            parameter,
+            value,
+            min,
+            max,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the library can encode the bit depth and subsampling format.
+pub(super) fn check_format(
+    cfg: &EbSvtAv1EncConfiguration,
+    subsampling_format: SubsamplingFormat,
+) -> Result<(), ConfigError> {
+    let bit_depth = cfg.encoder_bit_depth;
+    let supported = matches!(bit_depth, 8 | 10)
+        && matches!(
+            subsampling_format,
+            SubsamplingFormat::Yuv400 | SubsamplingFormat::Yuv420
+        );
+
+    if !supported {
+        return Err(ConfigError::UnsupportedFormat {
+            bit_depth,
+            subsampling_format,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the profile allows the bit depth and subsampling format.
+pub(super) fn check_profile(
+    cfg: &EbSvtAv1EncConfiguration,
+    subsampling_format: SubsamplingFormat,
+) -> Result<(), ConfigError> {
+    let profile = match cfg.profile {
+        0 => Av1Profile::Main,
+        1 => Av1Profile::High,
+        2 => Av1Profile::Professional,
+        v => {
+            return Err(ConfigError::OutOfRange {
+                parameter: "profile",
+                value: v.into(),
+                min: 0,
+                max: 2,
+            })
+        }
+    };
+
+    let bit_depth = cfg.encoder_bit_depth;
+    let supported = match (profile, bit_depth) {
+        (_, 8 | 10) => profile.supports(subsampling_format),
+        (Av1Profile::Professional, 12) => true,
+        _ => false,
+    };
+
+    if !supported {
+        return Err(ConfigError::IncompatibleProfile {
+            profile,
+            bit_depth,
+            subsampling_format,
+        });
+    }
+
+    Ok(())
+}
+
+/// Checks that the stream fits within the configured level, unless the level
+/// is detected automatically. The bitrate is only known for the VBR and CBR
+/// modes, or if a maximum bitrate is set.
+pub(super) fn check_level(cfg: &EbSvtAv1EncConfiguration) -> Result<(), ConfigError> {
+    if cfg.level == 0 {
+        return Ok(());
+    }
+
+    let level = Level::from_config(cfg.level).ok_or(ConfigError::UndefinedLevel(cfg.level))?;
+    let bitrate = match cfg.rate_control_mode {
+        1 | 2 => Some(cfg.target_bit_rate),
+        _ if cfg.max_bit_rate > 0 => Some(cfg.max_bit_rate),
+        _ => None,
+    };
+
+    // The High and Professional profiles allow two and three times the
+    // bitrate, respectively.
+    let profile_factor = cfg.profile as u64 + 1;
+    let framerate = if cfg.frame_rate_denominator > 0 {
+        cfg.frame_rate_numerator as f64 / cfg.frame_rate_denominator as f64
+    } else {
+        0.0
+    };
+
+    let stream = StreamParameters {
+        width: cfg.source_width,
+        height: cfg.source_height,
+        framerate,
+        bitrate: bitrate.map(|b| b as u64 / profile_factor),
+        high_tier: cfg.tier != 0,
+    };
+
+    level
+        .limits()
+        .check(&stream)
+        .map_err(|reason| ConfigError::ExceedsLevel { level, reason })
+}
+
+impl Av1EncoderConfig {
+    /// Checks the configuration for errors, without creating an encoder.
+    ///
+    /// Common parameters are range-checked first, so that the offending
+    /// parameter can be reported. The configuration is then passed to the
+    /// library on a scratch handle, which performs the full set of checks.
+    pub fn validate(
+        &self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<(), ConfigError> {
+        let mut cfg = self.cfg;
+        set_frame_size(&mut cfg, width, height, subsampling_format);
+
+        check_range("enc_mode", cfg.enc_mode, -2, 13)?;
+        check_range("encoder_bit_depth", cfg.encoder_bit_depth, 8, 12)?;
+        check_format(&cfg, subsampling_format)?;
+        check_profile(&cfg, subsampling_format)?;
+        check_level(&cfg)?;
+        check_range(
+            "frame_rate_numerator",
+            cfg.frame_rate_numerator,
+            1,
+            i64::MAX,
+        )?;
+        check_range(
+            "frame_rate_denominator",
+            cfg.frame_rate_denominator,
+            1,
+            i64::MAX,
+        )?;
+        check_range("qp", cfg.qp, 0, 63)?;
+        check_range("max_qp_allowed", cfg.max_qp_allowed, 0, 63)?;
+        check_range(
+            "min_qp_allowed",
+            cfg.min_qp_allowed,
+            0,
+            cfg.max_qp_allowed.into(),
+        )?;
+        check_range("vbr_min_section_pct", cfg.vbr_min_section_pct, 0, 100)?;
+        check_range("vbr_max_section_pct", cfg.vbr_max_section_pct, 0, 10000)?;
+        check_range("under_shoot_pct", cfg.under_shoot_pct, 0, 100)?;
+        check_range("over_shoot_pct", cfg.over_shoot_pct, 0, 100)?;
+        check_range("mbr_over_shoot_pct", cfg.mbr_over_shoot_pct, 0, 100)?;
+        check_range(
+            "starting_buffer_level_ms",
+            cfg.starting_buffer_level_ms,
+            20,
+            10000,
+        )?;
+        check_range(
+            "optimal_buffer_level_ms",
+            cfg.optimal_buffer_level_ms,
+            20,
+            10000,
+        )?;
+        check_range(
+            "maximum_buffer_size_ms",
+            cfg.maximum_buffer_size_ms,
+            20,
+            10000,
+        )?;
+        check_range(
+            "film_grain_denoise_strength",
+            cfg.film_grain_denoise_strength,
+            0,
+            50,
+        )?;
+        check_range("cdef_level", cfg.cdef_level, -1, 4)?;
+        check_range("tile_columns", cfg.tile_columns, 0, 6)?;
+        check_range("tile_rows", cfg.tile_rows, 0, 6)?;
+        #[cfg(svt_av1_variance_boost)]
+        check_range("variance_boost_strength", cfg.variance_boost_strength, 1, 4)?;
+        #[cfg(svt_av1_variance_boost)]
+        check_range("variance_octile", cfg.variance_octile, 1, 8)?;
+        check_range("sharpness", cfg.sharpness, -7, 7)?;
+        #[cfg(svt_av1_luminance_qp_bias)]
+        check_range("luminance_qp_bias", cfg.luminance_qp_bias, 0, 100)?;
+        check_range("superres_denom", cfg.superres_denom, 8, 16)?;
+        check_range("superres_kf_denom", cfg.superres_kf_denom, 8, 16)?;
+        check_range("superres_qthres", cfg.superres_qthres, 0, 63)?;
+        check_range("superres_kf_qthres", cfg.superres_kf_qthres, 0, 63)?;
+        check_range("resize_denom", cfg.resize_denom, 8, 16)?;
+        check_range("resize_kf_denom", cfg.resize_kf_denom, 8, 16)?;
+        #[cfg(svt_av1_tf_strength)]
+        check_range("tf_strength", cfg.tf_strength, 0, 4)?;
+        check_range("max_qm_level", cfg.max_qm_level, 0, 15)?;
+        check_range("min_qm_level", cfg.min_qm_level, 0, cfg.max_qm_level.into())?;
+
+        // Let the library check everything else, on a handle that we throw
+        // away afterwards.
+        unsafe {
+            let mut handle = std::ptr::null_mut();
+            let mut scratch_cfg = std::mem::zeroed();
+            result(svt_av1_enc_init_handle(
+                &mut handle,
+                std::ptr::null_mut(),
+                &mut scratch_cfg,
+            ))?;
+
+            let handle = LibraryHandle(handle);
+            result_with_detail(|| svt_av1_enc_set_parameter(handle.as_ptr(), &mut cfg))?;
+        }
+
+        Ok(())
+    }
+}
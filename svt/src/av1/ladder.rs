@@ -0,0 +1,240 @@
+use crate::scale::{scale_into, Filter};
+use crate::{ChannelGroup, ChannelGroupBuilder, Error, FrameTypeRequest, Picture, Stats};
+use crate::{SubsamplingFormat, YUVBuffer};
+
+use super::{Av1EncoderConfig, Av1Packet, IntraPeriod};
+
+/// An ABR ladder: one source encoded as several renditions, each at its own
+/// resolution and bitrate, with keyframes at the same pts in every
+/// rendition so that the segments line up for HLS or DASH.
+///
+/// Each source picture is scaled to the size of each rendition with the
+/// [`scale`](crate::scale) module, so the renditions can be fed from a
+/// single decoder or capture. This requires the `scale` feature. The encoders run
+/// as channels of a [`ChannelGroup`], sharing the machine's processors.
+///
+/// To keep the keyframes aligned, the encoders never place keyframes on
+/// their own: the intra period and scene change detection are disabled for
+/// every rendition, and the ladder forces keyframes at the configured
+/// interval, or when requested, across all of them.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), svt::Error> {
+/// use svt::av1::{Av1EncoderConfig, Ladder, RateControlMode};
+/// use svt::{SubsamplingFormat, YUVBuffer};
+///
+/// let vbr = |bps| Av1EncoderConfig::default().rate_control_mode(RateControlMode::VariableBitrate(bps));
+/// let mut ladder = Ladder::builder(SubsamplingFormat::Yuv420)
+///     .rendition(vbr(6_000_000), 1920, 1080)
+///     .rendition(vbr(3_000_000), 1280, 720)
+///     .rendition(vbr(1_000_000), 640, 360)
+///     .keyframe_interval(120)
+///     .build()?;
+///
+/// let picture = YUVBuffer::new(1920, 1080, SubsamplingFormat::Yuv420);
+/// ladder.send_picture(&picture, 0, false)?;
+/// for rendition in 0..ladder.len() {
+///     while let Some(packet) = ladder.get_packet(rendition, false)? {
+///         // Write the packet to the rendition's playlist...
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Ladder {
+    group: ChannelGroup<Av1EncoderConfig>,
+    sizes: Vec<(u32, u32)>,
+    scaled: Vec<YUVBuffer>,
+    subsampling_format: SubsamplingFormat,
+    keyframe_interval: Option<u32>,
+    since_keyframe: Option<u32>,
+}
+
+impl std::fmt::Debug for Ladder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ladder")
+            .field("sizes", &self.sizes)
+            .field("subsampling_format", &self.subsampling_format)
+            .field("keyframe_interval", &self.keyframe_interval)
+            .finish()
+    }
+}
+
+/// Builds a [`Ladder`].
+pub struct LadderBuilder {
+    group: ChannelGroupBuilder<Av1EncoderConfig>,
+    sizes: Vec<(u32, u32)>,
+    subsampling_format: SubsamplingFormat,
+    keyframe_interval: Option<u32>,
+}
+
+impl std::fmt::Debug for LadderBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LadderBuilder")
+            .field("sizes", &self.sizes)
+            .field("subsampling_format", &self.subsampling_format)
+            .field("keyframe_interval", &self.keyframe_interval)
+            .finish()
+    }
+}
+
+/// Disables the settings which would let an encoder place keyframes on its
+/// own.
+fn aligned(config: Av1EncoderConfig) -> Av1EncoderConfig {
+    config
+        .intra_period_length(IntraPeriod::None)
+        .enable_scene_change_detection(false)
+}
+
+impl LadderBuilder {
+    /// Adds a rendition, encoded at the given size. The config sets the
+    /// bitrate and any other per-rendition settings. Renditions are numbered
+    /// in the order they're added.
+    pub fn rendition(mut self, config: Av1EncoderConfig, width: u32, height: u32) -> Self {
+        self.group = self
+            .group
+            .channel(aligned(config), width, height, self.subsampling_format);
+        self.sizes.push((width, height));
+        self
+    }
+
+    /// Sets a function which is applied to the config of every rendition,
+    /// for settings shared by the whole ladder, like the preset. See
+    /// [`ChannelGroupBuilder::shared`]. The keyframe settings can't be
+    /// overridden.
+    pub fn shared(mut self, f: impl Fn(Av1EncoderConfig) -> Av1EncoderConfig + 'static) -> Self {
+        self.group = self.group.shared(move |config| aligned(f(config)));
+        self
+    }
+
+    /// Sets the number of frames between keyframes. By default, only the
+    /// first frame, and frames for which a keyframe is requested, are
+    /// keyframes. A requested keyframe restarts the interval.
+    pub fn keyframe_interval(mut self, frames: u32) -> Self {
+        self.keyframe_interval = Some(frames).filter(|&frames| frames > 0);
+        self
+    }
+
+    /// Creates the encoders.
+    pub fn build(self) -> Result<Ladder, Error> {
+        let group = self.group.build()?;
+        let scaled = self
+            .sizes
+            .iter()
+            .map(|&(width, height)| YUVBuffer::new(width, height, self.subsampling_format))
+            .collect();
+
+        Ok(Ladder {
+            group,
+            sizes: self.sizes,
+            scaled,
+            subsampling_format: self.subsampling_format,
+            keyframe_interval: self.keyframe_interval,
+            since_keyframe: None,
+        })
+    }
+}
+
+impl Ladder {
+    /// Starts building a ladder, for 8-bit source pictures in the given
+    /// subsampling format.
+    pub fn builder(subsampling_format: SubsamplingFormat) -> LadderBuilder {
+        LadderBuilder {
+            group: ChannelGroup::builder(),
+            sizes: Vec::new(),
+            subsampling_format,
+            keyframe_interval: None,
+        }
+    }
+
+    /// The number of renditions.
+    pub fn len(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// Whether the ladder has no renditions.
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    /// The width and height of each rendition, in rendition order.
+    pub fn sizes(&self) -> &[(u32, u32)] {
+        &self.sizes
+    }
+
+    /// The underlying group of encoders, with one channel per rendition.
+    pub fn group(&self) -> &ChannelGroup<Av1EncoderConfig> {
+        &self.group
+    }
+
+    /// Sends a source picture to every rendition, scaling it to each
+    /// rendition's size. The picture must be 8-bit, in the ladder's
+    /// subsampling format.
+    ///
+    /// Returns whether the picture was coded as a keyframe, which is the
+    /// case for the first picture, at each keyframe interval, and if
+    /// `force_keyframe` is set.
+    pub fn send_picture(
+        &mut self,
+        picture: &impl Picture,
+        pts: i64,
+        force_keyframe: bool,
+    ) -> Result<bool, Error> {
+        if picture.bytes_per_sample() != 1 {
+            return Err(Error::BadParameter {
+                detail: Some("ladder source pictures must be 8-bit".to_owned()),
+            });
+        }
+
+        let keyframe = force_keyframe
+            || match (self.since_keyframe, self.keyframe_interval) {
+                (None, _) => true,
+                (Some(frames), Some(interval)) => frames >= interval,
+                (Some(_), None) => false,
+            };
+
+        let frame_type = if keyframe {
+            FrameTypeRequest::Key
+        } else {
+            FrameTypeRequest::Auto
+        };
+
+        for (rendition, &(width, height)) in self.sizes.iter().enumerate() {
+            if (width, height) == (picture.width(), picture.height()) {
+                self.group
+                    .send_picture(rendition, picture, pts, frame_type)?;
+                continue;
+            }
+
+            let scaled = &mut self.scaled[rendition];
+            scale_into(picture, self.subsampling_format, scaled, Filter::Box)?;
+            self.group
+                .send_picture(rendition, scaled, pts, frame_type)?;
+        }
+
+        self.since_keyframe = Some(match self.since_keyframe {
+            Some(frames) if !keyframe => frames + 1,
+            _ => 1,
+        });
+
+        Ok(keyframe)
+    }
+
+    /// Retrieves a packet from a rendition. See
+    /// [`Encoder::get_packet`](crate::Encoder::get_packet).
+    pub fn get_packet(&self, rendition: usize, wait: bool) -> Result<Option<Av1Packet>, Error> {
+        self.group.get_packet(rendition, wait)
+    }
+
+    /// Finishes the stream of every rendition.
+    pub fn finish(&self) -> Result<(), Error> {
+        self.group.finish()
+    }
+
+    /// Returns statistics for each rendition, in rendition order.
+    pub fn stats(&self) -> Vec<Stats> {
+        self.group.stats()
+    }
+}
@@ -0,0 +1,168 @@
+use crate::level::{LevelLimits, StreamParameters};
+
+/// An AV1 level, which constrains the picture size, sample rate, and bitrate
+/// of a stream so that decoders can advertise what they support. Levels
+/// which are reserved in the specification are omitted.
+///
+/// Pass a level to [`Av1EncoderConfig::level`](super::Av1EncoderConfig::level)
+/// with `into()`. The stream is checked against it when the encoder is
+/// created.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(missing_docs)]
+pub enum Level {
+    L2_0,
+    L2_1,
+    L3_0,
+    L3_1,
+    L4_0,
+    L4_1,
+    L5_0,
+    L5_1,
+    L5_2,
+    L5_3,
+    L6_0,
+    L6_1,
+    L6_2,
+    L6_3,
+}
+
+const ALL: [Level; 14] = [
+    Level::L2_0,
+    Level::L2_1,
+    Level::L3_0,
+    Level::L3_1,
+    Level::L4_0,
+    Level::L4_1,
+    Level::L5_0,
+    Level::L5_1,
+    Level::L5_2,
+    Level::L5_3,
+    Level::L6_0,
+    Level::L6_1,
+    Level::L6_2,
+    Level::L6_3,
+];
+
+impl Level {
+    /// Returns the lowest level that allows a stream with the given
+    /// dimensions, framerate, and bitrate in bits per second, at the main
+    /// tier. If the stream exceeds every level, returns the highest.
+    pub fn for_stream(width: u32, height: u32, framerate: f64, bitrate: u64) -> Level {
+        let stream = StreamParameters {
+            width,
+            height,
+            framerate,
+            bitrate: Some(bitrate),
+            high_tier: false,
+        };
+
+        ALL.into_iter()
+            .find(|level| level.limits().check(&stream).is_ok())
+            .unwrap_or(Level::L6_3)
+    }
+
+    /// Parses a level in the form used by
+    /// [`Av1EncoderConfig::level`](super::Av1EncoderConfig::level): the level
+    /// multiplied by ten.
+    pub(crate) fn from_config(level: u32) -> Option<Level> {
+        ALL.into_iter().find(|&l| u32::from(l) == level)
+    }
+
+    /// The major and minor version, as in the level's name.
+    fn version(self) -> (u32, u32) {
+        let idx = self.seq_level_idx();
+        (idx / 4 + 2, idx % 4)
+    }
+
+    /// The `seq_level_idx` coded in the sequence header.
+    pub fn seq_level_idx(self) -> u32 {
+        match self {
+            Level::L2_0 => 0,
+            Level::L2_1 => 1,
+            Level::L3_0 => 4,
+            Level::L3_1 => 5,
+            Level::L4_0 => 8,
+            Level::L4_1 => 9,
+            Level::L5_0 => 12,
+            Level::L5_1 => 13,
+            Level::L5_2 => 14,
+            Level::L5_3 => 15,
+            Level::L6_0 => 16,
+            Level::L6_1 => 17,
+            Level::L6_2 => 18,
+            Level::L6_3 => 19,
+        }
+    }
+
+    /// The limits from Annex A.3 of the AV1 specification, for the Main
+    /// profile.
+    pub(crate) fn limits(self) -> LevelLimits {
+        // MaxPicSize, MaxHSize, MaxVSize, MaxDisplayRate, MainMbps, HighMbps
+        let (max_picture_size, max_width, max_height, max_sample_rate, main, high) = match self {
+            Level::L2_0 => (147_456, 2048, 1152, 4_423_680, 1.5, None),
+            Level::L2_1 => (278_784, 2816, 1584, 8_363_520, 3.0, None),
+            Level::L3_0 => (665_856, 4352, 2448, 19_975_680, 6.0, None),
+            Level::L3_1 => (1_065_024, 5504, 3096, 31_950_720, 10.0, None),
+            Level::L4_0 => (2_359_296, 6144, 3456, 70_778_880, 12.0, Some(30.0)),
+            Level::L4_1 => (2_359_296, 6144, 3456, 141_557_760, 20.0, Some(50.0)),
+            Level::L5_0 => (8_912_896, 8192, 4352, 267_386_880, 30.0, Some(100.0)),
+            Level::L5_1 => (8_912_896, 8192, 4352, 534_773_760, 40.0, Some(160.0)),
+            Level::L5_2 => (8_912_896, 8192, 4352, 1_069_547_520, 60.0, Some(240.0)),
+            Level::L5_3 => (8_912_896, 8192, 4352, 1_069_547_520, 60.0, Some(240.0)),
+            Level::L6_0 => (35_651_584, 16384, 8704, 1_069_547_520, 60.0, Some(240.0)),
+            Level::L6_1 => (35_651_584, 16384, 8704, 2_139_095_040, 100.0, Some(480.0)),
+            Level::L6_2 => (35_651_584, 16384, 8704, 4_278_190_080, 160.0, Some(800.0)),
+            Level::L6_3 => (35_651_584, 16384, 8704, 4_278_190_080, 160.0, Some(800.0)),
+        };
+
+        let mbps = |v: f64| (v * 1_000_000.0) as u64;
+        LevelLimits {
+            max_picture_size,
+            max_width,
+            max_height,
+            max_sample_rate,
+            max_bitrate: mbps(main),
+            max_bitrate_high: high.map(mbps),
+        }
+    }
+}
+
+impl From<Level> for u32 {
+    /// Returns the level multiplied by ten, for example 41 for level 4.1.
+    fn from(level: Level) -> u32 {
+        let (major, minor) = level.version();
+        major * 10 + minor
+    }
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (major, minor) = self.version();
+        write!(f, "{}.{}", major, minor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn for_stream() {
+        assert_eq!(Level::for_stream(1280, 720, 30.0, 2_000_000), Level::L3_1);
+        assert_eq!(Level::for_stream(1920, 1080, 30.0, 8_000_000), Level::L4_0);
+        assert_eq!(Level::for_stream(1920, 1080, 60.0, 8_000_000), Level::L4_1);
+        assert_eq!(Level::for_stream(1920, 1080, 30.0, 25_000_000), Level::L5_0);
+        assert_eq!(Level::for_stream(3840, 2160, 60.0, 20_000_000), Level::L5_1);
+        assert_eq!(Level::for_stream(640, 360, 30.0, 1_000_000), Level::L2_1);
+        assert_eq!(Level::for_stream(20000, 20000, 30.0, 0), Level::L6_3);
+    }
+
+    #[test]
+    fn config_value() {
+        assert_eq!(u32::from(Level::L2_0), 20);
+        assert_eq!(u32::from(Level::L5_3), 53);
+        assert_eq!(Level::from_config(41), Some(Level::L4_1));
+        assert_eq!(Level::from_config(22), None);
+        assert_eq!(Level::L6_2.to_string(), "6.2");
+    }
+}
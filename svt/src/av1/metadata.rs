@@ -0,0 +1,73 @@
+use svt_av1_sys::*;
+
+/// The type of a metadata OBU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataType {
+    /// HDR content light level information.
+    HdrCll,
+    /// HDR mastering display color volume information.
+    HdrMdcv,
+    /// Scalability information.
+    Scalability,
+    /// ITU-T T.35 registered user data, for example HDR10+ dynamic metadata.
+    ItutT35,
+    /// A timecode.
+    Timecode,
+}
+
+impl MetadataType {
+    fn as_raw(self) -> u32 {
+        match self {
+            MetadataType::HdrCll => EbAv1MetadataType_EB_AV1_METADATA_TYPE_HDR_CLL,
+            MetadataType::HdrMdcv => EbAv1MetadataType_EB_AV1_METADATA_TYPE_HDR_MDCV,
+            MetadataType::Scalability => EbAv1MetadataType_EB_AV1_METADATA_TYPE_SCALABILITY,
+            MetadataType::ItutT35 => EbAv1MetadataType_EB_AV1_METADATA_TYPE_ITUT_T35,
+            MetadataType::Timecode => EbAv1MetadataType_EB_AV1_METADATA_TYPE_TIMECODE,
+        }
+    }
+}
+
+/// A metadata OBU, which can be attached to a frame with
+/// [`Av1Encoder::send_picture_with_metadata`](super::Av1Encoder::send_picture_with_metadata).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// The metadata type.
+    pub ty: MetadataType,
+    /// The OBU payload, not including the metadata type.
+    pub payload: Vec<u8>,
+}
+
+impl Metadata {
+    /// Creates a new metadata OBU.
+    pub fn new(ty: MetadataType, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            ty,
+            payload: payload.into(),
+        }
+    }
+
+    /// Creates an ITU-T T.35 metadata OBU. For HDR10+, the payload starts with
+    /// the country code `0xB5`.
+    pub fn itu_t_t35(payload: impl Into<Vec<u8>>) -> Self {
+        Self::new(MetadataType::ItutT35, payload)
+    }
+
+    pub(crate) fn attach(&self, buffer: &mut EbBufferHeaderType) -> Result<(), crate::Error> {
+        let res = unsafe {
+            svt_add_metadata(
+                buffer,
+                self.ty.as_raw(),
+                self.payload.as_ptr(),
+                self.payload.len(),
+            )
+        };
+
+        if res != 0 {
+            return Err(crate::Error::BadParameter {
+                detail: Some(format!("failed to add {:?} metadata", self.ty)),
+            });
+        }
+
+        Ok(())
+    }
+}
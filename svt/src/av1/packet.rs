@@ -1,6 +1,15 @@
+use std::sync::Arc;
+
 use svt_av1_sys::*;
 
-use crate::Packet;
+use crate::gop::OutputPicture;
+use crate::mux::MuxError;
+use crate::{KeyframeReason, Packet, Rational, SubsamplingFormat, Timestamp};
+
+use super::{Library, StreamFormat};
+
+/// The PSNR reported for frames which are identical to the source.
+const MAX_PSNR: f64 = 100.0;
 
 /// The type of a coded frame.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,23 +24,51 @@ pub enum FrameType {
     IntraOnly,
     /// An alternative reference frame.
     AltRef,
+    /// An overlay frame, which displays a previous alt-ref frame with
+    /// additional residual coding. See
+    /// [`Av1EncoderConfig::enable_overlays`](super::Av1EncoderConfig::enable_overlays).
+    Overlay,
+    /// A switch frame, an inter frame which a decoder can use to switch
+    /// between streams at different bitrates. It only depends on the
+    /// contents of the reference frames. See
+    /// [`Av1EncoderConfig::switch_frame_insertion`](super::Av1EncoderConfig::switch_frame_insertion).
+    Switch,
     // These are present in the source, but not used by the encoder.
     // ShowExisting,
     // ForwardKey,
-    // Switch,
 }
 
 /// A packet of encoded data output by the encoder. The buffer is reference
-/// counted, and will be reused by the encoder once dropped.
+/// counted, and will be reused by the encoder once dropped. The packet may
+/// outlive the encoder, or a stream replaced with
+/// [`Av1Encoder::change_speed`](super::Av1Encoder::change_speed).
 pub struct Av1Packet {
     ptr: *mut EbBufferHeaderType,
-    is_headers: bool,
+    pub(crate) is_headers: bool,
+    format: Option<StreamFormat>,
+    pub(crate) has_metadata: bool,
+    pub(crate) is_overlay: bool,
+    pub(crate) is_switch: bool,
+    pub(crate) output: OutputPicture,
+    pub(crate) timebase: Option<Rational>,
+    owned: Option<OwnedBuffer>,
+    /// The library instance which owns the buffer, kept alive until the
+    /// buffer is released.
+    _library: Option<Arc<Library>>,
+}
+
+/// A copy of a buffer header, with data owned by the packet rather than the
+/// library.
+struct OwnedBuffer {
+    _header: Box<EbBufferHeaderType>,
+    _data: Vec<u8>,
 }
 
 impl std::fmt::Debug for Av1Packet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Packet")
             .field("frame_type", &self.frame_type())
+            .field("qp", &self.qp())
             .field("size", &unsafe { (*self.ptr).n_filled_len })
             .finish()
     }
@@ -47,6 +84,19 @@ impl Packet for Av1Packet {
     fn is_eos(&self) -> bool {
         unsafe { (*self.ptr).flags & EB_BUFFERFLAG_EOS != 0 }
     }
+
+    fn keyframe_reason(&self) -> Option<KeyframeReason> {
+        self.output.keyframe_reason
+    }
+
+    fn completed_pts(&self) -> Option<i64> {
+        let complete = !self.is_headers
+            && !self.is_eos()
+            && !self.as_bytes().is_empty()
+            && self.frame_type() != FrameType::AltRef;
+
+        complete.then(|| self.pts())
+    }
 }
 
 impl AsRef<[u8]> for Av1Packet {
@@ -58,6 +108,12 @@ impl AsRef<[u8]> for Av1Packet {
 impl Av1Packet {
     /// The type of frame in the output buffer.
     pub fn frame_type(&self) -> FrameType {
+        if self.is_overlay {
+            return FrameType::Overlay;
+        } else if self.is_switch {
+            return FrameType::Switch;
+        }
+
         unsafe {
             #[allow(non_upper_case_globals)]
             match (*self.ptr).pic_type {
@@ -72,27 +128,274 @@ impl Av1Packet {
         }
     }
 
-    pub(crate) fn new(p: *mut EbBufferHeaderType) -> Self {
+    /// Whether the frame can be decoded without any previously decoded
+    /// frames, so that a decoder which lost earlier packets can recover.
+    /// This is true for key frames and intra-only frames, although decoding
+    /// can only start at a key frame.
+    ///
+    /// Switch frames still depend on the reference frames, but not on any
+    /// other state from earlier frames.
+    pub fn is_independently_decodable(&self) -> bool {
+        !self.is_headers && matches!(self.frame_type(), FrameType::Key | FrameType::IntraOnly)
+    }
+
+    /// Builds the `av1C` decoder configuration record from a packet
+    /// containing a sequence header, like the stream headers returned by
+    /// [`Av1Encoder::code_headers`](super::Av1Encoder::code_headers). See
+    /// [`mux::av1c`](crate::mux::av1c).
+    pub fn to_av1c(&self) -> Result<Vec<u8>, MuxError> {
+        crate::mux::av1c(self.as_bytes())
+    }
+
+    /// Whether later frames may use the frame as a reference. A decoder
+    /// which loses a non-reference frame, like a frame in the top temporal
+    /// layer of the prediction structure, can carry on decoding the frames
+    /// after it without errors. See [`ReferenceTracker`](super::ReferenceTracker).
+    pub fn is_reference(&self) -> bool {
+        !self.is_headers
+            && !self.as_bytes().is_empty()
+            && unsafe { (*self.ptr).pic_type } != EbAv1PictureType_EB_AV1_NON_REF_PICTURE
+    }
+
+    /// The presentation timestamp of the frame.
+    pub fn pts(&self) -> i64 {
+        unsafe { (*self.ptr).pts }
+    }
+
+    /// The decode timestamp of the frame.
+    pub fn dts(&self) -> i64 {
+        unsafe { (*self.ptr).dts }
+    }
+
+    /// The presentation timestamp of the frame, in the encoder's timebase.
+    /// This is `None` for the stream headers.
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        Some(Timestamp::new(self.pts(), self.timebase?))
+    }
+
+    /// The index of the source picture, in the order pictures were sent to
+    /// the encoder. This is `None` for frames which don't correspond to a
+    /// newly sent picture, like overlay frames, and for the stream headers.
+    pub fn picture_number(&self) -> Option<u64> {
+        self.output.picture_number
+    }
+
+    /// The index of the frame in decode order, counting from the start of
+    /// the stream. Muxers can compare this with
+    /// [`Av1Packet::picture_number`] to detect reordered frames.
+    pub fn decode_order(&self) -> u64 {
+        self.output.decode_order
+    }
+
+    /// The temporal layer of the frame in the hierarchical prediction
+    /// structure, where 0 is the base layer. See
+    /// [`Av1EncoderConfig::hierarchical_levels`].
+    ///
+    /// [`Av1EncoderConfig::hierarchical_levels`]: super::Av1EncoderConfig::hierarchical_levels
+    pub fn temporal_layer(&self) -> u32 {
+        unsafe { (*self.ptr).temporal_layer_index as u32 }
+    }
+
+    /// Whether metadata OBUs were attached to the frame, using
+    /// [`Av1Encoder::send_picture_with_metadata`].
+    ///
+    /// [`Av1Encoder::send_picture_with_metadata`]: super::Av1Encoder::send_picture_with_metadata
+    pub fn has_metadata(&self) -> bool {
+        self.has_metadata
+    }
+
+    /// The QP used to encode the frame.
+    pub fn qp(&self) -> u32 {
+        unsafe { (*self.ptr).qp }
+    }
+
+    /// The sum of squared errors between the source and reconstructed luma
+    /// plane. Only available if [`Av1EncoderConfig::enable_stat_report`] was
+    /// set.
+    ///
+    /// [`Av1EncoderConfig::enable_stat_report`]: super::Av1EncoderConfig::enable_stat_report
+    pub fn luma_sse(&self) -> Option<u64> {
+        self.stat_report().then(|| unsafe { (*self.ptr).luma_sse })
+    }
+
+    /// The sum of squared errors for the Cb plane. See [`Av1Packet::luma_sse`].
+    pub fn cb_sse(&self) -> Option<u64> {
+        self.stat_report().then(|| unsafe { (*self.ptr).cb_sse })
+    }
+
+    /// The sum of squared errors for the Cr plane. See [`Av1Packet::luma_sse`].
+    pub fn cr_sse(&self) -> Option<u64> {
+        self.stat_report().then(|| unsafe { (*self.ptr).cr_sse })
+    }
+
+    /// The PSNR of the reconstructed luma plane, in decibels. Only available if
+    /// [`Av1EncoderConfig::enable_stat_report`] was set.
+    ///
+    /// [`Av1EncoderConfig::enable_stat_report`]: super::Av1EncoderConfig::enable_stat_report
+    pub fn luma_psnr(&self) -> Option<f64> {
+        let format = self.format?;
+        psnr(
+            self.luma_sse()?,
+            format.width as u64 * format.height as u64,
+            format.bit_depth,
+        )
+    }
+
+    /// The PSNR of the reconstructed Cb plane. See [`Av1Packet::luma_psnr`].
+    pub fn cb_psnr(&self) -> Option<f64> {
+        psnr(
+            self.cb_sse()?,
+            self.chroma_samples()?,
+            self.format?.bit_depth,
+        )
+    }
+
+    /// The PSNR of the reconstructed Cr plane. See [`Av1Packet::luma_psnr`].
+    pub fn cr_psnr(&self) -> Option<f64> {
+        psnr(
+            self.cr_sse()?,
+            self.chroma_samples()?,
+            self.format?.bit_depth,
+        )
+    }
+
+    /// The SSIM of the reconstructed luma plane. Only available if
+    /// [`Av1EncoderConfig::enable_stat_report`] was set.
+    ///
+    /// [`Av1EncoderConfig::enable_stat_report`]: super::Av1EncoderConfig::enable_stat_report
+    pub fn luma_ssim(&self) -> Option<f64> {
+        self.stat_report().then(|| unsafe { (*self.ptr).luma_ssim })
+    }
+
+    /// The SSIM of the reconstructed Cb plane. See [`Av1Packet::luma_ssim`].
+    pub fn cb_ssim(&self) -> Option<f64> {
+        self.stat_report().then(|| unsafe { (*self.ptr).cb_ssim })
+    }
+
+    /// The SSIM of the reconstructed Cr plane. See [`Av1Packet::luma_ssim`].
+    pub fn cr_ssim(&self) -> Option<f64> {
+        self.stat_report().then(|| unsafe { (*self.ptr).cr_ssim })
+    }
+
+    fn stat_report(&self) -> bool {
+        self.format.is_some_and(|f| f.stat_report) && !self.as_bytes().is_empty()
+    }
+
+    fn chroma_samples(&self) -> Option<u64> {
+        let format = self.format?;
+        let (w, h) = (format.width as u64, format.height as u64);
+        match format.subsampling_format {
+            SubsamplingFormat::Yuv400 => None,
+            SubsamplingFormat::Yuv420 => Some(w.div_ceil(2) * h.div_ceil(2)),
+            SubsamplingFormat::Yuv422 => Some(w.div_ceil(2) * h),
+            SubsamplingFormat::Yuv444 => Some(w * h),
+        }
+    }
+
+    pub(super) fn new(
+        p: *mut EbBufferHeaderType,
+        format: StreamFormat,
+        library: Arc<Library>,
+    ) -> Self {
         assert!(!p.is_null());
 
         Self {
             ptr: p,
             is_headers: false,
+            format: Some(format),
+            has_metadata: false,
+            is_overlay: false,
+            is_switch: false,
+            output: OutputPicture::default(),
+            timebase: None,
+            owned: None,
+            _library: Some(library),
+        }
+    }
+
+    /// Creates a packet with a copy of the metadata of this one, and the
+    /// given data. The new packet doesn't hold on to a library buffer.
+    pub(crate) fn with_data(&self, mut data: Vec<u8>) -> Self {
+        let mut header = Box::new(unsafe { *self.ptr });
+        header.p_buffer = data.as_mut_ptr();
+        header.n_filled_len = data.len() as u32;
+        header.n_alloc_len = data.len() as u32;
+        header.p_app_private = std::ptr::null_mut();
+        header.wrapper_ptr = std::ptr::null_mut();
+        header.metadata = std::ptr::null_mut();
+
+        Self {
+            ptr: &mut *header,
+            is_headers: self.is_headers,
+            format: self.format,
+            has_metadata: self.has_metadata,
+            is_overlay: self.is_overlay,
+            is_switch: self.is_switch,
+            output: self.output,
+            timebase: self.timebase,
+            owned: Some(OwnedBuffer {
+                _header: header,
+                _data: data,
+            }),
+            _library: None,
+        }
+    }
+
+    /// Like [`Av1Packet::with_data`], for a show-existing frame which
+    /// displays the frame with the given pts again.
+    pub(crate) fn show_existing(&self, data: Vec<u8>, pts: i64) -> Self {
+        let mut packet = self.with_data(data);
+        unsafe {
+            (*packet.ptr).pts = pts;
+            (*packet.ptr).pic_type = EbAv1PictureType_EB_AV1_SHOW_EXISTING_PICTURE;
         }
+
+        packet.has_metadata = false;
+        packet.is_overlay = false;
+        packet.is_switch = false;
+        packet.output = OutputPicture::default();
+        packet
     }
 
-    pub(crate) fn new_headers(p: *mut EbBufferHeaderType) -> Self {
+    pub(super) fn new_headers(p: *mut EbBufferHeaderType, library: Arc<Library>) -> Self {
         assert!(!p.is_null());
 
         Self {
             ptr: p,
             is_headers: true,
+            format: None,
+            has_metadata: false,
+            is_overlay: false,
+            is_switch: false,
+            output: OutputPicture::default(),
+            timebase: None,
+            owned: None,
+            _library: Some(library),
         }
     }
 }
 
+fn psnr(sse: u64, samples: u64, bit_depth: u32) -> Option<f64> {
+    if samples == 0 {
+        return None;
+    } else if sse == 0 {
+        return Some(MAX_PSNR);
+    }
+
+    let peak = ((1u64 << bit_depth) - 1) as f64;
+    let psnr = 10.0 * (peak * peak * samples as f64 / sse as f64).log10();
+    Some(psnr.min(MAX_PSNR))
+}
+
+// Releasing the buffer is thread-safe.
+unsafe impl Send for Av1Packet {}
+
 impl Drop for Av1Packet {
     fn drop(&mut self) {
+        if self.owned.is_some() {
+            return;
+        }
+
         unsafe {
             if self.is_headers {
                 svt_av1_enc_stream_header_release(self.ptr);
@@ -0,0 +1,312 @@
+use std::time::{Duration, Instant};
+
+use crate::Stats;
+
+/// The settings a [`RealtimeController`] adjusts to trade quality for
+/// encoding speed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Speed {
+    /// The preset. Higher presets are faster.
+    pub preset: i8,
+    /// Whether fast-decode mode is enabled.
+    pub fast_decode: bool,
+}
+
+/// Holds a real-time encode at a target framerate, by stepping the preset
+/// (and optionally fast-decode mode) as the achieved framerate changes.
+///
+/// Unlike SVT-HEVC, SVT-AV1 has no built-in speed control (see
+/// `HevcEncoderConfig::realtime`), so the controller runs on the application
+/// side. Pass it the encoder's [`Stats`] regularly with
+/// [`RealtimeController::update`], and apply any new [`Speed`] it returns
+/// with [`Av1Encoder::change_speed`](super::Av1Encoder::change_speed).
+///
+/// The encoder is falling behind if it completes fewer pictures than the
+/// target framerate, minus a tolerance, while pictures pile up inside it.
+/// The controller then steps to the next faster speed right away. Once the
+/// encoder has kept up for the recovery period, it steps back to the next
+/// slower speed. If that immediately falls behind again, the recovery period
+/// doubles, up to eight times its initial length, so that the speed doesn't
+/// oscillate.
+#[derive(Debug, Copy, Clone)]
+pub struct RealtimeController {
+    target_fps: f64,
+    tolerance: f64,
+    interval: Duration,
+    recovery: Duration,
+    min_preset: i8,
+    max_preset: i8,
+    allow_fast_decode: bool,
+    speed: Speed,
+    backoff: u32,
+    last_sample: Option<(Instant, Stats)>,
+    achieved_fps: Option<f64>,
+    keeping_up_since: Option<Instant>,
+    stepped_slower_at: Option<Instant>,
+}
+
+/// The largest multiple of the recovery period.
+const MAX_BACKOFF: u32 = 8;
+
+impl RealtimeController {
+    /// Creates a controller for the given target framerate, starting at
+    /// `speed`. By default, it steps between the starting preset and preset
+    /// 13, and never enables fast-decode mode.
+    pub fn new(target_fps: f64, speed: Speed) -> Self {
+        Self {
+            target_fps,
+            tolerance: 0.05,
+            interval: Duration::from_secs(1),
+            recovery: Duration::from_secs(10),
+            min_preset: speed.preset,
+            max_preset: speed.preset.max(13),
+            allow_fast_decode: false,
+            speed,
+            backoff: 1,
+            last_sample: None,
+            achieved_fps: None,
+            keeping_up_since: None,
+            stepped_slower_at: None,
+        }
+    }
+
+    /// Sets the range of presets the controller steps between. The starting
+    /// preset is clamped to the range.
+    pub fn preset_range(mut self, min: i8, max: i8) -> Self {
+        self.min_preset = min.min(max);
+        self.max_preset = max;
+        self.speed.preset = self.speed.preset.clamp(self.min_preset, max);
+        self
+    }
+
+    /// Allows the controller to enable fast-decode mode, as a last step once
+    /// the fastest preset isn't enough.
+    pub fn allow_fast_decode(mut self, v: bool) -> Self {
+        self.allow_fast_decode = v;
+        self
+    }
+
+    /// Sets the fraction of the target framerate the encoder may fall short
+    /// by before the controller steps faster. The default is 0.05.
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets how often the achieved framerate is measured. The default is
+    /// one second.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets how long the encoder must keep up before the controller steps
+    /// slower. The default is ten seconds.
+    pub fn recovery(mut self, recovery: Duration) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// The current speed.
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// The framerate achieved over the last interval, in pictures per
+    /// second, once one has been measured.
+    pub fn achieved_fps(&self) -> Option<f64> {
+        self.achieved_fps
+    }
+
+    /// Records the encoder's stats at time `now`. Returns the speed to
+    /// switch to, if it should change.
+    pub fn update(&mut self, stats: &Stats, now: Instant) -> Option<Speed> {
+        let Some((last_time, last_stats)) = self.last_sample else {
+            self.last_sample = Some((now, *stats));
+            return None;
+        };
+
+        let elapsed = now.saturating_duration_since(last_time);
+        if elapsed < self.interval || elapsed.is_zero() {
+            return None;
+        }
+
+        let completed = stats
+            .pictures_completed
+            .saturating_sub(last_stats.pictures_completed);
+        let fps = completed as f64 / elapsed.as_secs_f64();
+        self.achieved_fps = Some(fps);
+        self.last_sample = Some((now, *stats));
+
+        let slow = fps < self.target_fps * (1.0 - self.tolerance);
+        if slow && stats.pending_frames() > last_stats.pending_frames() {
+            self.keeping_up_since = None;
+
+            // The last step slower didn't hold, so wait longer next time.
+            if self
+                .stepped_slower_at
+                .take()
+                .is_some_and(|at| now.saturating_duration_since(at) < self.recovery_period())
+            {
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+            }
+
+            return self.step(self.faster());
+        }
+
+        if slow {
+            return None;
+        }
+
+        let since = *self.keeping_up_since.get_or_insert(now);
+        if now.saturating_duration_since(since) < self.recovery_period() {
+            return None;
+        }
+
+        self.keeping_up_since = None;
+        let slower = self.slower();
+        if slower.is_some() {
+            self.stepped_slower_at = Some(now);
+        }
+
+        self.step(slower)
+    }
+
+    fn recovery_period(&self) -> Duration {
+        self.recovery * self.backoff
+    }
+
+    fn step(&mut self, speed: Option<Speed>) -> Option<Speed> {
+        let speed = speed?;
+        self.speed = speed;
+        Some(speed)
+    }
+
+    fn faster(&self) -> Option<Speed> {
+        let Speed {
+            preset,
+            fast_decode,
+        } = self.speed;
+        if preset < self.max_preset {
+            Some(Speed {
+                preset: preset + 1,
+                fast_decode,
+            })
+        } else if self.allow_fast_decode && !fast_decode {
+            Some(Speed {
+                preset,
+                fast_decode: true,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn slower(&self) -> Option<Speed> {
+        let Speed {
+            preset,
+            fast_decode,
+        } = self.speed;
+        if fast_decode && self.allow_fast_decode {
+            Some(Speed {
+                preset,
+                fast_decode: false,
+            })
+        } else if preset > self.min_preset {
+            Some(Speed {
+                preset: preset - 1,
+                fast_decode,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(submitted: u64, completed: u64) -> Stats {
+        Stats {
+            frames_submitted: submitted,
+            pictures_completed: completed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn controller() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut controller = RealtimeController::new(
+            30.0,
+            Speed {
+                preset: 10,
+                fast_decode: false,
+            },
+        )
+        .preset_range(10, 11)
+        .allow_fast_decode(true)
+        .recovery(Duration::from_secs(2));
+
+        assert_eq!(controller.update(&stats(0, 0), at(0)), None);
+
+        // Falling behind.
+        let step = controller.update(&stats(30, 20), at(1));
+        assert_eq!(controller.achieved_fps(), Some(20.0));
+        assert_eq!(step.map(|s| s.preset), Some(11));
+        let step = controller.update(&stats(60, 40), at(2));
+        assert_eq!(step.map(|s| s.fast_decode), Some(true));
+        assert_eq!(controller.update(&stats(90, 60), at(3)), None);
+
+        // Keeping up, for the recovery period.
+        assert_eq!(controller.update(&stats(120, 90), at(4)), None);
+        assert_eq!(controller.update(&stats(150, 120), at(5)), None);
+        let step = controller.update(&stats(180, 150), at(6));
+        assert_eq!(step.map(|s| s.fast_decode), Some(false));
+
+        // The slower speed doesn't hold, so the recovery period doubles.
+        let step = controller.update(&stats(210, 170), at(7));
+        assert_eq!(step.map(|s| s.fast_decode), Some(true));
+        for secs in 8..12 {
+            let n = secs - 7;
+            assert_eq!(
+                controller.update(&stats(210 + n * 30, 170 + n * 30), at(secs)),
+                None
+            );
+        }
+
+        let step = controller.update(&stats(360, 320), at(12));
+        assert_eq!(
+            step,
+            Some(Speed {
+                preset: 11,
+                fast_decode: false
+            })
+        );
+    }
+
+    #[test]
+    fn slow_input() {
+        let start = Instant::now();
+        let mut controller = RealtimeController::new(
+            30.0,
+            Speed {
+                preset: 8,
+                fast_decode: false,
+            },
+        );
+
+        // The input arrives slower than the target, but the encoder keeps
+        // up with it.
+        controller.update(&stats(2, 0), start);
+        for secs in 1..5 {
+            let n = secs * 10;
+            let now = start + Duration::from_secs(secs);
+            assert_eq!(controller.update(&stats(n + 2, n), now), None);
+        }
+
+        assert_eq!(controller.speed().preset, 8);
+    }
+}
@@ -0,0 +1,111 @@
+use svt_av1_sys::*;
+
+use crate::{Picture, Plane, SubsamplingFormat};
+
+use super::StreamFormat;
+
+/// A reconstructed frame, as it will be seen by a decoder. Retrieved with
+/// [`Av1Encoder::get_recon`](super::Av1Encoder::get_recon).
+///
+/// The picture implements [`Picture`], so it can be compared with the
+/// source picture directly. High bit depth frames are stored with two
+/// bytes per sample.
+pub struct ReconPicture {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    uv_width: u32,
+    uv_height: u32,
+    bytes_per_sample: u32,
+    pts: i64,
+}
+
+impl std::fmt::Debug for ReconPicture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconPicture")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pts", &self.pts)
+            .finish()
+    }
+}
+
+impl ReconPicture {
+    /// Allocates a buffer large enough to hold a frame of the given format,
+    /// and a header pointing to it.
+    pub(crate) fn alloc(format: StreamFormat) -> Self {
+        let (uv_width, uv_height) = match format.subsampling_format {
+            SubsamplingFormat::Yuv400 => (0, 0),
+            SubsamplingFormat::Yuv420 => (format.width.div_ceil(2), format.height.div_ceil(2)),
+            SubsamplingFormat::Yuv422 => (format.width.div_ceil(2), format.height),
+            SubsamplingFormat::Yuv444 => (format.width, format.height),
+        };
+
+        let bytes_per_sample = if format.bit_depth > 8 { 2 } else { 1 };
+        let samples = format.width * format.height + 2 * uv_width * uv_height;
+
+        ReconPicture {
+            data: vec![0; (samples * bytes_per_sample) as usize],
+            width: format.width,
+            height: format.height,
+            uv_width,
+            uv_height,
+            bytes_per_sample,
+            pts: 0,
+        }
+    }
+
+    pub(crate) fn header(&mut self) -> EbBufferHeaderType {
+        EbBufferHeaderType {
+            size: size_of::<EbBufferHeaderType>() as u32,
+            p_buffer: self.data.as_mut_ptr(),
+            n_alloc_len: self.data.len() as u32,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_pts(&mut self, pts: i64) {
+        self.pts = pts;
+    }
+
+    /// The presentation timestamp of the frame.
+    pub fn pts(&self) -> i64 {
+        self.pts
+    }
+
+    fn plane_range(&self, plane: Plane) -> std::ops::Range<usize> {
+        let y_len = (self.width * self.height * self.bytes_per_sample) as usize;
+        let uv_len = (self.uv_width * self.uv_height * self.bytes_per_sample) as usize;
+
+        match plane {
+            Plane::Y => 0..y_len,
+            Plane::U => y_len..(y_len + uv_len),
+            Plane::V => (y_len + uv_len)..(y_len + 2 * uv_len),
+        }
+    }
+}
+
+impl Picture for ReconPicture {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn as_slice(&self, plane: Plane) -> &[u8] {
+        &self.data[self.plane_range(plane)]
+    }
+
+    fn stride(&self, plane: Plane) -> u32 {
+        match plane {
+            Plane::Y => self.width * self.bytes_per_sample,
+            Plane::U | Plane::V => self.uv_width * self.bytes_per_sample,
+        }
+    }
+
+    fn bytes_per_sample(&self) -> u32 {
+        self.bytes_per_sample
+    }
+}
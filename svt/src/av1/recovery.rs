@@ -0,0 +1,146 @@
+use crate::FrameTypeRequest;
+
+/// The kind of frame [`Av1Encoder::request_recovery`] codes, so that a
+/// receiver which lost packets can resume decoding.
+///
+/// Both kinds are fully intra-coded, so the recovery frame itself costs
+/// about the same either way. They differ in what comes after it:
+///
+/// - A keyframe resets every reference buffer, so the receiver is
+///   guaranteed to recover, but the frames after it can only be predicted
+///   from the keyframe, and cost more until the references build up again.
+/// - An intra-only frame leaves the other reference buffers in place, so
+///   the frames after it can keep predicting from older frames, like the
+///   last alt-ref frame, and stay cheaper. If the loss corrupted one of
+///   those references at the receiver, though, errors can carry on until
+///   the next keyframe. Decoding also can't start at an intra-only frame,
+///   so a receiver which joins mid-stream still needs a keyframe.
+///
+/// SVT-AV1 doesn't expose reference control, so there's no option to code
+/// an inter frame against a long-term reference the receiver is known to
+/// have.
+///
+/// [`Av1Encoder::request_recovery`]: super::Av1Encoder::request_recovery
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RecoveryFrame {
+    /// A keyframe.
+    #[default]
+    Keyframe,
+    /// An intra-only frame.
+    IntraOnly,
+}
+
+impl From<RecoveryFrame> for FrameTypeRequest {
+    fn from(frame: RecoveryFrame) -> Self {
+        match frame {
+            RecoveryFrame::Keyframe => FrameTypeRequest::Key,
+            RecoveryFrame::IntraOnly => FrameTypeRequest::IntraOnly,
+        }
+    }
+}
+
+/// Tracks recovery requests, and the intra frames which satisfy them.
+#[derive(Debug, Default)]
+pub(crate) struct RecoveryTracker {
+    frame: RecoveryFrame,
+    /// The last good pts of the outstanding request.
+    pending: Option<i64>,
+    /// The pts of the most recent intra frame submitted or output.
+    last_intra: Option<i64>,
+}
+
+impl RecoveryTracker {
+    pub(crate) fn new(frame: RecoveryFrame) -> Self {
+        Self {
+            frame,
+            ..Default::default()
+        }
+    }
+
+    /// Requests a recovery frame, unless an intra frame after
+    /// `last_good_pts` is already on its way. Returns whether one was
+    /// requested.
+    pub(crate) fn request(&mut self, last_good_pts: i64) -> bool {
+        if self.pending.is_some() || self.last_intra.is_some_and(|pts| pts > last_good_pts) {
+            return false;
+        }
+
+        self.pending = Some(last_good_pts);
+        true
+    }
+
+    /// The frame type to submit the next picture with.
+    pub(crate) fn frame_type(&self, requested: FrameTypeRequest) -> FrameTypeRequest {
+        match requested {
+            FrameTypeRequest::Auto if self.pending.is_some() => self.frame.into(),
+            v => v,
+        }
+    }
+
+    /// Records a picture, once it has been submitted with `frame_type`.
+    pub(crate) fn submitted(&mut self, pts: i64, frame_type: FrameTypeRequest) {
+        if matches!(
+            frame_type,
+            FrameTypeRequest::Key | FrameTypeRequest::IntraOnly
+        ) {
+            self.pending = None;
+            self.record_intra(pts);
+        }
+    }
+
+    /// Records an intra frame, including ones the encoder inserted on its
+    /// own, which also satisfy an outstanding request.
+    pub(crate) fn record_intra(&mut self, pts: i64) {
+        if self.last_intra.is_none_or(|last| last < pts) {
+            self.last_intra = Some(pts);
+        }
+
+        if self.pending.is_some_and(|good| good < pts) {
+            self.pending = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker() {
+        let mut tracker = RecoveryTracker::new(RecoveryFrame::IntraOnly);
+        assert_eq!(
+            tracker.frame_type(FrameTypeRequest::Auto),
+            FrameTypeRequest::Auto
+        );
+
+        // Repeated requests are coalesced.
+        assert!(tracker.request(3));
+        assert!(!tracker.request(4));
+        assert_eq!(
+            tracker.frame_type(FrameTypeRequest::Auto),
+            FrameTypeRequest::IntraOnly
+        );
+        assert_eq!(
+            tracker.frame_type(FrameTypeRequest::Key),
+            FrameTypeRequest::Key
+        );
+
+        tracker.submitted(6, FrameTypeRequest::IntraOnly);
+        assert_eq!(
+            tracker.frame_type(FrameTypeRequest::Auto),
+            FrameTypeRequest::Auto
+        );
+
+        // The intra frame at 6 already covers losses before it.
+        assert!(!tracker.request(5));
+        assert!(tracker.request(6));
+
+        // So does a keyframe the encoder inserted.
+        tracker.record_intra(7);
+        assert_eq!(
+            tracker.frame_type(FrameTypeRequest::Auto),
+            FrameTypeRequest::Auto
+        );
+        assert!(!tracker.request(6));
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+use crate::Packet;
+
+use super::{Av1Packet, FrameType};
+
+/// What an encoder needs to do after a receiver lost a frame, as decided by
+/// a [`ReferenceTracker`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LossRecovery {
+    /// Nothing: no frame decoded after the loss depends on the lost one,
+    /// either because it wasn't a reference frame, or because a keyframe
+    /// has been coded since.
+    None,
+    /// The frames after the loss can't be decoded correctly until the next
+    /// keyframe, which should be forced.
+    Keyframe,
+}
+
+/// Tracks which of the frames output by an [`Av1Encoder`](super::Av1Encoder)
+/// are used as references, to decide how to recover when a receiver reports
+/// a lost frame, for example with an RTCP NACK the sender couldn't satisfy.
+///
+/// SVT-AV1 doesn't expose per-frame control of the reference buffers, so
+/// there's no way to mark a frame as a long-term reference, or to code the
+/// next frame against the last one the receiver acknowledged. What the
+/// encoder can do is avoid unnecessary keyframes: with a hierarchical
+/// prediction structure, the frames in the top temporal layer aren't
+/// referenced at all, and losing one doesn't affect the frames after it.
+/// Otherwise, the receiver needs a keyframe, which a
+/// [`KeyframeGuard`](crate::KeyframeGuard) can rate-limit, or another
+/// recovery frame from
+/// [`Av1Encoder::request_recovery`](super::Av1Encoder::request_recovery). For receivers
+/// which can't wait that long, [`Av1EncoderConfig::switch_frame_insertion`]
+/// adds regular switch frames, which only reference frames in the base
+/// layer.
+///
+/// [`Av1EncoderConfig::switch_frame_insertion`]: super::Av1EncoderConfig::switch_frame_insertion
+///
+/// # Example
+///
+/// ```
+/// # use svt::{Encoder, KeyframeGuard};
+/// # use svt::av1::{Av1Encoder, LossRecovery, ReferenceTracker};
+/// # fn example(guard: KeyframeGuard<Av1Encoder>, lost_pts: i64) -> Result<(), svt::Error> {
+/// let mut tracker = ReferenceTracker::new(256);
+/// while let Some(packet) = guard.get_packet(false)? {
+///     tracker.observe(&packet);
+///     // Send the packet.
+/// }
+///
+/// // When a receiver reports a lost frame:
+/// if tracker.lost(lost_pts) == LossRecovery::Keyframe {
+///     guard.request_keyframe();
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReferenceTracker {
+    capacity: usize,
+    frames: VecDeque<Frame>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    pts: i64,
+    is_reference: bool,
+    is_keyframe: bool,
+}
+
+impl ReferenceTracker {
+    /// Creates a tracker which remembers the last `capacity` frames, in
+    /// decode order. Losses of older frames are treated as losses of
+    /// reference frames.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            frames: VecDeque::new(),
+        }
+    }
+
+    /// Records a packet output by the encoder. Packets must be observed in
+    /// the order the encoder outputs them.
+    pub fn observe(&mut self, packet: &Av1Packet) {
+        if packet.is_headers || packet.is_eos() || packet.as_bytes().is_empty() {
+            return;
+        }
+
+        self.record(
+            packet.pts(),
+            packet.is_reference(),
+            packet.frame_type() == FrameType::Key,
+        );
+    }
+
+    fn record(&mut self, pts: i64, is_reference: bool, is_keyframe: bool) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(Frame {
+            pts,
+            is_reference,
+            is_keyframe,
+        });
+    }
+
+    /// Decides how to recover from the loss of the frame with the given pts.
+    pub fn lost(&self, pts: i64) -> LossRecovery {
+        // An alt-ref frame and its overlay share a pts, so every frame with
+        // the pts counts, back to the most recent keyframe, which resets the
+        // references.
+        let mut found = false;
+        for frame in self.frames.iter().rev() {
+            if frame.pts == pts {
+                if frame.is_reference {
+                    return LossRecovery::Keyframe;
+                }
+
+                found = true;
+            } else if frame.is_keyframe {
+                found = found || self.frames.iter().any(|f| f.pts == pts);
+                break;
+            }
+        }
+
+        if found {
+            LossRecovery::None
+        } else {
+            LossRecovery::Keyframe
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lost() {
+        let mut tracker = ReferenceTracker::new(8);
+
+        // A keyframe, then a two-level hierarchy in low-delay order.
+        tracker.record(0, true, true);
+        tracker.record(1, false, false);
+        tracker.record(2, true, false);
+        tracker.record(3, false, false);
+        tracker.record(4, true, false);
+
+        assert_eq!(tracker.lost(1), LossRecovery::None);
+        assert_eq!(tracker.lost(2), LossRecovery::Keyframe);
+        assert_eq!(tracker.lost(0), LossRecovery::Keyframe);
+        assert_eq!(tracker.lost(100), LossRecovery::Keyframe);
+
+        // A keyframe makes earlier losses harmless.
+        tracker.record(5, true, true);
+        tracker.record(6, false, false);
+        assert_eq!(tracker.lost(2), LossRecovery::None);
+        assert_eq!(tracker.lost(5), LossRecovery::Keyframe);
+        assert_eq!(tracker.lost(6), LossRecovery::None);
+
+        // Forgotten frames need a keyframe.
+        for pts in 7..14 {
+            tracker.record(pts, true, false);
+        }
+        assert_eq!(tracker.lost(3), LossRecovery::Keyframe);
+    }
+}
@@ -0,0 +1,213 @@
+/// Target bitrates for parts of a stream, for events with a known running
+/// order, like an intro and outro at a low bitrate around the main content
+/// at a high one.
+///
+/// Each entry sets the target bitrate, in bits per second, from the picture
+/// with the given pts onward. Pictures before the first entry keep the
+/// bitrate the encoder was configured with. Set the schedule with
+/// [`Av1EncoderConfig::rate_schedule`](super::Av1EncoderConfig::rate_schedule),
+/// and the encoder changes the bitrate as the pictures are sent.
+///
+/// ```
+/// # use svt::av1::RateSchedule;
+/// let schedule = RateSchedule::new()
+///     .at(0, 1_000_000)
+///     .at(900, 6_000_000)
+///     .at(54_000, 1_000_000);
+///
+/// assert_eq!(schedule.bitrate_at(1_000), Some(6_000_000));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RateSchedule {
+    entries: Vec<(i64, u32)>,
+}
+
+impl RateSchedule {
+    /// Creates an empty schedule.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an entry, which sets the target bitrate from `pts` onward. An
+    /// existing entry at the same pts is replaced.
+    pub fn at(mut self, pts: i64, bitrate: u32) -> Self {
+        match self.entries.binary_search_by_key(&pts, |&(pts, _)| pts) {
+            Ok(i) => self.entries[i].1 = bitrate,
+            Err(i) => self.entries.insert(i, (pts, bitrate)),
+        }
+
+        self
+    }
+
+    /// The scheduled bitrate for the picture with the given pts, or `None`
+    /// if it comes before the first entry.
+    pub fn bitrate_at(&self, pts: i64) -> Option<u32> {
+        let i = self.entries.partition_point(|&(start, _)| start <= pts);
+        i.checked_sub(1).map(|i| self.entries[i].1)
+    }
+
+    /// The entries, in order of pts.
+    pub fn entries(&self) -> &[(i64, u32)] {
+        &self.entries
+    }
+}
+
+impl FromIterator<(i64, u32)> for RateSchedule {
+    fn from_iter<T: IntoIterator<Item = (i64, u32)>>(iter: T) -> Self {
+        iter.into_iter()
+            .fold(RateSchedule::new(), |schedule, (pts, bitrate)| {
+                schedule.at(pts, bitrate)
+            })
+    }
+}
+
+/// Tracks the target bitrate of a running encoder, and decides when to
+/// change it.
+#[derive(Debug)]
+pub(crate) struct RateTracker {
+    current: u32,
+    requested: Option<u32>,
+    schedule: Option<RateSchedule>,
+    last_entry: Option<usize>,
+}
+
+/// The state of a [`RateTracker`], saved so that it can be restored if a
+/// picture fails to submit.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RateCheckpoint {
+    current: u32,
+    requested: Option<u32>,
+    last_entry: Option<usize>,
+}
+
+impl RateTracker {
+    pub(crate) fn new(bitrate: u32, schedule: Option<RateSchedule>) -> Self {
+        Self {
+            current: bitrate,
+            requested: None,
+            schedule,
+            last_entry: None,
+        }
+    }
+
+    /// The bitrate the encoder was last set to.
+    pub(crate) fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Requests a change with the next picture.
+    pub(crate) fn request(&mut self, bitrate: u32) {
+        self.requested = Some(bitrate);
+    }
+
+    /// Returns the bitrate to switch to with the picture with the given pts,
+    /// if it changes. A scheduled change applies once, when its entry is
+    /// reached, so that a requested change holds until the next entry.
+    pub(crate) fn next(&mut self, pts: i64) -> Option<u32> {
+        let scheduled = self.schedule.as_ref().and_then(|schedule| {
+            let entry = schedule
+                .entries
+                .partition_point(|&(start, _)| start <= pts)
+                .checked_sub(1)?;
+            if self.last_entry == Some(entry) {
+                return None;
+            }
+
+            self.last_entry = Some(entry);
+            Some(schedule.entries[entry].1)
+        });
+
+        let bitrate = self.requested.take().or(scheduled)?;
+        if bitrate == self.current {
+            return None;
+        }
+
+        self.current = bitrate;
+        Some(bitrate)
+    }
+
+    /// Saves the current state. See [`RateTracker::restore`].
+    pub(crate) fn checkpoint(&self) -> RateCheckpoint {
+        RateCheckpoint {
+            current: self.current,
+            requested: self.requested,
+            last_entry: self.last_entry,
+        }
+    }
+
+    /// Reverts to a checkpoint taken before a picture which failed to
+    /// submit, so that any change applies to the next picture instead. A
+    /// change requested in the meantime takes precedence.
+    pub(crate) fn restore(&mut self, checkpoint: RateCheckpoint) {
+        self.current = checkpoint.current;
+        self.requested = self.requested.or(checkpoint.requested);
+        self.last_entry = checkpoint.last_entry;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker() {
+        let schedule = RateSchedule::new().at(2, 1_000).at(4, 2_000);
+        let mut tracker = RateTracker::new(1_000, Some(schedule));
+
+        let changes: Vec<_> = (0..6)
+            .map(|pts| {
+                if pts == 5 {
+                    tracker.request(500);
+                }
+
+                tracker.next(pts)
+            })
+            .collect();
+        assert_eq!(changes, [None, None, None, None, Some(2_000), Some(500)]);
+
+        // The request holds until the next entry.
+        assert_eq!(tracker.next(6), None);
+    }
+
+    #[test]
+    fn restore() {
+        let schedule = RateSchedule::new().at(2, 2_000);
+        let mut tracker = RateTracker::new(1_000, Some(schedule));
+
+        // A scheduled change is retried with the next picture.
+        let checkpoint = tracker.checkpoint();
+        assert_eq!(tracker.next(2), Some(2_000));
+        tracker.restore(checkpoint);
+        assert_eq!(tracker.current(), 1_000);
+        assert_eq!(tracker.next(3), Some(2_000));
+
+        // So is a requested one.
+        tracker.request(500);
+        let checkpoint = tracker.checkpoint();
+        assert_eq!(tracker.next(4), Some(500));
+        tracker.restore(checkpoint);
+        assert_eq!(tracker.next(5), Some(500));
+
+        // A newer request wins over the restored one.
+        tracker.request(700);
+        let checkpoint = tracker.checkpoint();
+        assert_eq!(tracker.next(6), Some(700));
+        tracker.request(800);
+        tracker.restore(checkpoint);
+        assert_eq!(tracker.next(7), Some(800));
+    }
+
+    #[test]
+    fn bitrate_at() {
+        let schedule: RateSchedule = [(100, 3_000), (10, 1_000), (50, 2_000), (100, 4_000)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(schedule.entries(), [(10, 1_000), (50, 2_000), (100, 4_000)]);
+        assert_eq!(schedule.bitrate_at(0), None);
+        assert_eq!(schedule.bitrate_at(10), Some(1_000));
+        assert_eq!(schedule.bitrate_at(99), Some(2_000));
+        assert_eq!(schedule.bitrate_at(1_000), Some(4_000));
+        assert_eq!(RateSchedule::new().bitrate_at(0), None);
+    }
+}
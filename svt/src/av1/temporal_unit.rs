@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+
+use crate::obu::{obus, ObuType};
+use crate::Packet;
+
+use super::{Av1Packet, FrameType};
+
+/// Regroups the packets output by the library into temporal units, for
+/// [`OutputMode::TemporalUnit`](super::OutputMode::TemporalUnit).
+///
+/// The library outputs a hidden alt-ref frame in a packet of its own, which
+/// starts the temporal unit of the next shown frame, and may append a
+/// show-existing frame, which is a temporal unit of its own, to a packet.
+#[derive(Debug, Default)]
+pub(crate) struct TemporalUnits {
+    /// Hidden frames waiting for the shown frame of their temporal unit.
+    pending: Vec<Av1Packet>,
+    /// The pts of the last hidden alt-ref frame, which a show-existing frame
+    /// displays.
+    alt_ref_pts: Option<i64>,
+    ready: VecDeque<Av1Packet>,
+}
+
+impl TemporalUnits {
+    /// The next complete temporal unit, or other packet.
+    pub(crate) fn pop(&mut self) -> Option<Av1Packet> {
+        self.ready.pop_front()
+    }
+
+    /// Adds a packet output by the library.
+    pub(crate) fn push(&mut self, packet: Av1Packet) {
+        if packet.is_headers || packet.is_eos() || packet.as_bytes().is_empty() {
+            // A hidden frame should never be left over at the end of the
+            // stream, but keep it rather than dropping it.
+            self.ready.extend(self.pending.drain(..));
+            self.ready.push_back(packet);
+            return;
+        }
+
+        if packet.frame_type() == FrameType::AltRef {
+            self.alt_ref_pts = Some(packet.pts());
+            self.pending.push(packet);
+            return;
+        }
+
+        let units = split(packet.as_bytes());
+        if self.pending.is_empty() && units.len() <= 1 {
+            self.ready.push_back(packet);
+            return;
+        }
+
+        let mut units = units.into_iter();
+        let mut data: Vec<u8> = self
+            .pending
+            .drain(..)
+            .flat_map(|p| p.as_bytes().to_vec())
+            .collect();
+        data.extend_from_slice(units.next().unwrap_or_default());
+        self.ready.push_back(packet.with_data(data));
+
+        for unit in units {
+            let pts = self.alt_ref_pts.take().unwrap_or(packet.pts());
+            self.ready
+                .push_back(packet.show_existing(unit.to_vec(), pts));
+        }
+    }
+}
+
+/// Splits data into temporal units, at each temporal delimiter. Data which
+/// can't be parsed is left in one piece.
+fn split(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = Vec::new();
+    for obu in obus(data) {
+        let Ok(obu) = obu else {
+            return vec![data];
+        };
+
+        let offset = obu.header().as_ptr() as usize - data.as_ptr() as usize;
+        if obu.obu_type() == ObuType::TemporalDelimiter && offset > 0 {
+            starts.push(offset);
+        }
+    }
+
+    let mut units = Vec::with_capacity(starts.len() + 1);
+    let mut last = 0;
+    for start in starts {
+        units.push(&data[last..start]);
+        last = start;
+    }
+
+    units.push(&data[last..]);
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TD: [u8; 2] = [0x12, 0x00];
+
+    fn frame(byte: u8) -> [u8; 3] {
+        [0x32, 0x01, byte]
+    }
+
+    #[test]
+    fn split_units() {
+        let one = [TD.as_slice(), &frame(1)].concat();
+        assert_eq!(split(&one), [one.as_slice()]);
+
+        // A frame followed by a show-existing frame.
+        let two = [TD.as_slice(), &frame(1), &TD, &frame(2)].concat();
+        assert_eq!(split(&two), [&two[..5], &two[5..]]);
+
+        // A hidden frame and the shown frame in one temporal unit.
+        let joined = [TD.as_slice(), &frame(1), &frame(2)].concat();
+        assert_eq!(split(&joined), [joined.as_slice()]);
+
+        let invalid = [0x80, 0x00];
+        assert_eq!(split(&invalid), [invalid.as_slice()]);
+        assert_eq!(split(&[]), [&[] as &[u8]]);
+    }
+}
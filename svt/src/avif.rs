@@ -0,0 +1,527 @@
+//! Still image encoding to AVIF.
+//!
+//! [`AvifEncoder`] converts an RGB image to YUV, encodes it as a single AV1
+//! keyframe in still-picture mode, and wraps the result in an AVIF
+//! container:
+//!
+//! ```no_run
+//! # fn example(rgb: &[u8]) -> Result<(), svt::Error> {
+//! use svt::avif::AvifEncoder;
+//! use svt::Av1Qp;
+//!
+//! let avif = AvifEncoder::default()
+//!     .preset(6)
+//!     .crf(Av1Qp::new(28)?)
+//!     .encode_rgb(rgb, 640, 480)?;
+//! std::fs::write("out.avif", avif).unwrap();
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The image is converted with the BT.601 matrix coefficients at full range,
+//! and tagged with BT.709 primaries and the sRGB transfer function, which
+//! matches the way most decoders display untagged RGB. Alpha channels are
+//! discarded.
+//!
+//! With the `image-interop` feature, [`AvifEncoder::encode_image`] takes an
+//! [`image::DynamicImage`] directly.
+
+use crate::av1::{Av1EncoderConfig, RateControlMode};
+use crate::obu::{obus, write_leb128, ObuType, SequenceHeader};
+use crate::{
+    Av1Qp, ColorDescription, ColorRange, Error, LibraryError, Packet, Picture, Plane,
+    SubsamplingFormat, YUVBuffer, YUVBuffer16,
+};
+
+/// The color description the converted pictures are tagged with: BT.709
+/// primaries, the sRGB transfer function, and BT.601 matrix coefficients.
+const PRIMARIES: u16 = 1;
+const TRANSFER_CHARACTERISTICS: u16 = 13;
+const MATRIX_COEFFICIENTS: u16 = 6;
+
+/// The item ID of the image in the container.
+const ITEM_ID: u16 = 1;
+
+/// Encodes RGB images to AVIF.
+#[derive(Debug, Copy, Clone)]
+pub struct AvifEncoder {
+    preset: i8,
+    crf: Av1Qp,
+    bit_depth: u32,
+    subsampling_format: SubsamplingFormat,
+}
+
+impl Default for AvifEncoder {
+    fn default() -> Self {
+        Self {
+            preset: 6,
+            crf: Av1Qp::new(30).unwrap(),
+            bit_depth: 8,
+            subsampling_format: SubsamplingFormat::Yuv420,
+        }
+    }
+}
+
+impl AvifEncoder {
+    /// Sets the encoder preset. See [`Av1EncoderConfig::preset`].
+    pub fn preset(mut self, preset: i8) -> Self {
+        self.preset = preset;
+        self
+    }
+
+    /// Sets the constant rate factor. The default is 30.
+    pub fn crf(mut self, crf: Av1Qp) -> Self {
+        self.crf = crf;
+        self
+    }
+
+    /// Sets the bit depth of the encoded image, either 8 (the default) or 10.
+    pub fn bit_depth(mut self, bit_depth: u32) -> Self {
+        self.bit_depth = bit_depth;
+        self
+    }
+
+    /// Sets the chroma subsampling of the encoded image. The default is
+    /// [`SubsamplingFormat::Yuv420`]. SVT-AV1 can only encode 4:2:0 and
+    /// monochrome images; other formats fail with [`Error::BadParameter`].
+    pub fn subsampling_format(mut self, format: SubsamplingFormat) -> Self {
+        self.subsampling_format = format;
+        self
+    }
+
+    /// Encodes an image from packed 8-bit RGB samples, with no row padding.
+    /// Returns the contents of an AVIF file.
+    pub fn encode_rgb(&self, rgb: &[u8], width: u32, height: u32) -> Result<Vec<u8>, Error> {
+        check_len(rgb.len(), width, height)?;
+        self.encode(width, height, |i| {
+            [rgb[i * 3], rgb[i * 3 + 1], rgb[i * 3 + 2]].map(|v| v as f32 / u8::MAX as f32)
+        })
+    }
+
+    /// Encodes an image from packed 16-bit RGB samples, with no row padding.
+    /// Returns the contents of an AVIF file.
+    pub fn encode_rgb16(&self, rgb: &[u16], width: u32, height: u32) -> Result<Vec<u8>, Error> {
+        check_len(rgb.len(), width, height)?;
+        self.encode(width, height, |i| {
+            [rgb[i * 3], rgb[i * 3 + 1], rgb[i * 3 + 2]].map(|v| v as f32 / u16::MAX as f32)
+        })
+    }
+
+    /// Encodes an image from the `image` crate. Images with more than 8 bits
+    /// per channel are converted from 16-bit RGB, and any alpha channel is
+    /// discarded. Returns the contents of an AVIF file.
+    #[cfg(feature = "image-interop")]
+    pub fn encode_image(&self, image: &image::DynamicImage) -> Result<Vec<u8>, Error> {
+        let color = image.color();
+        if color.bytes_per_pixel() / color.channel_count() > 1 {
+            let rgb = image.to_rgb16();
+            self.encode_rgb16(rgb.as_raw(), rgb.width(), rgb.height())
+        } else {
+            let rgb = image.to_rgb8();
+            self.encode_rgb(rgb.as_raw(), rgb.width(), rgb.height())
+        }
+    }
+
+    fn encode(
+        &self,
+        width: u32,
+        height: u32,
+        rgb: impl Fn(usize) -> [f32; 3],
+    ) -> Result<Vec<u8>, Error> {
+        let config = Av1EncoderConfig::default()
+            .preset(self.preset)
+            .rate_control_mode(RateControlMode::ConstantRateFactor(self.crf))
+            .bit_depth(self.bit_depth)
+            .color_description(ColorDescription::Other {
+                primaries: PRIMARIES.into(),
+                transfer_characteristics: TRANSFER_CHARACTERISTICS.into(),
+                matrix_coefficients: MATRIX_COEFFICIENTS.into(),
+            })
+            .color_range(ColorRange::Full)
+            .still_picture(true);
+
+        let data = if self.bit_depth > 8 {
+            let mut picture = YUVBuffer16::new(width, height, self.subsampling_format);
+            let max = ((1 << self.bit_depth) - 1) as f32;
+            to_yuv(
+                width,
+                height,
+                self.subsampling_format,
+                rgb,
+                |plane, i, v| {
+                    picture.as_mut_slice(plane)[i] = (v * max).round() as u16;
+                },
+            );
+
+            self.encode_picture(config, &picture)?
+        } else {
+            let mut picture = YUVBuffer::new(width, height, self.subsampling_format);
+            to_yuv(
+                width,
+                height,
+                self.subsampling_format,
+                rgb,
+                |plane, i, v| {
+                    picture.as_mut_slice(plane)[i] = (v * 255.0).round() as u8;
+                },
+            );
+
+            self.encode_picture(config, &picture)?
+        };
+
+        let header = SequenceHeader::find(&data)
+            .ok()
+            .flatten()
+            .ok_or(LibraryError::Undefined)?;
+        let av1c = crate::mux::av1c(&data).map_err(|_| LibraryError::Undefined)?;
+        Ok(write_avif(&data, &av1c, &header, width, height))
+    }
+
+    /// Encodes a single keyframe, and returns its OBUs without the temporal
+    /// delimiter, as AVIF expects.
+    fn encode_picture(
+        &self,
+        config: Av1EncoderConfig,
+        picture: &impl Picture,
+    ) -> Result<Vec<u8>, Error> {
+        let encoder =
+            config.create_encoder(picture.width(), picture.height(), self.subsampling_format)?;
+        let packet = encoder.encode_single(picture)?;
+
+        let mut data = Vec::new();
+        for obu in obus(packet.as_bytes()) {
+            let obu = obu.map_err(|_| LibraryError::Undefined)?;
+            if obu.obu_type() == ObuType::TemporalDelimiter {
+                continue;
+            }
+
+            // Always write the size field, which AVIF requires.
+            data.push(obu.header()[0] | 0x2);
+            data.extend_from_slice(&obu.header()[1..]);
+            write_leb128(&mut data, obu.size());
+            data.extend_from_slice(obu.payload());
+        }
+
+        Ok(data)
+    }
+}
+
+fn check_len(len: usize, width: u32, height: u32) -> Result<(), Error> {
+    let expected = width as usize * height as usize * 3;
+    if len != expected {
+        return Err(Error::BadParameter {
+            detail: Some(format!(
+                "expected {} RGB samples for {}x{}, got {}",
+                expected, width, height, len
+            )),
+        });
+    }
+
+    Ok(())
+}
+
+/// Converts RGB to full range BT.601 YUV. `rgb` returns the normalized color
+/// of the pixel at an index, and `write` receives the normalized samples of
+/// each plane. Chroma is averaged over each subsampled block.
+fn to_yuv(
+    width: u32,
+    height: u32,
+    format: SubsamplingFormat,
+    rgb: impl Fn(usize) -> [f32; 3],
+    mut write: impl FnMut(Plane, usize, f32),
+) {
+    let (width, height) = (width as usize, height as usize);
+    let luma = |[r, g, b]: [f32; 3]| 0.299 * r + 0.587 * g + 0.114 * b;
+
+    for i in 0..width * height {
+        write(Plane::Y, i, luma(rgb(i)).clamp(0.0, 1.0));
+    }
+
+    let (block_width, block_height) = match format {
+        SubsamplingFormat::Yuv400 => return,
+        SubsamplingFormat::Yuv420 => (2, 2),
+        SubsamplingFormat::Yuv422 => (2, 1),
+        SubsamplingFormat::Yuv444 => (1, 1),
+    };
+
+    let uv_width = width.div_ceil(block_width);
+    for cy in 0..height.div_ceil(block_height) {
+        for cx in 0..uv_width {
+            let mut sum = [0.0; 3];
+            let mut count = 0.0;
+            for y in cy * block_height..((cy + 1) * block_height).min(height) {
+                for x in cx * block_width..((cx + 1) * block_width).min(width) {
+                    let color = rgb(y * width + x);
+                    for c in 0..3 {
+                        sum[c] += color[c];
+                    }
+                    count += 1.0;
+                }
+            }
+
+            let [r, g, b] = sum.map(|v| v / count);
+            let y = luma([r, g, b]);
+            let i = cy * uv_width + cx;
+            write(Plane::U, i, ((b - y) / 1.772 + 0.5).clamp(0.0, 1.0));
+            write(Plane::V, i, ((r - y) / 1.402 + 0.5).clamp(0.0, 1.0));
+        }
+    }
+}
+
+fn write_box(buf: &mut Vec<u8>, box_type: &[u8; 4], contents: impl FnOnce(&mut Vec<u8>)) {
+    let start = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(box_type);
+    contents(buf);
+
+    let size = (buf.len() - start) as u32;
+    buf[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+fn write_full_box(
+    buf: &mut Vec<u8>,
+    box_type: &[u8; 4],
+    version: u8,
+    flags: u32,
+    contents: impl FnOnce(&mut Vec<u8>),
+) {
+    write_box(buf, box_type, |buf| {
+        buf.extend_from_slice(&((version as u32) << 24 | flags).to_be_bytes());
+        contents(buf);
+    });
+}
+
+/// Writes an AVIF file containing a single image item. The frame in `data`
+/// has the size from the sequence header, which may be padded; if so, the
+/// image is cropped back to `width` and `height` with a clean aperture.
+fn write_avif(
+    data: &[u8],
+    av1c: &[u8],
+    header: &SequenceHeader,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_box(&mut buf, b"ftyp", |buf| {
+        buf.extend_from_slice(b"avif");
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        for brand in [b"avif", b"mif1", b"miaf"] {
+            buf.extend_from_slice(brand);
+        }
+    });
+
+    let mut offset_pos = 0;
+    write_full_box(&mut buf, b"meta", 0, 0, |buf| {
+        write_full_box(buf, b"hdlr", 0, 0, |buf| {
+            buf.extend_from_slice(&0u32.to_be_bytes());
+            buf.extend_from_slice(b"pict");
+            buf.extend_from_slice(&[0; 12]);
+            buf.push(0);
+        });
+
+        write_full_box(buf, b"pitm", 0, 0, |buf| {
+            buf.extend_from_slice(&ITEM_ID.to_be_bytes());
+        });
+
+        write_full_box(buf, b"iloc", 0, 0, |buf| {
+            // Four byte offsets and lengths, and no base offset.
+            buf.extend_from_slice(&[0x44, 0]);
+            buf.extend_from_slice(&1u16.to_be_bytes());
+            buf.extend_from_slice(&ITEM_ID.to_be_bytes());
+            // The data reference index, and a single extent.
+            buf.extend_from_slice(&0u16.to_be_bytes());
+            buf.extend_from_slice(&1u16.to_be_bytes());
+            offset_pos = buf.len();
+            buf.extend_from_slice(&0u32.to_be_bytes());
+            buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        });
+
+        write_full_box(buf, b"iinf", 0, 0, |buf| {
+            buf.extend_from_slice(&1u16.to_be_bytes());
+            write_full_box(buf, b"infe", 2, 0, |buf| {
+                buf.extend_from_slice(&ITEM_ID.to_be_bytes());
+                buf.extend_from_slice(&0u16.to_be_bytes());
+                buf.extend_from_slice(b"av01");
+                buf.push(0);
+            });
+        });
+
+        write_box(buf, b"iprp", |buf| {
+            // Each property is associated with the image by its index, and
+            // whether it's essential.
+            let mut associations = Vec::new();
+            write_box(buf, b"ipco", |buf| {
+                write_full_box(buf, b"ispe", 0, 0, |buf| {
+                    buf.extend_from_slice(&header.max_width.to_be_bytes());
+                    buf.extend_from_slice(&header.max_height.to_be_bytes());
+                });
+                associations.push(false);
+
+                write_full_box(buf, b"pixi", 0, 0, |buf| {
+                    let channels = match header.subsampling_format {
+                        SubsamplingFormat::Yuv400 => 1,
+                        _ => 3,
+                    };
+
+                    buf.push(channels);
+                    for _ in 0..channels {
+                        buf.push(header.bit_depth as u8);
+                    }
+                });
+                associations.push(false);
+
+                write_box(buf, b"av1C", |buf| buf.extend_from_slice(av1c));
+                associations.push(true);
+
+                write_box(buf, b"colr", |buf| {
+                    buf.extend_from_slice(b"nclx");
+                    for v in [PRIMARIES, TRANSFER_CHARACTERISTICS, MATRIX_COEFFICIENTS] {
+                        buf.extend_from_slice(&v.to_be_bytes());
+                    }
+
+                    // full_range_flag
+                    buf.push(0x80);
+                });
+                associations.push(false);
+
+                if (width, height) != (header.max_width, header.max_height) {
+                    write_box(buf, b"clap", |buf| {
+                        // The aperture is centered, so the offsets move the
+                        // center back to the top left.
+                        let offset = |size: u32, full: u32| size as i32 - full as i32;
+                        for v in [
+                            width as i32,
+                            1,
+                            height as i32,
+                            1,
+                            offset(width, header.max_width),
+                            2,
+                            offset(height, header.max_height),
+                            2,
+                        ] {
+                            buf.extend_from_slice(&v.to_be_bytes());
+                        }
+                    });
+                    associations.push(true);
+                }
+            });
+
+            write_full_box(buf, b"ipma", 0, 0, |buf| {
+                buf.extend_from_slice(&1u32.to_be_bytes());
+                buf.extend_from_slice(&ITEM_ID.to_be_bytes());
+                buf.push(associations.len() as u8);
+                for (i, essential) in associations.into_iter().enumerate() {
+                    buf.push((essential as u8) << 7 | (i + 1) as u8);
+                }
+            });
+        });
+    });
+
+    // The image data starts after the mdat header.
+    let data_offset = buf.len() as u32 + 8;
+    buf[offset_pos..offset_pos + 4].copy_from_slice(&data_offset.to_be_bytes());
+    write_box(&mut buf, b"mdat", |buf| buf.extend_from_slice(data));
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Finds the payload of a top-level box, or a box nested in `meta` or
+    /// `iprp`.
+    fn find_box<'a>(data: &'a [u8], box_type: &[u8; 4]) -> Option<&'a [u8]> {
+        let mut pos = 0;
+        while pos + 8 <= data.len() {
+            let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+            let payload = &data[pos + 8..pos + size];
+            if &data[pos + 4..pos + 8] == box_type {
+                return Some(payload);
+            }
+
+            let nested = match &data[pos + 4..pos + 8] {
+                b"meta" => find_box(&payload[4..], box_type),
+                b"iprp" | b"ipco" => find_box(payload, box_type),
+                _ => None,
+            };
+
+            if nested.is_some() {
+                return nested;
+            }
+
+            pos += size;
+        }
+
+        None
+    }
+
+    #[test]
+    fn rgb_to_yuv() {
+        let colors = [[1.0, 1.0, 1.0], [0.0, 0.0, 0.0], [1.0, 0.0, 0.0]];
+        let mut planes = [vec![0; 3], vec![0; 3], vec![0; 3]];
+        to_yuv(
+            3,
+            1,
+            SubsamplingFormat::Yuv444,
+            |i| colors[i],
+            |p, i, v| {
+                planes[p as usize][i] = (v * 255.0).round() as u8;
+            },
+        );
+
+        assert_eq!(planes[0], [255, 0, 76]);
+        assert_eq!(planes[1], [128, 128, 84]);
+        assert_eq!(planes[2], [128, 128, 255]);
+
+        // Chroma is averaged over the block.
+        let colors = [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        let mut chroma = Vec::new();
+        to_yuv(
+            2,
+            1,
+            SubsamplingFormat::Yuv420,
+            |i| colors[i],
+            |p, _, v| {
+                if p != Plane::Y {
+                    chroma.push((v * 255.0).round() as u8);
+                }
+            },
+        );
+
+        assert_eq!(chroma, [170, 181]);
+    }
+
+    #[test]
+    fn encode_rgb() {
+        simple_logger::init_with_env().ok();
+
+        let (width, height) = (65, 33);
+        let rgb: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i % width * 3) as u8, (i / width * 7) as u8, 128])
+            .collect();
+
+        let avif = AvifEncoder::default()
+            .preset(12)
+            .encode_rgb(&rgb, width, height)
+            .unwrap();
+
+        assert_eq!(&avif[4..12], b"ftypavif");
+
+        let ispe = find_box(&avif, b"ispe").unwrap();
+        assert_eq!(&ispe[4..8], &66u32.to_be_bytes());
+        assert_eq!(&ispe[8..12], &34u32.to_be_bytes());
+        assert!(find_box(&avif, b"clap").is_some());
+        assert!(find_box(&avif, b"av1C").is_some());
+
+        let mdat = find_box(&avif, b"mdat").unwrap();
+        let header = SequenceHeader::find(mdat).unwrap().unwrap();
+        assert_eq!((header.max_width, header.max_height), (66, 34));
+        assert!(obus(mdat).all(|obu| obu.unwrap().obu_type() != ObuType::TemporalDelimiter));
+
+        #[cfg(svt_av1_avif)]
+        assert!(header.still_picture);
+
+        assert!(AvifEncoder::default().encode_rgb(&rgb, 64, 33).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+/// Reads the fixed and variable length fields of AV1 and HEVC headers.
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Reads up to 32 bits, most significant first.
+    pub(crate) fn read(&mut self, bits: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.data.get(self.pos / 8)?;
+            let bit = (byte >> (7 - self.pos % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.pos += 1;
+        }
+
+        Some(value)
+    }
+
+    pub(crate) fn flag(&mut self) -> Option<bool> {
+        Some(self.read(1)? == 1)
+    }
+
+    /// The number of bits read or skipped so far.
+    #[cfg_attr(not(feature = "hevc"), allow(dead_code))]
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn skip(&mut self, bits: u32) -> Option<()> {
+        self.pos += bits as usize;
+        (self.pos <= self.data.len() * 8).then_some(())
+    }
+
+    /// Reads an AV1 `uvlc()` field.
+    pub(crate) fn uvlc(&mut self) -> Option<u32> {
+        let mut leading_zeros = 0;
+        while !self.flag()? {
+            leading_zeros += 1;
+        }
+
+        if leading_zeros >= 32 {
+            return Some(u32::MAX);
+        }
+
+        Some(self.read(leading_zeros)? + ((1 << leading_zeros) - 1))
+    }
+
+    /// Reads an HEVC `ue(v)` field. The two are coded the same way, except
+    /// that values which don't fit are invalid.
+    #[cfg_attr(not(feature = "hevc"), allow(dead_code))]
+    pub(crate) fn ue(&mut self) -> Option<u32> {
+        match self.uvlc()? {
+            u32::MAX => None,
+            v => Some(v),
+        }
+    }
+}
+
+/// Writes fixed length fields, for rewriting HEVC headers.
+#[cfg_attr(not(feature = "hevc"), allow(dead_code))]
+#[derive(Debug, Default)]
+pub(crate) struct BitWriter {
+    data: Vec<u8>,
+    bits: usize,
+}
+
+#[cfg_attr(not(feature = "hevc"), allow(dead_code))]
+impl BitWriter {
+    /// Writes the low `bits` bits of `value`, most significant first.
+    pub(crate) fn write(&mut self, bits: u32, value: u32) {
+        for i in (0..bits).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.data.push(0);
+            }
+
+            let bit = ((value >> i) & 1) as u8;
+            *self.data.last_mut().unwrap() |= bit << (7 - self.bits % 8);
+            self.bits += 1;
+        }
+    }
+
+    pub(crate) fn flag(&mut self, value: bool) {
+        self.write(1, value as u32);
+    }
+
+    /// Writes an HEVC `ue(v)` field.
+    pub(crate) fn ue(&mut self, value: u32) {
+        let value = value as u64 + 1;
+        let suffix = 63 - value.leading_zeros();
+        self.write(suffix, 0);
+        self.write(1, 1);
+        self.write(suffix, value as u32);
+    }
+
+    /// Copies `bits` bits from a reader.
+    pub(crate) fn copy(&mut self, r: &mut BitReader<'_>, bits: usize) -> Option<()> {
+        for _ in 0..bits {
+            self.write(1, r.read(1)?);
+        }
+
+        Some(())
+    }
+
+    /// Appends `rbsp_trailing_bits()` and returns the data.
+    pub(crate) fn finish_rbsp(mut self) -> Vec<u8> {
+        self.write(1, 1);
+        self.data
+    }
+}
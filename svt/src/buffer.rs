@@ -1,4 +1,6 @@
-use crate::Plane;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{Error, Picture, Plane, SubsamplingFormat};
 
 /// A reusable YUV picture buffer, with each of the three planes as a separate
 /// `Vec<u8>` and no support for row padding.
@@ -22,23 +24,9 @@ impl std::fmt::Debug for YUVBuffer {
 
 impl YUVBuffer {
     /// Create a new YUV picture with the given subs width and height.
-    pub fn new(width: u32, height: u32, format: super::SubsamplingFormat) -> Self {
+    pub fn new(width: u32, height: u32, format: SubsamplingFormat) -> Self {
         let y_size = (width * height) as usize;
-
-        let uv_width = match format {
-            super::SubsamplingFormat::Yuv400 => 0,
-            super::SubsamplingFormat::Yuv420 => width / 2,
-            super::SubsamplingFormat::Yuv422 => width / 2,
-            super::SubsamplingFormat::Yuv444 => width,
-        };
-
-        let uv_height = match format {
-            super::SubsamplingFormat::Yuv400 => 0,
-            super::SubsamplingFormat::Yuv420 => height / 2,
-            super::SubsamplingFormat::Yuv422 => height,
-            super::SubsamplingFormat::Yuv444 => height,
-        };
-
+        let (uv_width, uv_height) = chroma_dimensions(width, height, format);
         let uv_size = (uv_width * uv_height) as usize;
 
         YUVBuffer {
@@ -61,7 +49,7 @@ impl YUVBuffer {
     }
 }
 
-impl super::Picture for YUVBuffer {
+impl Picture for YUVBuffer {
     fn width(&self) -> u32 {
         self.width
     }
@@ -85,3 +73,586 @@ impl super::Picture for YUVBuffer {
         }
     }
 }
+
+/// A reusable YUV picture buffer for high bit depth (10 or 12-bit) content.
+/// Each sample is stored as a `u16`, with each of the three planes as a
+/// separate `Vec<u16>` and no support for row padding.
+pub struct YUVBuffer16 {
+    y: Vec<u16>,
+    u: Vec<u16>,
+    v: Vec<u16>,
+    uv_stride: u32,
+    width: u32,
+    height: u32,
+}
+
+impl std::fmt::Debug for YUVBuffer16 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("YUVBuffer16")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl YUVBuffer16 {
+    /// Create a new YUV picture with the given width, height, and subsampling
+    /// format.
+    pub fn new(width: u32, height: u32, format: SubsamplingFormat) -> Self {
+        let y_size = (width * height) as usize;
+        let (uv_width, uv_height) = chroma_dimensions(width, height, format);
+        let uv_size = (uv_width * uv_height) as usize;
+
+        YUVBuffer16 {
+            y: vec![0; y_size],
+            u: vec![0; uv_size],
+            v: vec![0; uv_size],
+            uv_stride: uv_width,
+            width,
+            height,
+        }
+    }
+
+    /// Get mutable access to a plane.
+    pub fn as_mut_slice(&mut self, plane: Plane) -> &mut [u16] {
+        match plane {
+            Plane::Y => &mut self.y,
+            Plane::U => &mut self.u,
+            Plane::V => &mut self.v,
+        }
+    }
+}
+
+impl super::Picture16 for YUVBuffer16 {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn as_slice(&self, plane: Plane) -> &[u16] {
+        match plane {
+            Plane::Y => &self.y,
+            Plane::U => &self.u,
+            Plane::V => &self.v,
+        }
+    }
+
+    fn stride(&self, plane: Plane) -> u32 {
+        match plane {
+            Plane::Y => self.width,
+            Plane::U | Plane::V => self.uv_stride,
+        }
+    }
+}
+
+/// Returns the width and height of the chroma planes. Odd dimensions are
+/// rounded up, so that the last column or row of luma samples has chroma.
+pub(crate) fn chroma_dimensions(width: u32, height: u32, format: SubsamplingFormat) -> (u32, u32) {
+    let uv_width = match format {
+        SubsamplingFormat::Yuv400 => 0,
+        SubsamplingFormat::Yuv420 => width.div_ceil(2),
+        SubsamplingFormat::Yuv422 => width.div_ceil(2),
+        SubsamplingFormat::Yuv444 => width,
+    };
+
+    let uv_height = match format {
+        SubsamplingFormat::Yuv400 => 0,
+        SubsamplingFormat::Yuv420 => height.div_ceil(2),
+        SubsamplingFormat::Yuv422 => height,
+        SubsamplingFormat::Yuv444 => height,
+    };
+
+    (uv_width, uv_height)
+}
+
+/// The size at which a picture is encoded: the smallest size at least as
+/// large as the picture that the chroma subsampling can represent. The
+/// libraries reject odd dimensions for subsampled chroma. Monochrome pictures
+/// are encoded as 4:2:0, so they're rounded up the same way.
+pub(crate) fn coded_size(width: u32, height: u32, format: SubsamplingFormat) -> (u32, u32) {
+    let (x, y) = match format {
+        SubsamplingFormat::Yuv400 | SubsamplingFormat::Yuv420 => (2, 2),
+        SubsamplingFormat::Yuv422 => (2, 1),
+        SubsamplingFormat::Yuv444 => (1, 1),
+    };
+
+    (width.next_multiple_of(x), height.next_multiple_of(y))
+}
+
+/// The input pictures an encoder accepts. Each encoder reports its
+/// requirements with a `picture_requirements` method, so that pictures can be
+/// validated before they're sent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PictureRequirements {
+    /// The width of the picture in pixels.
+    pub width: u32,
+    /// The height of the picture in pixels.
+    pub height: u32,
+    /// The chroma subsampling format.
+    pub subsampling_format: SubsamplingFormat,
+    /// The number of bytes used to store each sample, as reported by
+    /// [`Picture::bytes_per_sample`].
+    pub bytes_per_sample: u32,
+}
+
+impl PictureRequirements {
+    /// Checks that a picture has the required dimensions and sample size, and
+    /// that each plane has the size implied by its stride. Returns
+    /// [`Error::InvalidPicture`] otherwise.
+    pub fn check(&self, picture: &impl Picture) -> Result<(), Error> {
+        let invalid = |expected: String, got: String| Err(Error::InvalidPicture { expected, got });
+
+        if picture.bytes_per_sample() != self.bytes_per_sample {
+            return invalid(
+                format!("{} bytes per sample", self.bytes_per_sample),
+                format!("{} bytes per sample", picture.bytes_per_sample()),
+            );
+        }
+
+        if (picture.width(), picture.height()) != (self.width, self.height) {
+            return invalid(
+                format!("{}x{}", self.width, self.height),
+                format!("{}x{}", picture.width(), picture.height()),
+            );
+        }
+
+        let (uv_width, uv_height) =
+            chroma_dimensions(self.width, self.height, self.subsampling_format);
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            let (width, rows) = match plane {
+                Plane::Y => (self.width, self.height),
+                _ => (uv_width, uv_height),
+            };
+
+            let stride = picture.stride(plane);
+            let min_stride = width * self.bytes_per_sample;
+            if rows > 0 && (stride < min_stride || !stride.is_multiple_of(self.bytes_per_sample)) {
+                return invalid(
+                    format!(
+                        "a {:?} stride of at least {} bytes, in whole samples",
+                        plane, min_stride
+                    ),
+                    format!("{} bytes", stride),
+                );
+            }
+
+            let len = picture.as_slice(plane).len();
+            let expected_len = (stride * rows) as usize;
+            if len != expected_len {
+                return invalid(
+                    format!("{} bytes in the {:?} plane", expected_len, plane),
+                    format!("{} bytes", len),
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The planes of an input picture, as the encoder libraries expect them. The
+/// libraries name the chroma planes `cb` and `cr`, which are [`Plane::U`] and
+/// [`Plane::V`] respectively, and take strides in samples rather than bytes.
+///
+/// Building the library structs from this, field by field, keeps the planes
+/// from being mixed up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    not(any(feature = "av1", feature = "hevc", feature = "vp9")),
+    allow(dead_code)
+)]
+pub(crate) struct PlaneLayout {
+    pub(crate) luma: *const u8,
+    pub(crate) cb: *const u8,
+    pub(crate) cr: *const u8,
+    pub(crate) y_stride: u32,
+    pub(crate) cb_stride: u32,
+    pub(crate) cr_stride: u32,
+    /// The total size of the three planes, in bytes.
+    pub(crate) len: usize,
+}
+
+#[cfg_attr(
+    not(any(feature = "av1", feature = "hevc", feature = "vp9")),
+    allow(dead_code)
+)]
+impl PlaneLayout {
+    /// Describes the planes of `picture`, after checking it against the
+    /// encoder's requirements.
+    pub(crate) fn new(
+        picture: &impl Picture,
+        requirements: &PictureRequirements,
+    ) -> Result<Self, Error> {
+        requirements.check(picture)?;
+
+        let bytes_per_sample = requirements.bytes_per_sample;
+        let [y, u, v] = [Plane::Y, Plane::U, Plane::V].map(|plane| picture.as_slice(plane));
+        let [y_stride, u_stride, v_stride] =
+            [Plane::Y, Plane::U, Plane::V].map(|plane| picture.stride(plane));
+
+        Ok(PlaneLayout {
+            luma: y.as_ptr(),
+            cb: u.as_ptr(),
+            cr: v.as_ptr(),
+            y_stride: y_stride / bytes_per_sample,
+            cb_stride: u_stride / bytes_per_sample,
+            cr_stride: v_stride / bytes_per_sample,
+            len: y.len() + u.len() + v.len(),
+        })
+    }
+}
+
+/// Neutral chroma planes, substituted for the missing chroma of monochrome
+/// pictures, which are encoded as 4:2:0. See [`SubsamplingFormat::Yuv400`].
+#[derive(Debug)]
+#[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+pub(crate) struct NeutralChroma {
+    // Stored as u16, so that high bit depth samples are aligned.
+    samples: Vec<u16>,
+    bit_depth: u32,
+    bytes_per_sample: u32,
+}
+
+#[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+impl NeutralChroma {
+    pub(crate) fn new(bit_depth: u32, bytes_per_sample: u32) -> Self {
+        Self {
+            samples: Vec::new(),
+            bit_depth,
+            bytes_per_sample,
+        }
+    }
+
+    /// Points both chroma planes of `layout` at neutral samples, for a
+    /// picture of the given dimensions. The samples are reallocated if the
+    /// picture is larger than any before it.
+    pub(crate) fn fill(&mut self, layout: &mut PlaneLayout, width: u32, height: u32) {
+        let (uv_width, uv_height) = chroma_dimensions(width, height, SubsamplingFormat::Yuv420);
+        let len = (uv_width * uv_height * self.bytes_per_sample) as usize;
+
+        if self.samples.len() * 2 < len {
+            let mid = if self.bytes_per_sample == 2 {
+                1 << (self.bit_depth - 1)
+            } else {
+                u16::from_ne_bytes([128, 128])
+            };
+
+            self.samples = vec![mid; len.div_ceil(2)];
+        }
+
+        layout.cb = self.samples.as_ptr() as *const u8;
+        layout.cr = self.samples.as_ptr() as *const u8;
+        layout.cb_stride = uv_width;
+        layout.cr_stride = uv_width;
+        layout.len += len * 2;
+    }
+}
+
+/// Pads the luma plane of pictures with odd dimensions to the coded size (see
+/// [`coded_size`]), by repeating the last column and row. The chroma planes
+/// already have the coded size, since [`chroma_dimensions`] rounds up.
+#[derive(Debug)]
+#[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+pub(crate) struct EdgePadding {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    coded_width: u32,
+    coded_height: u32,
+    // Stored as u16, so that high bit depth samples are aligned.
+    luma: Mutex<Vec<u16>>,
+}
+
+#[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+impl EdgePadding {
+    /// Returns `None` if pictures of the given size don't need padding.
+    pub(crate) fn new(width: u32, height: u32, format: SubsamplingFormat) -> Option<Self> {
+        let (coded_width, coded_height) = coded_size(width, height, format);
+        if (coded_width, coded_height) == (width, height) {
+            return None;
+        }
+
+        Some(Self {
+            width,
+            height,
+            coded_width,
+            coded_height,
+            luma: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Copies the luma plane of `picture` into a buffer of the coded size,
+    /// and points `layout` at it. The buffer stays locked, and so valid, until
+    /// the returned guard is dropped.
+    pub(crate) fn pad(
+        &self,
+        picture: &impl Picture,
+        layout: &mut PlaneLayout,
+    ) -> MutexGuard<'_, Vec<u16>> {
+        let bytes_per_sample = picture.bytes_per_sample() as usize;
+        let (width, height) = (self.width as usize, self.height as usize);
+        let row_len = self.coded_width as usize * bytes_per_sample;
+        let len = row_len * self.coded_height as usize;
+
+        let mut luma = self.luma.lock().unwrap();
+        luma.resize(len.div_ceil(2), 0);
+
+        // SAFETY: the buffer is at least `len` bytes long, and u8 has no
+        // alignment requirements.
+        let dst = unsafe { std::slice::from_raw_parts_mut(luma.as_mut_ptr() as *mut u8, len) };
+        let src = picture.as_slice(Plane::Y);
+        let stride = picture.stride(Plane::Y) as usize;
+
+        for (y, dst_row) in dst.chunks_exact_mut(row_len).enumerate() {
+            let src_row = &src[y.min(height - 1) * stride..][..width * bytes_per_sample];
+            let (inside, outside) = dst_row.split_at_mut(src_row.len());
+            inside.copy_from_slice(src_row);
+
+            let last = &src_row[src_row.len() - bytes_per_sample..];
+            for sample in outside.chunks_exact_mut(bytes_per_sample) {
+                sample.copy_from_slice(last);
+            }
+        }
+
+        layout.len = layout.len - src.len() + len;
+        layout.luma = luma.as_ptr() as *const u8;
+        layout.y_stride = self.coded_width;
+        luma
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A picture with a different stride for each plane.
+    struct Strided {
+        planes: [Vec<u8>; 3],
+        strides: [u32; 3],
+        bytes_per_sample: u32,
+    }
+
+    impl Strided {
+        fn new(format: SubsamplingFormat, bytes_per_sample: u32) -> Self {
+            let (_, uv_height) = chroma_dimensions(64, 32, format);
+            let strides = [80, 72, 96].map(|s| s * bytes_per_sample);
+            let heights = [32, uv_height, uv_height];
+
+            Strided {
+                planes: [0, 1, 2].map(|i| vec![0; (strides[i] * heights[i]) as usize]),
+                strides,
+                bytes_per_sample,
+            }
+        }
+    }
+
+    impl Picture for Strided {
+        fn width(&self) -> u32 {
+            64
+        }
+
+        fn height(&self) -> u32 {
+            32
+        }
+
+        fn as_slice(&self, plane: Plane) -> &[u8] {
+            &self.planes[plane as usize]
+        }
+
+        fn stride(&self, plane: Plane) -> u32 {
+            self.strides[plane as usize]
+        }
+
+        fn bytes_per_sample(&self) -> u32 {
+            self.bytes_per_sample
+        }
+    }
+
+    fn requirements(format: SubsamplingFormat, bytes_per_sample: u32) -> PictureRequirements {
+        PictureRequirements {
+            width: 64,
+            height: 32,
+            subsampling_format: format,
+            bytes_per_sample,
+        }
+    }
+
+    fn plane_layout(picture: &impl Picture, format: SubsamplingFormat, bps: u32) -> PlaneLayout {
+        PlaneLayout::new(picture, &requirements(format, bps)).expect("invalid picture")
+    }
+
+    #[test]
+    fn chroma_planes() {
+        let pic = Strided::new(SubsamplingFormat::Yuv420, 1);
+        let layout = plane_layout(&pic, SubsamplingFormat::Yuv420, 1);
+
+        assert_eq!(layout.luma, pic.planes[0].as_ptr());
+        assert_eq!(layout.cb, pic.planes[1].as_ptr());
+        assert_eq!(layout.cr, pic.planes[2].as_ptr());
+        assert_eq!(
+            (layout.y_stride, layout.cb_stride, layout.cr_stride),
+            (80, 72, 96)
+        );
+        assert_eq!(layout.len, 80 * 32 + 72 * 16 + 96 * 16);
+    }
+
+    #[test]
+    fn strides_in_samples() {
+        let pic = Strided::new(SubsamplingFormat::Yuv444, 2);
+        let layout = plane_layout(&pic, SubsamplingFormat::Yuv444, 2);
+
+        assert_eq!(
+            (layout.y_stride, layout.cb_stride, layout.cr_stride),
+            (80, 72, 96)
+        );
+        assert_eq!(layout.len, 2 * (80 + 72 + 96) * 32);
+    }
+
+    #[test]
+    fn buffers() {
+        let buf = YUVBuffer::new(64, 32, SubsamplingFormat::Yuv422);
+        let layout = plane_layout(&buf, SubsamplingFormat::Yuv422, 1);
+        assert_eq!((layout.y_stride, layout.cb_stride), (64, 32));
+
+        let buf = YUVBuffer16::new(64, 32, SubsamplingFormat::Yuv400);
+        let layout = plane_layout(&buf, SubsamplingFormat::Yuv400, 2);
+        assert_eq!(layout.y_stride, 64);
+        assert_eq!(layout.len, 64 * 32 * 2);
+    }
+
+    #[test]
+    fn neutral_chroma() {
+        let buf = YUVBuffer::new(64, 32, SubsamplingFormat::Yuv400);
+        let mut layout = plane_layout(&buf, SubsamplingFormat::Yuv400, 1);
+        let mut chroma = NeutralChroma::new(8, 1);
+        chroma.fill(&mut layout, 64, 32);
+
+        assert_eq!((layout.cb_stride, layout.cr_stride), (32, 32));
+        assert_eq!(layout.len, 64 * 32 + 2 * 32 * 16);
+        assert_eq!(layout.cb, layout.cr);
+        let samples = unsafe { std::slice::from_raw_parts(layout.cb, 32 * 16) };
+        assert!(samples.iter().all(|&v| v == 128));
+
+        let buf = YUVBuffer16::new(64, 32, SubsamplingFormat::Yuv400);
+        let mut layout = plane_layout(&buf, SubsamplingFormat::Yuv400, 2);
+        let mut chroma = NeutralChroma::new(10, 2);
+        chroma.fill(&mut layout, 64, 32);
+
+        assert_eq!(layout.cb_stride, 32);
+        assert_eq!(layout.len, 2 * (64 * 32 + 2 * 32 * 16));
+        assert!(chroma.samples.iter().all(|&v| v == 512));
+    }
+
+    #[test]
+    fn odd_dimensions() {
+        assert_eq!(
+            coded_size(1079, 607, SubsamplingFormat::Yuv420),
+            (1080, 608)
+        );
+        assert_eq!(
+            coded_size(1079, 607, SubsamplingFormat::Yuv422),
+            (1080, 607)
+        );
+        assert_eq!(
+            coded_size(1079, 607, SubsamplingFormat::Yuv444),
+            (1079, 607)
+        );
+        assert!(EdgePadding::new(1080, 608, SubsamplingFormat::Yuv420).is_none());
+
+        let mut buf = YUVBuffer::new(3, 3, SubsamplingFormat::Yuv420);
+        assert_eq!(buf.as_mut_slice(Plane::U).len(), 4);
+        buf.as_mut_slice(Plane::Y)
+            .copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let requirements = PictureRequirements {
+            width: 3,
+            height: 3,
+            subsampling_format: SubsamplingFormat::Yuv420,
+            bytes_per_sample: 1,
+        };
+
+        let padding = EdgePadding::new(3, 3, SubsamplingFormat::Yuv420).unwrap();
+        let mut layout = PlaneLayout::new(&buf, &requirements).unwrap();
+        let luma = padding.pad(&buf, &mut layout);
+
+        let padded = unsafe { std::slice::from_raw_parts(layout.luma, 16) };
+        assert_eq!(padded, &[1, 2, 3, 3, 4, 5, 6, 6, 7, 8, 9, 9, 7, 8, 9, 9]);
+        assert_eq!(layout.y_stride, 4);
+        assert_eq!(layout.len, 16 + 4 + 4);
+        drop(luma);
+    }
+
+    #[test]
+    fn odd_dimensions_high_bit_depth() {
+        let mut buf = YUVBuffer16::new(3, 1, SubsamplingFormat::Yuv422);
+        buf.as_mut_slice(Plane::Y).copy_from_slice(&[100, 200, 300]);
+
+        let requirements = PictureRequirements {
+            width: 3,
+            height: 1,
+            subsampling_format: SubsamplingFormat::Yuv422,
+            bytes_per_sample: 2,
+        };
+
+        let padding = EdgePadding::new(3, 1, SubsamplingFormat::Yuv422).unwrap();
+        let mut layout = PlaneLayout::new(&buf, &requirements).unwrap();
+        let luma = padding.pad(&buf, &mut layout);
+
+        assert_eq!(luma.as_slice(), &[100, 200, 300, 300]);
+        assert_eq!(layout.y_stride, 4);
+        assert_eq!(layout.len, 8 + 2 * 2 * 2);
+    }
+
+    #[test]
+    fn wrong_format() {
+        let pic = Strided::new(SubsamplingFormat::Yuv420, 1);
+        let err = requirements(SubsamplingFormat::Yuv444, 1)
+            .check(&pic)
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Invalid picture: expected 2304 bytes in the U plane, got 1152 bytes"
+        );
+    }
+
+    #[test]
+    fn wrong_bit_depth() {
+        let buf = YUVBuffer::new(64, 32, SubsamplingFormat::Yuv420);
+        let err = requirements(SubsamplingFormat::Yuv420, 2)
+            .check(&buf)
+            .unwrap_err();
+
+        assert!(matches!(err, Error::InvalidPicture { .. }));
+    }
+
+    #[test]
+    fn wrong_dimensions() {
+        let buf = YUVBuffer::new(32, 64, SubsamplingFormat::Yuv420);
+        let err = requirements(SubsamplingFormat::Yuv420, 1)
+            .check(&buf)
+            .unwrap_err();
+
+        match err {
+            Error::InvalidPicture { expected, got } => {
+                assert_eq!(expected, "64x32");
+                assert_eq!(got, "32x64");
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn stride_too_small() {
+        let mut pic = Strided::new(SubsamplingFormat::Yuv444, 1);
+        pic.strides[2] = 32;
+        pic.planes[2].truncate(32 * 32);
+
+        let err = requirements(SubsamplingFormat::Yuv444, 1)
+            .check(&pic)
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidPicture { .. }));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{Encoder, EncoderConfig, Error, FrameTypeRequest, Picture, Stats, SubsamplingFormat};
+
+/// An encoder config which can run as one of several channels on the same
+/// machine. The libraries divide the available processors between the active
+/// channels, using each channel's ID to pick its share.
+///
+/// This is implemented for the AV1 and HEVC configs.
+pub trait ChannelConfig: EncoderConfig {
+    /// Sets the ID of the channel, and the number of active channels.
+    fn channel(self, id: u32, active_channel_count: u32) -> Self;
+
+    /// Returns statistics about the encode so far, from an encoder created
+    /// with this config.
+    fn stats(encoder: &Self::Encoder) -> Stats;
+}
+
+/// A group of encoders running as channels of the same library instance, for
+/// example to encode each rendition of an ABR ladder on one machine.
+///
+/// Each encoder is configured with its channel ID and the number of channels,
+/// so that the library divides the processors between them rather than
+/// oversubscribing the machine. Settings shared by every channel, like the
+/// target socket, can be applied with [`ChannelGroupBuilder::shared`].
+///
+/// # Example
+///
+/// ```no_run
+/// # #[cfg(feature = "av1")]
+/// # fn main() -> Result<(), svt::Error> {
+/// use svt::av1::{Av1EncoderConfig, TargetSocket};
+/// use svt::{ChannelGroup, SubsamplingFormat, YUVBuffer};
+///
+/// let group = ChannelGroup::builder()
+///     .shared(|config: Av1EncoderConfig| config.preset(10).target_socket(TargetSocket::First))
+///     .channel(Av1EncoderConfig::default(), 1920, 1080, SubsamplingFormat::Yuv420)
+///     .channel(Av1EncoderConfig::default(), 1280, 720, SubsamplingFormat::Yuv420)
+///     .build()?;
+///
+/// let picture = YUVBuffer::new(1280, 720, SubsamplingFormat::Yuv420);
+/// group.send_picture(1, &picture, 0, false)?;
+/// while let Some(packet) = group.get_packet(1, false)? {
+///     // Write the packet for the 720p rendition...
+/// }
+///
+/// println!("{:?}", group.total_stats());
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "av1"))]
+/// # fn main() {}
+/// ```
+pub struct ChannelGroup<C: ChannelConfig> {
+    encoders: Vec<C::Encoder>,
+}
+
+impl<C: ChannelConfig> std::fmt::Debug for ChannelGroup<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChannelGroup")
+            .field("channels", &self.encoders.len())
+            .finish()
+    }
+}
+
+/// Builds a [`ChannelGroup`].
+pub struct ChannelGroupBuilder<C> {
+    channels: Vec<(C, u32, u32, SubsamplingFormat)>,
+    shared: Option<Box<dyn Fn(C) -> C>>,
+}
+
+impl<C> std::fmt::Debug for ChannelGroupBuilder<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChannelGroupBuilder")
+            .field("channels", &self.channels.len())
+            .finish()
+    }
+}
+
+impl<C: ChannelConfig> ChannelGroupBuilder<C> {
+    /// Adds a channel, which encodes pictures of the given size and format.
+    /// Channels are numbered in the order they're added.
+    pub fn channel(
+        mut self,
+        config: C,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Self {
+        self.channels
+            .push((config, width, height, subsampling_format));
+        self
+    }
+
+    /// Sets a function which is applied to the config of every channel, for
+    /// settings shared by the whole group, like threading and the target
+    /// socket.
+    pub fn shared(mut self, f: impl Fn(C) -> C + 'static) -> Self {
+        self.shared = Some(Box::new(f));
+        self
+    }
+
+    /// Creates the encoders. If any of them fails, the error is returned,
+    /// and the encoders already created are dropped.
+    pub fn build(self) -> Result<ChannelGroup<C>, Error> {
+        let count = self.channels.len() as u32;
+        let mut encoders = Vec::with_capacity(self.channels.len());
+        for (id, (mut config, width, height, format)) in self.channels.into_iter().enumerate() {
+            if let Some(shared) = &self.shared {
+                config = shared(config);
+            }
+
+            let config = config.channel(id as u32, count);
+            encoders.push(config.create_encoder(width, height, format)?);
+        }
+
+        Ok(ChannelGroup { encoders })
+    }
+}
+
+impl<C: ChannelConfig> ChannelGroup<C> {
+    /// Starts building a group.
+    pub fn builder() -> ChannelGroupBuilder<C> {
+        ChannelGroupBuilder {
+            channels: Vec::new(),
+            shared: None,
+        }
+    }
+
+    /// The number of channels.
+    pub fn len(&self) -> usize {
+        self.encoders.len()
+    }
+
+    /// Whether the group has no channels.
+    pub fn is_empty(&self) -> bool {
+        self.encoders.is_empty()
+    }
+
+    /// The encoder for a channel, or `None` if there is no such channel.
+    pub fn encoder(&self, channel: usize) -> Option<&C::Encoder> {
+        self.encoders.get(channel)
+    }
+
+    /// The encoders, in channel order.
+    pub fn encoders(&self) -> &[C::Encoder] {
+        &self.encoders
+    }
+
+    /// Returns the encoders, in channel order.
+    pub fn into_encoders(self) -> Vec<C::Encoder> {
+        self.encoders
+    }
+
+    /// Sends a picture to a channel. See [`Encoder::send_picture`].
+    pub fn send_picture(
+        &self,
+        channel: usize,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        self.get(channel)?.send_picture(picture, pts, frame_type)
+    }
+
+    /// Retrieves a packet from a channel. See [`Encoder::get_packet`].
+    pub fn get_packet(
+        &self,
+        channel: usize,
+        wait: bool,
+    ) -> Result<Option<<C::Encoder as Encoder>::Packet>, Error> {
+        self.get(channel)?.get_packet(wait)
+    }
+
+    /// Finishes the stream of every channel. See [`Encoder::finish`].
+    pub fn finish(&self) -> Result<(), Error> {
+        for encoder in &self.encoders {
+            encoder.finish()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns statistics for each channel, in channel order.
+    pub fn stats(&self) -> Vec<Stats> {
+        self.encoders.iter().map(C::stats).collect()
+    }
+
+    /// Returns statistics for the whole group. Counts, sizes and bitrates
+    /// are summed over the channels, and the average QPs are averaged over
+    /// every intra or inter frame output by any channel.
+    pub fn total_stats(&self) -> Stats {
+        sum_stats(self.stats())
+    }
+
+    fn get(&self, channel: usize) -> Result<&C::Encoder, Error> {
+        self.encoders
+            .get(channel)
+            .ok_or_else(|| Error::BadParameter {
+                detail: Some(format!(
+                    "no channel {} in a group of {}",
+                    channel,
+                    self.encoders.len()
+                )),
+            })
+    }
+}
+
+fn sum_stats(channels: impl IntoIterator<Item = Stats>) -> Stats {
+    let mut total = Stats::default();
+    let mut intra_qp = 0.0;
+    let mut inter_qp = 0.0;
+    for stats in channels {
+        total.frames_submitted += stats.frames_submitted;
+        total.frames_output += stats.frames_output;
+        total.pictures_completed += stats.pictures_completed;
+        total.bytes_output += stats.bytes_output;
+        total.intra_frames_output += stats.intra_frames_output;
+        total.inter_frames_output += stats.inter_frames_output;
+
+        if let Some(bitrate) = stats.bitrate {
+            *total.bitrate.get_or_insert(0.0) += bitrate;
+        }
+
+        if let Some(qp) = stats.average_intra_qp {
+            intra_qp += qp * stats.intra_frames_output as f64;
+        }
+
+        if let Some(qp) = stats.average_inter_qp {
+            inter_qp += qp * stats.inter_frames_output as f64;
+        }
+    }
+
+    let average = |sum: f64, frames: u64| (frames > 0).then(|| sum / frames as f64);
+    total.average_intra_qp = average(intra_qp, total.intra_frames_output);
+    total.average_inter_qp = average(inter_qp, total.inter_frames_output);
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qp_weighted_by_frame_type() {
+        // One keyframe and 99 inter frames, and the other way round.
+        let a = Stats {
+            frames_output: 100,
+            intra_frames_output: 1,
+            inter_frames_output: 99,
+            average_intra_qp: Some(10.0),
+            average_inter_qp: Some(30.0),
+            bitrate: Some(1000.0),
+            ..Default::default()
+        };
+
+        let b = Stats {
+            frames_output: 100,
+            intra_frames_output: 99,
+            inter_frames_output: 1,
+            average_intra_qp: Some(20.0),
+            average_inter_qp: Some(40.0),
+            ..Default::default()
+        };
+
+        let total = sum_stats([a, b]);
+        assert_eq!(total.frames_output, 200);
+        assert_eq!(total.intra_frames_output, 100);
+        assert_eq!(total.inter_frames_output, 100);
+        assert_eq!(total.average_intra_qp, Some(19.9));
+        assert_eq!(total.average_inter_qp, Some(30.1));
+        assert_eq!(total.bitrate, Some(1000.0));
+
+        let total = sum_stats([Stats::default()]);
+        assert_eq!(total.average_intra_qp, None);
+        assert_eq!(total.average_inter_qp, None);
+    }
+}
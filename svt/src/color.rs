@@ -0,0 +1,71 @@
+/// Input/output color space, according to ISO/IEC 23091-4/ITU-T H.273. AV1
+/// and HEVC use the same code points.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorDescription {
+    /// Unspecified color description (CP_UNSPECIFIED, TC_UNSPECIFIED, MC_UNSPECIFIED).
+    Unspecified,
+    /// CP_BT_709 color primaries, TC_BT_709 transfer characteristics, and MC_BT_709 matrix coefficients. Standard for HD.
+    Bt709,
+    /// CP_BT_2020 color primaries, TC_SMPTE_2084 transfer characteristics, and MC_BT_2020_NCL matrix coefficients. Standard for the HDR10 media profile.
+    Bt2020Pq,
+    /// Some other combination. See H.273, or section 6.4.2 of the AV1 spec,
+    /// for the values.
+    Other {
+        /// The color primaries.
+        primaries: u32,
+        /// The transfer characteristics.
+        transfer_characteristics: u32,
+        /// The matrix coefficients.
+        matrix_coefficients: u32,
+    },
+}
+
+impl ColorDescription {
+    /// The color primaries, transfer characteristics, and matrix
+    /// coefficients.
+    #[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+    pub(crate) fn code_points(self) -> (u32, u32, u32) {
+        match self {
+            ColorDescription::Unspecified => (2, 2, 2),
+            ColorDescription::Bt709 => (1, 1, 1),
+            ColorDescription::Bt2020Pq => (9, 16, 9),
+            ColorDescription::Other {
+                primaries,
+                transfer_characteristics,
+                matrix_coefficients,
+            } => (primaries, transfer_characteristics, matrix_coefficients),
+        }
+    }
+}
+
+/// Input/output color range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorRange {
+    /// Studio swing (16-235 for Y, 16-240 for U and V).
+    Limited,
+    /// Full swing (0-255 for Y, 0-255 for U and V).
+    Full,
+}
+
+/// Chroma sample position, for 4:2:0 input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChromaSamplePosition {
+    /// Top left.
+    Colocated,
+    /// Left.
+    Vertical,
+    /// Unknown.
+    Unknown,
+}
+
+impl ChromaSamplePosition {
+    /// The HEVC `chroma_sample_loc_type`, if the position is known.
+    #[cfg_attr(not(feature = "hevc"), allow(dead_code))]
+    pub(crate) fn chroma_sample_loc_type(self) -> Option<u32> {
+        match self {
+            ChromaSamplePosition::Colocated => Some(2),
+            ChromaSamplePosition::Vertical => Some(0),
+            ChromaSamplePosition::Unknown => None,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{Encoder, Error, Packet, Picture};
+
+/// An iterator over the packets encoded from an iterator of pictures,
+/// created with [`Encoder::encode_iter`].
+///
+/// Each call to `next` first returns any packet which is already available,
+/// and otherwise sends the next picture. Once the pictures run out, the
+/// stream is finished, and the remaining packets are returned as the encoder
+/// outputs them, up to and including the EOS packet. After an error, the
+/// iterator ends.
+pub struct EncodeIter<'a, E, I> {
+    encoder: &'a E,
+    pictures: I,
+    finished: bool,
+    done: bool,
+}
+
+impl<E, I> std::fmt::Debug for EncodeIter<'_, E, I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncodeIter")
+            .field("finished", &self.finished)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+impl<'a, E, I> EncodeIter<'a, E, I> {
+    pub(crate) fn new(encoder: &'a E, pictures: I) -> Self {
+        Self {
+            encoder,
+            pictures,
+            finished: false,
+            done: false,
+        }
+    }
+}
+
+impl<E, I, P> Iterator for EncodeIter<'_, E, I>
+where
+    E: Encoder,
+    I: Iterator<Item = (P, i64)>,
+    P: Picture,
+{
+    type Item = Result<E::Packet, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            // Draining before each send keeps the encoder's output queue from
+            // filling up, which would stall the pipeline and block the send.
+            let res = match self.encoder.get_packet(self.finished) {
+                Ok(Some(packet)) => Ok(packet),
+                Ok(None) if self.finished => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(None) => {
+                    let res = match self.pictures.next() {
+                        Some((picture, pts)) => self.encoder.send_picture(&picture, pts, false),
+                        None => {
+                            self.finished = true;
+                            self.encoder.finish()
+                        }
+                    };
+
+                    match res {
+                        Ok(()) => continue,
+                        Err(e) => Err(e),
+                    }
+                }
+                Err(e) => Err(e),
+            };
+
+            self.done = res.as_ref().map_or(true, |packet| packet.is_eos());
+            return Some(res);
+        }
+
+        None
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use crate::mock::MockEncoderConfig;
+    use crate::{Encoder, EncoderState, Error, Packet, SubsamplingFormat, YUVBuffer};
+
+    fn pictures(n: i64) -> impl Iterator<Item = (YUVBuffer, i64)> {
+        (0..n).map(|pts| (YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420), pts))
+    }
+
+    #[test]
+    fn encode_iter() {
+        let enc = MockEncoderConfig::default()
+            .delay(3)
+            .create_encoder(16, 16, SubsamplingFormat::Yuv420)
+            .unwrap();
+
+        let packets: Vec<_> = enc
+            .encode_iter(pictures(5))
+            .map(|p| p.map(|p| (p.pts(), p.is_eos())))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            packets,
+            [
+                (0, false),
+                (1, false),
+                (2, false),
+                (3, false),
+                (4, false),
+                (0, true)
+            ]
+        );
+        assert_eq!(enc.state(), EncoderState::Finished);
+    }
+
+    #[test]
+    fn encode_iter_error() {
+        let enc = MockEncoderConfig::default()
+            .create_encoder(16, 16, SubsamplingFormat::Yuv444)
+            .unwrap();
+
+        let mut iter = enc.encode_iter(pictures(5));
+        assert!(matches!(
+            iter.next(),
+            Some(Err(Error::InvalidPicture { .. }))
+        ));
+        assert!(iter.next().is_none());
+    }
+}
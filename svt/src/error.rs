@@ -1,51 +1,322 @@
-#[derive(Debug, Copy, Clone)]
-#[allow(missing_docs)]
+/// An error returned by an encoder.
+///
+/// The variants fall into four groups:
+///
+/// - Configuration: [`Error::BadParameter`] and [`Error::InvalidPicture`],
+///   for settings or input the encoder can't use.
+/// - Runtime: [`Error::WouldBlock`] and [`Error::TimedOut`], for calls which
+///   can be retried.
+/// - State: [`Error::InvalidState`] and [`Error::Poisoned`], for calls the
+///   encoder can't accept anymore.
+/// - FFI: [`Error::Library`], for any other error reported by one of the
+///   underlying libraries.
+///
+/// New variants may be added, for example for errors specific to one codec,
+/// so matches need a wildcard arm. [`Error::code`] gives the raw
+/// `EbErrorType` for errors which correspond to one.
+#[derive(Debug, Clone, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
+    /// One or more configuration parameters is invalid. If the library logged
+    /// the reason, or the crate caught the problem first, it's included as
+    /// `detail`.
+    #[error("EB_ErrorBadParameter{}", detail_suffix(.detail))]
+    BadParameter {
+        /// A description of the problem.
+        detail: Option<String>,
+    },
+    /// An input picture doesn't match the encoder's
+    /// [`PictureRequirements`](crate::PictureRequirements).
+    #[error("Invalid picture: expected {expected}, got {got}")]
+    InvalidPicture {
+        /// The expected layout.
+        expected: String,
+        /// The layout of the picture.
+        got: String,
+    },
+    /// The encoder can't accept another picture without blocking.
+    #[error("Operation would block")]
+    WouldBlock,
+    /// A deadline passed before the operation completed, for example while
+    /// waiting for an encoder to finish the stream before shutting down.
+    #[error("Timed out")]
+    TimedOut,
+    /// The call isn't allowed in the encoder's current state, for example
+    /// sending a picture after [`Encoder::finish`](crate::Encoder::finish).
+    #[error("Invalid encoder state: {state:?}")]
+    InvalidState {
+        /// The state of the encoder.
+        state: crate::EncoderState,
+    },
+    /// A panic interrupted a call into the library, for example in a log
+    /// sink, so the encoder can't be used any further. It can still be
+    /// dropped.
+    #[error("Encoder poisoned by a panic")]
+    Poisoned,
+    /// The library failed for a reason other than a bad parameter.
+    #[error("Library error: {error}{}", context_suffix(.context))]
+    Library {
+        /// The error reported by the library, which is also the
+        /// [`source`](std::error::Error::source) of this error.
+        #[source]
+        error: LibraryError,
+        /// Where the error happened, if it happened while encoding.
+        context: Option<ErrorContext>,
+    },
+}
+
+impl Error {
+    /// The raw `EbErrorType` code corresponding to the error, if there is
+    /// one. [`Error::BadParameter`] always maps to `EB_ErrorBadParameter`,
+    /// whether the library or the crate rejected the parameter.
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            Error::BadParameter { .. } => Some(BAD_PARAMETER),
+            Error::Library { error, .. } => Some(error.code()),
+            _ => None,
+        }
+    }
+
+    /// The context of an [`Error::Library`] which happened while encoding.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Library { context, .. } => context.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Converts an `EbErrorType` returned by one of the libraries, which all
+    /// share the same codes.
+    #[cfg(any(feature = "av1", feature = "hevc", feature = "vp9"))]
+    pub(crate) fn check(code: i32) -> Result<(), Error> {
+        match code {
+            0 => Ok(()),
+            BAD_PARAMETER => Err(Error::BadParameter { detail: None }),
+            code => Err(LibraryError::from_code(code).into()),
+        }
+    }
+
+    /// Attaches the context of the call to a library error which doesn't
+    /// have any yet. Other errors are returned unchanged.
+    #[cfg(any(feature = "av1", feature = "hevc", feature = "vp9"))]
+    pub(crate) fn in_call(
+        self,
+        call: EncoderCall,
+        pts: Option<i64>,
+        channel_id: Option<u32>,
+    ) -> Self {
+        match self {
+            Error::Library {
+                error,
+                context: None,
+            } => Error::Library {
+                error,
+                context: Some(ErrorContext {
+                    call,
+                    pts,
+                    channel_id,
+                }),
+            },
+            e => e,
+        }
+    }
+}
+
+impl From<LibraryError> for Error {
+    fn from(error: LibraryError) -> Self {
+        Error::Library {
+            error,
+            context: None,
+        }
+    }
+}
+
+fn detail_suffix(detail: &Option<String>) -> String {
+    detail
+        .as_ref()
+        .map(|detail| format!(": {}", detail))
+        .unwrap_or_default()
+}
+
+fn context_suffix(context: &Option<ErrorContext>) -> String {
+    context
+        .map(|context| format!(" ({})", context))
+        .unwrap_or_default()
+}
+
+/// The encoder call a library error happened in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EncoderCall {
+    /// [`Encoder::send_picture`](crate::Encoder::send_picture), or one of the
+    /// variants specific to a codec.
+    SendPicture,
+    /// [`Encoder::get_packet`](crate::Encoder::get_packet).
+    GetPacket,
+    /// [`Encoder::finish`](crate::Encoder::finish).
+    Finish,
+}
+
+impl std::fmt::Display for EncoderCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncoderCall::SendPicture => write!(f, "send_picture"),
+            EncoderCall::GetPacket => write!(f, "get_packet"),
+            EncoderCall::Finish => write!(f, "finish"),
+        }
+    }
+}
+
+/// Where a library error happened, so that an application running many
+/// encoders can tell which stream failed. See [`Error::context`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ErrorContext {
+    /// The call which failed.
+    pub call: EncoderCall,
+    /// The pts of the picture being sent, for [`EncoderCall::SendPicture`].
+    pub pts: Option<i64>,
+    /// The channel ID of the encoder, if the codec has them.
+    pub channel_id: Option<u32>,
+}
+
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "in {}", self.call)?;
+        if let Some(pts) = self.pts {
+            write!(f, ", pts {}", pts)?;
+        }
+
+        if let Some(id) = self.channel_id {
+            write!(f, ", channel {}", id)?;
+        }
+
+        Ok(())
+    }
+}
+
+const INSUFFICIENT_RESOURCES: i32 = 0x80001000u32 as i32;
+const UNDEFINED: i32 = 0x80001001u32 as i32;
+const INVALID_COMPONENT: i32 = 0x80001004u32 as i32;
+const BAD_PARAMETER: i32 = 0x80001005u32 as i32;
+const DESTROY_THREAD_FAILED: i32 = 0x80002012u32 as i32;
+const SEMAPHORE_UNRESPONSIVE: i32 = 0x80002021u32 as i32;
+const DESTROY_SEMAPHORE_FAILED: i32 = 0x80002022u32 as i32;
+const CREATE_MUTEX_FAILED: i32 = 0x80002030u32 as i32;
+const MUTEX_UNRESPONSIVE: i32 = 0x80002031u32 as i32;
+const DESTROY_MUTEX_FAILED: i32 = 0x80002032u32 as i32;
+
+/// An error reported by one of the underlying libraries, named after the
+/// `EbErrorType` it corresponds to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, thiserror::Error)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum LibraryError {
+    #[error("EB_ErrorInsufficientResources")]
     InsufficientResources,
+    /// The library failed without a more specific reason. This is also used
+    /// when the output of the library can't be interpreted.
+    #[error("EB_ErrorUndefined")]
     Undefined,
+    #[error("EB_ErrorInvalidComponent")]
     InvalidComponent,
-    BadParameter,
+    #[error("EB_ErrorDestroyThreadFailed")]
     DestroyThreadFailed,
+    #[error("EB_ErrorSemaphoreUnresponsive")]
     SemaphoreUnresponsive,
+    #[error("EB_ErrorDestroySemaphoreFailed")]
     DestroySemaphoreFailed,
+    #[error("EB_ErrorCreateMutexFailed")]
     CreateMutexFailed,
+    #[error("EB_ErrorMutexUnresponsive")]
     MutexUnresponsive,
+    #[error("EB_ErrorDestroyMutexFailed")]
     DestroyMutexFailed,
+    /// An error code without a name, including codes from libraries other
+    /// than SVT, like libvmaf.
+    #[error("Unknown error code: {0}")]
     Unknown(i32),
 }
 
-impl std::error::Error for Error {
-    fn description(&self) -> &str {
+impl LibraryError {
+    /// The raw error code.
+    pub fn code(self) -> i32 {
         match self {
-            Error::InsufficientResources => "EB_ErrorInsufficientResources",
-            Error::Undefined => "EB_ErrorUndefined",
-            Error::InvalidComponent => "EB_ErrorInvalidComponent",
-            Error::BadParameter => "EB_ErrorBadParameter",
-            Error::DestroyThreadFailed => "EB_ErrorDestroyThreadFailed",
-            Error::SemaphoreUnresponsive => "EB_ErrorSemaphoreUnresponsive",
-            Error::DestroySemaphoreFailed => "EB_ErrorDestroySemaphoreFailed",
-            Error::CreateMutexFailed => "EB_ErrorCreateMutexFailed",
-            Error::MutexUnresponsive => "EB_ErrorMutexUnresponsive",
-            Error::DestroyMutexFailed => "EB_ErrorDestroyMutexFailed",
-            Error::Unknown(_) => "Unknown error",
+            LibraryError::InsufficientResources => INSUFFICIENT_RESOURCES,
+            LibraryError::Undefined => UNDEFINED,
+            LibraryError::InvalidComponent => INVALID_COMPONENT,
+            LibraryError::DestroyThreadFailed => DESTROY_THREAD_FAILED,
+            LibraryError::SemaphoreUnresponsive => SEMAPHORE_UNRESPONSIVE,
+            LibraryError::DestroySemaphoreFailed => DESTROY_SEMAPHORE_FAILED,
+            LibraryError::CreateMutexFailed => CREATE_MUTEX_FAILED,
+            LibraryError::MutexUnresponsive => MUTEX_UNRESPONSIVE,
+            LibraryError::DestroyMutexFailed => DESTROY_MUTEX_FAILED,
+            LibraryError::Unknown(code) => code,
         }
     }
-}
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Error::InsufficientResources => write!(f, "EB_ErrorInsufficientResources"),
-            Error::Undefined => write!(f, "EB_ErrorUndefined"),
-            Error::InvalidComponent => write!(f, "EB_ErrorInvalidComponent"),
-            Error::BadParameter => write!(f, "EB_ErrorBadParameter"),
-            Error::DestroyThreadFailed => write!(f, "EB_ErrorDestroyThreadFailed"),
-            Error::SemaphoreUnresponsive => write!(f, "EB_ErrorSemaphoreUnresponsive"),
-            Error::DestroySemaphoreFailed => write!(f, "EB_ErrorDestroySemaphoreFailed"),
-            Error::CreateMutexFailed => write!(f, "EB_ErrorCreateMutexFailed"),
-            Error::MutexUnresponsive => write!(f, "EB_ErrorMutexUnresponsive"),
-            Error::DestroyMutexFailed => write!(f, "EB_ErrorDestroyMutexFailed"),
-            Error::Unknown(code) => write!(f, "Unknown error code: {}", code),
+    #[cfg(any(feature = "av1", feature = "hevc", feature = "vp9"))]
+    fn from_code(code: i32) -> Self {
+        match code {
+            INSUFFICIENT_RESOURCES => LibraryError::InsufficientResources,
+            UNDEFINED => LibraryError::Undefined,
+            INVALID_COMPONENT => LibraryError::InvalidComponent,
+            DESTROY_THREAD_FAILED => LibraryError::DestroyThreadFailed,
+            SEMAPHORE_UNRESPONSIVE => LibraryError::SemaphoreUnresponsive,
+            DESTROY_SEMAPHORE_FAILED => LibraryError::DestroySemaphoreFailed,
+            CREATE_MUTEX_FAILED => LibraryError::CreateMutexFailed,
+            MUTEX_UNRESPONSIVE => LibraryError::MutexUnresponsive,
+            DESTROY_MUTEX_FAILED => LibraryError::DestroyMutexFailed,
+            code => LibraryError::Unknown(code),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error as _;
+
+    use super::*;
+
+    #[test]
+    fn codes() {
+        let err = Error::BadParameter { detail: None };
+        assert_eq!(err.code(), Some(0x80001005u32 as i32));
+        assert!(err.source().is_none());
+
+        let err = Error::from(LibraryError::CreateMutexFailed);
+        assert_eq!(err.code(), Some(0x80002030u32 as i32));
+        assert_eq!(
+            err.source().unwrap().to_string(),
+            "EB_ErrorCreateMutexFailed"
+        );
+
+        assert_eq!(Error::from(LibraryError::Unknown(-7)).code(), Some(-7));
+        assert_eq!(Error::WouldBlock.code(), None);
+    }
+
+    #[cfg(any(feature = "av1", feature = "hevc", feature = "vp9"))]
+    #[test]
+    fn context() {
+        let err = Error::from(LibraryError::SemaphoreUnresponsive).in_call(
+            EncoderCall::SendPicture,
+            Some(42),
+            Some(3),
+        );
+        assert_eq!(
+            err.to_string(),
+            "Library error: EB_ErrorSemaphoreUnresponsive (in send_picture, pts 42, channel 3)"
+        );
+        assert_eq!(
+            err.context().map(|c| c.call),
+            Some(EncoderCall::SendPicture)
+        );
+
+        // The innermost call wins.
+        let err = err.in_call(EncoderCall::Finish, None, None);
+        assert_eq!(err.context().map(|c| c.pts), Some(Some(42)));
+
+        let err = Error::WouldBlock.in_call(EncoderCall::SendPicture, Some(42), Some(3));
+        assert!(err.context().is_none());
+    }
+}
@@ -0,0 +1,195 @@
+//! Interop with [`ffmpeg_next`], for encoding frames decoded by ffmpeg.
+//!
+//! With the `ffmpeg-interop` feature, [`ffmpeg_next::frame::Video`]
+//! implements [`Picture`], borrowing the frame's planes and strides directly.
+//! A decoded frame can be passed to an encoder without copying, as long as its
+//! pixel format matches the encoder configuration:
+//!
+//! ```no_run
+//! # use svt::{Encoder, FrameTypeRequest};
+//! # fn example(encoder: &impl Encoder, frame: &ffmpeg_next::frame::Video) -> Result<(), svt::Error> {
+//! let pts = frame.pts().unwrap_or_default();
+//! encoder.send_picture(frame, pts, FrameTypeRequest::Auto)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Use [`pixel_format`] to pick the encoder's subsampling format and bit depth
+//! for a stream, and the [`Rational`] conversions to carry timebases across.
+
+use ffmpeg_next::frame::Video;
+use ffmpeg_next::util::format::Pixel;
+
+use crate::{Error, Picture, Plane, Rational, SubsamplingFormat};
+
+/// Returns the subsampling format and bit depth of an ffmpeg pixel format, or
+/// `None` if the encoders can't take frames in that format directly.
+///
+/// Only planar YUV (and grayscale) formats are supported, with high bit depth
+/// samples stored as little-endian `u16`s. Full range (`yuvj`) formats are
+/// accepted; the range should be signalled separately in the encoder's color
+/// description.
+pub fn pixel_format(format: Pixel) -> Option<(SubsamplingFormat, u32)> {
+    let format = match format {
+        Pixel::GRAY8 => (SubsamplingFormat::Yuv400, 8),
+        Pixel::YUV420P | Pixel::YUVJ420P => (SubsamplingFormat::Yuv420, 8),
+        Pixel::YUV422P | Pixel::YUVJ422P => (SubsamplingFormat::Yuv422, 8),
+        Pixel::YUV444P | Pixel::YUVJ444P => (SubsamplingFormat::Yuv444, 8),
+        Pixel::GRAY10LE => (SubsamplingFormat::Yuv400, 10),
+        Pixel::YUV420P10LE => (SubsamplingFormat::Yuv420, 10),
+        Pixel::YUV422P10LE => (SubsamplingFormat::Yuv422, 10),
+        Pixel::YUV444P10LE => (SubsamplingFormat::Yuv444, 10),
+        Pixel::GRAY12LE => (SubsamplingFormat::Yuv400, 12),
+        Pixel::YUV420P12LE => (SubsamplingFormat::Yuv420, 12),
+        Pixel::YUV422P12LE => (SubsamplingFormat::Yuv422, 12),
+        Pixel::YUV444P12LE => (SubsamplingFormat::Yuv444, 12),
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+/// Returns the ffmpeg pixel format for a subsampling format and bit depth,
+/// for example to set up a scaler converting into a format the encoder
+/// accepts. This is the inverse of [`pixel_format`], for limited range
+/// formats.
+pub fn to_pixel_format(format: SubsamplingFormat, bit_depth: u32) -> Option<Pixel> {
+    let pixel = match (format, bit_depth) {
+        (SubsamplingFormat::Yuv400, 8) => Pixel::GRAY8,
+        (SubsamplingFormat::Yuv420, 8) => Pixel::YUV420P,
+        (SubsamplingFormat::Yuv422, 8) => Pixel::YUV422P,
+        (SubsamplingFormat::Yuv444, 8) => Pixel::YUV444P,
+        (SubsamplingFormat::Yuv400, 10) => Pixel::GRAY10LE,
+        (SubsamplingFormat::Yuv420, 10) => Pixel::YUV420P10LE,
+        (SubsamplingFormat::Yuv422, 10) => Pixel::YUV422P10LE,
+        (SubsamplingFormat::Yuv444, 10) => Pixel::YUV444P10LE,
+        (SubsamplingFormat::Yuv400, 12) => Pixel::GRAY12LE,
+        (SubsamplingFormat::Yuv420, 12) => Pixel::YUV420P12LE,
+        (SubsamplingFormat::Yuv422, 12) => Pixel::YUV422P12LE,
+        (SubsamplingFormat::Yuv444, 12) => Pixel::YUV444P12LE,
+        _ => return None,
+    };
+
+    Some(pixel)
+}
+
+/// The frame is borrowed as-is, so it must be in one of the formats
+/// [`pixel_format`] accepts. Frames in other formats have the wrong plane
+/// sizes, and are rejected by the encoder.
+impl Picture for Video {
+    fn width(&self) -> u32 {
+        Video::width(self)
+    }
+
+    fn height(&self) -> u32 {
+        Video::height(self)
+    }
+
+    fn as_slice(&self, plane: Plane) -> &[u8] {
+        // Grayscale frames have no chroma planes.
+        if plane as usize >= self.planes() {
+            return &[];
+        }
+
+        self.data(plane as usize)
+    }
+
+    fn stride(&self, plane: Plane) -> u32 {
+        if plane as usize >= self.planes() {
+            return 0;
+        }
+
+        Video::stride(self, plane as usize) as u32
+    }
+
+    fn bytes_per_sample(&self) -> u32 {
+        match pixel_format(self.format()) {
+            Some((_, bit_depth)) if bit_depth > 8 => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl TryFrom<ffmpeg_next::Rational> for Rational {
+    type Error = Error;
+
+    /// Converts an ffmpeg timebase. Returns [`Error::BadParameter`] if it is
+    /// negative or has a zero denominator.
+    fn try_from(rational: ffmpeg_next::Rational) -> Result<Self, Error> {
+        let (num, den) = (rational.numerator(), rational.denominator());
+        if num < 0 || den <= 0 {
+            return Err(Error::BadParameter {
+                detail: Some(format!("invalid timebase: {}/{}", num, den)),
+            });
+        }
+
+        Ok(Rational::new(num as u32, den as u32))
+    }
+}
+
+impl From<Rational> for ffmpeg_next::Rational {
+    /// Converts a timebase for use with ffmpeg. Values which don't fit in an
+    /// `i32` are clamped.
+    fn from(rational: Rational) -> Self {
+        let clamp = |v: u32| v.min(i32::MAX as u32) as i32;
+        ffmpeg_next::Rational::new(clamp(rational.num), clamp(rational.den))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PictureRequirements;
+
+    #[test]
+    fn pixel_formats() {
+        for format in [
+            SubsamplingFormat::Yuv400,
+            SubsamplingFormat::Yuv420,
+            SubsamplingFormat::Yuv422,
+            SubsamplingFormat::Yuv444,
+        ] {
+            for bit_depth in [8, 10, 12] {
+                let pixel = to_pixel_format(format, bit_depth).unwrap();
+                assert_eq!(pixel_format(pixel), Some((format, bit_depth)));
+            }
+        }
+
+        assert_eq!(
+            pixel_format(Pixel::YUVJ420P),
+            Some((SubsamplingFormat::Yuv420, 8))
+        );
+        assert_eq!(pixel_format(Pixel::NV12), None);
+        assert_eq!(to_pixel_format(SubsamplingFormat::Yuv420, 16), None);
+    }
+
+    #[test]
+    fn timebase() {
+        let timebase = Rational::try_from(ffmpeg_next::Rational::new(1, 90000)).unwrap();
+        assert_eq!(timebase, Rational::new(1, 90000));
+        assert_eq!(
+            ffmpeg_next::Rational::from(timebase),
+            ffmpeg_next::Rational::new(1, 90000)
+        );
+
+        assert!(Rational::try_from(ffmpeg_next::Rational::new(1, 0)).is_err());
+        assert!(Rational::try_from(ffmpeg_next::Rational::new(-1, 25)).is_err());
+    }
+
+    #[test]
+    fn frame_picture() {
+        for pixel in [Pixel::YUV420P, Pixel::YUV422P10LE, Pixel::GRAY8] {
+            let (subsampling_format, bit_depth) = pixel_format(pixel).unwrap();
+            let frame = Video::new(pixel, 63, 35);
+
+            let requirements = PictureRequirements {
+                width: 63,
+                height: 35,
+                subsampling_format,
+                bytes_per_sample: if bit_depth > 8 { 2 } else { 1 },
+            };
+
+            requirements.check(&frame).unwrap();
+        }
+    }
+}
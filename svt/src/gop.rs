@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+/// Why the encoder produced a keyframe. See [`Packet::keyframe_reason`].
+///
+/// [`Packet::keyframe_reason`]: crate::Packet::keyframe_reason
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyframeReason {
+    /// The first keyframe in the stream.
+    StreamStart,
+    /// The keyframe was requested when the picture was sent, for example
+    /// with [`FrameTypeRequest::Key`](crate::FrameTypeRequest::Key).
+    Forced,
+    /// The keyframe was inserted at the end of the configured intra period.
+    Periodic,
+    /// The keyframe was inserted early, because scene change detection
+    /// detected a scene cut.
+    SceneChange,
+}
+
+/// Information about an output frame, derived from the order in which
+/// pictures were submitted.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub(crate) struct OutputPicture {
+    /// The index of the source picture, in display order. This is `None` for
+    /// frames which don't correspond to a newly submitted picture, like
+    /// overlay frames.
+    pub(crate) picture_number: Option<u64>,
+    /// The index of the frame, in decode order.
+    pub(crate) decode_order: u64,
+    /// The reason for a keyframe.
+    pub(crate) keyframe_reason: Option<KeyframeReason>,
+}
+
+/// Tracks submitted pictures, in order to number and classify output frames.
+#[derive(Debug)]
+#[cfg_attr(
+    not(any(feature = "av1", feature = "hevc", feature = "mock")),
+    allow(dead_code)
+)]
+pub(crate) struct GopTracker {
+    key_interval: Option<u64>,
+    scene_change_detection: bool,
+    submitted: u64,
+    // Maps pts to the index of the picture, and whether a keyframe was
+    // requested for it.
+    pending: HashMap<i64, (u64, bool)>,
+    last_keyframe: Option<u64>,
+    decoded: u64,
+}
+
+#[cfg_attr(
+    not(any(feature = "av1", feature = "hevc", feature = "mock")),
+    allow(dead_code)
+)]
+impl GopTracker {
+    /// Creates a tracker for the given intra period, as passed to the
+    /// library (negative values mean none, or automatic).
+    pub(crate) fn new(intra_period_length: i32, scene_change_detection: bool) -> Self {
+        Self {
+            key_interval: u64::try_from(intra_period_length).ok().map(|n| n + 1),
+            scene_change_detection,
+            submitted: 0,
+            pending: HashMap::new(),
+            last_keyframe: None,
+            decoded: 0,
+        }
+    }
+
+    pub(crate) fn record_submitted(&mut self, pts: i64, forced: bool) {
+        self.pending.insert(pts, (self.submitted, forced));
+        self.submitted += 1;
+    }
+
+    /// Forgets a picture which failed to submit.
+    #[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+    pub(crate) fn cancel(&mut self, pts: i64) {
+        if self.pending.remove(&pts).is_some() {
+            self.submitted -= 1;
+        }
+    }
+
+    pub(crate) fn record_output(&mut self, pts: i64, keyframe: bool) -> OutputPicture {
+        let decode_order = self.decoded;
+        self.decoded += 1;
+
+        let Some((index, forced)) = self.pending.remove(&pts) else {
+            return OutputPicture {
+                decode_order,
+                ..Default::default()
+            };
+        };
+
+        let keyframe_reason = keyframe.then(|| {
+            // Anything submitted before a keyframe has already been output,
+            // or never will be.
+            self.pending.retain(|_, (i, _)| *i > index);
+            let last = self.last_keyframe.replace(index);
+
+            match last {
+                None => KeyframeReason::StreamStart,
+                _ if forced => KeyframeReason::Forced,
+                Some(last) => match self.key_interval {
+                    Some(interval) if self.scene_change_detection && index - last != interval => {
+                        KeyframeReason::SceneChange
+                    }
+                    _ => KeyframeReason::Periodic,
+                },
+            }
+        });
+
+        OutputPicture {
+            picture_number: Some(index),
+            decode_order,
+            keyframe_reason,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_keyframes() {
+        let mut tracker = GopTracker::new(3, true);
+        for pts in 0..12 {
+            tracker.record_submitted(pts, pts == 9);
+        }
+
+        let reason = |tracker: &mut GopTracker, pts, keyframe| {
+            tracker.record_output(pts, keyframe).keyframe_reason
+        };
+
+        assert_eq!(
+            reason(&mut tracker, 0, true),
+            Some(KeyframeReason::StreamStart)
+        );
+        assert_eq!(reason(&mut tracker, 1, false), None);
+        assert_eq!(
+            reason(&mut tracker, 4, true),
+            Some(KeyframeReason::Periodic)
+        );
+        assert_eq!(
+            reason(&mut tracker, 6, true),
+            Some(KeyframeReason::SceneChange)
+        );
+        assert_eq!(reason(&mut tracker, 9, true), Some(KeyframeReason::Forced));
+
+        // Earlier pictures are forgotten after a keyframe.
+        assert_eq!(
+            tracker.record_output(2, true),
+            OutputPicture {
+                picture_number: None,
+                decode_order: 5,
+                keyframe_reason: None,
+            }
+        );
+    }
+
+    #[test]
+    fn scene_change_detection_disabled() {
+        let mut tracker = GopTracker::new(-1, false);
+        for pts in 0..4 {
+            tracker.record_submitted(pts, false);
+        }
+
+        tracker.record_output(0, true);
+        assert_eq!(
+            tracker.record_output(2, true).keyframe_reason,
+            Some(KeyframeReason::Periodic)
+        );
+    }
+
+    #[test]
+    fn picture_numbers() {
+        let mut tracker = GopTracker::new(-1, false);
+        for pts in [10, 20, 30] {
+            tracker.record_submitted(pts, false);
+        }
+
+        // Output in decode order, with the last picture used as a reference
+        // for the middle one.
+        let output = [10, 30, 20].map(|pts| tracker.record_output(pts, pts == 10));
+        assert_eq!(
+            output.map(|o| o.picture_number),
+            [Some(0), Some(2), Some(1)]
+        );
+        assert_eq!(output.map(|o| o.decode_order), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,81 @@
+//! HDR static metadata, shared by the AV1 and HEVC encoders.
+//!
+//! Values are given in physical units, and converted to the fixed-point
+//! representation each codec uses.
+
+/// A CIE 1931 chromaticity coordinate.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Chromaticity {
+    /// The x coordinate, from 0.0 to 1.0.
+    pub x: f64,
+    /// The y coordinate, from 0.0 to 1.0.
+    pub y: f64,
+}
+
+impl Chromaticity {
+    /// Creates a new chromaticity coordinate.
+    pub const fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+}
+
+/// The color volume of the display used to master the content, as described
+/// by SMPTE ST 2086.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct MasteringDisplay {
+    /// The red primary.
+    pub red: Chromaticity,
+    /// The green primary.
+    pub green: Chromaticity,
+    /// The blue primary.
+    pub blue: Chromaticity,
+    /// The white point.
+    pub white_point: Chromaticity,
+    /// The maximum luminance of the display, in cd/m².
+    pub max_luminance: f64,
+    /// The minimum luminance of the display, in cd/m².
+    pub min_luminance: f64,
+}
+
+impl MasteringDisplay {
+    /// A display with BT.2020 primaries and a D65 white point, as commonly
+    /// used for HDR10 content.
+    pub const fn bt2020(max_luminance: f64, min_luminance: f64) -> Self {
+        Self {
+            red: Chromaticity::new(0.708, 0.292),
+            green: Chromaticity::new(0.170, 0.797),
+            blue: Chromaticity::new(0.131, 0.046),
+            white_point: Chromaticity::new(0.3127, 0.3290),
+            max_luminance,
+            min_luminance,
+        }
+    }
+
+    /// A display with P3 primaries and a D65 white point.
+    pub const fn display_p3(max_luminance: f64, min_luminance: f64) -> Self {
+        Self {
+            red: Chromaticity::new(0.680, 0.320),
+            green: Chromaticity::new(0.265, 0.690),
+            blue: Chromaticity::new(0.150, 0.060),
+            white_point: Chromaticity::new(0.3127, 0.3290),
+            max_luminance,
+            min_luminance,
+        }
+    }
+}
+
+/// Content light level information, as described by CTA-861.3.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// The maximum content light level (MaxCLL), in cd/m².
+    pub max_content_light_level: u16,
+    /// The maximum frame-average light level (MaxFALL), in cd/m².
+    pub max_frame_average_light_level: u16,
+}
+
+/// Converts a value to fixed point with the given number of fractional steps
+/// per unit.
+#[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+pub(crate) fn fixed(v: f64, scale: f64) -> u32 {
+    (v * scale).round().max(0.0) as u32
+}
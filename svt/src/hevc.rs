@@ -3,7 +3,7 @@
 //!
 //! # Example
 //! ```
-//! # use svt::{Encoder, Packet, YUVBuffer, SubsamplingFormat};
+//! # use svt::{Encoder, HevcQp, Packet, YUVBuffer, SubsamplingFormat};
 //! # use svt::hevc::{HevcEncoderConfig, RateControlMode};
 //! # fn copy_frame(_: &mut YUVBuffer)
 //! #     -> Result<i64, Box<dyn std::error::Error>> { Ok(0) }
@@ -14,7 +14,7 @@
 //! let encoder = HevcEncoderConfig::default()
 //!     .preset(8)
 //!     .rate_control_mode(RateControlMode::ConstantQp)
-//!     .qp(30)
+//!     .qp(HevcQp::new(30)?)
 //!     .create_encoder(width, height, colorspace)?;
 //!
 //! let mut buffer = YUVBuffer::new(width, height, colorspace);
@@ -50,17 +50,61 @@
 use svt_hevc_sys::*;
 
 mod config;
+mod hrd;
+mod level;
 mod packet;
+mod sei;
+mod ten_bit;
+mod vui;
 
 pub use config::*;
+pub use hrd::{check_conformance, HrdModel, HrdParameters, HrdViolation};
+pub use level::Level;
 pub use packet::*;
+pub use sei::UserDataSei;
+pub use ten_bit::{CompressedTenBitBuffer, CompressedTenBitPicture};
 
-use crate::{Encoder, Error, Picture, Plane, SubsamplingFormat};
+/// Splitting of encoded packets into NAL units, for muxing, packetization,
+/// and debugging.
+///
+/// ```
+/// # use svt::hevc::nal::nal_units;
+/// # fn example(packet: &[u8]) -> Result<(), svt::hevc::nal::NalError> {
+/// for nal in nal_units(packet) {
+///     let nal = nal?;
+///     println!("type {}: {} bytes", nal.nal_type(), nal.as_bytes().len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub mod nal {
+    pub use crate::nal::{nal_units, NalError, NalUnit, NalUnits};
+}
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::buffer::{EdgePadding, NeutralChroma, PlaneLayout};
+use crate::gop::GopTracker;
+use crate::instrument::queue_wait;
+use crate::shutdown;
+use crate::state::StateTracker;
+use crate::stats::StatsTracker;
+use crate::{
+    Encoder, EncoderCall, EncoderState, Error, FrameTypeRequest, Packet, Picture,
+    PictureRequirements, Plane,
+};
+use crate::{Orientation, Rational, Stats, SubsamplingFormat, Timestamp};
+use vui::VideoSignal;
 
 struct LibraryHandle(*mut EB_COMPONENTTYPE);
 
 unsafe impl Send for LibraryHandle {}
 
+// The library allows pictures to be sent and packets to be retrieved
+// concurrently. The encoder serializes calls of each kind.
+unsafe impl Sync for LibraryHandle {}
+
 impl LibraryHandle {
     fn as_ptr(&self) -> *mut EB_COMPONENTTYPE {
         self.0
@@ -78,8 +122,32 @@ impl Drop for LibraryHandle {
 /// An encoder instance.
 pub struct HevcEncoder {
     handle: LibraryHandle,
+    channel_id: u32,
+    width: u32,
+    height: u32,
     subsampling_format: SubsamplingFormat,
     intra_refresh_type: IntraRefreshType,
+    bit_depth: u32,
+    compressed_ten_bit: bool,
+    interlaced: bool,
+    stats: Mutex<StatsTracker>,
+    gop: Mutex<GopTracker>,
+    reorder_depth: u32,
+    // The picture number of the most recent IDR picture.
+    last_idr: Mutex<u64>,
+    max_pending_frames: Option<u32>,
+    drop_timeout: Duration,
+    padding: Option<EdgePadding>,
+    monochrome: Option<Mutex<NeutralChroma>>,
+    orientation: Option<Orientation>,
+    video_signal: Option<VideoSignal>,
+    timebase: Rational,
+    hrd: Option<(HrdParameters, Mutex<HrdModel>)>,
+    #[cfg(feature = "log")]
+    log_sink: Option<LogSink>,
+    state: StateTracker,
+    send_lock: Mutex<()>,
+    recv_lock: Mutex<()>,
 }
 
 impl std::fmt::Debug for HevcEncoder {
@@ -97,73 +165,93 @@ impl Encoder for HevcEncoder {
         &self,
         picture: &impl Picture,
         pts: i64,
-        force_keyframe: bool,
+        frame_type: impl Into<FrameTypeRequest>,
     ) -> Result<(), Error> {
-        let y = picture.as_slice(Plane::Y);
-        let u = picture.as_slice(Plane::U);
-        let v = picture.as_slice(Plane::V);
-
-        let y_stride = picture.stride(Plane::Y);
-        let u_stride = picture.stride(Plane::U);
-        let v_stride = picture.stride(Plane::V);
-
-        assert_eq!(y.len(), (y_stride * picture.height()) as usize);
-        match self.subsampling_format {
-            SubsamplingFormat::Yuv400 => {
-                assert_eq!(u.len(), 0);
-                assert_eq!(v.len(), 0);
-            }
-            SubsamplingFormat::Yuv420 => {
-                assert_eq!(u.len(), (u_stride * picture.height() / 2) as usize);
-                assert_eq!(v.len(), (v_stride * picture.height() / 2) as usize);
-            }
-            SubsamplingFormat::Yuv422 | SubsamplingFormat::Yuv444 => {
-                assert_eq!(u.len(), (u_stride * picture.height()) as usize);
-                assert_eq!(v.len(), (v_stride * picture.height()) as usize);
-            }
-        }
-
-        let mut input_pic = EB_H265_ENC_INPUT {
-            luma: picture.as_slice(Plane::Y).as_ptr() as *mut _,
-            cb: picture.as_slice(Plane::U).as_ptr() as *mut _,
-            cr: picture.as_slice(Plane::V).as_ptr() as *mut _,
-            yStride: y_stride,
-            crStride: u_stride,
-            cbStride: v_stride,
-            ..Default::default()
-        };
-
-        let slice_type = if force_keyframe {
-            match self.intra_refresh_type {
-                IntraRefreshType::Open => EB_I_PICTURE,
-                IntraRefreshType::Closed(_) => EB_IDR_PICTURE,
-            }
-        } else {
-            EB_INVALID_PICTURE
-        };
-
-        let mut input = EB_BUFFERHEADERTYPE {
-            nSize: size_of::<EB_BUFFERHEADERTYPE>() as u32,
-            pBuffer: &mut input_pic as *mut _ as *mut u8,
-            nFilledLen: (y.len() + u.len() + v.len()) as u32,
+        self.send_input(
+            picture,
+            None,
             pts,
-            sliceType: slice_type,
-            ..Default::default()
-        };
-
-        unsafe { result(EbH265EncSendPicture(self.handle.as_ptr(), &mut input)) }
+            frame_type.into(),
+            PictureStructure::Frame,
+            None,
+        )
     }
 
     fn get_packet(&self, done: bool) -> Result<Option<HevcPacket>, Error> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "get_packet",
+            codec = "hevc",
+            wait = done,
+            pts = tracing::field::Empty,
+            size = tracing::field::Empty,
+        )
+        .entered();
+
+        let _guard = self.state.lock(&self.recv_lock)?;
+        self.state.check_poisoned()?;
+        if self.state.get() == EncoderState::Finished {
+            return Ok(None);
+        }
+
         let mut p = std::ptr::null_mut();
         unsafe {
+            let code = queue_wait(|| {
+                self.logged(|| EbH265GetPacket(self.handle.as_ptr(), &mut p, done as u8))
+            });
+
             #[allow(non_upper_case_globals)]
-            match EbH265GetPacket(self.handle.as_ptr(), &mut p, done as u8) {
+            match code {
                 EB_ERRORTYPE_EB_NoErrorEmptyQueue => return Ok(None),
-                code => result(code)?,
+                code => result(code).map_err(self.in_call(EncoderCall::GetPacket, None))?,
+            }
+
+            let mut packet = HevcPacket::new(p);
+            if packet.is_eos() {
+                self.state.set_finished();
             }
 
-            Ok(Some(HevcPacket::new(p)))
+            packet.timebase = Some(self.timebase);
+            if !packet.as_bytes().is_empty() {
+                let intra = matches!(packet.nalu_type(), NaluType::I | NaluType::IDR);
+                if let Some(signal) = self.video_signal.filter(|_| intra) {
+                    packet.data = self.rewrite_sps(&signal, packet.as_bytes())?;
+                }
+
+                if let Some(orientation) = self.orientation.filter(|_| intra) {
+                    let nal = sei::display_orientation_nal(&orientation);
+                    packet.data = Some(sei::insert_prefix_nal(packet.as_bytes(), &nal));
+                }
+
+                packet.output = self.gop.lock().unwrap().record_output(packet.pts(), intra);
+                if let Some((_, model)) = &self.hrd {
+                    let bits = packet.as_bytes().len() as u64 * 8;
+                    packet.cpb_fullness = Some(model.lock().unwrap().push(bits));
+                }
+
+                if let Some(n) = packet.output.picture_number {
+                    let mut last_idr = self.last_idr.lock().unwrap();
+                    if packet.nalu_type() == NaluType::IDR {
+                        *last_idr = n;
+                    }
+
+                    packet.poc = Some((n - *last_idr) as u32);
+                }
+
+                let mut stats = self.stats.lock().unwrap();
+                stats.record_output(packet.as_bytes().len(), packet.qp(), intra);
+                if packet.completed_pts().is_some() {
+                    stats.record_completed();
+                }
+            }
+
+            #[cfg(feature = "tracing")]
+            {
+                span.record("pts", packet.pts());
+                span.record("size", packet.as_bytes().len());
+            }
+
+            Ok(Some(packet))
         }
     }
 
@@ -173,7 +261,26 @@ impl Encoder for HevcEncoder {
             ..Default::default()
         };
 
-        unsafe { result(EbH265EncSendPicture(self.handle.as_ptr(), &mut input)) }
+        let _guard = self.state.lock(&self.send_lock)?;
+        if !self.state.should_finish()? {
+            return Ok(());
+        }
+
+        unsafe {
+            result(self.logged(|| EbH265EncSendPicture(self.handle.as_ptr(), &mut input)))
+                .map_err(self.in_call(EncoderCall::Finish, None))?
+        }
+
+        self.state.set_draining();
+        Ok(())
+    }
+
+    fn state(&self) -> EncoderState {
+        self.state.get()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.stats.lock().unwrap().pending() as usize
     }
 }
 
@@ -188,7 +295,11 @@ impl HevcEncoder {
         handle: *mut EB_COMPONENTTYPE,
         cfg: *mut EB_H265_ENC_CONFIGURATION,
     ) -> Self {
-        let subsampling_format = match (*cfg).encoderColorFormat {
+        Self::new(LibraryHandle(handle), &*cfg)
+    }
+
+    fn new(handle: LibraryHandle, cfg: &EB_H265_ENC_CONFIGURATION) -> Self {
+        let subsampling_format = match cfg.encoderColorFormat {
             0 => SubsamplingFormat::Yuv400,
             1 => SubsamplingFormat::Yuv420,
             2 => SubsamplingFormat::Yuv422,
@@ -196,16 +307,366 @@ impl HevcEncoder {
             _ => panic!("invalid subsampling format"),
         };
 
-        let intra_refresh_type = match (*cfg).intraRefreshType {
+        let intra_refresh_type = match cfg.intraRefreshType {
             -1 => IntraRefreshType::Open,
             v => IntraRefreshType::Closed(v),
         };
 
         Self {
-            handle: LibraryHandle(handle),
+            handle,
+            channel_id: cfg.channelId,
+            width: cfg.sourceWidth,
+            height: cfg.sourceHeight,
             subsampling_format,
             intra_refresh_type,
+            bit_depth: cfg.encoderBitDepth,
+            compressed_ten_bit: cfg.compressedTenBitFormat != 0,
+            interlaced: cfg.interlacedVideo != 0,
+            stats: Mutex::new(StatsTracker::new(
+                cfg.frameRateNumerator as u32,
+                cfg.frameRateDenominator as u32,
+            )),
+            gop: Mutex::new(GopTracker::new(
+                cfg.intraPeriodLength,
+                cfg.sceneChangeDetection != 0,
+            )),
+            // Only random access reorders frames.
+            reorder_depth: if cfg.predStructure == 2 {
+                cfg.hierarchicalLevels
+            } else {
+                0
+            },
+            hrd: HrdParameters::from_cfg(cfg)
+                .filter(|_| cfg.hrdFlag != 0)
+                .map(|params| (params, Mutex::new(HrdModel::new(params)))),
+            last_idr: Mutex::new(0),
+            max_pending_frames: None,
+            drop_timeout: shutdown::DEFAULT_DROP_TIMEOUT,
+            padding: None,
+            monochrome: None,
+            orientation: None,
+            video_signal: None,
+            timebase: if cfg.frameRateNumerator > 0 && cfg.frameRateDenominator > 0 {
+                Rational::from_framerate(
+                    cfg.frameRateNumerator as u32,
+                    cfg.frameRateDenominator as u32,
+                )
+            } else {
+                Rational::from_framerate(cfg.frameRate.max(1) as u32, 1)
+            },
+            #[cfg(feature = "log")]
+            log_sink: None,
+            state: StateTracker::new(),
+            send_lock: Mutex::new(()),
+            recv_lock: Mutex::new(()),
+        }
+    }
+
+    /// Sends an input picture to the encoder, like [`Encoder::send_picture`],
+    /// and attaches an unregistered user data SEI message to the resulting
+    /// frame.
+    ///
+    /// The encoder must have been created with
+    /// [`HevcEncoderConfig::unregistered_user_data_sei`] enabled.
+    pub fn send_picture_with_sei(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+        sei: &UserDataSei,
+    ) -> Result<(), Error> {
+        self.send_input(
+            picture,
+            None,
+            pts,
+            frame_type.into(),
+            PictureStructure::Frame,
+            Some(sei),
+        )
+    }
+
+    /// Sends a picture in the compressed 10-bit format to the encoder. The
+    /// encoder must have been created with
+    /// [`HevcEncoderConfig::compressed_ten_bit_format`] enabled.
+    pub fn send_compressed_picture(
+        &self,
+        picture: &impl CompressedTenBitPicture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        let lsb = [Plane::Y, Plane::U, Plane::V].map(|p| picture.lsb_slice(p));
+        self.send_input(
+            picture,
+            Some(lsb),
+            pts,
+            frame_type.into(),
+            PictureStructure::Frame,
+            None,
+        )
+    }
+
+    /// Sends a single field of an interlaced frame to the encoder, like
+    /// [`Encoder::send_picture`]. Fields are encoded as separate pictures, so
+    /// each field should have its own timestamp.
+    ///
+    /// The encoder must have been created with
+    /// [`HevcEncoderConfig::enable_interlaced_video`] enabled for fields to
+    /// be signaled.
+    pub fn send_field(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+        structure: PictureStructure,
+    ) -> Result<(), Error> {
+        self.send_input(picture, None, pts, frame_type.into(), structure, None)
+    }
+
+    fn send_input(
+        &self,
+        picture: &impl Picture,
+        lsb: Option<[&[u8]; 3]>,
+        pts: i64,
+        frame_type: FrameTypeRequest,
+        structure: PictureStructure,
+        sei: Option<&UserDataSei>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("send_picture", codec = "hevc", pts).entered();
+
+        if lsb.is_some() != self.compressed_ten_bit {
+            return Err(Error::BadParameter {
+                detail: Some(if self.compressed_ten_bit {
+                    "pictures must be sent with send_compressed_picture".to_owned()
+                } else {
+                    "compressed 10-bit format is not enabled".to_owned()
+                }),
+            });
+        }
+
+        if structure != PictureStructure::Frame && !self.interlaced {
+            return Err(Error::BadParameter {
+                detail: Some("interlaced video is not enabled".to_owned()),
+            });
+        }
+
+        let mut layout = PlaneLayout::new(picture, &self.picture_requirements())?;
+
+        // Keep the padded luma and neutral chroma locked until the library
+        // has copied them.
+        let _luma = self
+            .padding
+            .as_ref()
+            .map(|padding| padding.pad(picture, &mut layout));
+        let _chroma = self.monochrome.as_ref().map(|chroma| {
+            let mut chroma = chroma.lock().unwrap();
+            chroma.fill(&mut layout, self.width, self.height);
+            chroma
+        });
+
+        let mut input_pic = EB_H265_ENC_INPUT {
+            luma: layout.luma as *mut _,
+            cb: layout.cb as *mut _,
+            cr: layout.cr as *mut _,
+            yStride: layout.y_stride,
+            cbStride: layout.cb_stride,
+            crStride: layout.cr_stride,
+            ..Default::default()
+        };
+
+        if let Some([y_lsb, u_lsb, v_lsb]) = lsb {
+            // Each byte packs the low bits of four samples.
+            for (plane, lsb) in [(Plane::Y, y_lsb), (Plane::U, u_lsb), (Plane::V, v_lsb)] {
+                let expected = picture.as_slice(plane).len() / 4;
+                if lsb.len() != expected {
+                    return Err(Error::InvalidPicture {
+                        expected: format!("{} bytes in the {:?} LSB plane", expected, plane),
+                        got: format!("{} bytes", lsb.len()),
+                    });
+                }
+            }
+
+            input_pic.lumaExt = y_lsb.as_ptr() as *mut _;
+            input_pic.cbExt = u_lsb.as_ptr() as *mut _;
+            input_pic.crExt = v_lsb.as_ptr() as *mut _;
+        }
+
+        let slice_type = match frame_type {
+            FrameTypeRequest::Auto => EB_INVALID_PICTURE,
+            FrameTypeRequest::Key => match self.intra_refresh_type {
+                IntraRefreshType::Open => EB_I_PICTURE,
+                IntraRefreshType::Closed(_) => EB_IDR_PICTURE,
+            },
+            FrameTypeRequest::Idr => EB_IDR_PICTURE,
+            FrameTypeRequest::Cra => EB_I_PICTURE,
+            v @ (FrameTypeRequest::IntraOnly | FrameTypeRequest::AltRef) => {
+                return Err(Error::BadParameter {
+                    detail: Some(format!("unsupported frame type for HEVC: {:?}", v)),
+                })
+            }
+        };
+
+        let _guard = self.state.lock(&self.send_lock)?;
+        self.state.check_running()?;
+
+        let mut stats = self.stats.lock().unwrap();
+        let mut input = EB_BUFFERHEADERTYPE {
+            nSize: size_of::<EB_BUFFERHEADERTYPE>() as u32,
+            pBuffer: &mut input_pic as *mut _ as *mut u8,
+            nFilledLen: layout.len as u32,
+            pts,
+            sliceType: slice_type,
+            pictStruct: match structure {
+                PictureStructure::Frame => 0,
+                PictureStructure::TopField => 1,
+                PictureStructure::BottomField => 2,
+            },
+            ..Default::default()
+        };
+
+        // The library decodes and copies the payload before returning.
+        let payload = sei.map(UserDataSei::encode);
+        if let Some(payload) = &payload {
+            input.naluFound = 1;
+            input.naluPOC = stats.snapshot().frames_submitted as u32;
+            input.naluPrefix = 0;
+            input.naluNalType = sei::NAL_UNIT_PREFIX_SEI;
+            input.naluPayloadType = sei::SEI_USER_DATA_UNREGISTERED;
+            input.naluBase64Encode = payload.as_ptr() as *mut u8;
+        }
+
+        let forced = slice_type != EB_INVALID_PICTURE;
+        self.gop.lock().unwrap().record_submitted(pts, forced);
+        let res = unsafe {
+            result(queue_wait(|| {
+                self.logged(|| EbH265EncSendPicture(self.handle.as_ptr(), &mut input))
+            }))
+            .map_err(self.in_call(EncoderCall::SendPicture, Some(pts)))
+        };
+
+        if let Err(e) = res {
+            self.gop.lock().unwrap().cancel(pts);
+            return Err(e);
         }
+
+        stats.record_submitted();
+        Ok(())
+    }
+
+    /// The parameters of the HRD coded picture buffer, if
+    /// [`HevcEncoderConfig::enable_hrd`] was set and a VBV buffer size was
+    /// configured. These can be passed to [`check_conformance`] to validate
+    /// a stream.
+    pub fn hrd_parameters(&self) -> Option<HrdParameters> {
+        self.hrd.as_ref().map(|(params, _)| *params)
+    }
+
+    /// The timebase of the presentation timestamps passed to the encoder and
+    /// reported on packets. See [`HevcEncoderConfig::timebase`].
+    pub fn timebase(&self) -> Rational {
+        self.timebase
+    }
+
+    /// Sends an input picture to the encoder, like [`Encoder::send_picture`],
+    /// with a timestamp in any timebase. The timestamp is converted to the
+    /// encoder's timebase.
+    pub fn send_picture_at(
+        &self,
+        picture: &impl Picture,
+        timestamp: Timestamp,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        let pts = timestamp.rescale(self.timebase).value;
+        self.send_picture(picture, pts, frame_type)
+    }
+
+    /// Sends an input picture to the encoder, like [`Encoder::send_picture`],
+    /// unless the limit set with [`HevcEncoderConfig::max_pending_frames`] has been
+    /// reached, in which case [`Error::WouldBlock`] is returned. Real-time
+    /// applications can use this to drop frames rather than stall.
+    pub fn try_send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        if self.available_input_buffers() == Some(0) {
+            return Err(Error::WouldBlock);
+        }
+
+        self.send_picture(picture, pts, frame_type)
+    }
+
+    /// The number of pictures that can be submitted before reaching the limit
+    /// set with [`HevcEncoderConfig::max_pending_frames`], or `None` if there is no
+    /// limit.
+    pub fn available_input_buffers(&self) -> Option<u32> {
+        let pending = self.stats.lock().unwrap().pending();
+        self.max_pending_frames
+            .map(|max| (max as u64).saturating_sub(pending) as u32)
+    }
+
+    /// The maximum number of frames by which output is delayed because of
+    /// frame reordering. This is zero for the low delay prediction
+    /// structures, and otherwise the number of hierarchical levels. See
+    /// [`TimestampReorderer`](crate::TimestampReorderer).
+    pub fn reorder_depth(&self) -> u32 {
+        self.reorder_depth
+    }
+
+    /// The display orientation set with
+    /// [`HevcEncoderConfig::display_orientation`].
+    pub fn display_orientation(&self) -> Option<Orientation> {
+        self.orientation
+    }
+
+    /// The size of the encoded pictures. This is larger than the pictures sent
+    /// to the encoder if they have odd dimensions that the chroma subsampling
+    /// can't represent, in which case a container can crop the difference.
+    pub fn coded_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Finishes the stream, waits at most `timeout` for the remaining
+    /// packets, and shuts the encoder down. Any packets not yet retrieved are
+    /// discarded. Returns [`Error::TimedOut`] if the stream didn't finish in
+    /// time, in which case the encoder is deinitialized anyway.
+    ///
+    /// Dropping the encoder does the same, with the timeout set by
+    /// [`HevcEncoderConfig::drop_timeout`], but ignores any errors.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<(), Error> {
+        let res = shutdown::drain(&self, timeout);
+        self.drop_timeout = Duration::ZERO;
+        res
+    }
+
+    /// The dimensions and format of the pictures the encoder accepts. With
+    /// the compressed 10-bit format, this describes the main planes, which
+    /// hold one byte per sample.
+    pub fn picture_requirements(&self) -> PictureRequirements {
+        let (width, height) = match &self.padding {
+            Some(padding) => (padding.width, padding.height),
+            None => (self.width, self.height),
+        };
+
+        PictureRequirements {
+            width,
+            height,
+            subsampling_format: match self.monochrome {
+                Some(_) => SubsamplingFormat::Yuv400,
+                None => self.subsampling_format,
+            },
+            bytes_per_sample: if self.bit_depth > 8 && !self.compressed_ten_bit {
+                2
+            } else {
+                1
+            },
+        }
+    }
+
+    /// Returns statistics about the encode so far.
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().snapshot()
     }
 
     /// Generates a VPS/SPS/PPS header NAL unit.
@@ -213,57 +674,135 @@ impl HevcEncoder {
     /// This is not generally necessary, as the encoder will automatically
     /// generate headers as needed.
     pub fn code_headers(&self) -> Result<HevcPacket, Error> {
+        self.state.check_poisoned()?;
+
         let mut p = std::ptr::null_mut();
         unsafe {
-            result(EbH265EncStreamHeader(self.handle.as_ptr(), &mut p))?;
+            result(self.logged(|| EbH265EncStreamHeader(self.handle.as_ptr(), &mut p)))?;
 
-            Ok(HevcPacket::new_headers(p))
+            let mut packet = HevcPacket::new_headers(p);
+            if let Some(signal) = &self.video_signal {
+                packet.data = self.rewrite_sps(signal, packet.as_bytes())?;
+            }
+
+            Ok(packet)
         }
     }
 
+    /// Writes the configured video signal type into any SPS NAL units in
+    /// `data`.
+    fn rewrite_sps(&self, signal: &VideoSignal, data: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        // The headers were already rewritten once when the encoder was
+        // created, so this isn't expected.
+        signal.apply(data).map_err(|e| Error::BadParameter {
+            detail: Some(format!("failed to write video signal type: {}", e)),
+        })
+    }
+
     /// Generates an EOS (end-of-stream) NAL unit.
     ///
     /// This is not generally necessary, as the encoder will automatically
     /// generate EOS NAL units at the end of the stream.
     pub fn code_eos(&self) -> Result<HevcPacket, Error> {
+        self.state.check_poisoned()?;
+
         let mut p = std::ptr::null_mut();
         unsafe {
-            result(EbH265EncEosNal(self.handle.as_ptr(), &mut p))?;
+            result(self.logged(|| EbH265EncEosNal(self.handle.as_ptr(), &mut p)))?;
 
             Ok(HevcPacket::new_eos(p))
         }
     }
+
+    /// Attaches the context of a failed call to a library error.
+    fn in_call(&self, call: EncoderCall, pts: Option<i64>) -> impl FnOnce(Error) -> Error {
+        let channel_id = self.channel_id;
+        move |e| e.in_call(call, pts, Some(channel_id))
+    }
+
+    /// Calls `f`, passing any messages logged by the library on the current
+    /// thread in the meantime to the encoder's log sink, if one is set.
+    ///
+    /// If a panic was caught in the log callback, the encoder is poisoned.
+    fn logged<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "log")]
+        {
+            let v = match &self.log_sink {
+                Some(sink) => with_log_sink(sink.clone(), f),
+                None => f(),
+            };
+
+            // The panic hook has already reported the panic.
+            if take_callback_panic().is_some() {
+                self.state.set_poisoned();
+            }
+
+            v
+        }
+
+        #[cfg(not(feature = "log"))]
+        f()
+    }
 }
 
 impl Drop for HevcEncoder {
     fn drop(&mut self) {
+        // Deinitializing the library while pictures are in flight can hang.
+        // Drains can't be attempted while unwinding, since the locks may be
+        // poisoned.
+        if !std::thread::panicking() {
+            let _ = shutdown::drain(&*self, self.drop_timeout);
+        }
+
         unsafe {
-            EbDeinitEncoder(self.handle.as_ptr());
+            self.logged(|| EbDeinitEncoder(self.handle.as_ptr()));
         }
     }
 }
 
-#[allow(non_upper_case_globals)]
 pub(crate) fn result(code: EB_ERRORTYPE) -> Result<(), Error> {
-    match code {
-        0 => Ok(()),
-        EB_ERRORTYPE_EB_ErrorInsufficientResources => Err(Error::InsufficientResources),
-        EB_ERRORTYPE_EB_ErrorUndefined => Err(Error::Undefined),
-        EB_ERRORTYPE_EB_ErrorInvalidComponent => Err(Error::InvalidComponent),
-        EB_ERRORTYPE_EB_ErrorBadParameter => Err(Error::BadParameter),
-        EB_ERRORTYPE_EB_ErrorDestroyThreadFailed => Err(Error::DestroyThreadFailed),
-        EB_ERRORTYPE_EB_ErrorSemaphoreUnresponsive => Err(Error::SemaphoreUnresponsive),
-        EB_ERRORTYPE_EB_ErrorDestroySemaphoreFailed => Err(Error::DestroySemaphoreFailed),
-        EB_ERRORTYPE_EB_ErrorCreateMutexFailed => Err(Error::CreateMutexFailed),
-        EB_ERRORTYPE_EB_ErrorMutexUnresponsive => Err(Error::MutexUnresponsive),
-        EB_ERRORTYPE_EB_ErrorDestroyMutexFailed => Err(Error::DestroyMutexFailed),
-        _ => Err(Error::Unknown(code)),
+    Error::check(code)
+}
+
+/// Wraps a log sink for records from the given channel into one accepted by
+/// the library.
+#[cfg(feature = "log")]
+fn channel_log_sink(sink: crate::logging::LogSink, channel_id: u32) -> LogSink {
+    std::sync::Arc::new(move |m: &LogMessage<'_>| {
+        sink(&crate::LogRecord {
+            level: m.level,
+            target: m.target,
+            message: m.message,
+            channel_id,
+        })
+    })
+}
+
+/// Like [`result`], but attaches any errors logged by the library during `f`
+/// to a resulting [`Error::BadParameter`].
+pub(crate) fn result_with_detail(f: impl FnOnce() -> EB_ERRORTYPE) -> Result<(), Error> {
+    #[cfg(feature = "log")]
+    {
+        let (code, messages) = capture_messages(f);
+        match result(code) {
+            Err(Error::BadParameter { .. }) if !messages.is_empty() => Err(Error::BadParameter {
+                detail: Some(messages.join("\n")),
+            }),
+            res => res,
+        }
     }
+
+    #[cfg(not(feature = "log"))]
+    result(f())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::YUVBuffer;
+    use crate::hdr::{ContentLightLevel, MasteringDisplay};
+    use crate::nal::nal_units;
+    use crate::{
+        ChromaSamplePosition, ColorDescription, ColorRange, Rotation, YUVBuffer, YUVBuffer16,
+    };
 
     use super::*;
 
@@ -280,6 +819,7 @@ mod tests {
 
         enc.send_picture(&buf, 0, false)
             .expect("failed to send picture");
+        assert_eq!(enc.stats().frames_submitted, 1);
 
         let _packet = enc.get_packet(false).expect("failed to get packet");
 
@@ -288,6 +828,480 @@ mod tests {
         let _packet = enc.get_packet(true).expect("failed to get final packet");
     }
 
+    #[test]
+    fn shutdown_mid_stream() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..10 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        enc.shutdown(Duration::from_secs(30))
+            .expect("failed to shut down");
+    }
+
+    #[test]
+    fn encode_frame_with_sei() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .unregistered_user_data_sei(true)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        let sei = UserDataSei::new([0xab; 16], b"hello".as_slice());
+
+        enc.send_picture_with_sei(&buf, 0, true, &sei)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let _packet = enc.get_packet(true).expect("failed to get packet");
+    }
+
+    #[test]
+    fn create_encoder_with_hdr_metadata() {
+        simple_logger::init_with_env().ok();
+
+        let _enc = HevcEncoderConfig::default()
+            .preset(9)
+            .encoder_bit_depth(10)
+            .code_vui(true)
+            .hdr_input(true)
+            .mastering_display(MasteringDisplay::bt2020(1000.0, 0.0001))
+            .content_light_level(ContentLightLevel {
+                max_content_light_level: 1000,
+                max_frame_average_light_level: 400,
+            })
+            .dolby_vision_profile(DolbyVisionProfile::Profile8_1)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    fn create_encoder_with_tiles() {
+        simple_logger::init_with_env().ok();
+
+        let _enc = HevcEncoderConfig::default()
+            .preset(9)
+            .tiling(TilingMode::Multi {
+                columns: 2,
+                rows: 2,
+            })
+            .slice_mode(SliceMode::SinglePerPicture)
+            .frames_to_be_encoded(100)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    fn send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<HevcEncoder>();
+    }
+
+    #[test]
+    fn split_encoder() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let (sender, receiver) = enc.split();
+        let receiver = std::thread::spawn(move || {
+            let mut frames = 0;
+            loop {
+                let packet = receiver
+                    .get_packet(true)
+                    .expect("failed to get packet")
+                    .expect("no packet");
+                if !packet.as_bytes().is_empty() {
+                    frames += 1;
+                }
+
+                if packet.is_eos() {
+                    break frames;
+                }
+            }
+        });
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..60 {
+            sender
+                .send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        sender.finish().expect("failed to finish");
+        let frames = receiver.join().expect("receiver panicked");
+        assert!(frames >= 60);
+    }
+
+    #[test]
+    fn try_send_picture() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .max_pending_frames(2)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        assert_eq!(enc.available_input_buffers(), Some(2));
+        enc.try_send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        enc.try_send_picture(&buf, 1, false)
+            .expect("failed to send picture");
+
+        if enc.available_input_buffers() == Some(0) {
+            let err = enc
+                .try_send_picture(&buf, 2, false)
+                .expect_err("expected WouldBlock");
+            assert!(matches!(err, Error::WouldBlock));
+        }
+
+        enc.finish().expect("failed to finish");
+    }
+
+    #[test]
+    fn picture_order() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        for pts in 0..16 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        enc.finish().expect("failed to finish");
+        let mut pictures = Vec::new();
+        loop {
+            let packet = enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet");
+            if let Some(n) = packet.picture_number() {
+                assert_eq!(packet.decode_order(), pictures.len() as u64);
+                assert_eq!(packet.poc(), Some(n as u32));
+                pictures.push(n);
+            }
+
+            if packet.is_eos() {
+                break;
+            }
+        }
+
+        pictures.sort();
+        assert_eq!(pictures, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn encode_compressed_ten_bit() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .encoder_bit_depth(10)
+            .compressed_ten_bit_format(true)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let mut source = YUVBuffer16::new(320, 240, SubsamplingFormat::Yuv420);
+        source.as_mut_slice(Plane::Y).fill(0x201);
+
+        let mut buf = CompressedTenBitBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        buf.copy_from(&source);
+
+        assert!(matches!(
+            enc.send_picture(&buf, 0, false),
+            Err(Error::BadParameter { .. })
+        ));
+
+        enc.send_compressed_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let _packet = enc.get_packet(true).expect("failed to get packet");
+    }
+
+    #[test]
+    fn encode_monochrome() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv400)
+            .expect("failed to create encoder");
+        assert_eq!(
+            enc.picture_requirements().subsampling_format,
+            SubsamplingFormat::Yuv400
+        );
+
+        let mut buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv400);
+        buf.as_mut_slice(Plane::Y).fill(200);
+        for pts in 0..4 {
+            enc.send_picture(&buf, pts, false)
+                .expect("failed to send picture");
+        }
+
+        enc.finish().expect("failed to finish");
+
+        let mut frames = 0;
+        loop {
+            let packet = enc
+                .get_packet(true)
+                .expect("failed to get packet")
+                .expect("no packet");
+            if packet.is_eos() {
+                break;
+            }
+
+            frames += 1;
+        }
+
+        assert_eq!(frames, 4);
+
+        let err = HevcEncoderConfig::default()
+            .encoder_bit_depth(10)
+            .compressed_ten_bit_format(true)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv400)
+            .expect_err("expected an error");
+        assert!(matches!(err, Error::BadParameter { .. }));
+    }
+
+    #[test]
+    fn hvcc() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let headers = enc.code_headers().expect("failed to code headers");
+        let hvcc = headers.to_hvcc().expect("failed to build hvcC");
+
+        // Version 1, main profile, 4:2:0, 8-bit, and three parameter sets.
+        assert_eq!(hvcc[..2], [0x01, 0x01]);
+        assert_eq!(hvcc[16..19], [0xfd, 0xf8, 0xf8]);
+        assert_eq!(hvcc[22], 3);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = HevcSettings {
+            preset: Some(9),
+            framerate: Some((25, 1)),
+            intra_period: Some(IntraPeriod::Fixed(50)),
+            pred_structure: Some(PredictionStructure::LowDelayB),
+            rate_control: Some(RateControlMode::VariableBitrate),
+            target_bitrate: Some(3_000_000),
+            tiling: Some(TilingMode::Multi {
+                columns: 2,
+                rows: 2,
+            }),
+            ..Default::default()
+        };
+
+        let applied = HevcEncoderConfig::from(settings).settings();
+        assert_eq!(applied.preset, settings.preset);
+        assert_eq!(applied.framerate, settings.framerate);
+        assert_eq!(applied.intra_period, settings.intra_period);
+        assert_eq!(applied.pred_structure, settings.pred_structure);
+        assert_eq!(applied.rate_control, settings.rate_control);
+        assert_eq!(applied.target_bitrate, settings.target_bitrate);
+        assert_eq!(applied.tiling, settings.tiling);
+
+        let reapplied = HevcEncoderConfig::default().apply_settings(&applied);
+        assert_eq!(reapplied.settings(), applied);
+    }
+
+    #[test]
+    fn encode_odd_dimensions() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .create_encoder(321, 241, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.coded_size(), (322, 242));
+
+        let requirements = enc.picture_requirements();
+        assert_eq!((requirements.width, requirements.height), (321, 241));
+
+        let buf = YUVBuffer::new(321, 241, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, false)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let packet = enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet");
+        assert!(!packet.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn display_orientation() {
+        simple_logger::init_with_env().ok();
+
+        let orientation = Orientation::rotated(Rotation::Ccw270);
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .display_orientation(orientation)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert_eq!(enc.display_orientation(), Some(orientation));
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, true)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let packet = enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet");
+        let nal = sei::display_orientation_nal(&orientation);
+        assert!(packet
+            .as_bytes()
+            .windows(nal.len())
+            .any(|w| w == nal.as_slice()));
+    }
+
+    #[test]
+    fn level_exceeded() {
+        simple_logger::init_with_env().ok();
+
+        let err = HevcEncoderConfig::default()
+            .rate_control_mode(RateControlMode::VariableBitrate)
+            .target_bitrate(20_000_000)
+            .level(Level::L4.into())
+            .create_encoder(1280, 720, SubsamplingFormat::Yuv420)
+            .expect_err("expected EB_BadParameter");
+        assert!(matches!(
+            err,
+            Error::BadParameter {
+                detail: Some(ref detail)
+            } if detail.contains("main tier maximum")
+        ));
+
+        let _enc = HevcEncoderConfig::default()
+            .preset(9)
+            .rate_control_mode(RateControlMode::VariableBitrate)
+            .target_bitrate(20_000_000)
+            .tier(HevcTier::High)
+            .level(Level::L4.into())
+            .create_encoder(1280, 720, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+    }
+
+    #[test]
+    fn color_description() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .color_description(ColorDescription::Bt709)
+            .color_range(ColorRange::Full)
+            .chroma_sample_position(ChromaSamplePosition::Vertical)
+            .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, true)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let packet = enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet");
+
+        // Rewriting the SPS again doesn't change it.
+        let signal = VideoSignal {
+            description: ColorDescription::Bt709,
+            range: ColorRange::Full,
+            chroma_sample_position: ChromaSamplePosition::Vertical,
+        };
+        let data = signal
+            .apply(packet.as_bytes())
+            .expect("failed to parse SPS")
+            .expect("no SPS");
+        assert_eq!(data, packet.as_bytes());
+
+        let headers = enc.code_headers().expect("failed to code headers");
+        let sps = nal_units(headers.as_bytes())
+            .map(|nal| nal.unwrap())
+            .find(|nal| nal.nal_type() == 33)
+            .expect("no SPS");
+        assert!(packet
+            .as_bytes()
+            .windows(sps.as_bytes().len())
+            .any(|w| w == sps.as_bytes()));
+    }
+
+    #[test]
+    fn encode_fields() {
+        simple_logger::init_with_env().ok();
+
+        let enc = HevcEncoderConfig::default()
+            .preset(9)
+            .enable_interlaced_video(true)
+            .code_picture_timing_sei(true)
+            .create_encoder(320, 120, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(320, 120, SubsamplingFormat::Yuv420);
+        for pts in 0..4 {
+            let structure = if pts % 2 == 0 {
+                PictureStructure::TopField
+            } else {
+                PictureStructure::BottomField
+            };
+
+            enc.send_field(&buf, pts, false, structure)
+                .expect("failed to send field");
+        }
+
+        enc.finish().expect("failed to finish");
+
+        let mut frames = 0;
+        while let Some(packet) = enc.get_packet(true).expect("failed to get packet") {
+            if packet.is_eos() {
+                break;
+            }
+
+            frames += 1;
+        }
+
+        assert_eq!(frames, 4);
+
+        // Fields require interlaced signaling.
+        let enc = HevcEncoderConfig::default()
+            .create_encoder(320, 120, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+        assert!(matches!(
+            enc.send_field(&buf, 0, false, PictureStructure::TopField),
+            Err(Error::BadParameter { .. })
+        ));
+    }
+
     #[test]
     fn create_encoder_error() {
         simple_logger::init_with_env().ok();
@@ -1,11 +1,29 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
 use svt_hevc_sys::*;
 
-use crate::{Error, SubsamplingFormat};
+use crate::buffer::{coded_size, EdgePadding, NeutralChroma};
+use crate::hdr::{fixed, ContentLightLevel, MasteringDisplay};
+use crate::{
+    ChannelConfig, ChromaSamplePosition, ColorDescription, ColorRange, EncoderConfig, Error,
+    HevcQp, Orientation, RateControl, Rational, Stats, SubsamplingFormat,
+};
+
+use super::vui::VideoSignal;
+use super::{result_with_detail, HevcEncoder, Level, LibraryHandle};
+use crate::level::StreamParameters;
 
-use super::{result, HevcEncoder, LibraryHandle};
+mod settings;
+pub use settings::HevcSettings;
 
 /// How often (in frames) to insert an intra refresh.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum IntraPeriod {
     /// Automatically determine the intra period.
     Auto,
@@ -26,6 +44,11 @@ pub enum IntraRefreshType {
 
 /// The prediction structure for each GOP.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum PredictionStructure {
     /// Forward prediction only, using P frames.
     LowDelayP,
@@ -46,6 +69,11 @@ pub enum BaseLayerSwitchMode {
 
 /// The tiling mode to use.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum TilingMode {
     /// Use a single tile.
     Single,
@@ -58,8 +86,32 @@ pub enum TilingMode {
     },
 }
 
+/// How pictures are divided into slices.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SliceMode {
+    /// Use a single slice for each picture, regardless of tiling.
+    SinglePerPicture,
+    /// Use one slice per tile.
+    PerTile,
+}
+
+/// The Dolby Vision profile to signal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DolbyVisionProfile {
+    /// Don't signal Dolby Vision.
+    None,
+    /// Profile 8.1, which is backwards compatible with HDR10. Requires 10-bit
+    /// input, with [`HevcEncoderConfig::mastering_display`] set.
+    Profile8_1,
+}
+
 /// The rate control mode to use.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
 pub enum RateControlMode {
     /// Use a constant quantization parameter.
     ConstantQp,
@@ -105,6 +157,19 @@ pub enum TargetSocket {
     Both,
 }
 
+/// The structure of an input picture, signaled in the picture timing SEI.
+/// Fields require [`HevcEncoderConfig::enable_interlaced_video`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PictureStructure {
+    /// A progressive frame.
+    #[default]
+    Frame,
+    /// The top field of an interlaced frame.
+    TopField,
+    /// The bottom field of an interlaced frame.
+    BottomField,
+}
+
 /// A helper for building an encode configuration.
 ///
 /// For configuration options, see the upstream docs:
@@ -112,6 +177,18 @@ pub enum TargetSocket {
 /// <https://github.com/OpenVisualCloud/SVT-HEVC/blob/master/Docs/svt-hevc_encoder_user_guide.md>
 pub struct HevcEncoderConfig {
     handle: LibraryHandle,
+    max_pending_frames: Option<u32>,
+    drop_timeout: Option<Duration>,
+    timebase: Option<Rational>,
+    orientation: Option<Orientation>,
+    color_description: Option<ColorDescription>,
+    color_range: Option<ColorRange>,
+    chroma_sample_position: Option<ChromaSamplePosition>,
+    /// An invalid QP passed to [`EncoderConfig::rate_control`], returned by
+    /// `create_encoder`.
+    rate_control_error: Option<Error>,
+    #[cfg(feature = "log")]
+    log_sink: Option<crate::logging::LogSink>,
     cfg: EB_H265_ENC_CONFIGURATION,
 }
 
@@ -126,6 +203,16 @@ impl Default for HevcEncoderConfig {
 
             HevcEncoderConfig {
                 handle: LibraryHandle(handle),
+                max_pending_frames: None,
+                drop_timeout: None,
+                timebase: None,
+                orientation: None,
+                color_description: None,
+                color_range: None,
+                chroma_sample_position: None,
+                rate_control_error: None,
+                #[cfg(feature = "log")]
+                log_sink: None,
                 cfg,
             }
         }
@@ -142,6 +229,12 @@ impl std::fmt::Debug for HevcEncoderConfig {
 
 impl HevcEncoderConfig {
     /// Creates a new encoder from the config.
+    ///
+    /// If the subsampling format requires even dimensions and `width` or
+    /// `height` is odd, the encoder pads each picture to the next even size by
+    /// repeating the last column or row. The stream has the padded size,
+    /// since a conformance window can't crop a single column or row of
+    /// subsampled chroma; see [`HevcEncoder::coded_size`].
     pub fn create_encoder(
         mut self,
         width: u32,
@@ -149,35 +242,130 @@ impl HevcEncoderConfig {
         subsampling_format: SubsamplingFormat,
     ) -> Result<HevcEncoder, Error> {
         // Set the frame size.
-        self.cfg.sourceWidth = width;
-        self.cfg.sourceHeight = height;
+        let (coded_width, coded_height) = coded_size(width, height, subsampling_format);
+        self.cfg.sourceWidth = coded_width;
+        self.cfg.sourceHeight = coded_height;
         self.cfg.encoderColorFormat = match subsampling_format {
-            SubsamplingFormat::Yuv400 => 0,
-            SubsamplingFormat::Yuv420 => 1,
+            // Monochrome input is encoded as 4:2:0, with neutral chroma.
+            SubsamplingFormat::Yuv400 | SubsamplingFormat::Yuv420 => 1,
             SubsamplingFormat::Yuv422 => 2,
             SubsamplingFormat::Yuv444 => 3,
         };
 
-        // Copy config parameters onto the encoder handle.
-        unsafe { result(EbH265EncSetParameter(self.handle.as_ptr(), &mut self.cfg))? }
+        let monochrome = subsampling_format == SubsamplingFormat::Yuv400;
+        if monochrome && self.cfg.compressedTenBitFormat != 0 {
+            return Err(Error::BadParameter {
+                detail: Some(
+                    "monochrome input isn't supported with the compressed 10-bit format".to_owned(),
+                ),
+            });
+        }
 
-        // Create the encoder.
-        unsafe { result(EbInitEncoder(self.handle.as_ptr()))? }
+        let padding = EdgePadding::new(width, height, subsampling_format);
+        if padding.is_some() && self.cfg.compressedTenBitFormat != 0 {
+            return Err(Error::BadParameter {
+                detail: Some(format!(
+                    "{}x{} {:?} input isn't supported with the compressed 10-bit format",
+                    width, height, subsampling_format
+                )),
+            });
+        }
 
-        Ok(HevcEncoder {
-            handle: self.handle,
-            intra_refresh_type: match self.cfg.intraRefreshType {
-                -1 => IntraRefreshType::Open,
-                gop_size => IntraRefreshType::Closed(gop_size),
-            },
-            subsampling_format: match self.cfg.encoderColorFormat {
-                0 => SubsamplingFormat::Yuv400,
-                1 => SubsamplingFormat::Yuv420,
-                2 => SubsamplingFormat::Yuv422,
-                3 => SubsamplingFormat::Yuv444,
-                _ => unreachable!(),
-            },
-        })
+        // The video signal type is written into the VUI of each SPS.
+        let video_signal = match (
+            self.color_description,
+            self.color_range,
+            self.chroma_sample_position,
+        ) {
+            (None, None, None) => None,
+            (description, range, chroma_sample_position) => {
+                self.cfg.videoUsabilityInfo = 1;
+                let default_description = if self.cfg.highDynamicRangeInput != 0 {
+                    ColorDescription::Bt2020Pq
+                } else {
+                    ColorDescription::Unspecified
+                };
+
+                Some(VideoSignal {
+                    description: description.unwrap_or(default_description),
+                    range: range.unwrap_or(ColorRange::Limited),
+                    chroma_sample_position: chroma_sample_position
+                        .unwrap_or(ChromaSamplePosition::Unknown),
+                })
+            }
+        };
+
+        if let Some(err) = self.rate_control_error.take() {
+            return Err(err);
+        }
+
+        check_level(&self.cfg)?;
+
+        #[cfg(feature = "log")]
+        let log_sink = self
+            .log_sink
+            .take()
+            .map(|sink| super::channel_log_sink(sink, self.cfg.channelId));
+
+        // Copy config parameters onto the encoder handle, then create the
+        // encoder.
+        let mut init = || unsafe {
+            result_with_detail(|| EbH265EncSetParameter(self.handle.as_ptr(), &mut self.cfg))?;
+            result_with_detail(|| EbInitEncoder(self.handle.as_ptr()))
+        };
+
+        #[cfg(feature = "log")]
+        {
+            let res = match &log_sink {
+                Some(sink) => with_log_sink(sink.clone(), init),
+                None => init(),
+            };
+
+            // The library's state can't be trusted if a panic in the log
+            // callback interrupted it.
+            if take_callback_panic().is_some() {
+                return Err(Error::Poisoned);
+            }
+
+            res?;
+        }
+
+        #[cfg(not(feature = "log"))]
+        init()?;
+
+        let mut encoder = HevcEncoder::new(self.handle, &self.cfg);
+        encoder.max_pending_frames = self.max_pending_frames;
+        if let Some(timeout) = self.drop_timeout {
+            encoder.drop_timeout = timeout;
+        }
+        encoder.padding = padding;
+        if monochrome {
+            let bytes_per_sample = if self.cfg.encoderBitDepth > 8 { 2 } else { 1 };
+            encoder.monochrome = Some(Mutex::new(NeutralChroma::new(
+                self.cfg.encoderBitDepth,
+                bytes_per_sample,
+            )));
+        }
+        #[cfg(feature = "log")]
+        {
+            encoder.log_sink = log_sink;
+        }
+        if let Some(timebase) = self.timebase {
+            encoder.timebase = timebase;
+        }
+        encoder.orientation = self.orientation;
+        encoder.video_signal = video_signal;
+
+        // Make sure the SPS the library writes can be rewritten.
+        if video_signal.is_some() {
+            if let Err(e) = encoder.code_headers() {
+                return Err(Error::BadParameter {
+                    detail: Some(format!("failed to write video signal type: {}", e)),
+                });
+            }
+        }
+
+        Ok(encoder)
     }
 
     /// Sets the encoder preset, from 0-11, with 0 being the highest quality and
@@ -187,6 +375,43 @@ impl HevcEncoderConfig {
         self
     }
 
+    /// Limits the number of pictures that can be submitted to the encoder
+    /// before the corresponding packets are retrieved. Once the limit is
+    /// reached, [`HevcEncoder::try_send_picture`] returns [`Error::WouldBlock`].
+    ///
+    /// This should be no larger than the number of input buffers the library
+    /// allocates, which depends on the look-ahead distance and prediction
+    /// structure; otherwise, sending a picture may still block.
+    pub fn max_pending_frames(mut self, frames: u32) -> Self {
+        self.max_pending_frames = Some(frames);
+        self
+    }
+
+    /// Sets how long dropping the encoder waits for the stream to finish
+    /// before deinitializing the library, which can hang if pictures are
+    /// still being encoded. The default is five seconds. See
+    /// [`HevcEncoder::shutdown`].
+    pub fn drop_timeout(mut self, timeout: Duration) -> Self {
+        self.drop_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the timebase of the presentation timestamps passed to the
+    /// encoder. Timestamps passed to [`HevcEncoder::send_picture_at`] are
+    /// converted to this timebase. By default, timestamps are counted in
+    /// frames at the configured framerate.
+    pub fn timebase(mut self, timebase: Rational) -> Self {
+        self.timebase = Some(timebase);
+        self
+    }
+
+    /// Sets how decoded pictures should be oriented for display. The encoder
+    /// writes a display orientation SEI message before each intra picture.
+    pub fn display_orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = Some(orientation);
+        self
+    }
+
     /// Sets the intra refresh period.
     pub fn intra_period_length(mut self, intra_period_length: IntraPeriod) -> Self {
         self.cfg.intraPeriodLength = match intra_period_length {
@@ -242,6 +467,14 @@ impl HevcEncoderConfig {
         self
     }
 
+    /// Sets the total number of frames that will be encoded, if known. This
+    /// helps the rate control algorithm distribute bits, but is otherwise
+    /// optional.
+    pub fn frames_to_be_encoded(mut self, frames: u64) -> Self {
+        self.cfg.framesToBeEncoded = frames;
+        self
+    }
+
     /// Sets the input bit depth (8 or 10).
     pub fn encoder_bit_depth(mut self, bit_depth: u32) -> Self {
         self.cfg.encoderBitDepth = bit_depth;
@@ -249,7 +482,9 @@ impl HevcEncoderConfig {
     }
 
     /// Configures the encoder to expect a special format for the input, where
-    /// the extra two bits in 10-bit are packed.
+    /// the extra two bits in 10-bit are packed. Pictures must then be sent
+    /// with [`HevcEncoder::send_compressed_picture`]; see
+    /// [`CompressedTenBitBuffer`](super::CompressedTenBitBuffer).
     pub fn compressed_ten_bit_format(mut self, v: bool) -> Self {
         self.cfg.compressedTenBitFormat = v as u32;
         self
@@ -267,15 +502,17 @@ impl HevcEncoderConfig {
         self
     }
 
-    /// Enables SEI messages with interlaced signaling.
+    /// Enables SEI messages with interlaced signaling. Fields can then be
+    /// submitted with [`HevcEncoder::send_field`], with the encoder
+    /// dimensions set to those of a single field.
     pub fn enable_interlaced_video(mut self, v: bool) -> Self {
         self.cfg.interlacedVideo = v as u8;
         self
     }
 
     /// Sets the target QP for [RateControlMode::ConstantQp].
-    pub fn qp(mut self, qp: u32) -> Self {
-        self.cfg.qp = qp;
+    pub fn qp(mut self, qp: HevcQp) -> Self {
+        self.cfg.qp = qp.get();
         self
     }
 
@@ -295,6 +532,18 @@ impl HevcEncoderConfig {
         self
     }
 
+    /// Sets how pictures are divided into slices. This overrides the slice
+    /// mode set by [`HevcEncoderConfig::tiling`], so it should be called
+    /// afterwards.
+    pub fn slice_mode(mut self, mode: SliceMode) -> Self {
+        self.cfg.tileSliceMode = match mode {
+            SliceMode::SinglePerPicture => 0,
+            SliceMode::PerTile => 1,
+        };
+
+        self
+    }
+
     /// Disables deblocking loop filtering.
     pub fn disable_dlf(mut self, v: bool) -> Self {
         self.cfg.disableDlfFlag = v as u8;
@@ -370,14 +619,14 @@ impl HevcEncoderConfig {
     }
 
     /// Sets the maximum QP for the [RateControlMode::VariableBitrate] mode.
-    pub fn max_qp_allowed(mut self, qp: u32) -> Self {
-        self.cfg.maxQpAllowed = qp;
+    pub fn max_qp_allowed(mut self, qp: HevcQp) -> Self {
+        self.cfg.maxQpAllowed = qp.get();
         self
     }
 
     /// Sets the minimum QP for the [RateControlMode::VariableBitrate] mode.
-    pub fn min_qp_allowed(mut self, qp: u32) -> Self {
-        self.cfg.minQpAllowed = qp;
+    pub fn min_qp_allowed(mut self, qp: HevcQp) -> Self {
+        self.cfg.minQpAllowed = qp.get();
         self
     }
 
@@ -387,6 +636,30 @@ impl HevcEncoderConfig {
         self
     }
 
+    /// Sets the color primaries, transfer characteristics, and matrix
+    /// coefficients signaled in the VUI. Enables
+    /// [`HevcEncoderConfig::code_vui`].
+    ///
+    /// This only describes the input; the encoder doesn't convert it.
+    pub fn color_description(mut self, color_space: ColorDescription) -> Self {
+        self.color_description = Some(color_space);
+        self
+    }
+
+    /// Sets the color range signaled in the VUI. The default is
+    /// [`ColorRange::Limited`]. Enables [`HevcEncoderConfig::code_vui`].
+    pub fn color_range(mut self, color_range: ColorRange) -> Self {
+        self.color_range = Some(color_range);
+        self
+    }
+
+    /// Sets the chroma sample position signaled in the VUI. Enables
+    /// [`HevcEncoderConfig::code_vui`].
+    pub fn chroma_sample_position(mut self, chroma_sample_position: ChromaSamplePosition) -> Self {
+        self.chroma_sample_position = Some(chroma_sample_position);
+        self
+    }
+
     /// Enables generation of EOS NAL units.
     pub fn code_eos(mut self, v: bool) -> Self {
         self.cfg.codeEosNal = v as u8;
@@ -400,11 +673,48 @@ impl HevcEncoderConfig {
     }
 
     /// Configures the encoder to expect input in the BT2020 color space. Only applicable for 10-bit input. Requries [HevcEncoderConfig::code_vui] to be enabled.
+    ///
+    /// This signals BT.2020 with the PQ transfer function; for other color
+    /// descriptions, use [`HevcEncoderConfig::color_description`], which
+    /// takes precedence.
     pub fn hdr_input(mut self, v: bool) -> Self {
         self.cfg.highDynamicRangeInput = v as u32;
         self
     }
 
+    /// Enables generation of mastering display colour volume SEI messages,
+    /// describing the display used to master the content.
+    pub fn mastering_display(mut self, display: MasteringDisplay) -> Self {
+        // Chromaticity is coded in increments of 0.00002, and luminance in
+        // increments of 0.0001 cd/m². The primaries are in G, B, R order.
+        let primaries = [display.green, display.blue, display.red];
+        self.cfg.useMasteringDisplayColorVolume = 1;
+        self.cfg.displayPrimaryX = primaries.map(|c| fixed(c.x, 50000.0) as u16);
+        self.cfg.displayPrimaryY = primaries.map(|c| fixed(c.y, 50000.0) as u16);
+        self.cfg.whitePointX = fixed(display.white_point.x, 50000.0) as u16;
+        self.cfg.whitePointY = fixed(display.white_point.y, 50000.0) as u16;
+        self.cfg.maxDisplayMasteringLuminance = fixed(display.max_luminance, 10000.0);
+        self.cfg.minDisplayMasteringLuminance = fixed(display.min_luminance, 10000.0);
+        self
+    }
+
+    /// Sets the Dolby Vision profile to signal.
+    pub fn dolby_vision_profile(mut self, profile: DolbyVisionProfile) -> Self {
+        self.cfg.dolbyVisionProfile = match profile {
+            DolbyVisionProfile::None => 0,
+            DolbyVisionProfile::Profile8_1 => 81,
+        };
+
+        self
+    }
+
+    /// Enables generation of content light level SEI messages.
+    pub fn content_light_level(mut self, level: ContentLightLevel) -> Self {
+        self.cfg.maxCLL = level.max_content_light_level;
+        self.cfg.maxFALL = level.max_frame_average_light_level;
+        self
+    }
+
     /// Enables generation of access unit delimiters.
     pub fn code_access_unit_delimiters(mut self, v: bool) -> Self {
         self.cfg.accessUnitDelimiter = v as u32;
@@ -430,8 +740,10 @@ impl HevcEncoderConfig {
     }
 
     /// Enables generation of user data SEI NAL units for unregistered users.
+    /// This is required to use [`HevcEncoder::send_picture_with_sei`].
     pub fn unregistered_user_data_sei(mut self, v: bool) -> Self {
         self.cfg.unregisteredUserDataSeiFlag = v as u32;
+        self.cfg.useNaluFile = v as u8;
         self
     }
 
@@ -469,7 +781,11 @@ impl HevcEncoderConfig {
 
     /// Sets the IDC level. A value of 0 configures the encoder to auto-detect
     /// the level. Any other value represents the level multiplied by ten - for
-    /// example, 31 for level 3.1.
+    /// example, 31 for level 3.1, or [`Level::L3_1`](super::Level::L3_1)
+    /// converted with `into()`.
+    ///
+    /// Creating the encoder fails if the stream doesn't fit within the level;
+    /// see [`Level::for_stream`](super::Level::for_stream).
     pub fn level(mut self, level: u32) -> Self {
         self.cfg.level = level;
         self
@@ -493,13 +809,16 @@ impl HevcEncoderConfig {
         self
     }
 
-    /// Sets the initial VBV buffer fullness. Only used with [RateControlMode::VariableBitrate].
+    /// Sets the initial VBV buffer fullness, as a percentage of the buffer
+    /// size. Only used with [RateControlMode::VariableBitrate].
     pub fn vbv_buf_init(mut self, init: u64) -> Self {
         self.cfg.vbvBufInit = init;
         self
     }
 
-    /// Enables the Hypothetical Reference Decoder flag.
+    /// Enables the Hypothetical Reference Decoder flag. If a VBV buffer size is
+    /// set, the encoder also simulates the coded picture buffer; see
+    /// [`HevcPacket::cpb_fullness`](super::HevcPacket::cpb_fullness).
     pub fn enable_hrd(mut self, v: bool) -> Self {
         self.cfg.hrdFlag = v as u32;
         self
@@ -511,6 +830,22 @@ impl HevcEncoderConfig {
         self
     }
 
+    /// Passes messages logged by the library on behalf of the encoder to
+    /// `sink`, instead of the logger. Each record carries the encoder's
+    /// [channel ID](Self::channel_id), so that the output of several encoders
+    /// can be told apart.
+    ///
+    /// Messages logged from the library's worker threads can't be attributed
+    /// to a specific encoder, and still go to the logger.
+    #[cfg(feature = "log")]
+    pub fn log_sink(
+        mut self,
+        sink: impl Fn(&crate::LogRecord<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.log_sink = Some(std::sync::Arc::new(sink));
+        self
+    }
+
     /// Sets the number of active channels.
     pub fn active_channel_count(mut self, count: u32) -> Self {
         self.cfg.activeChannelCount = count;
@@ -575,9 +910,112 @@ impl HevcEncoderConfig {
         self
     }
 
+    /// Enables a managed real-time mode, in which the library steps the preset
+    /// on its own to encode `fps` pictures per second. This enables speed
+    /// control, and sets the rate it aims for, the injector framerate, to
+    /// `fps`. The achieved rate can be followed with [`HevcEncoder::stats`].
+    pub fn realtime(self, fps: u32) -> Self {
+        self.enable_speed_control(true).injector_framerate(fps)
+    }
+
     /// Configures the encoder to allow motion vectors to point outside the frame.
     pub fn unrestricted_motion_vector(mut self, v: bool) -> Self {
         self.cfg.unrestrictedMotionVector = v as u8;
         self
     }
 }
+
+/// Checks that the stream fits within the configured level, unless the level
+/// is detected automatically. The bitrate is only known in VBR mode.
+fn check_level(cfg: &EB_H265_ENC_CONFIGURATION) -> Result<(), Error> {
+    if cfg.level == 0 {
+        return Ok(());
+    }
+
+    let bad_level = |detail: String| Error::BadParameter {
+        detail: Some(detail),
+    };
+
+    let level = Level::from_config(cfg.level)
+        .ok_or_else(|| bad_level(format!("undefined level {}", cfg.level)))?;
+    let bitrate = match (cfg.rateControlMode, cfg.vbvMaxrate) {
+        (1, 0) => Some(cfg.targetBitRate as u64),
+        (1, max) => Some(max as u64),
+        _ => None,
+    };
+
+    let framerate = if cfg.frameRateNumerator > 0 && cfg.frameRateDenominator > 0 {
+        cfg.frameRateNumerator as f64 / cfg.frameRateDenominator as f64
+    } else {
+        cfg.frameRate as f64
+    };
+
+    let stream = StreamParameters {
+        width: cfg.sourceWidth,
+        height: cfg.sourceHeight,
+        framerate,
+        bitrate,
+        high_tier: cfg.tier != 0,
+    };
+
+    level
+        .limits()
+        .check(&stream)
+        .map_err(|reason| bad_level(format!("stream doesn't fit level {}: {}", level, reason)))
+}
+
+impl EncoderConfig for HevcEncoderConfig {
+    type Encoder = HevcEncoder;
+
+    fn preset(self, preset: u8) -> Self {
+        HevcEncoderConfig::preset(self, preset)
+    }
+
+    fn framerate(self, numerator: u32, denominator: u32) -> Self {
+        HevcEncoderConfig::framerate(self, numerator, denominator)
+    }
+
+    fn rate_control(mut self, rate_control: RateControl) -> Self {
+        self.rate_control_error = None;
+        match rate_control {
+            // An invalid value is rejected by create_encoder.
+            RateControl::ConstantQp(qp) => match HevcQp::new(qp) {
+                Ok(qp) => self.rate_control_mode(RateControlMode::ConstantQp).qp(qp),
+                Err(err) => {
+                    self.rate_control_error = Some(err);
+                    self
+                }
+            },
+            RateControl::VariableBitrate(bitrate) => self
+                .rate_control_mode(RateControlMode::VariableBitrate)
+                .target_bitrate(bitrate),
+        }
+    }
+
+    fn gop(self, intra_period: Option<u32>) -> Self {
+        self.intra_period_length(match intra_period {
+            Some(frames) => IntraPeriod::Fixed(frames),
+            None => IntraPeriod::Auto,
+        })
+    }
+
+    fn create_encoder(
+        self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<HevcEncoder, Error> {
+        HevcEncoderConfig::create_encoder(self, width, height, subsampling_format)
+    }
+}
+
+impl ChannelConfig for HevcEncoderConfig {
+    fn channel(self, id: u32, active_channel_count: u32) -> Self {
+        self.channel_id(id)
+            .active_channel_count(active_channel_count)
+    }
+
+    fn stats(encoder: &HevcEncoder) -> Stats {
+        encoder.stats()
+    }
+}
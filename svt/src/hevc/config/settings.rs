@@ -0,0 +1,159 @@
+use crate::HevcQp;
+
+use super::{HevcEncoderConfig, IntraPeriod, PredictionStructure, RateControlMode, TilingMode};
+
+/// Encoder settings as plain data, for loading from a configuration file or
+/// recording the settings used for an encode. With the `serde` feature, this
+/// implements `Serialize` and `Deserialize`.
+///
+/// Each field is optional, and unset fields keep the library default.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct HevcSettings {
+    /// See [`HevcEncoderConfig::preset`].
+    pub preset: Option<u8>,
+    /// The framerate, as a numerator and denominator. See
+    /// [`HevcEncoderConfig::framerate`].
+    pub framerate: Option<(u32, u32)>,
+    /// See [`HevcEncoderConfig::encoder_bit_depth`].
+    pub bit_depth: Option<u32>,
+    /// See [`HevcEncoderConfig::intra_period_length`].
+    pub intra_period: Option<IntraPeriod>,
+    /// See [`HevcEncoderConfig::pred_structure`].
+    pub pred_structure: Option<PredictionStructure>,
+    /// See [`HevcEncoderConfig::hierarchical_levels`].
+    pub hierarchical_levels: Option<u32>,
+    /// See [`HevcEncoderConfig::rate_control_mode`].
+    pub rate_control: Option<RateControlMode>,
+    /// See [`HevcEncoderConfig::qp`].
+    pub qp: Option<HevcQp>,
+    /// See [`HevcEncoderConfig::target_bitrate`].
+    pub target_bitrate: Option<u32>,
+    /// The minimum and maximum QP. See [`HevcEncoderConfig::min_qp_allowed`]
+    /// and [`HevcEncoderConfig::max_qp_allowed`].
+    pub qp_range: Option<(HevcQp, HevcQp)>,
+    /// See [`HevcEncoderConfig::tiling`].
+    pub tiling: Option<TilingMode>,
+    /// See [`HevcEncoderConfig::look_ahead_distance`].
+    pub look_ahead_distance: Option<u32>,
+    /// See [`HevcEncoderConfig::enable_scene_change_detection`].
+    pub scene_change_detection: Option<bool>,
+}
+
+impl From<HevcSettings> for HevcEncoderConfig {
+    fn from(settings: HevcSettings) -> Self {
+        HevcEncoderConfig::default().apply_settings(&settings)
+    }
+}
+
+impl HevcEncoderConfig {
+    /// Applies the fields of `settings` which are set, on top of the
+    /// current configuration.
+    pub fn apply_settings(mut self, settings: &HevcSettings) -> Self {
+        if let Some(preset) = settings.preset {
+            self = self.preset(preset);
+        }
+
+        if let Some((numerator, denominator)) = settings.framerate {
+            self = self.framerate(numerator, denominator);
+        }
+
+        if let Some(bit_depth) = settings.bit_depth {
+            self = self.encoder_bit_depth(bit_depth);
+        }
+
+        if let Some(intra_period) = settings.intra_period {
+            self = self.intra_period_length(intra_period);
+        }
+
+        if let Some(pred_structure) = settings.pred_structure {
+            self = self.pred_structure(pred_structure);
+        }
+
+        if let Some(levels) = settings.hierarchical_levels {
+            self = self.hierarchical_levels(levels);
+        }
+
+        if let Some(rate_control) = settings.rate_control {
+            self = self.rate_control_mode(rate_control);
+        }
+
+        if let Some(qp) = settings.qp {
+            self = self.qp(qp);
+        }
+
+        if let Some(bitrate) = settings.target_bitrate {
+            self = self.target_bitrate(bitrate);
+        }
+
+        if let Some((min_qp, max_qp)) = settings.qp_range {
+            self = self.min_qp_allowed(min_qp).max_qp_allowed(max_qp);
+        }
+
+        if let Some(tiling) = settings.tiling {
+            self = self.tiling(tiling);
+        }
+
+        if let Some(distance) = settings.look_ahead_distance {
+            self = self.look_ahead_distance(distance);
+        }
+
+        if let Some(v) = settings.scene_change_detection {
+            self = self.enable_scene_change_detection(v);
+        }
+
+        self
+    }
+
+    /// Reads the current configuration back as [`HevcSettings`], for example
+    /// to log the settings used for an encode. Every field is set.
+    pub fn settings(&self) -> HevcSettings {
+        let cfg = &self.cfg;
+
+        let intra_period = match cfg.intraPeriodLength {
+            -1 => IntraPeriod::None,
+            frames if frames < 0 => IntraPeriod::Auto,
+            frames => IntraPeriod::Fixed(frames as u32),
+        };
+
+        let pred_structure = match cfg.predStructure {
+            0 => Some(PredictionStructure::LowDelayP),
+            1 => Some(PredictionStructure::LowDelayB),
+            2 => Some(PredictionStructure::RandomAccess),
+            _ => None,
+        };
+
+        let rate_control = match cfg.rateControlMode {
+            0 => Some(RateControlMode::ConstantQp),
+            1 => Some(RateControlMode::VariableBitrate),
+            _ => None,
+        };
+
+        let tiling = match (cfg.tileColumnCount, cfg.tileRowCount) {
+            (0..=1, 0..=1) => TilingMode::Single,
+            (columns, rows) => TilingMode::Multi { columns, rows },
+        };
+
+        HevcSettings {
+            preset: Some(cfg.encMode),
+            framerate: Some((
+                cfg.frameRateNumerator as u32,
+                cfg.frameRateDenominator as u32,
+            )),
+            bit_depth: Some(cfg.encoderBitDepth),
+            intra_period: Some(intra_period),
+            pred_structure,
+            hierarchical_levels: Some(cfg.hierarchicalLevels),
+            rate_control,
+            qp: HevcQp::new(cfg.qp).ok(),
+            target_bitrate: Some(cfg.targetBitRate),
+            qp_range: HevcQp::new(cfg.minQpAllowed)
+                .ok()
+                .zip(HevcQp::new(cfg.maxQpAllowed).ok()),
+            tiling: Some(tiling),
+            look_ahead_distance: Some(cfg.lookAheadDistance),
+            scene_change_detection: Some(cfg.sceneChangeDetection != 0),
+        }
+    }
+}
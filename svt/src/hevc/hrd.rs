@@ -0,0 +1,214 @@
+use svt_hevc_sys::EB_H265_ENC_CONFIGURATION;
+
+/// The parameters of the coded picture buffer (CPB) of the hypothetical
+/// reference decoder (HRD), which models a decoder receiving the stream at a
+/// fixed rate.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HrdParameters {
+    /// The rate at which bits arrive in the buffer, in bits per second.
+    pub bit_rate: u32,
+    /// The size of the buffer, in bits.
+    pub buffer_size: u32,
+    /// The initial fullness of the buffer, in bits.
+    pub initial_fullness: u32,
+    /// The framerate, as a numerator and denominator.
+    pub framerate: (u32, u32),
+    /// Whether the stream must be constant bitrate. If so, the buffer must
+    /// never overflow; otherwise, the input is paused while the buffer is
+    /// full.
+    pub constant_bitrate: bool,
+}
+
+impl HrdParameters {
+    /// Derives the parameters from an encoder configuration, using the VBV
+    /// settings. Returns `None` if no VBV buffer is configured.
+    pub(crate) fn from_cfg(cfg: &EB_H265_ENC_CONFIGURATION) -> Option<Self> {
+        if cfg.vbvBufsize == 0 {
+            return None;
+        }
+
+        let bit_rate = if cfg.vbvMaxrate > 0 {
+            cfg.vbvMaxrate
+        } else {
+            cfg.targetBitRate
+        };
+
+        let framerate = if cfg.frameRateNumerator > 0 && cfg.frameRateDenominator > 0 {
+            (
+                cfg.frameRateNumerator as u32,
+                cfg.frameRateDenominator as u32,
+            )
+        } else {
+            (cfg.frameRate.max(1) as u32, 1)
+        };
+
+        // The initial fullness is configured as a percentage.
+        let initial_fullness = cfg.vbvBufsize as u64 * cfg.vbvBufInit.min(100) / 100;
+
+        Some(Self {
+            bit_rate,
+            buffer_size: cfg.vbvBufsize,
+            initial_fullness: initial_fullness as u32,
+            framerate,
+            constant_bitrate: bit_rate == cfg.targetBitRate,
+        })
+    }
+}
+
+/// A violation of the buffer constraints.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HrdViolation {
+    /// A frame was larger than the contents of the buffer at the time it was
+    /// due to be decoded.
+    Underflow {
+        /// The index of the frame, in decode order.
+        frame: u64,
+        /// The size of the frame, in bits.
+        frame_bits: u64,
+        /// The fullness of the buffer before the frame was removed, in bits.
+        fullness: u64,
+    },
+    /// The buffer filled up in a constant bitrate stream, meaning the
+    /// encoder didn't produce enough bits.
+    Overflow {
+        /// The index of the frame, in decode order.
+        frame: u64,
+    },
+}
+
+impl std::error::Error for HrdViolation {}
+
+impl std::fmt::Display for HrdViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HrdViolation::Underflow {
+                frame,
+                frame_bits,
+                fullness,
+            } => write!(
+                f,
+                "CPB underflow at frame {}: {} bits needed, {} available",
+                frame, frame_bits, fullness
+            ),
+            HrdViolation::Overflow { frame } => write!(f, "CPB overflow at frame {}", frame),
+        }
+    }
+}
+
+/// A simulation of the coded picture buffer, fed with the size of each frame
+/// in decode order.
+#[derive(Debug, Copy, Clone)]
+pub struct HrdModel {
+    params: HrdParameters,
+    fullness: f64,
+    frames: u64,
+}
+
+impl HrdModel {
+    /// Creates a model with the buffer at its initial fullness.
+    pub fn new(params: HrdParameters) -> Self {
+        Self {
+            fullness: params.initial_fullness as f64,
+            params,
+            frames: 0,
+        }
+    }
+
+    /// Removes a frame of the given size from the buffer, and then fills it
+    /// for one frame interval. Returns the fullness of the buffer after the
+    /// frame was removed, in bits.
+    ///
+    /// After a violation, the model continues from an empty or full buffer.
+    pub fn push(&mut self, frame_bits: u64) -> Result<u64, HrdViolation> {
+        let frame = self.frames;
+        self.frames += 1;
+
+        let mut res = if (frame_bits as f64) > self.fullness {
+            Err(HrdViolation::Underflow {
+                frame,
+                frame_bits,
+                fullness: self.fullness as u64,
+            })
+        } else {
+            Ok((self.fullness - frame_bits as f64) as u64)
+        };
+
+        self.fullness = (self.fullness - frame_bits as f64).max(0.0);
+
+        let (num, den) = self.params.framerate;
+        self.fullness += self.params.bit_rate as f64 * den as f64 / num as f64;
+        if self.fullness > self.params.buffer_size as f64 {
+            self.fullness = self.params.buffer_size as f64;
+            if self.params.constant_bitrate && res.is_ok() {
+                res = Err(HrdViolation::Overflow { frame });
+            }
+        }
+
+        res
+    }
+}
+
+/// Checks that a stream conforms to the given buffer parameters. The frame
+/// sizes, in bytes, must be given in decode order.
+pub fn check_conformance(
+    params: HrdParameters,
+    frame_sizes: impl IntoIterator<Item = usize>,
+) -> Result<(), HrdViolation> {
+    let mut model = HrdModel::new(params);
+    for size in frame_sizes {
+        model.push(size as u64 * 8)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: HrdParameters = HrdParameters {
+        bit_rate: 8000,
+        buffer_size: 16000,
+        initial_fullness: 8000,
+        framerate: (10, 1),
+        constant_bitrate: false,
+    };
+
+    #[test]
+    fn conforming_stream() {
+        // 100 bytes per frame matches the bitrate exactly.
+        assert_eq!(check_conformance(PARAMS, [100; 50]), Ok(()));
+
+        let mut model = HrdModel::new(PARAMS);
+        assert_eq!(model.push(8000), Ok(0));
+    }
+
+    #[test]
+    fn underflow() {
+        let res = check_conformance(PARAMS, [100, 2000]);
+        assert_eq!(
+            res,
+            Err(HrdViolation::Underflow {
+                frame: 1,
+                frame_bits: 16000,
+                fullness: 8000,
+            })
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        // Small frames are fine for VBR, but not for CBR.
+        assert_eq!(check_conformance(PARAMS, [10; 50]), Ok(()));
+
+        let cbr = HrdParameters {
+            constant_bitrate: true,
+            ..PARAMS
+        };
+
+        assert!(matches!(
+            check_conformance(cbr, [10; 50]),
+            Err(HrdViolation::Overflow { .. })
+        ));
+    }
+}
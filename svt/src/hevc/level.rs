@@ -0,0 +1,162 @@
+use crate::level::{LevelLimits, StreamParameters};
+
+/// An HEVC level, which constrains the picture size, sample rate, and bitrate
+/// of a stream so that decoders can advertise what they support.
+///
+/// Pass a level to [`HevcEncoderConfig::level`](super::HevcEncoderConfig::level)
+/// with `into()`. The stream is checked against it when the encoder is
+/// created.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(missing_docs)]
+pub enum Level {
+    L1,
+    L2,
+    L2_1,
+    L3,
+    L3_1,
+    L4,
+    L4_1,
+    L5,
+    L5_1,
+    L5_2,
+    L6,
+    L6_1,
+    L6_2,
+}
+
+const ALL: [Level; 13] = [
+    Level::L1,
+    Level::L2,
+    Level::L2_1,
+    Level::L3,
+    Level::L3_1,
+    Level::L4,
+    Level::L4_1,
+    Level::L5,
+    Level::L5_1,
+    Level::L5_2,
+    Level::L6,
+    Level::L6_1,
+    Level::L6_2,
+];
+
+impl Level {
+    /// Returns the lowest level that allows a stream with the given
+    /// dimensions, framerate, and bitrate in bits per second, at the main
+    /// tier. If the stream exceeds every level, returns the highest.
+    pub fn for_stream(width: u32, height: u32, framerate: f64, bitrate: u64) -> Level {
+        let stream = StreamParameters {
+            width,
+            height,
+            framerate,
+            bitrate: Some(bitrate),
+            high_tier: false,
+        };
+
+        ALL.into_iter()
+            .find(|level| level.limits().check(&stream).is_ok())
+            .unwrap_or(Level::L6_2)
+    }
+
+    /// Parses a level in the form used by
+    /// [`HevcEncoderConfig::level`](super::HevcEncoderConfig::level): the
+    /// level multiplied by ten.
+    pub(crate) fn from_config(level: u32) -> Option<Level> {
+        ALL.into_iter().find(|&l| u32::from(l) == level)
+    }
+
+    /// The `general_level_idc` coded in the profile, tier, and level: the
+    /// level multiplied by 30.
+    pub fn level_idc(self) -> u32 {
+        u32::from(self) * 3
+    }
+
+    /// The limits from tables A.8 and A.9 of the HEVC specification, for the
+    /// Main and Main 10 profiles.
+    pub(crate) fn limits(self) -> LevelLimits {
+        // MaxLumaPs, MaxLumaSr, and MaxBR in units of 1000 bits per second.
+        let (max_picture_size, max_sample_rate, main, high) = match self {
+            Level::L1 => (36_864, 552_960, 128, None),
+            Level::L2 => (122_880, 3_686_400, 1_500, None),
+            Level::L2_1 => (245_760, 7_372_800, 3_000, None),
+            Level::L3 => (552_960, 16_588_800, 6_000, None),
+            Level::L3_1 => (983_040, 33_177_600, 10_000, None),
+            Level::L4 => (2_228_224, 66_846_720, 12_000, Some(30_000)),
+            Level::L4_1 => (2_228_224, 133_693_440, 20_000, Some(50_000)),
+            Level::L5 => (8_912_896, 267_386_880, 25_000, Some(100_000)),
+            Level::L5_1 => (8_912_896, 534_773_760, 40_000, Some(160_000)),
+            Level::L5_2 => (8_912_896, 1_069_547_520, 60_000, Some(240_000)),
+            Level::L6 => (35_651_584, 1_069_547_520, 60_000, Some(240_000)),
+            Level::L6_1 => (35_651_584, 2_139_095_040, 120_000, Some(480_000)),
+            Level::L6_2 => (35_651_584, 4_278_190_080, 240_000, Some(800_000)),
+        };
+
+        // Neither dimension may exceed Sqrt(MaxLumaPs * 8).
+        let max_dimension = ((max_picture_size * 8) as f64).sqrt() as u32;
+        LevelLimits {
+            max_picture_size,
+            max_width: max_dimension,
+            max_height: max_dimension,
+            max_sample_rate,
+            max_bitrate: main * 1000,
+            max_bitrate_high: high.map(|v: u64| v * 1000),
+        }
+    }
+}
+
+impl From<Level> for u32 {
+    /// Returns the level multiplied by ten, for example 41 for level 4.1.
+    fn from(level: Level) -> u32 {
+        match level {
+            Level::L1 => 10,
+            Level::L2 => 20,
+            Level::L2_1 => 21,
+            Level::L3 => 30,
+            Level::L3_1 => 31,
+            Level::L4 => 40,
+            Level::L4_1 => 41,
+            Level::L5 => 50,
+            Level::L5_1 => 51,
+            Level::L5_2 => 52,
+            Level::L6 => 60,
+            Level::L6_1 => 61,
+            Level::L6_2 => 62,
+        }
+    }
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let v = u32::from(*self);
+        match v % 10 {
+            0 => write!(f, "{}", v / 10),
+            minor => write!(f, "{}.{}", v / 10, minor),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn for_stream() {
+        assert_eq!(Level::for_stream(1280, 720, 30.0, 2_000_000), Level::L3_1);
+        assert_eq!(Level::for_stream(1920, 1080, 30.0, 8_000_000), Level::L4);
+        assert_eq!(Level::for_stream(1920, 1080, 60.0, 8_000_000), Level::L4_1);
+        assert_eq!(Level::for_stream(3840, 2160, 60.0, 20_000_000), Level::L5_1);
+        assert_eq!(Level::for_stream(176, 144, 15.0, 100_000), Level::L1);
+
+        // Too tall for levels 2 and 2.1, despite the picture size.
+        assert_eq!(Level::for_stream(64, 1600, 30.0, 100_000), Level::L3);
+    }
+
+    #[test]
+    fn config_value() {
+        assert_eq!(Level::from_config(41), Some(Level::L4_1));
+        assert_eq!(Level::from_config(42), None);
+        assert_eq!(Level::L5_1.level_idc(), 153);
+        assert_eq!(Level::L4.to_string(), "4");
+        assert_eq!(Level::L6_2.to_string(), "6.2");
+    }
+}
@@ -1,6 +1,9 @@
 use svt_hevc_sys::*;
 
-use crate::Packet;
+use super::HrdViolation;
+use crate::gop::OutputPicture;
+use crate::mux::MuxError;
+use crate::{KeyframeReason, Packet, Rational, Timestamp};
 
 enum DropType {
     Headers,
@@ -28,6 +31,12 @@ pub enum NaluType {
 pub struct HevcPacket {
     handle: *mut EB_BUFFERHEADERTYPE,
     ty: DropType,
+    pub(crate) output: OutputPicture,
+    pub(crate) timebase: Option<Rational>,
+    pub(crate) poc: Option<u32>,
+    pub(crate) cpb_fullness: Option<Result<u64, HrdViolation>>,
+    // The output with SEI messages spliced in, if any.
+    pub(crate) data: Option<Vec<u8>>,
 }
 
 impl std::fmt::Debug for HevcPacket {
@@ -42,6 +51,10 @@ impl std::fmt::Debug for HevcPacket {
 
 impl Packet for HevcPacket {
     fn as_bytes(&self) -> &[u8] {
+        if let Some(data) = &self.data {
+            return data;
+        }
+
         unsafe {
             std::slice::from_raw_parts((*self.handle).pBuffer, (*self.handle).nFilledLen as usize)
         }
@@ -50,6 +63,15 @@ impl Packet for HevcPacket {
     fn is_eos(&self) -> bool {
         unsafe { (*self.handle).nFlags & EB_BUFFERFLAG_EOS != 0 }
     }
+
+    fn keyframe_reason(&self) -> Option<KeyframeReason> {
+        self.output.keyframe_reason
+    }
+
+    fn completed_pts(&self) -> Option<i64> {
+        let complete = matches!(self.ty, DropType::Output) && !self.as_bytes().is_empty();
+        complete.then(|| self.pts())
+    }
 }
 
 impl AsRef<[u8]> for HevcPacket {
@@ -72,10 +94,70 @@ impl HevcPacket {
         }
     }
 
+    /// Builds the `hvcC` decoder configuration record from the parameter
+    /// sets returned by [`HevcEncoder::code_headers`](super::HevcEncoder::code_headers).
+    /// See [`mux::hvcc`](crate::mux::hvcc).
+    pub fn to_hvcc(&self) -> Result<Vec<u8>, MuxError> {
+        crate::mux::hvcc(self.as_bytes())
+    }
+
+    /// The slice QP used to encode the frame.
+    pub fn qp(&self) -> u32 {
+        unsafe { (*self.handle).qpValue }
+    }
+
+    /// The presentation timestamp of the frame.
+    pub fn pts(&self) -> i64 {
+        unsafe { (*self.handle).pts }
+    }
+
+    /// The decode timestamp of the frame.
+    pub fn dts(&self) -> i64 {
+        unsafe { (*self.handle).dts }
+    }
+
+    /// The presentation timestamp of the frame, in the encoder's timebase.
+    /// This is `None` for the stream headers and end of stream.
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        Some(Timestamp::new(self.pts(), self.timebase?))
+    }
+
+    /// The index of the source picture, in the order pictures were sent to
+    /// the encoder. This is `None` for the stream headers and end of stream.
+    pub fn picture_number(&self) -> Option<u64> {
+        self.output.picture_number
+    }
+
+    /// The index of the frame in decode order, counting from the start of
+    /// the stream.
+    pub fn decode_order(&self) -> u64 {
+        self.output.decode_order
+    }
+
+    /// The fullness of the HRD coded picture buffer after this frame was
+    /// removed, in bits, or the constraint it violated. This is only
+    /// available if [`HevcEncoder::hrd_parameters`] is set.
+    ///
+    /// [`HevcEncoder::hrd_parameters`]: super::HevcEncoder::hrd_parameters
+    pub fn cpb_fullness(&self) -> Option<Result<u64, HrdViolation>> {
+        self.cpb_fullness
+    }
+
+    /// The picture order count of the frame, which counts up from the most
+    /// recent IDR picture.
+    pub fn poc(&self) -> Option<u32> {
+        self.poc
+    }
+
     pub(crate) fn new(p: *mut EB_BUFFERHEADERTYPE) -> Self {
         Self {
             handle: p,
             ty: DropType::Output,
+            output: OutputPicture::default(),
+            timebase: None,
+            poc: None,
+            cpb_fullness: None,
+            data: None,
         }
     }
 
@@ -83,6 +165,11 @@ impl HevcPacket {
         Self {
             handle: p,
             ty: DropType::Headers,
+            output: OutputPicture::default(),
+            timebase: None,
+            poc: None,
+            cpb_fullness: None,
+            data: None,
         }
     }
 
@@ -90,10 +177,18 @@ impl HevcPacket {
         Self {
             handle: p,
             ty: DropType::Eos,
+            output: OutputPicture::default(),
+            timebase: None,
+            poc: None,
+            cpb_fullness: None,
+            data: None,
         }
     }
 }
 
+// Releasing the buffer is thread-safe.
+unsafe impl Send for HevcPacket {}
+
 impl Drop for HevcPacket {
     fn drop(&mut self) {
         match self.ty {
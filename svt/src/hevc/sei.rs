@@ -0,0 +1,183 @@
+use std::ffi::CString;
+
+use crate::nal::escape;
+use crate::{Orientation, Rotation};
+
+/// The NAL unit type of a prefix SEI message.
+pub(crate) const NAL_UNIT_PREFIX_SEI: u32 = 39;
+
+/// The SEI payload type for unregistered user data.
+pub(crate) const SEI_USER_DATA_UNREGISTERED: u32 = 5;
+
+/// The SEI payload type for display orientation.
+const SEI_DISPLAY_ORIENTATION: u8 = 47;
+
+/// NAL unit types below this one contain slices.
+const NAL_UNIT_FIRST_NON_VCL: u8 = 32;
+
+/// An unregistered user data SEI message, which can be attached to a frame
+/// with [`HevcEncoder::send_picture_with_sei`](super::HevcEncoder::send_picture_with_sei).
+///
+/// This is commonly used to carry captions, timecodes, or other application
+/// data through the encoder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserDataSei {
+    /// The UUID identifying the payload format.
+    pub uuid: [u8; 16],
+    /// The payload itself.
+    pub data: Vec<u8>,
+}
+
+impl UserDataSei {
+    /// Creates a new SEI message with the given UUID and payload.
+    pub fn new(uuid: [u8; 16], data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            uuid,
+            data: data.into(),
+        }
+    }
+
+    /// The library expects the full payload (UUID included) to be base64
+    /// encoded.
+    pub(crate) fn encode(&self) -> CString {
+        let mut payload = Vec::with_capacity(16 + self.data.len());
+        payload.extend_from_slice(&self.uuid);
+        payload.extend_from_slice(&self.data);
+
+        CString::new(base64(&payload)).unwrap()
+    }
+}
+
+/// Encodes a display orientation SEI message as a complete NAL unit,
+/// including the start code. The library can only insert user data SEI
+/// messages itself, so this is spliced into the output.
+pub(crate) fn display_orientation_nal(orientation: &Orientation) -> Vec<u8> {
+    // The rotation is in units of 2^-16 of a full turn.
+    let rotation: u32 = match orientation.rotation {
+        Rotation::None => 0,
+        Rotation::Ccw90 => 0x4000,
+        Rotation::Ccw180 => 0x8000,
+        Rotation::Ccw270 => 0xc000,
+    };
+
+    // display_orientation_cancel_flag, hor_flip, ver_flip,
+    // anticlockwise_rotation, display_orientation_repetition_period (ue(v)
+    // 0, a single 1 bit), display_orientation_persistence_flag, and then the
+    // payload alignment: a 1 bit followed by zeroes.
+    let bits = (orientation.horizontal_flip as u32) << 22
+        | (orientation.vertical_flip as u32) << 21
+        | rotation << 5
+        | 1 << 4
+        | 1 << 3
+        | 1 << 2;
+    let payload = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+
+    let mut rbsp = vec![SEI_DISPLAY_ORIENTATION, payload.len() as u8];
+    rbsp.extend_from_slice(&payload);
+    // rbsp_trailing_bits.
+    rbsp.push(0x80);
+
+    let mut nal = vec![0, 0, 0, 1, (NAL_UNIT_PREFIX_SEI as u8) << 1, 1];
+    nal.extend_from_slice(&escape(&rbsp));
+    nal
+}
+
+/// Inserts a prefix SEI NAL unit into an Annex B access unit, before the
+/// first slice.
+pub(crate) fn insert_prefix_nal(access_unit: &[u8], nal: &[u8]) -> Vec<u8> {
+    let mut pos = access_unit.len();
+    for i in 0..access_unit.len().saturating_sub(3) {
+        if access_unit[i..i + 3] == [0, 0, 1]
+            && (access_unit[i + 3] >> 1) & 0x3f < NAL_UNIT_FIRST_NON_VCL
+        {
+            // Include the leading zero of a four byte start code.
+            pos = if i > 0 && access_unit[i - 1] == 0 {
+                i - 1
+            } else {
+                i
+            };
+            break;
+        }
+    }
+
+    let mut out = Vec::with_capacity(access_unit.len() + nal.len());
+    out.extend_from_slice(&access_unit[..pos]);
+    out.extend_from_slice(nal);
+    out.extend_from_slice(&access_unit[pos..]);
+    out
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+
+        let sei = UserDataSei::new([0; 16], b"hi".as_slice());
+        assert_eq!(sei.encode().to_str().unwrap(), "AAAAAAAAAAAAAAAAAAAAAGhp");
+    }
+
+    #[test]
+    fn display_orientation() {
+        let nal = display_orientation_nal(&Orientation {
+            rotation: Rotation::Ccw90,
+            horizontal_flip: true,
+            vertical_flip: false,
+        });
+
+        // 0 1 0 | 0100 0000 0000 0000 | 1 | 1 | 1 00
+        assert_eq!(nal, [0, 0, 0, 1, 0x4e, 0x01, 47, 3, 0x48, 0x00, 0x1c, 0x80]);
+
+        let nal = display_orientation_nal(&Orientation::default());
+        assert_eq!(nal, [0, 0, 0, 1, 0x4e, 0x01, 47, 3, 0x00, 0x00, 0x1c, 0x80]);
+    }
+
+    #[test]
+    fn insert_before_first_slice() {
+        let vps = [0, 0, 0, 1, 32 << 1, 1, 0xaa];
+        let idr = [0, 0, 0, 1, 19 << 1, 1, 0xbb];
+        let sei = [0, 0, 0, 1, 39 << 1, 1, 0xcc];
+
+        let au = [vps.as_slice(), &idr].concat();
+        assert_eq!(
+            insert_prefix_nal(&au, &sei),
+            [vps.as_slice(), &sei, &idr].concat()
+        );
+
+        // Three byte start codes work too.
+        let au = [&vps[..], &idr[1..]].concat();
+        assert_eq!(
+            insert_prefix_nal(&au, &sei),
+            [&vps[..], &sei, &idr[1..]].concat()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::buffer::chroma_dimensions;
+use crate::{Picture, Picture16, Plane, SubsamplingFormat};
+
+/// A 10-bit picture in the compressed format accepted by SVT-HEVC, with the
+/// 8 most significant bits of each sample stored in the main planes, and the
+/// 2 least significant bits stored separately, packed four samples to a
+/// byte.
+///
+/// The main planes are accessed through [`Picture`], with one byte per
+/// sample. The encoder must be configured with
+/// [`HevcEncoderConfig::compressed_ten_bit_format`], and pictures sent with
+/// [`HevcEncoder::send_compressed_picture`].
+///
+/// [`HevcEncoderConfig::compressed_ten_bit_format`]: super::HevcEncoderConfig::compressed_ten_bit_format
+/// [`HevcEncoder::send_compressed_picture`]: super::HevcEncoder::send_compressed_picture
+pub trait CompressedTenBitPicture: Picture {
+    /// The packed least significant bits of a plane. Each row is a quarter
+    /// of the plane's stride, and the first sample in each byte is stored in
+    /// the most significant bits.
+    fn lsb_slice(&self, plane: Plane) -> &[u8];
+}
+
+/// A reusable picture buffer in the compressed 10-bit format. See
+/// [`CompressedTenBitPicture`].
+pub struct CompressedTenBitBuffer {
+    msb: [Vec<u8>; 3],
+    lsb: [Vec<u8>; 3],
+    uv_stride: u32,
+    width: u32,
+    height: u32,
+}
+
+impl std::fmt::Debug for CompressedTenBitBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompressedTenBitBuffer")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl CompressedTenBitBuffer {
+    /// Create a new picture with the given width, height, and subsampling
+    /// format. The width of each plane must be a multiple of four.
+    pub fn new(width: u32, height: u32, format: SubsamplingFormat) -> Self {
+        let (uv_width, uv_height) = chroma_dimensions(width, height, format);
+        assert!(
+            width.is_multiple_of(4) && uv_width.is_multiple_of(4),
+            "plane widths must be a multiple of four"
+        );
+
+        let y_size = (width * height) as usize;
+        let uv_size = (uv_width * uv_height) as usize;
+
+        CompressedTenBitBuffer {
+            msb: [vec![0; y_size], vec![0; uv_size], vec![0; uv_size]],
+            lsb: [
+                vec![0; y_size / 4],
+                vec![0; uv_size / 4],
+                vec![0; uv_size / 4],
+            ],
+            uv_stride: uv_width,
+            width,
+            height,
+        }
+    }
+
+    /// Fills the buffer from a 10-bit picture with the same dimensions.
+    pub fn copy_from(&mut self, picture: &impl Picture16) {
+        assert_eq!(Picture16::width(picture), self.width);
+        assert_eq!(Picture16::height(picture), self.height);
+
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            let width = Picture::stride(self, plane) as usize;
+            let src_stride = Picture16::stride(picture, plane) as usize;
+            let src = Picture16::as_slice(picture, plane);
+
+            let msb = &mut self.msb[plane as usize];
+            let lsb = &mut self.lsb[plane as usize];
+            let rows = msb.len().checked_div(width).unwrap_or(0);
+            for row in 0..rows {
+                let src = &src[row * src_stride..][..width];
+                let msb = &mut msb[row * width..][..width];
+                let lsb = &mut lsb[row * width / 4..][..width / 4];
+
+                for (i, &sample) in src.iter().enumerate() {
+                    msb[i] = (sample >> 2) as u8;
+                    lsb[i / 4] = (lsb[i / 4] << 2) | (sample & 0b11) as u8;
+                }
+            }
+        }
+    }
+}
+
+impl Picture for CompressedTenBitBuffer {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn as_slice(&self, plane: Plane) -> &[u8] {
+        &self.msb[plane as usize]
+    }
+
+    fn stride(&self, plane: Plane) -> u32 {
+        match plane {
+            Plane::Y => self.width,
+            Plane::U | Plane::V => self.uv_stride,
+        }
+    }
+}
+
+impl CompressedTenBitPicture for CompressedTenBitBuffer {
+    fn lsb_slice(&self, plane: Plane) -> &[u8] {
+        &self.lsb[plane as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::YUVBuffer16;
+
+    #[test]
+    fn pack_samples() {
+        let mut source = YUVBuffer16::new(8, 2, SubsamplingFormat::Yuv420);
+        source.as_mut_slice(Plane::Y).copy_from_slice(&[
+            0x3ff, 0x001, 0x002, 0x003, 4, 5, 6, 7, 0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+
+        let mut buf = CompressedTenBitBuffer::new(8, 2, SubsamplingFormat::Yuv420);
+        buf.copy_from(&source);
+
+        assert_eq!(
+            buf.as_slice(Plane::Y)[..8],
+            [0xff, 0x00, 0x00, 0x00, 1, 1, 1, 1]
+        );
+        assert_eq!(
+            buf.lsb_slice(Plane::Y),
+            [0b11_01_10_11, 0b00_01_10_11, 0, 0]
+        );
+        assert_eq!(buf.lsb_slice(Plane::U), [0]);
+    }
+}
@@ -0,0 +1,507 @@
+//! Rewrites the video signal type in the VUI of each SPS the library outputs.
+//!
+//! The library can only signal BT.2020 with the PQ transfer function (with
+//! `highDynamicRangeInput`), so other color descriptions, and the color
+//! range, are written into the SPS after the fact. The VUI must be present,
+//! which the encoder config ensures.
+
+use crate::bits::{BitReader, BitWriter};
+use crate::nal::{escape, nal_units, rbsp, NalError};
+use crate::{ChromaSamplePosition, ColorDescription, ColorRange};
+
+/// The NAL unit type of an SPS.
+const NAL_UNIT_SPS: u8 = 33;
+
+/// The `video_format` written if the SPS doesn't already have one:
+/// unspecified.
+const VIDEO_FORMAT_UNSPECIFIED: u32 = 5;
+
+/// The color description, range, and chroma sample position to signal in the
+/// SPS.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct VideoSignal {
+    pub(crate) description: ColorDescription,
+    pub(crate) range: ColorRange,
+    pub(crate) chroma_sample_position: ChromaSamplePosition,
+}
+
+impl VideoSignal {
+    /// Rewrites any SPS NAL units in an access unit. Returns `None` if there
+    /// aren't any.
+    pub(crate) fn apply(&self, access_unit: &[u8]) -> Result<Option<Vec<u8>>, NalError> {
+        let mut out = Vec::new();
+        let mut copied = 0;
+        for nal in nal_units(access_unit) {
+            let nal = nal?;
+            if nal.nal_type() != NAL_UNIT_SPS {
+                continue;
+            }
+
+            // The NAL unit is a slice of the access unit.
+            let start = nal.as_bytes().as_ptr() as usize - access_unit.as_ptr() as usize;
+            out.extend_from_slice(&access_unit[copied..start]);
+            out.extend_from_slice(&self.rewrite_sps(nal.as_bytes())?);
+            copied = start + nal.as_bytes().len();
+        }
+
+        if copied == 0 {
+            return Ok(None);
+        }
+
+        out.extend_from_slice(&access_unit[copied..]);
+        Ok(Some(out))
+    }
+
+    /// Rewrites the video signal type of an SPS NAL unit, including the NAL
+    /// unit header.
+    fn rewrite_sps(&self, nal: &[u8]) -> Result<Vec<u8>, NalError> {
+        let invalid = |msg: &str| NalError(format!("invalid SPS: {}", msg));
+
+        let rbsp = rbsp(nal);
+        let stop_bit = rbsp
+            .iter()
+            .rposition(|&b| b != 0)
+            .map(|i| i * 8 + 7 - rbsp[i].trailing_zeros() as usize)
+            .ok_or_else(|| invalid("missing trailing bits"))?;
+
+        let mut r = BitReader::new(&rbsp);
+        let pos = find_video_signal_type(&mut r).ok_or_else(|| invalid("truncated"))?;
+        let mut r = BitReader::new(&rbsp);
+        let mut w = BitWriter::default();
+        w.copy(&mut r, pos).unwrap();
+
+        let video_format =
+            skip_video_signal_type(&mut r).ok_or_else(|| invalid("truncated VUI"))?;
+
+        // video_signal_type_present_flag
+        w.flag(true);
+        w.write(3, video_format);
+        w.flag(self.range == ColorRange::Full);
+        if self.description == ColorDescription::Unspecified {
+            w.flag(false);
+        } else {
+            let (cp, tc, mc) = self.description.code_points();
+            w.flag(true);
+            w.write(8, cp);
+            w.write(8, tc);
+            w.write(8, mc);
+        }
+
+        if r.flag().ok_or_else(|| invalid("truncated VUI"))? {
+            // chroma_sample_loc_type_top_field and
+            // chroma_sample_loc_type_bottom_field
+            r.ue()
+                .and_then(|_| r.ue())
+                .ok_or_else(|| invalid("truncated VUI"))?;
+        }
+
+        // chroma_loc_info_present_flag
+        match self.chroma_sample_position.chroma_sample_loc_type() {
+            Some(loc_type) => {
+                w.flag(true);
+                w.ue(loc_type);
+                w.ue(loc_type);
+            }
+            None => w.flag(false),
+        }
+
+        let rest = stop_bit
+            .checked_sub(r.position())
+            .ok_or_else(|| invalid("truncated VUI"))?;
+        w.copy(&mut r, rest).unwrap();
+        Ok(escape(&w.finish_rbsp()))
+    }
+}
+
+/// Reads an SPS, with the emulation prevention bytes removed, up to the
+/// `video_signal_type_present_flag` in the VUI, which is followed by the
+/// chroma sample location, and returns the position of
+/// the flag in bits. Returns `None` if the SPS is truncated or has no VUI.
+fn find_video_signal_type(r: &mut BitReader<'_>) -> Option<usize> {
+    // The NAL unit header, sps_video_parameter_set_id.
+    r.skip(16 + 4)?;
+    let max_sub_layers_minus1 = r.read(3)?;
+    // sps_temporal_id_nesting_flag
+    r.skip(1)?;
+    skip_profile_tier_level(r, max_sub_layers_minus1)?;
+
+    // sps_seq_parameter_set_id
+    r.ue()?;
+    if r.ue()? == 3 {
+        // separate_colour_plane_flag
+        r.skip(1)?;
+    }
+
+    // pic_width_in_luma_samples, pic_height_in_luma_samples
+    r.ue()?;
+    r.ue()?;
+    if r.flag()? {
+        // The conformance window offsets.
+        for _ in 0..4 {
+            r.ue()?;
+        }
+    }
+
+    // bit_depth_luma_minus8, bit_depth_chroma_minus8
+    r.ue()?;
+    r.ue()?;
+    let log2_max_pic_order_cnt_lsb = r.ue()?.checked_add(4)?;
+    if log2_max_pic_order_cnt_lsb > 16 {
+        return None;
+    }
+
+    let sub_layer_ordering_info_present = r.flag()?;
+    let first = if sub_layer_ordering_info_present {
+        0
+    } else {
+        max_sub_layers_minus1
+    };
+
+    for _ in first..=max_sub_layers_minus1 {
+        // sps_max_dec_pic_buffering_minus1, sps_max_num_reorder_pics,
+        // sps_max_latency_increase_plus1
+        for _ in 0..3 {
+            r.ue()?;
+        }
+    }
+
+    // The coding block and transform sizes, and the maximum transform
+    // hierarchy depths.
+    for _ in 0..6 {
+        r.ue()?;
+    }
+
+    // scaling_list_enabled_flag, sps_scaling_list_data_present_flag
+    if r.flag()? && r.flag()? {
+        skip_scaling_list_data(r)?;
+    }
+
+    // amp_enabled_flag, sample_adaptive_offset_enabled_flag
+    r.skip(2)?;
+    if r.flag()? {
+        // The PCM sample bit depths, sizes, and pcm_loop_filter_disabled_flag.
+        r.skip(8)?;
+        r.ue()?;
+        r.ue()?;
+        r.skip(1)?;
+    }
+
+    let num_short_term_ref_pic_sets = r.ue()?;
+    if num_short_term_ref_pic_sets > 64 {
+        return None;
+    }
+
+    let mut num_delta_pocs = Vec::with_capacity(num_short_term_ref_pic_sets as usize);
+    for idx in 0..num_short_term_ref_pic_sets as usize {
+        let count = skip_st_ref_pic_set(r, idx, &num_delta_pocs)?;
+        num_delta_pocs.push(count);
+    }
+
+    if r.flag()? {
+        // lt_ref_pic_poc_lsb_sps and used_by_curr_pic_lt_sps_flag.
+        let num_long_term_ref_pics = r.ue()?;
+        if num_long_term_ref_pics > 32 {
+            return None;
+        }
+
+        for _ in 0..num_long_term_ref_pics {
+            r.skip(log2_max_pic_order_cnt_lsb + 1)?;
+        }
+    }
+
+    // sps_temporal_mvp_enabled_flag, strong_intra_smoothing_enabled_flag
+    r.skip(2)?;
+    if !r.flag()? {
+        // No VUI.
+        return None;
+    }
+
+    if r.flag()? {
+        // aspect_ratio_idc, and sar_width and sar_height if it's
+        // EXTENDED_SAR.
+        if r.read(8)? == 255 {
+            r.skip(32)?;
+        }
+    }
+
+    if r.flag()? {
+        // overscan_appropriate_flag
+        r.skip(1)?;
+    }
+
+    Some(r.position())
+}
+
+/// Skips the existing video signal type, returning its `video_format`, or
+/// [`VIDEO_FORMAT_UNSPECIFIED`] if it isn't present.
+fn skip_video_signal_type(r: &mut BitReader<'_>) -> Option<u32> {
+    if !r.flag()? {
+        return Some(VIDEO_FORMAT_UNSPECIFIED);
+    }
+
+    let video_format = r.read(3)?;
+    // video_full_range_flag, and the color description.
+    r.skip(1)?;
+    if r.flag()? {
+        r.skip(24)?;
+    }
+
+    Some(video_format)
+}
+
+fn skip_profile_tier_level(r: &mut BitReader<'_>, max_sub_layers_minus1: u32) -> Option<()> {
+    // The general profile, tier, and level.
+    r.skip(96)?;
+
+    let mut sub_layers = [(false, false); 7];
+    for sub_layer in sub_layers.iter_mut().take(max_sub_layers_minus1 as usize) {
+        // sub_layer_profile_present_flag, sub_layer_level_present_flag
+        *sub_layer = (r.flag()?, r.flag()?);
+    }
+
+    if max_sub_layers_minus1 > 0 {
+        // reserved_zero_2bits
+        r.skip(2 * (8 - max_sub_layers_minus1))?;
+    }
+
+    for &(profile_present, level_present) in &sub_layers[..max_sub_layers_minus1 as usize] {
+        if profile_present {
+            r.skip(88)?;
+        }
+
+        if level_present {
+            r.skip(8)?;
+        }
+    }
+
+    Some(())
+}
+
+fn skip_scaling_list_data(r: &mut BitReader<'_>) -> Option<()> {
+    for size_id in 0..4 {
+        let step = if size_id == 3 { 3 } else { 1 };
+        for _ in (0..6).step_by(step) {
+            // scaling_list_pred_mode_flag
+            if !r.flag()? {
+                // scaling_list_pred_matrix_id_delta
+                r.ue()?;
+                continue;
+            }
+
+            let coefficients = 64.min(1 << (4 + (size_id << 1)));
+            if size_id > 1 {
+                // scaling_list_dc_coef_minus8
+                r.ue()?;
+            }
+
+            for _ in 0..coefficients {
+                // scaling_list_delta_coef
+                r.ue()?;
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// Skips an `st_ref_pic_set()`, returning the number of pictures in it.
+fn skip_st_ref_pic_set(r: &mut BitReader<'_>, idx: usize, num_delta_pocs: &[u32]) -> Option<u32> {
+    // inter_ref_pic_set_prediction_flag
+    if idx > 0 && r.flag()? {
+        // In an SPS, sets are always predicted from the previous one.
+        // delta_rps_sign, abs_delta_rps_minus1
+        r.skip(1)?;
+        r.ue()?;
+
+        let mut count = 0;
+        for _ in 0..=num_delta_pocs[idx - 1] {
+            // used_by_curr_pic_flag, and use_delta_flag if it isn't set.
+            if r.flag()? || r.flag()? {
+                count += 1;
+            }
+        }
+
+        return Some(count);
+    }
+
+    let num_negative_pics = r.ue()?;
+    let num_positive_pics = r.ue()?;
+    if num_negative_pics > 16 || num_positive_pics > 16 {
+        return None;
+    }
+
+    for _ in 0..num_negative_pics + num_positive_pics {
+        // delta_poc_s0_minus1 or delta_poc_s1_minus1, and the used flag.
+        r.ue()?;
+        r.skip(1)?;
+    }
+
+    Some(num_negative_pics + num_positive_pics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `video_format`, `video_full_range_flag`, and color description.
+    type VideoSignalType = (u32, bool, Option<(u32, u32, u32)>);
+
+    /// Builds an SPS for a 64x64 4:2:0 stream with two short-term reference
+    /// picture sets, the second predicted from the first, followed by a VUI
+    /// with the given video signal type, chroma sample location, and timing
+    /// info.
+    fn sps(video_signal_type: Option<VideoSignalType>, chroma_loc: Option<u32>) -> Vec<u8> {
+        let mut w = BitWriter::default();
+
+        // NAL unit header, VPS id, one sub-layer, temporal_id_nesting.
+        w.write(16, 0x4201);
+        w.write(4, 0);
+        w.write(3, 0);
+        w.flag(true);
+        // general_profile_space, tier, profile_idc = 1 (Main).
+        w.write(8, 0x01);
+        w.write(32, 0x6000_0000);
+        w.write(32, 0x9000_0000);
+        w.write(16, 0);
+        w.write(8, 93);
+
+        w.ue(0); // sps_seq_parameter_set_id
+        w.ue(1); // chroma_format_idc
+        w.ue(64);
+        w.ue(64);
+        w.flag(false); // conformance_window_flag
+        w.ue(0);
+        w.ue(0);
+        w.ue(4); // log2_max_pic_order_cnt_lsb_minus4
+        w.flag(true);
+        w.ue(4);
+        w.ue(0);
+        w.ue(0);
+        for v in [0, 3, 0, 3, 1, 1] {
+            w.ue(v);
+        }
+
+        w.flag(false); // scaling_list_enabled_flag
+        w.flag(false); // amp_enabled_flag
+        w.flag(true); // sample_adaptive_offset_enabled_flag
+        w.flag(false); // pcm_enabled_flag
+
+        w.ue(2); // num_short_term_ref_pic_sets
+        w.ue(2); // num_negative_pics
+        w.ue(0); // num_positive_pics
+        for _ in 0..2 {
+            w.ue(0);
+            w.flag(true);
+        }
+
+        w.flag(true); // inter_ref_pic_set_prediction_flag
+        w.flag(false);
+        w.ue(0);
+        // Three entries: used, unused but kept, unused and dropped.
+        w.flag(true);
+        w.flag(false);
+        w.flag(true);
+        w.flag(false);
+        w.flag(false);
+
+        w.flag(false); // long_term_ref_pics_present_flag
+        w.flag(true); // sps_temporal_mvp_enabled_flag
+        w.flag(true); // strong_intra_smoothing_enabled_flag
+
+        w.flag(true); // vui_parameters_present_flag
+        w.flag(false); // aspect_ratio_info_present_flag
+        w.flag(false); // overscan_info_present_flag
+        match video_signal_type {
+            None => w.flag(false),
+            Some((format, full_range, description)) => {
+                w.flag(true);
+                w.write(3, format);
+                w.flag(full_range);
+                w.flag(description.is_some());
+                if let Some((cp, tc, mc)) = description {
+                    w.write(8, cp);
+                    w.write(8, tc);
+                    w.write(8, mc);
+                }
+            }
+        }
+
+        w.flag(chroma_loc.is_some()); // chroma_loc_info_present_flag
+        if let Some(loc_type) = chroma_loc {
+            w.ue(loc_type);
+            w.ue(loc_type);
+        }
+
+        w.write(3, 0);
+        w.flag(false); // default_display_window_flag
+        w.flag(true); // vui_timing_info_present_flag
+        w.write(32, 1);
+        w.write(32, 30);
+        w.write(2, 0);
+        w.flag(false); // bitstream_restriction_flag
+        w.flag(false); // sps_extension_present_flag
+
+        escape(&w.finish_rbsp())
+    }
+
+    #[test]
+    fn rewrite() {
+        let signal = VideoSignal {
+            description: ColorDescription::Bt709,
+            range: ColorRange::Full,
+            chroma_sample_position: ChromaSamplePosition::Colocated,
+        };
+
+        let expected = sps(Some((5, true, Some((1, 1, 1)))), Some(2));
+        assert_eq!(signal.rewrite_sps(&sps(None, None)).unwrap(), expected);
+        assert_eq!(
+            signal
+                .rewrite_sps(&sps(Some((2, false, Some((9, 16, 9)))), Some(0)))
+                .unwrap(),
+            sps(Some((2, true, Some((1, 1, 1)))), Some(2))
+        );
+
+        let unspecified = VideoSignal {
+            description: ColorDescription::Unspecified,
+            range: ColorRange::Limited,
+            chroma_sample_position: ChromaSamplePosition::Unknown,
+        };
+        assert_eq!(
+            unspecified.rewrite_sps(&expected).unwrap(),
+            sps(Some((5, false, None)), None)
+        );
+    }
+
+    #[test]
+    fn apply() {
+        let signal = VideoSignal {
+            description: ColorDescription::Bt2020Pq,
+            range: ColorRange::Limited,
+            chroma_sample_position: ChromaSamplePosition::Vertical,
+        };
+
+        let vps = [0x40, 0x01, 0x0c, 0x01];
+        let pps = [0x44, 0x01, 0xc1, 0x72];
+        let mut au = vec![0, 0, 0, 1];
+        au.extend_from_slice(&vps);
+        au.extend_from_slice(&[0, 0, 0, 1]);
+        au.extend_from_slice(&sps(None, None));
+        au.extend_from_slice(&[0, 0, 0, 1]);
+        au.extend_from_slice(&pps);
+
+        let mut expected = vec![0, 0, 0, 1];
+        expected.extend_from_slice(&vps);
+        expected.extend_from_slice(&[0, 0, 0, 1]);
+        expected.extend_from_slice(&sps(Some((5, false, Some((9, 16, 9)))), Some(0)));
+        expected.extend_from_slice(&[0, 0, 0, 1]);
+        expected.extend_from_slice(&pps);
+
+        assert_eq!(signal.apply(&au).unwrap(), Some(expected));
+        assert_eq!(signal.apply(&[0, 0, 1, 0x26, 0x01, 0xaf]).unwrap(), None);
+
+        // Without a VUI, the SPS can't be rewritten.
+        let mut truncated = vec![0, 0, 1];
+        truncated.extend_from_slice(&sps(None, None)[..20]);
+        assert!(signal.apply(&truncated).is_err());
+    }
+}
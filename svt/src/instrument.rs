@@ -0,0 +1,8 @@
+/// Calls `f`, which may block on one of the library's internal queues. With
+/// the `tracing` feature, the call is wrapped in a `queue_wait` span.
+pub(crate) fn queue_wait<T>(f: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("queue_wait").entered();
+
+    f()
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::{Encoder, EncoderState, Error, FrameTypeRequest, Packet, Picture};
+
+/// Limits on the distance between keyframes, in pictures, enforced by a
+/// [`KeyframeGuard`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyframePolicy {
+    /// The minimum number of pictures from one keyframe to the next
+    /// requested keyframe. Requests which come sooner are deferred until the
+    /// interval has passed, and coalesced into a single keyframe.
+    pub min_interval: u64,
+    /// The maximum number of pictures from one keyframe to the next. Once
+    /// it's reached, a keyframe is forced whether or not one was requested.
+    pub max_interval: Option<u64>,
+}
+
+/// An encoder which applies a [`KeyframePolicy`] to keyframe requests.
+///
+/// In real-time applications, every viewer who loses a packet may request a
+/// keyframe (for example, with an RTCP PLI), and honoring each request can
+/// flood the stream with keyframes. Instead, pass requests to
+/// [`KeyframeGuard::request_keyframe`], and send pictures through the guard:
+/// it forces a keyframe once the minimum interval since the last one has
+/// passed, and at the latest after the maximum interval.
+///
+/// Pictures sent with [`FrameTypeRequest::Key`] count as requests. Keyframes
+/// the encoder inserts on its own, like periodic keyframes, reset the
+/// interval once their packets are retrieved through the guard. Other frame
+/// types are passed through unchanged.
+///
+/// # Example
+///
+/// ```
+/// # use svt::{Encoder, KeyframeGuard, KeyframePolicy, YUVBuffer};
+/// # fn example(encoder: impl Encoder, picture: YUVBuffer) -> Result<(), svt::Error> {
+/// let guard = KeyframeGuard::new(
+///     encoder,
+///     KeyframePolicy {
+///         min_interval: 30,
+///         max_interval: Some(300),
+///     },
+/// );
+///
+/// // When a viewer requests a keyframe:
+/// guard.request_keyframe();
+///
+/// // The keyframe is forced on this picture, unless the last one was too
+/// // recent.
+/// guard.send_picture(&picture, 0, false)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct KeyframeGuard<E> {
+    encoder: E,
+    policy: KeyframePolicy,
+    intervals: Mutex<Intervals>,
+    send_lock: Mutex<()>,
+}
+
+#[derive(Debug, Default)]
+struct Intervals {
+    /// The number of pictures sent.
+    sent: u64,
+    /// The index of the most recent keyframe.
+    last_keyframe: Option<u64>,
+    /// The index of the next picture at the time of the oldest outstanding
+    /// request.
+    requested_at: Option<u64>,
+    /// Maps the pts of pictures in flight to their index.
+    pending: HashMap<i64, u64>,
+}
+
+impl Intervals {
+    fn record_keyframe(&mut self, index: u64) {
+        if self.last_keyframe.is_none_or(|last| last < index) {
+            self.last_keyframe = Some(index);
+        }
+
+        if self.requested_at.is_some_and(|at| at <= index) {
+            self.requested_at = None;
+        }
+    }
+}
+
+impl<E: Encoder> KeyframeGuard<E> {
+    /// Wraps an encoder, which should be at the start of a stream.
+    pub fn new(encoder: E, policy: KeyframePolicy) -> Self {
+        Self {
+            encoder,
+            policy,
+            intervals: Mutex::new(Intervals::default()),
+            send_lock: Mutex::new(()),
+        }
+    }
+
+    /// Requests a keyframe, which is forced on the next picture sent if the
+    /// minimum interval has passed, or otherwise as soon as it does.
+    pub fn request_keyframe(&self) {
+        let mut intervals = self.intervals.lock().unwrap();
+        let next = intervals.sent;
+        intervals.requested_at.get_or_insert(next);
+    }
+
+    /// Whether a keyframe has been requested, but not yet forced.
+    pub fn keyframe_pending(&self) -> bool {
+        self.intervals.lock().unwrap().requested_at.is_some()
+    }
+
+    /// The wrapped encoder.
+    pub fn get_ref(&self) -> &E {
+        &self.encoder
+    }
+
+    /// Consumes the guard, returning the wrapped encoder.
+    pub fn into_inner(self) -> E {
+        self.encoder
+    }
+}
+
+impl<E: Encoder> Encoder for KeyframeGuard<E> {
+    type Packet = E::Packet;
+
+    fn send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        let mut frame_type = frame_type.into();
+        if frame_type == FrameTypeRequest::Key {
+            self.request_keyframe();
+            frame_type = FrameTypeRequest::Auto;
+        }
+
+        // The interval lock isn't held while sending, which can block until
+        // packets are retrieved on another thread.
+        let _guard = self.send_lock.lock().unwrap();
+        let (index, saved) = {
+            let mut intervals = self.intervals.lock().unwrap();
+            let index = intervals.sent;
+            let saved = (intervals.last_keyframe, intervals.requested_at);
+            let force = match intervals.last_keyframe {
+                // The encoder starts the stream with a keyframe anyway.
+                None => false,
+                Some(last) => {
+                    let since = index - last;
+                    (intervals.requested_at.is_some() && since >= self.policy.min_interval)
+                        || self.policy.max_interval.is_some_and(|max| since >= max)
+                }
+            };
+
+            if force {
+                frame_type = FrameTypeRequest::Key;
+            }
+
+            if force || intervals.last_keyframe.is_none() {
+                intervals.record_keyframe(index);
+            }
+
+            (index, saved)
+        };
+
+        let res = self.encoder.send_picture(picture, pts, frame_type);
+
+        let mut intervals = self.intervals.lock().unwrap();
+        match res {
+            Ok(()) => {
+                intervals.sent += 1;
+                intervals.pending.insert(pts, index);
+            }
+            // Leave the request outstanding, so that the next picture is
+            // forced instead.
+            Err(_) if intervals.last_keyframe == Some(index) => {
+                (intervals.last_keyframe, intervals.requested_at) = saved;
+            }
+            Err(_) => (),
+        }
+
+        res
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        self.encoder.finish()
+    }
+
+    fn get_packet(&self, wait: bool) -> Result<Option<Self::Packet>, Error> {
+        let packet = self.encoder.get_packet(wait)?;
+        if let Some(pts) = packet.as_ref().and_then(|p| p.completed_pts()) {
+            let mut intervals = self.intervals.lock().unwrap();
+            let index = intervals.pending.remove(&pts);
+            if let (Some(index), Some(_)) = (index, packet.as_ref().unwrap().keyframe_reason()) {
+                intervals.record_keyframe(index);
+            }
+        }
+
+        Ok(packet)
+    }
+
+    fn state(&self) -> EncoderState {
+        self.encoder.state()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.encoder.in_flight()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockEncoderConfig;
+    use crate::{EncoderConfig, SubsamplingFormat, YUVBuffer};
+
+    #[test]
+    fn intervals() {
+        let encoder = MockEncoderConfig::default()
+            .create_encoder(16, 16, SubsamplingFormat::Yuv420)
+            .unwrap();
+        let guard = KeyframeGuard::new(
+            encoder,
+            KeyframePolicy {
+                min_interval: 4,
+                max_interval: Some(10),
+            },
+        );
+
+        let picture = YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420);
+        let mut keyframes = Vec::new();
+        for pts in 0..25 {
+            // A storm of requests right after the first keyframe.
+            if (1..=3).contains(&pts) {
+                guard.request_keyframe();
+            }
+
+            guard.send_picture(&picture, pts, pts == 13).unwrap();
+            while let Some(packet) = guard.get_packet(false).unwrap() {
+                if packet.is_keyframe() {
+                    keyframes.push(packet.pts());
+                }
+            }
+        }
+
+        assert_eq!(keyframes, [0, 4, 13, 23]);
+        assert!(!guard.keyframe_pending());
+    }
+
+    #[test]
+    fn encoder_keyframes() {
+        let encoder = MockEncoderConfig::default()
+            .gop(Some(5))
+            .delay(1)
+            .create_encoder(16, 16, SubsamplingFormat::Yuv420)
+            .unwrap();
+        let guard = KeyframeGuard::new(
+            encoder,
+            KeyframePolicy {
+                min_interval: 3,
+                max_interval: None,
+            },
+        );
+
+        let picture = YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420);
+        let mut keyframes = Vec::new();
+        for pts in 0..12 {
+            // Too soon after the periodic keyframe at 6.
+            if pts == 8 {
+                guard.request_keyframe();
+            }
+
+            guard.send_picture(&picture, pts, false).unwrap();
+            while let Some(packet) = guard.get_packet(false).unwrap() {
+                if packet.is_keyframe() {
+                    keyframes.push(packet.pts());
+                }
+            }
+        }
+
+        assert_eq!(keyframes, [0, 6, 9]);
+        assert!(!guard.keyframe_pending());
+    }
+}
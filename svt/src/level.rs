@@ -0,0 +1,70 @@
+/// The limits of an AV1 or HEVC level which depend on the stream's
+/// dimensions, framerate, and bitrate.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct LevelLimits {
+    /// The maximum number of luma samples in a picture.
+    pub(crate) max_picture_size: u64,
+    pub(crate) max_width: u32,
+    pub(crate) max_height: u32,
+    /// The maximum number of luma samples per second.
+    pub(crate) max_sample_rate: u64,
+    /// The maximum bitrate for the main tier, in bits per second.
+    pub(crate) max_bitrate: u64,
+    /// The maximum bitrate for the high tier, if the level has one.
+    pub(crate) max_bitrate_high: Option<u64>,
+}
+
+/// The properties of a stream which are constrained by its level.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct StreamParameters {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    /// The framerate, or zero if it isn't known.
+    pub(crate) framerate: f64,
+    /// The bitrate in bits per second, if it's known. Constant QP streams
+    /// don't have one.
+    pub(crate) bitrate: Option<u64>,
+    pub(crate) high_tier: bool,
+}
+
+impl LevelLimits {
+    /// Checks that a stream fits within the limits, returning a description
+    /// of the first one it exceeds.
+    pub(crate) fn check(&self, stream: &StreamParameters) -> Result<(), String> {
+        let picture_size = stream.width as u64 * stream.height as u64;
+        if picture_size > self.max_picture_size {
+            return Err(format!(
+                "{}x{} exceeds the maximum picture size of {} samples",
+                stream.width, stream.height, self.max_picture_size
+            ));
+        }
+
+        if stream.width > self.max_width || stream.height > self.max_height {
+            return Err(format!(
+                "{}x{} exceeds the maximum dimensions of {}x{}",
+                stream.width, stream.height, self.max_width, self.max_height
+            ));
+        }
+
+        let sample_rate = picture_size as f64 * stream.framerate;
+        if sample_rate > self.max_sample_rate as f64 {
+            return Err(format!(
+                "{}x{} at {} fps exceeds the maximum of {} samples per second",
+                stream.width, stream.height, stream.framerate, self.max_sample_rate
+            ));
+        }
+
+        let (tier, max_bitrate) = match self.max_bitrate_high {
+            Some(max) if stream.high_tier => ("high", max),
+            _ => ("main", self.max_bitrate),
+        };
+
+        match stream.bitrate {
+            Some(bitrate) if bitrate > max_bitrate => Err(format!(
+                "a bitrate of {} bps exceeds the {} tier maximum of {} bps",
+                bitrate, tier, max_bitrate
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -15,11 +15,107 @@
     variant_size_differences
 )]
 
+use std::time::{Duration, Instant};
+
+#[cfg(any(feature = "av1", feature = "hevc"))]
+mod bits;
+
 mod buffer;
-pub use buffer::YUVBuffer;
+pub use buffer::{PictureRequirements, YUVBuffer, YUVBuffer16};
+
+mod color;
+pub use color::{ChromaSamplePosition, ColorDescription, ColorRange};
+
+mod encode_iter;
+pub use encode_iter::EncodeIter;
 
 mod error;
-pub use error::Error;
+pub use error::{EncoderCall, Error, ErrorContext, LibraryError};
+
+mod gop;
+pub use gop::KeyframeReason;
+
+#[cfg(any(feature = "av1", feature = "hevc", feature = "vp9"))]
+mod instrument;
+
+mod keyframes;
+pub use keyframes::{KeyframeGuard, KeyframePolicy};
+
+#[cfg(any(feature = "av1", feature = "hevc"))]
+mod level;
+
+#[cfg(feature = "log")]
+mod logging;
+#[cfg(feature = "log")]
+pub use logging::LogRecord;
+
+#[cfg(any(feature = "hevc", feature = "rtp"))]
+mod nal;
+
+// Only re-exported by the av1 module.
+#[cfg(any(feature = "av1", feature = "rtp"))]
+#[cfg_attr(not(feature = "av1"), allow(unreachable_pub))]
+mod obu;
+
+mod orientation;
+pub use orientation::{Orientation, Rotation};
+
+mod pipeline;
+pub use pipeline::PipelinedEncoder;
+
+mod pool;
+pub use pool::{PicturePool, PooledPicture};
+
+mod qp;
+pub use qp::{Av1Qp, HevcQp};
+
+mod session;
+pub use session::{OwnedPacket, Session};
+
+#[cfg(any(feature = "av1", feature = "hevc", feature = "vp9"))]
+mod shutdown;
+
+mod split;
+pub use split::{EncoderSender, PacketReceiver, ShutdownHandle};
+
+mod state;
+pub use state::EncoderState;
+
+mod stats;
+pub use stats::Stats;
+
+mod thread;
+pub use thread::{ThreadOptions, ThreadPriority};
+
+mod time;
+pub use time::{Rational, Timestamp};
+
+mod timestamps;
+pub use timestamps::TimestampReorderer;
+
+#[cfg(feature = "avif")]
+pub mod avif;
+#[cfg(feature = "ffmpeg-interop")]
+pub mod ffmpeg;
+pub mod hdr;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(any(feature = "av1", feature = "hevc"))]
+pub mod mux;
+#[cfg(feature = "pacing")]
+pub mod pacing;
+pub mod parallel;
+#[cfg(feature = "av1")]
+pub mod probe;
+pub mod quality;
+#[cfg(feature = "rtp")]
+pub mod rtp;
+#[cfg(feature = "scale")]
+pub mod scale;
+pub mod sink;
+pub mod testsrc;
+#[cfg(feature = "y4m")]
+pub mod y4m;
 
 #[cfg(feature = "av1")]
 pub mod av1;
@@ -27,10 +123,26 @@ pub mod av1;
 #[cfg(feature = "hevc")]
 pub mod hevc;
 
+#[cfg(feature = "vp9")]
+pub mod vp9;
+
+#[cfg(any(feature = "av1", feature = "hevc"))]
+mod any;
+#[cfg(any(feature = "av1", feature = "hevc"))]
+mod channels;
+#[cfg(any(feature = "av1", feature = "hevc"))]
+pub use any::*;
+#[cfg(any(feature = "av1", feature = "hevc"))]
+pub use channels::{ChannelConfig, ChannelGroup, ChannelGroupBuilder};
+
 /// The chroma subsampling format of a YUV picture.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SubsamplingFormat {
     /// 4:0:0 Monochrome (with no chroma planes).
+    ///
+    /// Neither SVT-AV1 nor SVT-HEVC encodes monochrome natively, so the
+    /// encoders fill in neutral chroma and produce a 4:2:0 stream, which
+    /// decodes to the same grey image.
     Yuv400,
     /// 4:2:0 chroma subsampling.
     ///
@@ -75,9 +187,63 @@ pub trait Picture {
     /// format. For the Y plane, it is always `stride` * `height` bytes.
     fn as_slice(&self, plane: Plane) -> &[u8];
 
-    /// The stride, or row width, of a plane. Stride affects the number of bytes
-    /// used to store a plane, but not the size of the picture in pixels.
+    /// The stride, or row width, of a plane in bytes. Stride affects the number
+    /// of bytes used to store a plane, but not the size of the picture in
+    /// pixels.
     fn stride(&self, plane: Plane) -> u32;
+
+    /// The number of bytes used to store each sample. This is 1 for 8-bit
+    /// pictures, and 2 for high bit depth pictures.
+    fn bytes_per_sample(&self) -> u32 {
+        1
+    }
+}
+
+/// A high bit depth (10 or 12-bit) YUV picture, with each sample stored as a
+/// `u16`.
+///
+/// Any type implementing this trait can be used as an encoder input frame,
+/// as long as the encoder is configured with a matching bit depth. For a
+/// ready-made implementation, see [`YUVBuffer16`].
+pub trait Picture16 {
+    /// The width of the picture in pixels.
+    fn width(&self) -> u32;
+
+    /// The height of the picture in pixels.
+    fn height(&self) -> u32;
+
+    /// The samples of a plane. The size depends on the chroma subsampling
+    /// format. For the Y plane, it is always `stride` * `height` samples.
+    fn as_slice(&self, plane: Plane) -> &[u16];
+
+    /// The stride, or row width, of a plane in samples.
+    fn stride(&self, plane: Plane) -> u32;
+}
+
+impl<T: Picture16> Picture for T {
+    fn width(&self) -> u32 {
+        Picture16::width(self)
+    }
+
+    fn height(&self) -> u32 {
+        Picture16::height(self)
+    }
+
+    fn as_slice(&self, plane: Plane) -> &[u8] {
+        let samples = Picture16::as_slice(self, plane);
+
+        // SAFETY: u8 has no alignment requirements, and the length covers
+        // exactly the same memory as the u16 slice.
+        unsafe { std::slice::from_raw_parts(samples.as_ptr() as *const u8, samples.len() * 2) }
+    }
+
+    fn stride(&self, plane: Plane) -> u32 {
+        Picture16::stride(self, plane) * 2
+    }
+
+    fn bytes_per_sample(&self) -> u32 {
+        2
+    }
 }
 
 /// A packet of encoded data output by the encoder.
@@ -87,12 +253,88 @@ pub trait Packet: AsRef<[u8]> + std::fmt::Debug {
 
     /// Whether this packet is the last one in the stream.
     fn is_eos(&self) -> bool;
+
+    /// If the packet contains a keyframe, the reason the encoder produced
+    /// it. Segmenters can use this to align segment boundaries with
+    /// keyframes.
+    ///
+    /// Scene changes are only distinguished from periodic keyframes if scene
+    /// change detection is enabled and the intra period is fixed.
+    fn keyframe_reason(&self) -> Option<KeyframeReason> {
+        None
+    }
+
+    /// If the packet completes the encoding of a submitted picture, the pts
+    /// of that picture. Once such a packet has been output, the encoder no
+    /// longer needs the picture.
+    ///
+    /// Alt-ref frames don't complete their source picture, since it's coded
+    /// again by a later overlay frame. Header and EOS packets don't complete
+    /// any picture.
+    fn completed_pts(&self) -> Option<i64> {
+        None
+    }
+
+    /// Whether the packet ends a coded frame. A transport which sends
+    /// packets as they arrive should only mark the end of a frame, like with
+    /// the RTP marker bit, on packets where this is true.
+    ///
+    /// None of SVT-AV1, SVT-HEVC or SVT-VP9 can output part of a frame
+    /// before the whole frame is encoded, at tile group or slice
+    /// granularity, so the packets output by the encoders in this crate
+    /// always hold complete frames, and this is always true. For the lowest
+    /// latency, limit the frame size instead, with tiles and a low-delay
+    /// prediction structure, and fragment each frame for the network as it
+    /// arrives, for example with the payloaders in the `rtp` module.
+    fn is_frame_complete(&self) -> bool {
+        true
+    }
+}
+
+/// The type of frame an encoder should produce for a given input picture.
+///
+/// Not every codec supports every frame type; passing an unsupported request
+/// to [`Encoder::send_picture`] results in [`Error::BadParameter`]. A `bool`
+/// converts to either [`FrameTypeRequest::Key`] or [`FrameTypeRequest::Auto`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum FrameTypeRequest {
+    /// Let the encoder decide.
+    #[default]
+    Auto,
+    /// A keyframe, or intra refresh. For HEVC, whether this produces an IDR
+    /// or CRA picture depends on the configured intra refresh type.
+    ///
+    /// Neither SVT-AV1 nor SVT-HEVC supports gradual decoder refresh, so an
+    /// intra refresh always codes the entire picture as intra.
+    Key,
+    /// An intra-only frame, which doesn't reset the reference buffers (AV1
+    /// only).
+    IntraOnly,
+    /// An alternative reference (golden) frame (AV1 only).
+    AltRef,
+    /// An IDR picture, which closes the GOP (HEVC only).
+    Idr,
+    /// A CRA picture, which starts an open GOP (HEVC only).
+    Cra,
+}
+
+impl From<bool> for FrameTypeRequest {
+    fn from(force_keyframe: bool) -> Self {
+        if force_keyframe {
+            FrameTypeRequest::Key
+        } else {
+            FrameTypeRequest::Auto
+        }
+    }
 }
 
 /// An encoder generates compressed video bitstreams.
 ///
 /// # Example
 ///
+/// For a simple offline encode, [`Encoder::encode_iter`] handles the loop
+/// below. Otherwise:
+///
 /// ```
 /// # use svt::{YUVBuffer, Plane, Packet};
 /// # fn example(encoder: impl svt::Encoder) -> Result<(), svt::Error> {
@@ -128,31 +370,185 @@ pub trait Packet: AsRef<[u8]> + std::fmt::Debug {
 /// # Ok(())
 /// # }
 /// ```
+///
+/// # Threading
+///
+/// The encoders in this crate are `Send` and `Sync`. Pictures can be sent from
+/// one thread while packets are retrieved on another, which avoids stalling
+/// the encoder while the application is busy with input. Concurrent calls to
+/// [`Encoder::send_picture`] (or concurrent calls to [`Encoder::get_packet`])
+/// are serialized internally.
+///
+/// [`Encoder::split`] provides separate handles for each side.
 pub trait Encoder {
     /// The output of the encoder.
     type Packet: Packet + AsRef<[u8]>;
 
     /// Sends an input picture to the encoder. The picture should have the same
     /// dimensions as the encoder, and the same chroma subsampling layout that
-    /// the encoder was configured with (usually 4:2:0).
+    /// the encoder was configured with (usually 4:2:0). Otherwise,
+    /// [`Error::InvalidPicture`] is returned; see [`PictureRequirements`].
     ///
-    /// `pts` is will be used as the presentation timestamp. `force_keyframe`
-    /// will force the encoder to perform an intra refresh.
+    /// `pts` is will be used as the presentation timestamp. `frame_type` can be
+    /// used to force a specific type of frame; passing `true` will force the
+    /// encoder to perform an intra refresh.
     fn send_picture(
         &self,
         picture: &impl Picture,
         pts: i64,
-        force_keyframe: bool,
+        frame_type: impl Into<FrameTypeRequest>,
     ) -> Result<(), Error>;
 
     /// Requests that the encoder finish encoding and generate an EOS packet to
-    /// end the stream.
+    /// end the stream. Calling this more than once has no effect. Afterwards,
+    /// sending a picture returns [`Error::InvalidState`].
     fn finish(&self) -> Result<(), Error>;
 
     /// Retrieves an encoded packet from the encoder.
     ///
-    /// If `wait` is true, this function will block until a packet is available,
-    /// or indefinitely if the stream is already finished. Therefore, Callers
-    /// should check [`Packet::is_eos`] to determine when the stream has ended.
+    /// If `wait` is true, this function will block until a packet is
+    /// available. Callers should check [`Packet::is_eos`] to determine when
+    /// the stream has ended; once the EOS packet has been retrieved, this
+    /// returns `None` without blocking.
     fn get_packet(&self, wait: bool) -> Result<Option<Self::Packet>, Error>;
+
+    /// The state of the encoder. See [`EncoderState`].
+    fn state(&self) -> EncoderState;
+
+    /// The number of pictures which have been sent to the encoder but not
+    /// yet fully encoded. Latency-sensitive callers can use this to cap the
+    /// number of pictures queued inside the encoder.
+    ///
+    /// Encoded frames don't correspond one-to-one with input pictures: an
+    /// alt-ref frame is coded from a future picture, which is only counted
+    /// as complete once the overlay frame that displays it is output.
+    fn in_flight(&self) -> usize;
+
+    /// Retrieves an encoded packet from the encoder, waiting at most
+    /// `timeout` for one to become available. Returns `None` if the timeout
+    /// elapses first.
+    ///
+    /// The libraries can't time out a blocking call, so this polls
+    /// [`Encoder::get_packet`] without waiting, sleeping for increasing
+    /// intervals in between.
+    fn get_packet_timeout(&self, timeout: Duration) -> Result<Option<Self::Packet>, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut interval = MIN_POLL_INTERVAL;
+        loop {
+            if let Some(packet) = self.get_packet(false)? {
+                return Ok(Some(packet));
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+
+            std::thread::sleep(interval.min(remaining));
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+
+    /// Encodes a sequence of pictures and their presentation timestamps,
+    /// returning an iterator over the packets, in decode order, which ends
+    /// with the EOS packet.
+    ///
+    /// The iterator drains any available packets before sending each
+    /// picture, and finishes the stream once the pictures run out. Sending
+    /// pictures without draining the output eventually blocks the encoder
+    /// forever, once its internal queues are full.
+    ///
+    /// ```
+    /// # use svt::{Encoder, Packet, YUVBuffer};
+    /// # fn example(encoder: impl Encoder, pictures: Vec<YUVBuffer>) -> Result<(), svt::Error> {
+    /// let frames = pictures.into_iter().zip(0..);
+    /// for packet in encoder.encode_iter(frames) {
+    ///     let packet = packet?;
+    ///     // Write the packet to a file or send it over the network.
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn encode_iter<I, P>(&self, pictures: I) -> EncodeIter<'_, Self, I::IntoIter>
+    where
+        Self: Sized,
+        I: IntoIterator<Item = (P, i64)>,
+        P: Picture,
+    {
+        EncodeIter::new(self, pictures.into_iter())
+    }
+
+    /// Splits the encoder into a sending half and a receiving half, which can
+    /// be moved to different threads. The encoder is dropped once both halves
+    /// are dropped.
+    fn split(self) -> (EncoderSender<Self>, PacketReceiver<Self>)
+    where
+        Self: Sized + Send + Sync,
+    {
+        split::split(self)
+    }
+}
+
+/// The shortest and longest sleeps between polls in
+/// [`Encoder::get_packet_timeout`].
+const MIN_POLL_INTERVAL: Duration = Duration::from_micros(100);
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A codec-independent rate control mode, for use with
+/// [`EncoderConfig::rate_control`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RateControl {
+    /// Use a constant quantization parameter. The range depends on the codec
+    /// (see [`Av1Qp`] and [`HevcQp`]); the value is checked when the rate
+    /// control is set, and if it's out of range, creating the encoder fails
+    /// with [`Error::BadParameter`].
+    ConstantQp(u32),
+    /// Use variable bitrate. The value is in bits per second.
+    VariableBitrate(u32),
+}
+
+/// Common configuration shared by all encoders.
+///
+/// This allows applications to configure and create an encoder without
+/// depending on a specific codec. Codec-specific settings are available as
+/// inherent methods on each implementation.
+///
+/// # Example
+///
+/// ```
+/// # use svt::{EncoderConfig, RateControl, SubsamplingFormat};
+/// fn create<C: EncoderConfig>(config: C) -> Result<C::Encoder, svt::Error> {
+///     config
+///         .preset(8)
+///         .framerate(30, 1)
+///         .rate_control(RateControl::VariableBitrate(2_000_000))
+///         .gop(Some(120))
+///         .create_encoder(1280, 720, SubsamplingFormat::Yuv420)
+/// }
+/// ```
+pub trait EncoderConfig: Sized {
+    /// The encoder created by this configuration.
+    type Encoder: Encoder;
+
+    /// Sets the encoder preset. Lower values are slower and produce higher
+    /// quality. The valid range depends on the codec.
+    fn preset(self, preset: u8) -> Self;
+
+    /// Sets the framerate, as a fraction.
+    fn framerate(self, numerator: u32, denominator: u32) -> Self;
+
+    /// Sets the rate control mode.
+    fn rate_control(self, rate_control: RateControl) -> Self;
+
+    /// Sets the number of frames between intra refreshes, or lets the
+    /// encoder decide if `None`.
+    fn gop(self, intra_period: Option<u32>) -> Self;
+
+    /// Creates a new encoder from the config.
+    fn create_encoder(
+        self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<Self::Encoder, Error>;
 }
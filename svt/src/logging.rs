@@ -0,0 +1,16 @@
+/// A message logged by the library on behalf of a specific encoder. See the
+/// `log_sink` option on the encoder configs.
+#[derive(Debug, Clone, Copy)]
+pub struct LogRecord<'a> {
+    /// The severity of the message.
+    pub level: log::Level,
+    /// The module path the message is logged under, like `svt_av1::malloc`.
+    pub target: &'a str,
+    /// The message, without a trailing newline.
+    pub message: &'a str,
+    /// The channel ID of the encoder that logged the message.
+    pub channel_id: u32,
+}
+
+#[cfg(any(feature = "av1", feature = "hevc"))]
+pub(crate) type LogSink = std::sync::Arc<dyn Fn(&LogRecord<'_>) + Send + Sync>;
@@ -0,0 +1,475 @@
+//! An encoder which doesn't encode anything, for testing.
+//!
+//! [`MockEncoder`] implements [`Encoder`] without calling into any of the
+//! libraries, so that applications can test their pipelines quickly and
+//! deterministically, without building the libraries. Since there's no FFI,
+//! it also runs under Miri and the sanitizers.
+//!
+//! Each picture produces one packet, in the order the pictures were sent,
+//! once [`MockEncoderConfig::delay`] more pictures have been sent or the
+//! stream has been finished. The packet contains the pts as 8 little-endian
+//! bytes, then a 1 for keyframes or a 0 otherwise, padded to
+//! [`MockEncoderConfig::packet_size`] with the low byte of the pts.
+//!
+//! ```
+//! # use svt::{Encoder, EncoderConfig, Packet, SubsamplingFormat, YUVBuffer};
+//! # use svt::mock::MockEncoderConfig;
+//! # fn main() -> Result<(), svt::Error> {
+//! let encoder = MockEncoderConfig::default()
+//!     .delay(1)
+//!     .create_encoder(64, 48, SubsamplingFormat::Yuv420)?;
+//!
+//! let picture = YUVBuffer::new(64, 48, SubsamplingFormat::Yuv420);
+//! encoder.send_picture(&picture, 0, false)?;
+//! assert!(encoder.get_packet(false)?.is_none());
+//!
+//! encoder.send_picture(&picture, 1, false)?;
+//! let packet = encoder.get_packet(false)?.unwrap();
+//! assert_eq!(packet.pts(), 0);
+//! assert!(packet.is_keyframe());
+//!
+//! encoder.finish()?;
+//! assert_eq!(encoder.get_packet(true)?.unwrap().pts(), 1);
+//! assert!(encoder.get_packet(true)?.unwrap().is_eos());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+use crate::gop::GopTracker;
+use crate::state::StateTracker;
+use crate::{
+    Encoder, EncoderConfig, EncoderState, Error, FrameTypeRequest, KeyframeReason, Packet, Picture,
+    PictureRequirements, RateControl, SubsamplingFormat,
+};
+
+/// The size of the pts and keyframe flag at the start of each packet.
+const HEADER_SIZE: usize = 9;
+
+/// Configuration for a [`MockEncoder`].
+///
+/// The preset, framerate and rate control are accepted, for compatibility
+/// with [`EncoderConfig`], but have no effect.
+#[derive(Debug, Copy, Clone)]
+pub struct MockEncoderConfig {
+    intra_period: Option<u32>,
+    delay: usize,
+    packet_size: usize,
+}
+
+impl Default for MockEncoderConfig {
+    fn default() -> Self {
+        Self {
+            intra_period: None,
+            delay: 0,
+            packet_size: 16,
+        }
+    }
+}
+
+impl MockEncoderConfig {
+    /// Sets the number of pictures the encoder holds back before outputting
+    /// a packet, to simulate the latency of a real encoder. The default is 0.
+    pub fn delay(mut self, pictures: usize) -> Self {
+        self.delay = pictures;
+        self
+    }
+
+    /// Sets the size of each packet, in bytes. Packets are never smaller than
+    /// 9 bytes, the size of the pts and keyframe flag. The default is 16.
+    pub fn packet_size(mut self, bytes: usize) -> Self {
+        self.packet_size = bytes;
+        self
+    }
+
+    /// Creates a new encoder from the config. Returns
+    /// [`Error::BadParameter`] if the width or height is zero.
+    pub fn create_encoder(
+        self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<MockEncoder, Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::BadParameter {
+                detail: Some(format!("invalid frame size {}x{}", width, height)),
+            });
+        }
+
+        let intra_period_length = self.intra_period.map_or(-1, |n| n as i32);
+        Ok(MockEncoder {
+            requirements: PictureRequirements {
+                width,
+                height,
+                subsampling_format,
+                bytes_per_sample: 1,
+            },
+            intra_period: self.intra_period,
+            delay: self.delay,
+            packet_size: self.packet_size.max(HEADER_SIZE),
+            state: StateTracker::new(),
+            inner: Mutex::new(Inner {
+                submitted: 0,
+                last_keyframe: None,
+                pending: VecDeque::new(),
+                output: VecDeque::new(),
+                gop: GopTracker::new(intra_period_length, false),
+            }),
+            ready: Condvar::new(),
+        })
+    }
+}
+
+impl EncoderConfig for MockEncoderConfig {
+    type Encoder = MockEncoder;
+
+    fn preset(self, _preset: u8) -> Self {
+        self
+    }
+
+    fn framerate(self, _numerator: u32, _denominator: u32) -> Self {
+        self
+    }
+
+    fn rate_control(self, _rate_control: RateControl) -> Self {
+        self
+    }
+
+    fn gop(mut self, intra_period: Option<u32>) -> Self {
+        self.intra_period = intra_period;
+        self
+    }
+
+    fn create_encoder(
+        self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<MockEncoder, Error> {
+        MockEncoderConfig::create_encoder(self, width, height, subsampling_format)
+    }
+}
+
+/// A packet output by a [`MockEncoder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockPacket {
+    data: Vec<u8>,
+    pts: i64,
+    keyframe_reason: Option<KeyframeReason>,
+    eos: bool,
+}
+
+impl MockPacket {
+    /// The presentation timestamp of the picture.
+    pub fn pts(&self) -> i64 {
+        self.pts
+    }
+
+    /// Whether the packet contains a keyframe.
+    pub fn is_keyframe(&self) -> bool {
+        self.keyframe_reason.is_some()
+    }
+}
+
+impl Packet for MockPacket {
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn is_eos(&self) -> bool {
+        self.eos
+    }
+
+    fn keyframe_reason(&self) -> Option<KeyframeReason> {
+        self.keyframe_reason
+    }
+
+    fn completed_pts(&self) -> Option<i64> {
+        (!self.eos).then_some(self.pts)
+    }
+}
+
+impl AsRef<[u8]> for MockPacket {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    submitted: u64,
+    last_keyframe: Option<u64>,
+    // Pictures which haven't been output yet, with whether each is a
+    // keyframe.
+    pending: VecDeque<(i64, bool)>,
+    output: VecDeque<MockPacket>,
+    gop: GopTracker,
+}
+
+/// An encoder which outputs deterministic fake packets. See the
+/// [module documentation](self).
+pub struct MockEncoder {
+    requirements: PictureRequirements,
+    intra_period: Option<u32>,
+    delay: usize,
+    packet_size: usize,
+    state: StateTracker,
+    inner: Mutex<Inner>,
+    ready: Condvar,
+}
+
+impl std::fmt::Debug for MockEncoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MockEncoder")
+            .field("width", &self.requirements.width)
+            .field("height", &self.requirements.height)
+            .field("state", &self.state.get())
+            .finish()
+    }
+}
+
+impl MockEncoder {
+    /// The dimensions and format of the pictures the encoder accepts, which
+    /// are always 8-bit.
+    pub fn picture_requirements(&self) -> PictureRequirements {
+        self.requirements
+    }
+
+    fn packet(&self, inner: &mut Inner, pts: i64, keyframe: bool) -> MockPacket {
+        let mut data = Vec::with_capacity(self.packet_size);
+        data.extend_from_slice(&pts.to_le_bytes());
+        data.push(keyframe as u8);
+        data.resize(self.packet_size, pts as u8);
+
+        MockPacket {
+            data,
+            pts,
+            keyframe_reason: inner.gop.record_output(pts, keyframe).keyframe_reason,
+            eos: false,
+        }
+    }
+}
+
+impl Encoder for MockEncoder {
+    type Packet = MockPacket;
+
+    /// Sends a picture to the encoder. Keyframes are requested with
+    /// [`FrameTypeRequest::Key`], [`FrameTypeRequest::Idr`] or
+    /// [`FrameTypeRequest::Cra`]; other frame types are accepted, but
+    /// treated like [`FrameTypeRequest::Auto`].
+    fn send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        self.requirements.check(picture)?;
+
+        let forced = matches!(
+            frame_type.into(),
+            FrameTypeRequest::Key | FrameTypeRequest::Idr | FrameTypeRequest::Cra
+        );
+
+        let mut inner = self.state.lock(&self.inner)?;
+        self.state.check_running()?;
+
+        let index = inner.submitted;
+        let periodic = match (inner.last_keyframe, self.intra_period) {
+            (None, _) => true,
+            (Some(last), Some(period)) => index - last > period as u64,
+            (Some(_), None) => false,
+        };
+
+        let keyframe = forced || periodic;
+        if keyframe {
+            inner.last_keyframe = Some(index);
+        }
+
+        inner.submitted += 1;
+        inner.gop.record_submitted(pts, forced);
+        inner.pending.push_back((pts, keyframe));
+
+        while inner.pending.len() > self.delay {
+            let (pts, keyframe) = inner.pending.pop_front().unwrap();
+            let packet = self.packet(&mut inner, pts, keyframe);
+            inner.output.push_back(packet);
+        }
+
+        self.ready.notify_all();
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        let mut inner = self.state.lock(&self.inner)?;
+        if !self.state.should_finish()? {
+            return Ok(());
+        }
+
+        while let Some((pts, keyframe)) = inner.pending.pop_front() {
+            let packet = self.packet(&mut inner, pts, keyframe);
+            inner.output.push_back(packet);
+        }
+
+        inner.output.push_back(MockPacket {
+            data: Vec::new(),
+            pts: 0,
+            keyframe_reason: None,
+            eos: true,
+        });
+
+        self.state.set_draining();
+        self.ready.notify_all();
+        Ok(())
+    }
+
+    /// Retrieves the next packet. If `wait` is true, this blocks until
+    /// another thread sends enough pictures for a packet to be output, or
+    /// finishes the stream.
+    fn get_packet(&self, wait: bool) -> Result<Option<MockPacket>, Error> {
+        let mut inner = self.state.lock(&self.inner)?;
+        loop {
+            self.state.check_poisoned()?;
+            if self.state.get() == EncoderState::Finished {
+                return Ok(None);
+            }
+
+            if let Some(packet) = inner.output.pop_front() {
+                if packet.eos {
+                    self.state.set_finished();
+                }
+
+                return Ok(Some(packet));
+            } else if !wait {
+                return Ok(None);
+            }
+
+            inner = match self.ready.wait(inner) {
+                Ok(inner) => inner,
+                Err(_) => {
+                    self.state.set_poisoned();
+                    return Err(Error::Poisoned);
+                }
+            };
+        }
+    }
+
+    fn state(&self) -> EncoderState {
+        self.state.get()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.inner.lock().map_or(0, |inner| inner.pending.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::YUVBuffer;
+
+    fn encoder(config: MockEncoderConfig) -> MockEncoder {
+        config
+            .create_encoder(16, 16, SubsamplingFormat::Yuv420)
+            .unwrap()
+    }
+
+    #[test]
+    fn packets() {
+        let enc = encoder(MockEncoderConfig::default().delay(2).packet_size(12));
+        let picture = YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420);
+
+        for pts in 0..3 {
+            enc.send_picture(&picture, pts, false).unwrap();
+        }
+
+        assert_eq!(enc.in_flight(), 2);
+        let packet = enc.get_packet(false).unwrap().unwrap();
+        assert_eq!(packet.as_bytes(), &[0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(packet.keyframe_reason(), Some(KeyframeReason::StreamStart));
+        assert_eq!(packet.completed_pts(), Some(0));
+        assert!(enc.get_packet(false).unwrap().is_none());
+
+        enc.finish().unwrap();
+        assert_eq!(enc.state(), EncoderState::Draining);
+        assert!(matches!(
+            enc.send_picture(&picture, 3, false),
+            Err(Error::InvalidState { .. })
+        ));
+
+        let pts: Vec<_> = std::iter::from_fn(|| enc.get_packet(true).unwrap())
+            .map(|p| (p.pts(), p.is_eos(), p.as_bytes().len()))
+            .collect();
+        assert_eq!(pts, [(1, false, 12), (2, false, 12), (0, true, 0)]);
+        assert_eq!(enc.state(), EncoderState::Finished);
+        assert_eq!(enc.in_flight(), 0);
+    }
+
+    #[test]
+    fn keyframes() {
+        let enc = encoder(MockEncoderConfig::default().gop(Some(2)));
+        let picture = YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420);
+
+        for pts in 0..8 {
+            enc.send_picture(&picture, pts, pts == 4).unwrap();
+        }
+
+        let reasons: Vec<_> = std::iter::from_fn(|| enc.get_packet(false).unwrap())
+            .map(|p| p.keyframe_reason())
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                Some(KeyframeReason::StreamStart),
+                None,
+                None,
+                Some(KeyframeReason::Periodic),
+                Some(KeyframeReason::Forced),
+                None,
+                None,
+                Some(KeyframeReason::Periodic),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_picture() {
+        let enc = encoder(MockEncoderConfig::default());
+        let picture = YUVBuffer::new(8, 8, SubsamplingFormat::Yuv420);
+        assert!(matches!(
+            enc.send_picture(&picture, 0, false),
+            Err(Error::InvalidPicture { .. })
+        ));
+
+        assert!(MockEncoderConfig::default()
+            .create_encoder(0, 16, SubsamplingFormat::Yuv420)
+            .is_err());
+    }
+
+    #[test]
+    fn wait_for_packet() {
+        let enc = std::sync::Arc::new(encoder(MockEncoderConfig::default().delay(1)));
+        let sender = enc.clone();
+        let handle = std::thread::spawn(move || {
+            let picture = YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420);
+            for pts in 0..2 {
+                sender.send_picture(&picture, pts, false).unwrap();
+            }
+
+            sender.finish().unwrap();
+        });
+
+        let mut pts = Vec::new();
+        while let Some(packet) = enc.get_packet(true).unwrap() {
+            if packet.is_eos() {
+                break;
+            }
+
+            pts.push(packet.pts());
+        }
+
+        handle.join().unwrap();
+        assert_eq!(pts, [0, 1]);
+    }
+}
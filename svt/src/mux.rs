@@ -0,0 +1,325 @@
+//! Decoder configuration records, for muxing.
+//!
+//! Containers carry the stream headers out of band, in a codec-specific
+//! record: the `av1C` box for AV1, and the `hvcC` box for HEVC. The same
+//! records are used by MP4 and Matroska (as `CodecPrivate`), and by
+//! WebCodecs as the decoder config's `description`. The functions in this
+//! module build them from the headers output by an encoder's
+//! `code_headers`.
+//!
+//! Each function returns the contents of the box, without the box header.
+//!
+//! ```no_run
+//! # #[cfg(feature = "av1")]
+//! # fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! # use svt::{Packet, SubsamplingFormat};
+//! # use svt::av1::Av1EncoderConfig;
+//! let encoder = Av1EncoderConfig::default().create_encoder(
+//!     1920,
+//!     1080,
+//!     SubsamplingFormat::Yuv420,
+//! )?;
+//!
+//! let headers = encoder.code_headers()?;
+//! let av1c = svt::mux::av1c(headers.as_bytes())?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "hevc")]
+use crate::bits::BitReader;
+#[cfg(feature = "hevc")]
+use crate::nal::{nal_units, rbsp, NalError};
+#[cfg(feature = "av1")]
+use crate::obu::{obus, write_leb128, ObuError, ObuType, SequenceHeader};
+#[cfg(feature = "av1")]
+use crate::SubsamplingFormat;
+
+/// An error building a decoder configuration record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MuxError {
+    /// The headers are malformed.
+    Invalid(String),
+    /// The headers don't include a required sequence header or parameter
+    /// set.
+    Missing(&'static str),
+}
+
+impl std::error::Error for MuxError {}
+
+impl std::fmt::Display for MuxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MuxError::Invalid(detail) => write!(f, "invalid headers: {}", detail),
+            MuxError::Missing(what) => write!(f, "missing {}", what),
+        }
+    }
+}
+
+#[cfg(feature = "av1")]
+impl From<ObuError> for MuxError {
+    fn from(e: ObuError) -> Self {
+        MuxError::Invalid(e.to_string())
+    }
+}
+
+#[cfg(feature = "hevc")]
+impl From<NalError> for MuxError {
+    fn from(e: NalError) -> Self {
+        MuxError::Invalid(e.to_string())
+    }
+}
+
+/// Builds an `AV1CodecConfigurationRecord` (the contents of an `av1C` box)
+/// from data containing a sequence header, like the output of
+/// [`Av1Encoder::code_headers`](crate::av1::Av1Encoder::code_headers) or a
+/// keyframe.
+#[cfg(feature = "av1")]
+pub fn av1c(headers: &[u8]) -> Result<Vec<u8>, MuxError> {
+    let mut sequence_header = None;
+    for obu in obus(headers) {
+        let obu = obu?;
+        if obu.obu_type() == ObuType::SequenceHeader {
+            sequence_header = Some(obu);
+            break;
+        }
+    }
+
+    let obu = sequence_header.ok_or(MuxError::Missing("sequence header"))?;
+    let header = SequenceHeader::parse(obu.payload())?;
+    let (subsampling_x, subsampling_y) = match header.subsampling_format {
+        SubsamplingFormat::Yuv400 | SubsamplingFormat::Yuv420 => (1, 1),
+        SubsamplingFormat::Yuv422 => (1, 0),
+        SubsamplingFormat::Yuv444 => (0, 0),
+    };
+
+    let mut record = vec![
+        // marker, version
+        0x81,
+        (header.profile << 5) | header.level,
+        (header.high_tier as u8) << 7
+            | ((header.bit_depth > 8) as u8) << 6
+            | ((header.bit_depth == 12) as u8) << 5
+            | ((header.subsampling_format == SubsamplingFormat::Yuv400) as u8) << 4
+            | subsampling_x << 3
+            | subsampling_y << 2
+            | header.chroma_sample_position,
+        // initial_presentation_delay_present = 0
+        0,
+    ];
+
+    // The sequence header OBU itself, with a size field.
+    record.push(obu.header()[0] | 0x2);
+    record.extend_from_slice(&obu.header()[1..]);
+    write_leb128(&mut record, obu.size());
+    record.extend_from_slice(obu.payload());
+
+    Ok(record)
+}
+
+/// The NAL unit types of the parameter sets, in the order they're written.
+#[cfg(feature = "hevc")]
+const PARAMETER_SETS: [(u8, &str); 3] = [(32, "VPS"), (33, "SPS"), (34, "PPS")];
+
+/// Builds an `HEVCDecoderConfigurationRecord` (the contents of an `hvcC`
+/// box) from the VPS, SPS, and PPS output by
+/// [`HevcEncoder::code_headers`](crate::hevc::HevcEncoder::code_headers).
+///
+/// The record declares four byte NAL unit lengths, so the start codes in
+/// each packet must be replaced with four byte length prefixes.
+#[cfg(feature = "hevc")]
+pub fn hvcc(headers: &[u8]) -> Result<Vec<u8>, MuxError> {
+    let mut arrays: [Vec<&[u8]>; 3] = Default::default();
+    for nal in nal_units(headers) {
+        let nal = nal?;
+        if let Some(i) = PARAMETER_SETS
+            .iter()
+            .position(|(ty, _)| *ty == nal.nal_type())
+        {
+            arrays[i].push(nal.as_bytes());
+        }
+    }
+
+    for (nals, (_, name)) in arrays.iter().zip(PARAMETER_SETS) {
+        if nals.is_empty() {
+            return Err(MuxError::Missing(name));
+        }
+    }
+
+    let sps = parse_sps(&rbsp(arrays[1][0]))
+        .ok_or_else(|| MuxError::Invalid("truncated SPS".to_string()))?;
+
+    let mut record = Vec::with_capacity(64);
+    // configurationVersion
+    record.push(1);
+    record.extend_from_slice(&sps.general_profile_tier_level);
+    // min_spatial_segmentation_idc = 0, parallelismType = 0 (unknown)
+    record.extend_from_slice(&[0xf0, 0x00, 0xfc]);
+    record.push(0xfc | sps.chroma_format_idc);
+    record.push(0xf8 | sps.bit_depth_luma_minus8);
+    record.push(0xf8 | sps.bit_depth_chroma_minus8);
+    // avgFrameRate = 0 (unspecified)
+    record.extend_from_slice(&[0, 0]);
+    // constantFrameRate = 0, lengthSizeMinusOne = 3
+    record.push((sps.max_sub_layers << 3) | (sps.temporal_id_nesting as u8) << 2 | 0x3);
+
+    record.push(arrays.len() as u8);
+    for (nals, (ty, _)) in arrays.iter().zip(PARAMETER_SETS) {
+        // array_completeness = 1
+        record.push(0x80 | ty);
+        record.extend_from_slice(&(nals.len() as u16).to_be_bytes());
+        for nal in nals {
+            record.extend_from_slice(&(nal.len() as u16).to_be_bytes());
+            record.extend_from_slice(nal);
+        }
+    }
+
+    Ok(record)
+}
+
+/// The fields of an SPS copied into an `hvcC` box.
+#[cfg(feature = "hevc")]
+struct Sps {
+    max_sub_layers: u8,
+    temporal_id_nesting: bool,
+    /// The general profile, tier, and level fields, which are byte aligned
+    /// and laid out the same way in the record.
+    general_profile_tier_level: [u8; 12],
+    chroma_format_idc: u8,
+    bit_depth_luma_minus8: u8,
+    bit_depth_chroma_minus8: u8,
+}
+
+/// Parses an SPS, with the emulation prevention bytes removed.
+#[cfg(feature = "hevc")]
+fn parse_sps(rbsp: &[u8]) -> Option<Sps> {
+    // sps_video_parameter_set_id u(4), sps_max_sub_layers_minus1 u(3),
+    // sps_temporal_id_nesting_flag u(1), following the NAL unit header.
+    let first = *rbsp.get(2)?;
+    let max_sub_layers_minus1 = (first >> 1) & 0x7;
+    let general_profile_tier_level = rbsp.get(3..15)?.try_into().ok()?;
+
+    let mut r = BitReader::new(&rbsp[15..]);
+    let mut sub_layers = [(false, false); 7];
+    for sub_layer in sub_layers.iter_mut().take(max_sub_layers_minus1 as usize) {
+        // sub_layer_profile_present_flag, sub_layer_level_present_flag
+        *sub_layer = (r.flag()?, r.flag()?);
+    }
+
+    if max_sub_layers_minus1 > 0 {
+        // reserved_zero_2bits
+        r.skip(2 * (8 - max_sub_layers_minus1 as u32))?;
+    }
+
+    for &(profile_present, level_present) in &sub_layers[..max_sub_layers_minus1 as usize] {
+        if profile_present {
+            r.skip(88)?;
+        }
+
+        if level_present {
+            r.skip(8)?;
+        }
+    }
+
+    // sps_seq_parameter_set_id
+    r.ue()?;
+    let chroma_format_idc = r.ue()?;
+    if chroma_format_idc == 3 {
+        // separate_colour_plane_flag
+        r.skip(1)?;
+    }
+
+    // pic_width_in_luma_samples, pic_height_in_luma_samples
+    r.ue()?;
+    r.ue()?;
+    if r.flag()? {
+        // The conformance window offsets.
+        for _ in 0..4 {
+            r.ue()?;
+        }
+    }
+
+    let bit_depth_luma_minus8 = r.ue()?;
+    let bit_depth_chroma_minus8 = r.ue()?;
+    if chroma_format_idc > 3 || bit_depth_luma_minus8 > 7 || bit_depth_chroma_minus8 > 7 {
+        return None;
+    }
+
+    Some(Sps {
+        max_sub_layers: max_sub_layers_minus1 + 1,
+        temporal_id_nesting: first & 0x1 != 0,
+        general_profile_tier_level,
+        chroma_format_idc: chroma_format_idc as u8,
+        bit_depth_luma_minus8: bit_depth_luma_minus8 as u8,
+        bit_depth_chroma_minus8: bit_depth_chroma_minus8 as u8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "av1")]
+    fn av1c() {
+        // A sequence header for 1920x1080, 10-bit 4:2:0, main profile at
+        // level 4.0, following a temporal delimiter.
+        let headers = [
+            0x12, 0x00, 0x0a, 0x0b, 0x00, 0x00, 0x00, 0x42, 0xab, 0xbf, 0xc3, 0x73, 0xff, 0xe7,
+            0x01,
+        ];
+
+        let record = super::av1c(&headers).unwrap();
+        assert_eq!(record[..4], [0x81, 0x08, 0x4c, 0x00]);
+        assert_eq!(record[4..], headers[2..]);
+
+        assert_eq!(
+            super::av1c(&headers[..2]),
+            Err(MuxError::Missing("sequence header"))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hevc")]
+    fn hvcc() {
+        let vps = [0x40, 0x01, 0x0c, 0x01, 0xff, 0xff];
+        // An SPS for 1920x1080 (with a conformance window), 8-bit 4:2:0,
+        // main profile at level 4.0. It contains emulation prevention bytes.
+        let sps = [
+            0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x03, 0x00, 0x78, 0xa0, 0x03, 0xc0, 0x80, 0x11, 0x07, 0xcb, 0x96,
+        ];
+        let pps = [0x44, 0x01, 0xc1, 0x72];
+
+        let mut headers = Vec::new();
+        for nal in [&vps[..], &sps, &pps] {
+            headers.extend_from_slice(&[0, 0, 0, 1]);
+            headers.extend_from_slice(nal);
+        }
+
+        let record = super::hvcc(&headers).unwrap();
+        let mut expected = vec![
+            0x01, // configurationVersion
+            0x01, // general_profile_idc = 1 (main)
+            0x60, 0x00, 0x00, 0x00, // general_profile_compatibility_flags
+            0x90, 0x00, 0x00, 0x00, 0x00, 0x00, // general_constraint_indicator_flags
+            0x78, // general_level_idc = 120 (4.0)
+            0xf0, 0x00, 0xfc, // min_spatial_segmentation_idc, parallelismType
+            0xfd, // chromaFormat = 1
+            0xf8, 0xf8, // bitDepthLumaMinus8, bitDepthChromaMinus8
+            0x00, 0x00, // avgFrameRate
+            0x0f, // numTemporalLayers = 1, temporalIdNested, lengthSizeMinusOne = 3
+            0x03, // numOfArrays
+        ];
+
+        for (ty, nal) in [(0xa0, &vps[..]), (0xa1, &sps), (0xa2, &pps)] {
+            expected.extend_from_slice(&[ty, 0x00, 0x01, 0x00, nal.len() as u8]);
+            expected.extend_from_slice(nal);
+        }
+
+        assert_eq!(record, expected);
+
+        let without_pps = &headers[..headers.len() - pps.len() - 4];
+        assert_eq!(super::hvcc(without_pps), Err(MuxError::Missing("PPS")));
+    }
+}
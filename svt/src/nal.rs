@@ -0,0 +1,203 @@
+/// An error splitting NAL units.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NalError(pub(crate) String);
+
+impl std::error::Error for NalError {}
+
+impl std::fmt::Display for NalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid NAL unit: {}", self.0)
+    }
+}
+
+fn invalid(detail: impl Into<String>) -> NalError {
+    NalError(detail.into())
+}
+
+/// A NAL unit, as found in an access unit. See [`nal_units`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NalUnit<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> NalUnit<'a> {
+    /// The type of the NAL unit, from the NAL unit header.
+    pub fn nal_type(&self) -> u8 {
+        (self.data[0] >> 1) & 0x3f
+    }
+
+    /// The layer of the NAL unit (`nuh_layer_id`).
+    pub fn layer_id(&self) -> u8 {
+        ((self.data[0] & 0x1) << 5) | (self.data[1] >> 3)
+    }
+
+    /// The temporal layer of the NAL unit (`nuh_temporal_id_plus1` - 1).
+    pub fn temporal_id(&self) -> u8 {
+        (self.data[1] & 0x7).saturating_sub(1)
+    }
+
+    /// The NAL unit, starting with the two byte header, without the start
+    /// code.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    #[cfg_attr(not(feature = "rtp"), allow(dead_code))]
+    pub(crate) fn temporal_id_plus1(&self) -> u8 {
+        self.data[1] & 0x7
+    }
+}
+
+/// Splits an access unit in the Annex B format into NAL units, removing the
+/// start codes.
+pub fn nal_units(data: &[u8]) -> NalUnits<'_> {
+    NalUnits {
+        data,
+        started: false,
+    }
+}
+
+/// An iterator over the NAL units in an access unit, returned by
+/// [`nal_units`].
+#[derive(Debug, Clone)]
+pub struct NalUnits<'a> {
+    data: &'a [u8],
+    started: bool,
+}
+
+impl<'a> Iterator for NalUnits<'a> {
+    type Item = Result<NalUnit<'a>, NalError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            match find_start_code(self.data) {
+                Some(pos) if self.data[..pos].iter().all(|&b| b == 0) => {
+                    self.data = &self.data[pos + 3..];
+                }
+                _ if self.data.is_empty() => return None,
+                _ => {
+                    self.data = &[];
+                    return Some(Err(invalid("missing start code")));
+                }
+            }
+        }
+
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let (mut nal, rest) = match find_start_code(self.data) {
+            Some(pos) => (&self.data[..pos], &self.data[pos + 3..]),
+            None => (self.data, &[][..]),
+        };
+
+        // Trailing zeros, including the first byte of a four byte start
+        // code, aren't part of the NAL unit.
+        while let Some((0, init)) = nal.split_last() {
+            nal = init;
+        }
+
+        if nal.len() < 2 {
+            self.data = &[];
+            return Some(Err(invalid("truncated header")));
+        } else if nal[0] & 0x80 != 0 {
+            self.data = &[];
+            return Some(Err(invalid("forbidden bit set")));
+        }
+
+        self.data = rest;
+        Some(Ok(NalUnit { data: nal }))
+    }
+}
+
+fn find_start_code(data: &[u8]) -> Option<usize> {
+    data.windows(3).position(|w| w == [0, 0, 1])
+}
+
+/// Removes emulation prevention bytes from a NAL unit, leaving the raw
+/// byte sequence payload.
+#[cfg_attr(not(feature = "hevc"), allow(dead_code))]
+pub(crate) fn rbsp(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &b in data {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+
+    out
+}
+
+/// Inserts emulation prevention bytes into a raw byte sequence payload, so
+/// that it can't be mistaken for a start code.
+#[cfg_attr(not(feature = "hevc"), allow(dead_code))]
+pub(crate) fn escape(rbsp: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rbsp.len() + rbsp.len() / 64);
+    let mut zeros = 0;
+    for &b in rbsp {
+        if zeros == 2 && b <= 3 {
+            out.push(3);
+            zeros = 0;
+        }
+
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_nal_units() {
+        let vps = [0x40, 0x01, 0x0c, 0x01];
+        let idr = [0x26, 0x01, 0xaf, 0x01];
+
+        // Four and three byte start codes, with trailing zeros.
+        let mut au = vec![0, 0, 0, 1];
+        au.extend_from_slice(&vps);
+        au.extend_from_slice(&[0, 0, 1]);
+        au.extend_from_slice(&idr);
+        au.extend_from_slice(&[0, 0]);
+
+        let nals = nal_units(&au).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(nals.len(), 2);
+        assert_eq!(nals[0].as_bytes(), &vps);
+        assert_eq!(nals[1].as_bytes(), &idr);
+        assert_eq!(nals[1].nal_type(), 19);
+        assert_eq!((nals[1].layer_id(), nals[1].temporal_id()), (0, 0));
+
+        assert!(nal_units(&[]).next().is_none());
+        assert!(nal_units(&vps).next().unwrap().is_err());
+        assert!(nal_units(&[0, 0, 1, 0x80, 0x01]).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn remove_emulation_prevention() {
+        assert_eq!(
+            rbsp(&[0x42, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x01]),
+            vec![0x42, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01]
+        );
+        assert_eq!(rbsp(&[0x00, 0x03, 0x00]), vec![0x00, 0x03, 0x00]);
+    }
+
+    #[test]
+    fn add_emulation_prevention() {
+        let raw = [0x42, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x04];
+        let escaped = escape(&raw);
+        assert_eq!(
+            escaped,
+            [0x42, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x01, 0x00, 0x00, 0x04]
+        );
+        assert_eq!(rbsp(&escaped), raw);
+    }
+}
@@ -0,0 +1,500 @@
+#[cfg(feature = "av1")]
+use crate::bits::BitReader;
+#[cfg(feature = "av1")]
+use crate::SubsamplingFormat;
+
+/// An error parsing OBUs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObuError(pub(crate) String);
+
+impl std::error::Error for ObuError {}
+
+impl std::fmt::Display for ObuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid OBU: {}", self.0)
+    }
+}
+
+fn invalid(detail: impl Into<String>) -> ObuError {
+    ObuError(detail.into())
+}
+
+/// The type of an OBU.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObuType {
+    /// A sequence header.
+    SequenceHeader,
+    /// A temporal delimiter, which starts each temporal unit.
+    TemporalDelimiter,
+    /// A frame header, without tile data.
+    FrameHeader,
+    /// A tile group.
+    TileGroup,
+    /// Metadata, like HDR information.
+    Metadata,
+    /// A frame header and tile group.
+    Frame,
+    /// A redundant copy of a frame header.
+    RedundantFrameHeader,
+    /// A tile list, used only for large scale tile decoding.
+    TileList,
+    /// Padding.
+    Padding,
+    /// A reserved type.
+    Reserved(u8),
+}
+
+impl ObuType {
+    fn from_raw(v: u8) -> Self {
+        match v {
+            1 => ObuType::SequenceHeader,
+            2 => ObuType::TemporalDelimiter,
+            3 => ObuType::FrameHeader,
+            4 => ObuType::TileGroup,
+            5 => ObuType::Metadata,
+            6 => ObuType::Frame,
+            7 => ObuType::RedundantFrameHeader,
+            8 => ObuType::TileList,
+            15 => ObuType::Padding,
+            v => ObuType::Reserved(v),
+        }
+    }
+}
+
+/// An OBU, borrowed from a packet. See [`obus`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Obu<'a> {
+    header: &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> Obu<'a> {
+    /// The type of the OBU.
+    pub fn obu_type(&self) -> ObuType {
+        ObuType::from_raw((self.header[0] >> 3) & 0xf)
+    }
+
+    /// The temporal layer of the OBU, or 0 if it has no extension header.
+    #[cfg_attr(not(feature = "av1"), allow(dead_code))]
+    pub fn temporal_id(&self) -> u8 {
+        self.header.get(1).map_or(0, |ext| ext >> 5)
+    }
+
+    /// The spatial layer of the OBU, or 0 if it has no extension header.
+    #[cfg_attr(not(feature = "av1"), allow(dead_code))]
+    pub fn spatial_id(&self) -> u8 {
+        self.header.get(1).map_or(0, |ext| (ext >> 3) & 0x3)
+    }
+
+    /// The OBU header, including the extension header if present. The
+    /// `obu_has_size_field` flag is kept as it was in the source data.
+    pub fn header(&self) -> &'a [u8] {
+        self.header
+    }
+
+    /// The OBU payload, without the header or size field.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// The size of the payload, in bytes.
+    pub fn size(&self) -> usize {
+        self.payload.len()
+    }
+}
+
+/// Splits a packet into OBUs, without copying. Every OBU but the last must
+/// have a size field, as in the output of the encoder.
+pub fn obus(data: &[u8]) -> Obus<'_> {
+    Obus { data }
+}
+
+/// An iterator over the OBUs in a packet, returned by [`obus`].
+#[derive(Debug, Clone)]
+pub struct Obus<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Obus<'a> {
+    type Item = Result<Obu<'a>, ObuError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let res = split_obu(self.data);
+        match res {
+            Ok((_, rest)) => self.data = rest,
+            Err(_) => self.data = &[],
+        }
+
+        Some(res.map(|(obu, _)| obu))
+    }
+}
+
+fn split_obu(data: &[u8]) -> Result<(Obu<'_>, &[u8]), ObuError> {
+    let first = data[0];
+    if first & 0x80 != 0 {
+        return Err(invalid("forbidden bit set"));
+    }
+
+    let header_len = if first & 0x4 != 0 { 2 } else { 1 };
+    if data.len() < header_len {
+        return Err(invalid("truncated header"));
+    }
+
+    let (header, rest) = data.split_at(header_len);
+    if first & 0x2 == 0 {
+        return Ok((
+            Obu {
+                header,
+                payload: rest,
+            },
+            &[],
+        ));
+    }
+
+    let (size, len) = read_leb128(rest).ok_or_else(|| invalid("invalid size field"))?;
+    let rest = &rest[len..];
+    if size > rest.len() {
+        return Err(invalid(format!(
+            "size {} exceeds remaining data ({} bytes)",
+            size,
+            rest.len()
+        )));
+    }
+
+    let (payload, rest) = rest.split_at(size);
+    Ok((Obu { header, payload }, rest))
+}
+
+pub(crate) fn read_leb128(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().take(8).enumerate() {
+        value |= ((byte & 0x7f) as usize) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+
+    None
+}
+
+pub(crate) fn write_leb128(buf: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+
+        buf.push(byte | 0x80);
+    }
+}
+
+/// The `frame_type` of a switch frame, in the AV1 frame header.
+#[cfg_attr(not(feature = "av1"), allow(dead_code))]
+pub(crate) const SWITCH_FRAME: u8 = 3;
+
+/// Returns the `frame_type` from the first frame header in `data` which
+/// doesn't just show an existing frame, or `None` if there isn't one.
+///
+/// The sequence header must not use `reduced_still_picture_header`, which
+/// the encoder only sets for still pictures.
+#[cfg_attr(not(feature = "av1"), allow(dead_code))]
+pub(crate) fn frame_type(data: &[u8]) -> Option<u8> {
+    obus(data)
+        .map_while(Result::ok)
+        .filter(|obu| matches!(obu.obu_type(), ObuType::FrameHeader | ObuType::Frame))
+        .filter_map(|obu| obu.payload().first().copied())
+        // show_existing_frame f(1), followed by frame_type f(2).
+        .find(|first| first & 0x80 == 0)
+        .map(|first| (first >> 5) & 0x3)
+}
+
+/// The fields of a sequence header which describe the stream as a whole,
+/// for example to fill in an `av1C` box when muxing to MP4 or Matroska.
+#[cfg(feature = "av1")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SequenceHeader {
+    /// The profile: 0 (main), 1 (high), or 2 (professional).
+    pub profile: u8,
+    /// Whether the stream contains a single picture.
+    pub still_picture: bool,
+    /// The level of the first operating point, as `seq_level_idx`. Level
+    /// X.Y is coded as `(X - 2) * 4 + Y`, so 8 is level 4.0.
+    pub level: u8,
+    /// Whether the first operating point uses the high tier.
+    pub high_tier: bool,
+    /// The maximum frame width.
+    pub max_width: u32,
+    /// The maximum frame height.
+    pub max_height: u32,
+    /// The size of the superblocks, either 64 or 128.
+    pub superblock_size: u32,
+    /// The bit depth.
+    pub bit_depth: u32,
+    /// The chroma subsampling.
+    pub subsampling_format: SubsamplingFormat,
+    /// The position of 4:2:0 chroma samples: 0 (unknown), 1 (vertically
+    /// aligned with luma), or 2 (co-located with luma).
+    pub chroma_sample_position: u8,
+}
+
+#[cfg(feature = "av1")]
+impl SequenceHeader {
+    /// Parses the payload of a sequence header OBU.
+    pub fn parse(payload: &[u8]) -> Result<Self, ObuError> {
+        parse_sequence_header(&mut BitReader::new(payload))
+            .ok_or_else(|| invalid("truncated sequence header"))
+    }
+
+    /// Finds and parses the first sequence header in a packet, or in the
+    /// stream headers output by the encoder.
+    pub fn find(data: &[u8]) -> Result<Option<Self>, ObuError> {
+        for obu in obus(data) {
+            let obu = obu?;
+            if obu.obu_type() == ObuType::SequenceHeader {
+                return Self::parse(obu.payload()).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "av1")]
+fn parse_sequence_header(r: &mut BitReader<'_>) -> Option<SequenceHeader> {
+    let profile = r.read(3)? as u8;
+    let still_picture = r.flag()?;
+    let reduced_still_picture_header = r.flag()?;
+
+    let level;
+    let mut high_tier = false;
+    if reduced_still_picture_header {
+        level = r.read(5)? as u8;
+    } else {
+        let mut buffer_delay_length = 0;
+        let mut decoder_model_info_present = false;
+        if r.flag()? {
+            // timing_info()
+            r.skip(64)?;
+            if r.flag()? {
+                r.uvlc()?;
+            }
+
+            decoder_model_info_present = r.flag()?;
+            if decoder_model_info_present {
+                buffer_delay_length = r.read(5)? + 1;
+                r.skip(32 + 5 + 5)?;
+            }
+        }
+
+        let initial_display_delay_present = r.flag()?;
+        let operating_points = r.read(5)? + 1;
+        let mut first = None;
+        for _ in 0..operating_points {
+            r.skip(12)?;
+            let seq_level_idx = r.read(5)? as u8;
+            let seq_tier = seq_level_idx > 7 && r.flag()?;
+            first.get_or_insert((seq_level_idx, seq_tier));
+
+            if decoder_model_info_present && r.flag()? {
+                r.skip(2 * buffer_delay_length + 1)?;
+            }
+
+            if initial_display_delay_present && r.flag()? {
+                r.skip(4)?;
+            }
+        }
+
+        (level, high_tier) = first?;
+    }
+
+    let width_bits = r.read(4)? + 1;
+    let height_bits = r.read(4)? + 1;
+    let max_width = r.read(width_bits)? + 1;
+    let max_height = r.read(height_bits)? + 1;
+
+    if !reduced_still_picture_header && r.flag()? {
+        // delta_frame_id_length_minus_2, additional_frame_id_length_minus_1
+        r.skip(4 + 3)?;
+    }
+
+    let superblock_size = if r.flag()? { 128 } else { 64 };
+
+    // enable_filter_intra, enable_intra_edge_filter
+    r.skip(2)?;
+    if !reduced_still_picture_header {
+        // enable_interintra_compound, enable_masked_compound,
+        // enable_warped_motion, enable_dual_filter
+        r.skip(4)?;
+        let enable_order_hint = r.flag()?;
+        if enable_order_hint {
+            // enable_jnt_comp, enable_ref_frame_mvs
+            r.skip(2)?;
+        }
+
+        let force_screen_content_tools = if r.flag()? { 2 } else { r.read(1)? };
+        if force_screen_content_tools > 0 && !r.flag()? {
+            // seq_force_integer_mv
+            r.skip(1)?;
+        }
+
+        if enable_order_hint {
+            // order_hint_bits_minus_1
+            r.skip(3)?;
+        }
+    }
+
+    // enable_superres, enable_cdef, enable_restoration
+    r.skip(3)?;
+
+    // color_config()
+    let high_bitdepth = r.flag()?;
+    let bit_depth = if profile == 2 && high_bitdepth {
+        if r.flag()? {
+            12
+        } else {
+            10
+        }
+    } else if high_bitdepth {
+        10
+    } else {
+        8
+    };
+
+    let monochrome = profile != 1 && r.flag()?;
+    let (mut color_primaries, mut transfer, mut matrix) = (2, 2, 2);
+    if r.flag()? {
+        color_primaries = r.read(8)?;
+        transfer = r.read(8)?;
+        matrix = r.read(8)?;
+    }
+
+    let mut chroma_sample_position = 0;
+    let subsampling_format = if monochrome {
+        SubsamplingFormat::Yuv400
+    } else if color_primaries == 1 && transfer == 13 && matrix == 0 {
+        // sRGB is always 4:4:4, with full range.
+        SubsamplingFormat::Yuv444
+    } else {
+        // color_range
+        r.skip(1)?;
+        let (x, y) = match profile {
+            0 => (true, true),
+            1 => (false, false),
+            _ if bit_depth == 12 => {
+                let x = r.flag()?;
+                (x, x && r.flag()?)
+            }
+            _ => (true, false),
+        };
+
+        if x && y {
+            chroma_sample_position = r.read(2)? as u8;
+        }
+
+        match (x, y) {
+            (true, true) => SubsamplingFormat::Yuv420,
+            (true, false) => SubsamplingFormat::Yuv422,
+            _ => SubsamplingFormat::Yuv444,
+        }
+    };
+
+    Some(SequenceHeader {
+        profile,
+        still_picture,
+        level,
+        high_tier,
+        max_width,
+        max_height,
+        superblock_size,
+        bit_depth,
+        subsampling_format,
+        chroma_sample_position,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The payload of a sequence header for 1920x1080, 10-bit 4:2:0, main
+    /// profile at level 4.0.
+    #[cfg(feature = "av1")]
+    const SEQUENCE_HEADER: [u8; 11] = [
+        0x00, 0x00, 0x00, 0x42, 0xab, 0xbf, 0xc3, 0x73, 0xff, 0xe7, 0x01,
+    ];
+
+    #[test]
+    fn split_obus() {
+        let data = [
+            0x12, 0x00, // Temporal delimiter.
+            0x32, 0x03, 0x10, 0x00, 0x00, // Frame.
+            0x2e, 0x48, 0x01, 0xff, // Metadata, with an extension header.
+        ];
+
+        let split = obus(&data).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(split.len(), 3);
+        assert_eq!(split[0].obu_type(), ObuType::TemporalDelimiter);
+        assert_eq!(split[0].size(), 0);
+        assert_eq!(split[1].obu_type(), ObuType::Frame);
+        assert_eq!(split[1].payload(), &[0x10, 0x00, 0x00]);
+        assert_eq!(split[2].obu_type(), ObuType::Metadata);
+        assert_eq!((split[2].temporal_id(), split[2].spatial_id()), (2, 1));
+        assert_eq!(split[2].header(), &[0x2e, 0x48]);
+
+        assert!(obus(&[0x32, 0x05, 0x00]).next().unwrap().is_err());
+        assert!(obus(&[0x92, 0x00]).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn frame_types() {
+        let data = [
+            0x12, 0x00, // Temporal delimiter.
+            0x32, 0x02, 0x70, 0x00, // Frame, frame_type = 3 (switch).
+        ];
+        assert_eq!(frame_type(&data), Some(SWITCH_FRAME));
+
+        // A frame header showing an existing frame, followed by an inter
+        // frame.
+        let data = [
+            0x1e, 0x08, 0x01, 0x80, // Frame header, show_existing_frame = 1.
+            0x32, 0x02, 0x30, 0x00, // Frame, frame_type = 1 (inter).
+        ];
+        assert_eq!(frame_type(&data), Some(1));
+
+        assert_eq!(frame_type(&[0x32, 0x05, 0x70]), None);
+        assert_eq!(frame_type(&[0x12, 0x00]), None);
+    }
+
+    #[cfg(feature = "av1")]
+    #[test]
+    fn sequence_header() {
+        let mut data = vec![0x12, 0x00, 0x0a, SEQUENCE_HEADER.len() as u8];
+        data.extend_from_slice(&SEQUENCE_HEADER);
+
+        let header = SequenceHeader::find(&data).unwrap().unwrap();
+        assert_eq!(
+            header,
+            SequenceHeader {
+                profile: 0,
+                still_picture: false,
+                level: 8,
+                high_tier: false,
+                max_width: 1920,
+                max_height: 1080,
+                superblock_size: 64,
+                bit_depth: 10,
+                subsampling_format: SubsamplingFormat::Yuv420,
+                chroma_sample_position: 0,
+            }
+        );
+
+        assert!(SequenceHeader::parse(&SEQUENCE_HEADER[..6]).is_err());
+        assert_eq!(SequenceHeader::find(&[0x12, 0x00]), Ok(None));
+    }
+}
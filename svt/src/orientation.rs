@@ -0,0 +1,57 @@
+/// A rotation to apply to decoded pictures before displaying them,
+/// counter-clockwise.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// No rotation.
+    #[default]
+    None,
+    /// 90 degrees.
+    Ccw90,
+    /// 180 degrees.
+    Ccw180,
+    /// 270 degrees.
+    Ccw270,
+}
+
+impl Rotation {
+    /// The rotation in degrees.
+    pub fn degrees(self) -> u32 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Ccw90 => 90,
+            Rotation::Ccw180 => 180,
+            Rotation::Ccw270 => 270,
+        }
+    }
+}
+
+/// How decoded pictures should be oriented for display, for example to
+/// account for the orientation of a camera sensor. Signaling this in the
+/// stream avoids rotating every picture before it's encoded.
+///
+/// Flips are applied before the rotation, as in the HEVC display
+/// orientation SEI message, which HEVC encoders write (see
+/// [`HevcEncoderConfig::display_orientation`](crate::hevc::HevcEncoderConfig::display_orientation)).
+/// AV1 has no equivalent in the bitstream, so AV1 encoders only carry the
+/// orientation for a muxer to write into the container, for example as an
+/// MP4 track matrix (see
+/// [`Av1EncoderConfig::display_orientation`](crate::av1::Av1EncoderConfig::display_orientation)).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Orientation {
+    /// The rotation to apply.
+    pub rotation: Rotation,
+    /// Whether to mirror the picture horizontally.
+    pub horizontal_flip: bool,
+    /// Whether to mirror the picture vertically.
+    pub vertical_flip: bool,
+}
+
+impl Orientation {
+    /// An orientation which only rotates the picture.
+    pub fn rotated(rotation: Rotation) -> Self {
+        Self {
+            rotation,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Frame pacing for live encoding.
+//!
+//! When the encoder can't keep up with a live source, pictures have to be
+//! dropped (or the encoder made faster) to keep latency bounded. A [`Pacer`]
+//! buffers incoming pictures, and releases them for encoding only while the
+//! encoder has fewer than a configured number of frames in flight.
+//!
+//! ```
+//! # use svt::pacing::{Pacer, PacingPolicy};
+//! # use svt::{Encoder, YUVBuffer};
+//! # fn example(encoder: impl Encoder, stats: impl Fn() -> svt::Stats,
+//! #     capture: impl Fn() -> YUVBuffer) -> Result<(), svt::Error> {
+//! let mut pacer = Pacer::new(PacingPolicy::DropOldest, 4, 1);
+//!
+//! for pts in 0.. {
+//!     pacer.offer(capture(), pts);
+//!     while let Some((picture, pts)) = pacer.next(&stats()) {
+//!         encoder.send_picture(&picture, pts, false)?;
+//!     }
+//!     # break;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::Stats;
+
+/// What to do when the encoder falls behind.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PacingPolicy {
+    /// Drop incoming pictures until the encoder catches up.
+    DropNewest,
+    /// Drop the oldest buffered pictures, so that the most recent picture is
+    /// always encoded next.
+    DropOldest,
+    /// Drop incoming pictures, like [`PacingPolicy::DropNewest`], and
+    /// recommend a faster preset. See [`Pacer::recommended_preset`].
+    ReducePreset {
+        /// The preset currently in use.
+        current: u8,
+        /// The fastest preset to recommend.
+        max: u8,
+    },
+}
+
+/// Buffers pictures from a live source, dropping them according to a
+/// [`PacingPolicy`] when the encoder falls behind.
+#[derive(Debug)]
+pub struct Pacer<P> {
+    policy: PacingPolicy,
+    max_pending: u64,
+    capacity: usize,
+    queue: VecDeque<(P, i64)>,
+    dropped: u64,
+    recommended_preset: Option<u8>,
+    first_offer: Option<Instant>,
+    offered: u64,
+}
+
+impl<P> Pacer<P> {
+    /// Creates a pacer which releases pictures while fewer than `max_pending`
+    /// frames are in flight in the encoder, and buffers up to `capacity`
+    /// pictures otherwise.
+    pub fn new(policy: PacingPolicy, max_pending: u64, capacity: usize) -> Self {
+        Self {
+            policy,
+            max_pending,
+            capacity: capacity.max(1),
+            queue: VecDeque::with_capacity(capacity),
+            dropped: 0,
+            recommended_preset: None,
+            first_offer: None,
+            offered: 0,
+        }
+    }
+
+    /// Offers a picture from the source. If the buffer is full, either this
+    /// picture or the oldest buffered picture is dropped, depending on the
+    /// policy.
+    pub fn offer(&mut self, picture: P, pts: i64) {
+        self.first_offer.get_or_insert_with(Instant::now);
+        self.offered += 1;
+
+        if self.queue.len() < self.capacity {
+            self.queue.push_back((picture, pts));
+            return;
+        }
+
+        self.dropped += 1;
+        match self.policy {
+            PacingPolicy::DropNewest => (),
+            PacingPolicy::DropOldest => {
+                self.queue.pop_front();
+                self.queue.push_back((picture, pts));
+            }
+            PacingPolicy::ReducePreset { current, max } => {
+                let preset = self.recommended_preset.unwrap_or(current);
+                self.recommended_preset = Some(preset.saturating_add(1).min(max));
+            }
+        }
+    }
+
+    /// Returns the next picture to encode, if the encoder has room for it.
+    /// `stats` should be a recent snapshot from the encoder.
+    pub fn next(&mut self, stats: &Stats) -> Option<(P, i64)> {
+        if stats.pending_frames() >= self.max_pending {
+            return None;
+        }
+
+        self.queue.pop_front()
+    }
+
+    /// The number of pictures dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The rate at which pictures have been offered, in frames per second.
+    /// Comparing this with the configured framerate shows whether the source
+    /// is running fast or slow.
+    pub fn input_rate(&self) -> Option<f64> {
+        let elapsed = self.first_offer?.elapsed().as_secs_f64();
+        (elapsed > 0.0 && self.offered > 1).then(|| (self.offered - 1) as f64 / elapsed)
+    }
+
+    /// With [`PacingPolicy::ReducePreset`], the faster preset recommended
+    /// after pictures were dropped. Changing the preset requires creating a
+    /// new encoder, so applications should switch at a convenient point, like
+    /// the next keyframe.
+    pub fn recommended_preset(&self) -> Option<u8> {
+        self.recommended_preset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(pending: u64) -> Stats {
+        Stats {
+            frames_submitted: pending,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn drop_newest() {
+        let mut pacer = Pacer::new(PacingPolicy::DropNewest, 1, 2);
+        for pts in 0..4 {
+            pacer.offer((), pts);
+        }
+
+        assert_eq!(pacer.dropped(), 2);
+        assert_eq!(pacer.next(&stats(1)), None);
+        assert_eq!(pacer.next(&stats(0)), Some(((), 0)));
+        assert_eq!(pacer.next(&stats(0)), Some(((), 1)));
+        assert_eq!(pacer.next(&stats(0)), None);
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let mut pacer = Pacer::new(PacingPolicy::DropOldest, 1, 2);
+        for pts in 0..4 {
+            pacer.offer((), pts);
+        }
+
+        assert_eq!(pacer.dropped(), 2);
+        assert_eq!(pacer.next(&stats(0)), Some(((), 2)));
+        assert_eq!(pacer.next(&stats(0)), Some(((), 3)));
+    }
+
+    #[test]
+    fn reduce_preset() {
+        let mut pacer = Pacer::new(PacingPolicy::ReducePreset { current: 8, max: 9 }, 1, 1);
+        pacer.offer((), 0);
+        assert_eq!(pacer.recommended_preset(), None);
+
+        pacer.offer((), 1);
+        assert_eq!(pacer.recommended_preset(), Some(9));
+        pacer.offer((), 2);
+        assert_eq!(pacer.recommended_preset(), Some(9));
+    }
+}
@@ -0,0 +1,324 @@
+//! Segment-parallel encoding, for offline encodes on machines with more
+//! cores than a single encoder instance can keep busy.
+//!
+//! The input is split into segments of consecutive pictures, which are
+//! encoded concurrently by separate encoders. Each segment starts with a
+//! keyframe, so the segments' packets can simply be concatenated, in order,
+//! into a single stream. For regular keyframe placement, the segment length
+//! should be a multiple of the configured intra period.
+//!
+//! ```no_run
+//! # #[cfg(all(feature = "av1", feature = "y4m"))]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::fs::File;
+//! use std::io::{BufReader, Write};
+//!
+//! use svt::av1::Av1EncoderConfig;
+//! use svt::parallel::encode_file_parallel;
+//! use svt::y4m::Y4mReader;
+//! use svt::{EncoderConfig, Packet};
+//!
+//! let reader = Y4mReader::new(BufReader::new(File::open("input.y4m")?))?;
+//! let packets = encode_file_parallel(
+//!     reader,
+//!     || Av1EncoderConfig::default().preset(8).gop(Some(240)),
+//!     4,
+//! )?;
+//!
+//! let mut output = File::create("output.obu")?;
+//! for packet in packets {
+//!     output.write_all(packet.as_bytes())?;
+//! }
+//! # Ok(())
+//! # }
+//! # #[cfg(not(all(feature = "av1", feature = "y4m")))]
+//! # fn main() {}
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Mutex};
+use std::time::Instant;
+
+use crate::{EncoderConfig, Error, OwnedPacket, Picture, Session, SubsamplingFormat};
+
+/// The segment length used by [`encode_file_parallel`], in frames.
+pub const DEFAULT_SEGMENT_LENGTH: usize = 240;
+
+/// An error from a parallel encode: either reading the input failed, or one
+/// of the encoders did.
+#[derive(Debug)]
+pub enum ParallelError<E> {
+    /// Reading a picture from the input failed.
+    Input(E),
+    /// Encoding a segment failed.
+    Encode(Error),
+}
+
+impl<E: std::error::Error> std::error::Error for ParallelError<E> {}
+
+impl<E: std::fmt::Display> std::fmt::Display for ParallelError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParallelError::Input(e) => write!(f, "failed to read input: {}", e),
+            ParallelError::Encode(e) => write!(f, "failed to encode segment: {}", e),
+        }
+    }
+}
+
+impl<E> From<Error> for ParallelError<E> {
+    fn from(e: Error) -> Self {
+        ParallelError::Encode(e)
+    }
+}
+
+/// Encodes pictures in segments of `segment_length` pictures, on `jobs`
+/// encoders at a time, each created with `config`. Returns one packet for
+/// each picture, in decode order, with the picture's position in the input
+/// as its pts.
+///
+/// Pictures are read on the calling thread, and at most about twice `jobs`
+/// segments are held in memory at once. Encoding stops at the first error.
+pub fn encode_parallel<C, P, E>(
+    pictures: impl IntoIterator<Item = Result<P, E>>,
+    config: impl Fn() -> C + Sync,
+    jobs: usize,
+    segment_length: usize,
+    subsampling_format: SubsamplingFormat,
+) -> Result<Vec<OwnedPacket>, ParallelError<E>>
+where
+    C: EncoderConfig,
+    P: Picture + Send,
+{
+    let jobs = jobs.max(1);
+    let segment_length = segment_length.max(1);
+
+    let (segment_tx, segment_rx) = mpsc::sync_channel::<(usize, Vec<P>)>(jobs);
+    let segment_rx = Mutex::new(segment_rx);
+    let (result_tx, result_rx) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            let (segment_rx, result_tx, config) = (&segment_rx, result_tx.clone(), &config);
+            scope.spawn(move || loop {
+                let Ok((index, segment)) = segment_rx.lock().unwrap().recv() else {
+                    break;
+                };
+
+                let packets = encode_segment(config(), &segment, subsampling_format);
+                if result_tx.send((index, packets)).is_err() {
+                    break;
+                }
+            });
+        }
+
+        drop(result_tx);
+
+        let mut results = BTreeMap::new();
+        let mut error = None;
+        let mut pictures = pictures.into_iter();
+        for index in 0.. {
+            let mut segment = Vec::with_capacity(segment_length);
+            for picture in pictures.by_ref().take(segment_length) {
+                match picture {
+                    Ok(picture) => segment.push(picture),
+                    Err(e) => {
+                        error = Some(ParallelError::Input(e));
+                        break;
+                    }
+                }
+            }
+
+            if segment.is_empty() || error.is_some() {
+                break;
+            }
+
+            if segment_tx.send((index, segment)).is_err() {
+                break;
+            }
+
+            // Stop reading early if an encoder has already failed.
+            for (index, packets) in result_rx.try_iter() {
+                match packets {
+                    Ok(packets) => {
+                        results.insert(index, packets);
+                    }
+                    Err(e) => {
+                        error.get_or_insert(ParallelError::Encode(e));
+                    }
+                }
+            }
+
+            if error.is_some() {
+                break;
+            }
+        }
+
+        // Let the workers finish the queued segments and exit.
+        drop(segment_tx);
+        for (index, packets) in result_rx {
+            match packets {
+                Ok(packets) => {
+                    results.insert(index, packets);
+                }
+                Err(e) => {
+                    error.get_or_insert(ParallelError::Encode(e));
+                }
+            }
+        }
+
+        if let Some(e) = error {
+            return Err(e);
+        }
+
+        Ok(results
+            .into_iter()
+            .flat_map(|(index, packets)| {
+                let offset = (index * segment_length) as i64;
+                packets.into_iter().map(move |p| p.rebase(offset))
+            })
+            .collect())
+    })
+}
+
+/// Encodes a y4m stream with [`encode_parallel`], in segments of
+/// [`DEFAULT_SEGMENT_LENGTH`] frames. The framerate is taken from the
+/// stream; other parameters, like the bit depth, must be set by `config`.
+#[cfg(feature = "y4m")]
+pub fn encode_file_parallel<R: std::io::Read, C: EncoderConfig>(
+    mut reader: crate::y4m::Y4mReader<R>,
+    config: impl Fn() -> C + Sync,
+    jobs: usize,
+) -> Result<Vec<OwnedPacket>, ParallelError<crate::y4m::Y4mError>> {
+    use crate::{YUVBuffer, YUVBuffer16};
+
+    let header = reader.header();
+    let (width, height, format) = (header.width, header.height, header.subsampling_format);
+    let with_framerate = || config().framerate(header.framerate.num, header.framerate.den);
+
+    if header.bit_depth > 8 {
+        let pictures = read_frames(
+            |buf| reader.read_frame16(buf),
+            || YUVBuffer16::new(width, height, format),
+        );
+        encode_parallel(
+            pictures,
+            with_framerate,
+            jobs,
+            DEFAULT_SEGMENT_LENGTH,
+            format,
+        )
+    } else {
+        let pictures = read_frames(
+            |buf| reader.read_frame(buf),
+            || YUVBuffer::new(width, height, format),
+        );
+        encode_parallel(
+            pictures,
+            with_framerate,
+            jobs,
+            DEFAULT_SEGMENT_LENGTH,
+            format,
+        )
+    }
+}
+
+/// Reads pictures into new buffers until the end of the stream, or the first
+/// error.
+#[cfg(feature = "y4m")]
+fn read_frames<B, E>(
+    mut read: impl FnMut(&mut B) -> Result<bool, E>,
+    new: impl Fn() -> B,
+) -> impl Iterator<Item = Result<B, E>> {
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+
+        let mut buf = new();
+        match read(&mut buf) {
+            Ok(true) => Some(Ok(buf)),
+            Ok(false) => None,
+            Err(e) => {
+                done = true;
+                Some(Err(e))
+            }
+        }
+    })
+}
+
+/// Encodes a segment on a fresh encoder, starting with a keyframe.
+fn encode_segment<C: EncoderConfig>(
+    config: C,
+    pictures: &[impl Picture],
+    subsampling_format: SubsamplingFormat,
+) -> Result<Vec<OwnedPacket>, Error> {
+    let mut session = Session::new(config).subsampling_format(subsampling_format);
+    let now = Instant::now();
+    for (i, picture) in pictures.iter().enumerate() {
+        if i == 0 {
+            session.encode_keyframe(picture, now)?;
+        } else {
+            session.encode(picture, now)?;
+        }
+    }
+
+    session.flush()
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::MockEncoderConfig;
+    use crate::{Packet, YUVBuffer};
+
+    fn pictures(n: usize) -> impl Iterator<Item = Result<YUVBuffer, String>> {
+        (0..n).map(|_| Ok(YUVBuffer::new(64, 64, SubsamplingFormat::Yuv420)))
+    }
+
+    #[test]
+    fn segments() {
+        let config = || MockEncoderConfig::default().delay(2);
+        let packets = encode_parallel(pictures(10), config, 3, 4, SubsamplingFormat::Yuv420)
+            .expect("failed to encode");
+
+        let pts: Vec<i64> = packets.iter().map(|p| p.pts()).collect();
+        assert_eq!(pts, (0..10).collect::<Vec<_>>());
+
+        let keyframes: Vec<i64> = packets
+            .iter()
+            .filter(|p| p.is_keyframe())
+            .map(|p| p.pts())
+            .collect();
+        assert_eq!(keyframes, [0, 4, 8]);
+        assert!(packets.iter().all(|p| p.timestamp().is_none()));
+        assert!(!packets[9].as_bytes().is_empty());
+    }
+
+    #[test]
+    fn input_error() {
+        let input = pictures(6).chain(std::iter::once(Err("truncated".to_owned())));
+        let err = encode_parallel(
+            input,
+            MockEncoderConfig::default,
+            2,
+            4,
+            SubsamplingFormat::Yuv420,
+        )
+        .expect_err("expected an input error");
+        assert!(matches!(err, ParallelError::Input(ref e) if e == "truncated"));
+    }
+
+    #[test]
+    fn encode_error() {
+        let err = encode_parallel(
+            pictures(8),
+            MockEncoderConfig::default,
+            2,
+            4,
+            SubsamplingFormat::Yuv444,
+        )
+        .expect_err("expected an encode error");
+        assert!(matches!(err, ParallelError::Encode(_)));
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use crate::ThreadOptions;
+use crate::{Encoder, EncoderSender, Error, FrameTypeRequest, Packet, Picture, ShutdownHandle};
+
+/// An encoder which retrieves packets on a background thread, and delivers
+/// them over a channel.
+///
+/// This saves applications from interleaving calls to
+/// [`Encoder::send_picture`] and [`Encoder::get_packet`]; packets are always
+/// drained as soon as they're ready, so the encoder never stalls waiting for
+/// the application.
+///
+/// # Example
+///
+/// ```
+/// # use svt::{Encoder, PipelinedEncoder, YUVBuffer};
+/// # fn example<E>(encoder: E) -> Result<(), svt::Error>
+/// # where
+/// #     E: Encoder + Send + Sync + 'static,
+/// #     E::Packet: Send + 'static,
+/// # {
+/// let pipeline = PipelinedEncoder::new(encoder);
+///
+/// let picture = YUVBuffer::new(800, 600, svt::SubsamplingFormat::Yuv420);
+/// pipeline.send_picture(&picture, 0, false)?;
+/// pipeline.finish()?;
+///
+/// for packet in pipeline.packets() {
+///     let packet = packet?;
+///     // Write the packet to a file or send it over the network.
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PipelinedEncoder<E: Encoder> {
+    sender: EncoderSender<E>,
+    packets: mpsc::Receiver<Result<E::Packet, Error>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<E> PipelinedEncoder<E>
+where
+    E: Encoder + Send + Sync + 'static,
+    E::Packet: Send + 'static,
+{
+    /// Wraps an encoder, spawning a thread to retrieve packets from it.
+    pub fn new(encoder: E) -> Self {
+        Self::with_thread_options(encoder, &ThreadOptions::default())
+            .expect("failed to spawn packet thread")
+    }
+
+    /// Wraps an encoder, like [`PipelinedEncoder::new`], spawning the packet
+    /// thread with the given name, affinity and priority. If the options
+    /// can't be applied, the error is returned.
+    pub fn with_thread_options(encoder: E, options: &ThreadOptions) -> Result<Self, Error> {
+        let (sender, receiver) = encoder.split();
+        let (tx, packets) = mpsc::channel();
+
+        let thread = options.spawn(move || loop {
+            let (res, done) = match receiver.get_packet(true) {
+                Ok(Some(packet)) => {
+                    let eos = packet.is_eos();
+                    (Ok(packet), eos)
+                }
+                Ok(None) => continue,
+                Err(e) => (Err(e), true),
+            };
+
+            if tx.send(res).is_err() || done {
+                break;
+            }
+        })?;
+
+        Ok(Self {
+            sender,
+            packets,
+            thread: Some(thread),
+        })
+    }
+
+    /// Sends an input picture to the encoder. See [`Encoder::send_picture`].
+    pub fn send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        self.sender.send_picture(picture, pts, frame_type)
+    }
+
+    /// Requests that the encoder finish encoding. The channel returned by
+    /// [`PipelinedEncoder::packets`] is closed after the EOS packet.
+    pub fn finish(&self) -> Result<(), Error> {
+        self.sender.finish()
+    }
+
+    /// Returns a handle which can finish the stream from any thread. See
+    /// [`ShutdownHandle`].
+    pub fn shutdown_handle(&self) -> ShutdownHandle<E> {
+        self.sender.shutdown_handle()
+    }
+
+    /// The channel over which packets are delivered. If retrieving a packet
+    /// fails, the error is delivered instead, and the channel is closed.
+    pub fn packets(&self) -> &mpsc::Receiver<Result<E::Packet, Error>> {
+        &self.packets
+    }
+}
+
+impl<E: Encoder> Drop for PipelinedEncoder<E> {
+    fn drop(&mut self) {
+        // The background thread blocks until the stream is finished. This
+        // does nothing if it already has been.
+        if self.sender.finish().is_err() {
+            return;
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
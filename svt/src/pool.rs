@@ -0,0 +1,306 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{Encoder, Error, FrameTypeRequest, Packet, Picture, Plane};
+use crate::{SubsamplingFormat, YUVBuffer};
+
+/// A pool of reusable input pictures.
+///
+/// Callers that can't be sure when the encoder is done with an input picture
+/// often copy each frame into a fresh buffer before submitting it. A pool
+/// avoids the copy: pictures are filled in place, and only handed out again
+/// once the encoder has output a packet completing them (see
+/// [`Packet::completed_pts`]).
+///
+/// The capacity must be larger than the number of pictures the encoder
+/// buffers before it outputs its first packet, which depends on the
+/// lookahead and prediction structure. Otherwise the pool runs dry while the
+/// encoder is still waiting for input.
+///
+/// ```no_run
+/// # use svt::{Encoder, PicturePool, Plane, SubsamplingFormat};
+/// # fn example(encoder: &impl Encoder) -> Result<(), svt::Error> {
+/// let pool = PicturePool::new(320, 240, SubsamplingFormat::Yuv420, 64);
+///
+/// for pts in 0..100 {
+///     let mut picture = loop {
+///         if let Some(picture) = pool.get() {
+///             break picture;
+///         }
+///
+///         // Wait for the encoder to release a picture.
+///         if let Some(packet) = encoder.get_packet(true)? {
+///             pool.recycle(&packet);
+///         }
+///     };
+///
+///     picture.as_mut_slice(Plane::Y).fill(128);
+///     pool.send(encoder, picture, pts, false)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The pool can be cloned and shared between threads, for example between
+/// the two halves of a split encoder. In that case, send the picture with
+/// [`EncoderSender::send_picture`](crate::EncoderSender::send_picture) and
+/// then mark it as sent with [`PooledPicture::mark_sent`].
+#[derive(Clone)]
+pub struct PicturePool {
+    inner: Arc<Mutex<PoolInner>>,
+    width: u32,
+    height: u32,
+    format: SubsamplingFormat,
+}
+
+struct PoolInner {
+    free: Vec<YUVBuffer>,
+    sent: Vec<(i64, YUVBuffer)>,
+    allocated: usize,
+    capacity: usize,
+}
+
+impl std::fmt::Debug for PicturePool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inner = self.inner.lock().unwrap();
+        f.debug_struct("PicturePool")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("allocated", &inner.allocated)
+            .field("sent", &inner.sent.len())
+            .finish()
+    }
+}
+
+impl PicturePool {
+    /// Creates a pool of at most `capacity` pictures with the given
+    /// dimensions and subsampling format. Pictures are allocated on demand.
+    pub fn new(width: u32, height: u32, format: SubsamplingFormat, capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(PoolInner {
+                free: Vec::with_capacity(capacity),
+                sent: Vec::with_capacity(capacity),
+                allocated: 0,
+                capacity,
+            })),
+            width,
+            height,
+            format,
+        }
+    }
+
+    /// Takes a picture from the pool, or returns `None` if all of them are
+    /// in use. The contents of a recycled picture are left as they were.
+    ///
+    /// If the picture is dropped without being sent, it's returned to the
+    /// pool immediately.
+    pub fn get(&self) -> Option<PooledPicture> {
+        let mut inner = self.inner.lock().unwrap();
+        let buffer = match inner.free.pop() {
+            Some(buffer) => buffer,
+            None if inner.allocated < inner.capacity => {
+                inner.allocated += 1;
+                YUVBuffer::new(self.width, self.height, self.format)
+            }
+            None => return None,
+        };
+
+        Some(PooledPicture {
+            buffer: Some(buffer),
+            pool: self.inner.clone(),
+        })
+    }
+
+    /// Sends a picture to the encoder, keeping it out of the pool until a
+    /// packet completing it is passed to [`PicturePool::recycle`]. If sending
+    /// fails, the picture is returned to the pool.
+    pub fn send<E: Encoder>(
+        &self,
+        encoder: &E,
+        picture: PooledPicture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        encoder.send_picture(&picture, pts, frame_type)?;
+        picture.mark_sent(pts);
+        Ok(())
+    }
+
+    /// Returns the picture completed by `packet`, if any, to the pool. Once
+    /// the EOS packet is passed in, all sent pictures are returned.
+    pub fn recycle(&self, packet: &impl Packet) {
+        let mut inner = self.inner.lock().unwrap();
+        if packet.is_eos() {
+            let sent = std::mem::take(&mut inner.sent);
+            inner
+                .free
+                .extend(sent.into_iter().map(|(_, buffer)| buffer));
+            return;
+        }
+
+        let Some(pts) = packet.completed_pts() else {
+            return;
+        };
+
+        if let Some(idx) = inner.sent.iter().position(|(p, _)| *p == pts) {
+            let (_, buffer) = inner.sent.swap_remove(idx);
+            inner.free.push(buffer);
+        }
+    }
+
+    /// The number of pictures that can be taken from the pool without
+    /// waiting for the encoder.
+    pub fn available(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner.free.len() + (inner.capacity - inner.allocated)
+    }
+}
+
+/// A picture taken from a [`PicturePool`].
+pub struct PooledPicture {
+    buffer: Option<YUVBuffer>,
+    pool: Arc<Mutex<PoolInner>>,
+}
+
+impl std::fmt::Debug for PooledPicture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PooledPicture").field(self.buffer()).finish()
+    }
+}
+
+impl PooledPicture {
+    /// Get mutable access to a plane.
+    pub fn as_mut_slice(&mut self, plane: Plane) -> &mut [u8] {
+        self.buffer.as_mut().unwrap().as_mut_slice(plane)
+    }
+
+    /// Records that the picture has been sent to the encoder with the given
+    /// pts. It's returned to the pool once a packet completing it is passed
+    /// to [`PicturePool::recycle`].
+    ///
+    /// This is only necessary if the picture was sent without
+    /// [`PicturePool::send`].
+    pub fn mark_sent(mut self, pts: i64) {
+        let buffer = self.buffer.take().unwrap();
+        self.pool.lock().unwrap().sent.push((pts, buffer));
+    }
+
+    fn buffer(&self) -> &YUVBuffer {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl Picture for PooledPicture {
+    fn width(&self) -> u32 {
+        self.buffer().width()
+    }
+
+    fn height(&self) -> u32 {
+        self.buffer().height()
+    }
+
+    fn as_slice(&self, plane: Plane) -> &[u8] {
+        self.buffer().as_slice(plane)
+    }
+
+    fn stride(&self, plane: Plane) -> u32 {
+        self.buffer().stride(plane)
+    }
+}
+
+impl Drop for PooledPicture {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.lock().unwrap().free.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestPacket {
+        completed_pts: Option<i64>,
+        eos: bool,
+    }
+
+    impl AsRef<[u8]> for TestPacket {
+        fn as_ref(&self) -> &[u8] {
+            &[]
+        }
+    }
+
+    impl Packet for TestPacket {
+        fn as_bytes(&self) -> &[u8] {
+            &[]
+        }
+
+        fn is_eos(&self) -> bool {
+            self.eos
+        }
+
+        fn completed_pts(&self) -> Option<i64> {
+            self.completed_pts
+        }
+    }
+
+    fn completing(pts: i64) -> TestPacket {
+        TestPacket {
+            completed_pts: Some(pts),
+            eos: false,
+        }
+    }
+
+    #[test]
+    fn recycle() {
+        let pool = PicturePool::new(16, 16, SubsamplingFormat::Yuv420, 2);
+        pool.get().unwrap().mark_sent(0);
+        pool.get().unwrap().mark_sent(1);
+        assert!(pool.get().is_none());
+        assert_eq!(pool.available(), 0);
+
+        // An alt-ref frame doesn't complete its picture.
+        pool.recycle(&TestPacket {
+            completed_pts: None,
+            eos: false,
+        });
+        assert_eq!(pool.available(), 0);
+
+        pool.recycle(&completing(1));
+        assert_eq!(pool.available(), 1);
+
+        // Completing the same picture twice has no effect.
+        pool.recycle(&completing(1));
+        assert_eq!(pool.available(), 1);
+
+        let picture = pool.get().unwrap();
+        assert_eq!(picture.width(), 16);
+        assert!(pool.get().is_none());
+    }
+
+    #[test]
+    fn unsent_pictures_are_returned() {
+        let pool = PicturePool::new(16, 16, SubsamplingFormat::Yuv420, 1);
+        drop(pool.get().unwrap());
+        assert_eq!(pool.available(), 1);
+        assert!(pool.get().is_some());
+    }
+
+    #[test]
+    fn eos_returns_all() {
+        let pool = PicturePool::new(16, 16, SubsamplingFormat::Yuv420, 3);
+        for pts in 0..3 {
+            pool.get().unwrap().mark_sent(pts);
+        }
+
+        pool.recycle(&completing(2));
+        assert_eq!(pool.available(), 1);
+
+        pool.recycle(&TestPacket {
+            completed_pts: None,
+            eos: true,
+        });
+        assert_eq!(pool.available(), 3);
+    }
+}
@@ -0,0 +1,318 @@
+//! Quick probe encodes for per-title encoding.
+//!
+//! A [`Probe`] encodes a sample of a title's frames with AV1 at several CRF
+//! values, using a fast preset, and measures the size and quality of each
+//! encode by comparing the reconstructed frames with the source. The
+//! resulting rate/quality points can be reduced to their
+//! [`convex_hull`], from which the bitrate ladder for the title is chosen.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), svt::Error> {
+//! use svt::av1::Av1EncoderConfig;
+//! use svt::probe::{convex_hull, Probe};
+//! use svt::{SubsamplingFormat, YUVBuffer};
+//!
+//! let frames: Vec<YUVBuffer> = Vec::new(); // Decoded from the title.
+//! let points = Probe::new([20, 30, 40, 50]).run(
+//!     || Av1EncoderConfig::default().framerate(24, 1),
+//!     &frames,
+//!     1920,
+//!     1080,
+//!     SubsamplingFormat::Yuv420,
+//! )?;
+//!
+//! for point in convex_hull(&points) {
+//!     println!("crf {}: {:.0} bps, {:.2} dB", point.crf, point.bitrate(24.0), point.score());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::av1::{Av1Encoder, Av1EncoderConfig, RateControlMode};
+use crate::quality::{QualityAnalyzer, SessionQuality};
+use crate::{Av1Qp, Encoder, Error, Packet, Picture, SubsamplingFormat};
+
+/// The size and quality of a probe encode at a single CRF value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RatePoint {
+    /// The CRF value used for the encode.
+    pub crf: u32,
+    /// The number of frames encoded.
+    pub frames: usize,
+    /// The total size of the encoded frames, in bytes.
+    pub bytes: u64,
+    /// The quality of the reconstructed frames.
+    pub quality: SessionQuality,
+}
+
+impl RatePoint {
+    /// The mean size of each frame, in bits.
+    pub fn bits_per_frame(&self) -> f64 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+
+        self.bytes as f64 * 8.0 / self.frames as f64
+    }
+
+    /// The bitrate at the given framerate, in bits per second.
+    pub fn bitrate(&self, framerate: f64) -> f64 {
+        self.bits_per_frame() * framerate
+    }
+
+    /// The quality score used to compare points: the mean VMAF score if VMAF
+    /// was enabled, or the mean luma PSNR otherwise.
+    pub fn score(&self) -> f64 {
+        self.quality.vmaf.unwrap_or(self.quality.psnr[0])
+    }
+}
+
+/// Runs probe encodes of a title at several CRF values.
+#[derive(Debug, Clone)]
+pub struct Probe {
+    crf_values: Vec<u32>,
+    preset: i8,
+    segments: usize,
+    segment_length: usize,
+}
+
+impl Probe {
+    /// Creates a probe which encodes at each of the given CRF values (1-63).
+    ///
+    /// By default, the probe uses preset 12, and samples four evenly spaced
+    /// segments of eight consecutive frames each.
+    pub fn new(crf_values: impl IntoIterator<Item = u32>) -> Self {
+        Self {
+            crf_values: crf_values.into_iter().collect(),
+            preset: 12,
+            segments: 4,
+            segment_length: 8,
+        }
+    }
+
+    /// Sets the preset used for the probe encodes. Faster presets make the
+    /// probe cheaper, but less representative of the final encode.
+    pub fn preset(mut self, preset: i8) -> Self {
+        self.preset = preset;
+        self
+    }
+
+    /// Sets how the frames are sampled: `count` evenly spaced segments of
+    /// `length` consecutive frames each. Consecutive frames are needed for
+    /// inter prediction to behave as it would in the final encode.
+    pub fn segments(mut self, count: usize, length: usize) -> Self {
+        self.segments = count.max(1);
+        self.segment_length = length.max(1);
+        self
+    }
+
+    /// The indices of the frames to encode, out of `len`.
+    fn sample(&self, len: usize) -> Vec<usize> {
+        if len <= self.segments * self.segment_length {
+            return (0..len).collect();
+        }
+
+        let spacing = len / self.segments;
+        (0..self.segments)
+            .flat_map(|segment| {
+                let start = segment * spacing;
+                start..start + self.segment_length
+            })
+            .collect()
+    }
+
+    /// Encodes the sampled frames once for each CRF value, and returns the
+    /// rate/quality points in order of increasing size.
+    ///
+    /// Each encode uses a fresh config from `config`, which should set
+    /// everything but the preset and rate control, like the framerate and bit
+    /// depth. Recon output is enabled on it for the quality measurement.
+    pub fn run<P: Picture>(
+        &self,
+        config: impl Fn() -> Av1EncoderConfig,
+        frames: &[P],
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<Vec<RatePoint>, Error> {
+        let sample: Vec<&P> = self
+            .sample(frames.len())
+            .into_iter()
+            .map(|i| &frames[i])
+            .collect();
+
+        let mut points = Vec::with_capacity(self.crf_values.len());
+        for &crf in &self.crf_values {
+            let encoder = config()
+                .preset(self.preset)
+                .rate_control_mode(RateControlMode::ConstantRateFactor(Av1Qp::new(crf)?))
+                .enable_recon(true)
+                .create_encoder(width, height, subsampling_format)?;
+
+            let (bytes, quality) = encode_sample(&encoder, &sample)?;
+            points.push(RatePoint {
+                crf,
+                frames: sample.len(),
+                bytes,
+                quality,
+            });
+        }
+
+        points.sort_by_key(|p| p.bytes);
+        Ok(points)
+    }
+}
+
+/// Encodes the pictures and returns the total size of the packets and the
+/// quality of the reconstructed frames.
+fn encode_sample<P: Picture>(
+    encoder: &Av1Encoder,
+    pictures: &[&P],
+) -> Result<(u64, SessionQuality), Error> {
+    let format = encoder.format;
+    let mut analyzer = QualityAnalyzer::new(
+        format.width,
+        format.height,
+        format.subsampling_format,
+        format.bit_depth,
+    );
+
+    let mut bytes = 0;
+    let mut drain = |analyzer: &mut QualityAnalyzer, wait: bool| -> Result<bool, Error> {
+        while let Some(packet) = encoder.get_packet(wait)? {
+            bytes += packet.as_bytes().len() as u64;
+            analyzer.analyze_recon(encoder)?;
+            if packet.is_eos() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    };
+
+    for (pts, picture) in pictures.iter().enumerate() {
+        analyzer.push_source(*picture, pts as i64);
+        encoder.send_picture(*picture, pts as i64, false)?;
+        drain(&mut analyzer, false)?;
+    }
+
+    encoder.finish()?;
+    while !drain(&mut analyzer, true)? {}
+    analyzer.analyze_recon(encoder)?;
+    analyzer.finish()?;
+
+    Ok((bytes, analyzer.summary()))
+}
+
+/// Returns the points on the upper convex hull of the rate/quality curve, in
+/// order of increasing size. Points below the hull are never worth choosing:
+/// some mix of their neighbors gives better quality for the same bitrate.
+/// Points which are larger than another without improving on its
+/// [`score`](RatePoint::score) are dropped too.
+pub fn convex_hull(points: &[RatePoint]) -> Vec<RatePoint> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.bytes.cmp(&b.bytes).then(b.score().total_cmp(&a.score())));
+
+    let mut hull: Vec<RatePoint> = Vec::with_capacity(sorted.len());
+    for point in sorted {
+        if hull
+            .last()
+            .is_some_and(|last| point.score() <= last.score())
+        {
+            continue;
+        }
+
+        // Remove points which fall on or below the line from their
+        // predecessor to the new point.
+        while hull.len() >= 2 {
+            let a = &hull[hull.len() - 2];
+            let b = &hull[hull.len() - 1];
+            let cross = (b.bytes as f64 - a.bytes as f64) * (point.score() - a.score())
+                - (b.score() - a.score()) * (point.bytes as f64 - a.bytes as f64);
+            if cross < 0.0 {
+                break;
+            }
+
+            hull.pop();
+        }
+
+        hull.push(point);
+    }
+
+    hull
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsrc::Pattern;
+    use crate::YUVBuffer;
+
+    fn point(crf: u32, bytes: u64, psnr: f64) -> RatePoint {
+        RatePoint {
+            crf,
+            frames: 1,
+            bytes,
+            quality: SessionQuality {
+                frames: 1,
+                psnr: [psnr, psnr, psnr],
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn hull() {
+        let points = [
+            point(50, 1000, 30.0),
+            point(40, 2000, 36.0),
+            // Below the line from 2000 to 4000.
+            point(35, 3000, 36.5),
+            point(30, 4000, 39.0),
+            point(20, 8000, 42.0),
+            // No better than a smaller point.
+            point(10, 9000, 41.0),
+        ];
+
+        let crfs: Vec<u32> = convex_hull(&points).iter().map(|p| p.crf).collect();
+        assert_eq!(crfs, [50, 40, 30, 20]);
+        assert!(convex_hull(&[]).is_empty());
+    }
+
+    #[test]
+    fn sample() {
+        let probe = Probe::new([30]).segments(2, 3);
+        assert_eq!(probe.sample(4), [0, 1, 2, 3]);
+        assert_eq!(probe.sample(10), [0, 1, 2, 5, 6, 7]);
+    }
+
+    #[test]
+    fn run() {
+        simple_logger::init_with_env().ok();
+
+        let frames: Vec<YUVBuffer> = (0..16)
+            .map(|frame| {
+                let mut buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+                Pattern::MovingBox.fill(&mut buf, frame);
+                buf
+            })
+            .collect();
+
+        let points = Probe::new([55, 25])
+            .segments(2, 4)
+            .run(
+                || Av1EncoderConfig::default().framerate(30, 1),
+                &frames,
+                320,
+                240,
+                SubsamplingFormat::Yuv420,
+            )
+            .expect("failed to probe");
+
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].crf, 55);
+        assert_eq!(points[0].frames, 8);
+        assert_eq!(points[0].quality.frames, 8);
+        assert!(points[0].score() < points[1].score());
+    }
+}
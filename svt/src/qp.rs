@@ -0,0 +1,138 @@
+use crate::Error;
+
+/// A quantization parameter for AV1, in the range 1-63. Lower values give
+/// higher quality and larger output.
+///
+/// ```
+/// # use svt::Av1Qp;
+/// let qp = Av1Qp::new(30)?;
+/// assert_eq!(qp.get(), 30);
+/// assert!(Av1Qp::new(64).is_err());
+/// # Ok::<(), svt::Error>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u32", into = "u32")
+)]
+pub struct Av1Qp(u32);
+
+impl Av1Qp {
+    /// The lowest QP, for the highest quality.
+    pub const MIN: Self = Self(1);
+    /// The highest QP, for the lowest quality.
+    pub const MAX: Self = Self(63);
+
+    /// Creates a QP, or returns [`Error::BadParameter`] if `qp` is out of
+    /// range.
+    pub fn new(qp: u32) -> Result<Self, Error> {
+        check("AV1", qp, Self::MIN.0, Self::MAX.0).map(Self)
+    }
+
+    /// The QP value.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for Av1Qp {
+    type Error = Error;
+
+    fn try_from(qp: u32) -> Result<Self, Error> {
+        Self::new(qp)
+    }
+}
+
+impl From<Av1Qp> for u32 {
+    fn from(qp: Av1Qp) -> Self {
+        qp.0
+    }
+}
+
+impl std::fmt::Display for Av1Qp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A quantization parameter for HEVC, in the range 0-51. Lower values give
+/// higher quality and larger output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u32", into = "u32")
+)]
+pub struct HevcQp(u32);
+
+impl HevcQp {
+    /// The lowest QP, for the highest quality.
+    pub const MIN: Self = Self(0);
+    /// The highest QP, for the lowest quality.
+    pub const MAX: Self = Self(51);
+
+    /// Creates a QP, or returns [`Error::BadParameter`] if `qp` is out of
+    /// range.
+    pub fn new(qp: u32) -> Result<Self, Error> {
+        check("HEVC", qp, Self::MIN.0, Self::MAX.0).map(Self)
+    }
+
+    /// The QP value.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<u32> for HevcQp {
+    type Error = Error;
+
+    fn try_from(qp: u32) -> Result<Self, Error> {
+        Self::new(qp)
+    }
+}
+
+impl From<HevcQp> for u32 {
+    fn from(qp: HevcQp) -> Self {
+        qp.0
+    }
+}
+
+impl std::fmt::Display for HevcQp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+fn check(codec: &str, qp: u32, min: u32, max: u32) -> Result<u32, Error> {
+    if qp < min || qp > max {
+        return Err(Error::BadParameter {
+            detail: Some(format!(
+                "{} QP must be in the range {}-{}, got {}",
+                codec, min, max, qp
+            )),
+        });
+    }
+
+    Ok(qp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert!(Av1Qp::new(0).is_err());
+        assert_eq!(Av1Qp::new(1).unwrap(), Av1Qp::MIN);
+        assert_eq!(Av1Qp::new(63).unwrap(), Av1Qp::MAX);
+        assert!(Av1Qp::try_from(64).is_err());
+
+        assert_eq!(HevcQp::new(0).unwrap(), HevcQp::MIN);
+        assert_eq!(u32::from(HevcQp::new(51).unwrap()), 51);
+        assert!(HevcQp::new(52).is_err());
+
+        let err = Av1Qp::new(70).unwrap_err().to_string();
+        assert!(err.contains("1-63, got 70"), "{}", err);
+    }
+}
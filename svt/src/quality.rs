@@ -0,0 +1,438 @@
+//! Objective quality metrics, for comparing source pictures with the
+//! encoder's reconstructed frames.
+
+use std::collections::BTreeMap;
+
+use crate::buffer::chroma_dimensions;
+use crate::{Error, Picture, Plane, SubsamplingFormat};
+
+#[cfg(feature = "vmaf")]
+mod vmaf;
+
+/// The PSNR reported for planes which are identical to the source.
+const MAX_PSNR: f64 = 100.0;
+
+/// Quality metrics for a single frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameQuality {
+    /// The presentation timestamp of the frame.
+    pub pts: i64,
+    /// The PSNR of each plane, in decibels. Chroma values are zero for 4:0:0
+    /// pictures.
+    pub psnr: [f64; 3],
+    /// The SSIM of the luma plane.
+    pub ssim: f64,
+    /// The VMAF score, if VMAF is enabled. The score depends on the motion
+    /// between the frame and the next one, so it's `None` in the value
+    /// returned by [`QualityAnalyzer::compare`], and filled in, in
+    /// [`QualityAnalyzer::frames`], once the next frame has been compared,
+    /// or for the last frame, by [`QualityAnalyzer::finish`].
+    pub vmaf: Option<f64>,
+}
+
+/// Quality metrics averaged over all analyzed frames.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct SessionQuality {
+    /// The number of frames analyzed.
+    pub frames: usize,
+    /// The mean PSNR of each plane.
+    pub psnr: [f64; 3],
+    /// The mean luma SSIM.
+    pub ssim: f64,
+    /// The mean VMAF score over the frames scored so far, if VMAF is
+    /// enabled.
+    pub vmaf: Option<f64>,
+}
+
+struct SourcePicture {
+    planes: [Vec<u8>; 3],
+    strides: [u32; 3],
+}
+
+/// Computes quality metrics by comparing source pictures with reconstructed
+/// frames, matched by presentation timestamp.
+///
+/// Source pictures are copied when submitted with
+/// [`QualityAnalyzer::push_source`], and released once the matching
+/// reconstructed frame has been compared.
+pub struct QualityAnalyzer {
+    width: u32,
+    height: u32,
+    subsampling_format: SubsamplingFormat,
+    bit_depth: u32,
+    sources: BTreeMap<i64, SourcePicture>,
+    frames: Vec<FrameQuality>,
+    /// The VMAF context, and the position in `frames` of the first frame
+    /// it scores.
+    #[cfg(feature = "vmaf")]
+    vmaf: Option<(vmaf::Vmaf, usize)>,
+}
+
+impl std::fmt::Debug for QualityAnalyzer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QualityAnalyzer")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("frames", &self.frames.len())
+            .finish()
+    }
+}
+
+impl QualityAnalyzer {
+    /// Creates an analyzer for pictures with the given dimensions and format.
+    pub fn new(
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+        bit_depth: u32,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            subsampling_format,
+            bit_depth,
+            sources: BTreeMap::new(),
+            frames: Vec::new(),
+            #[cfg(feature = "vmaf")]
+            vmaf: None,
+        }
+    }
+
+    /// Enables VMAF scoring, using the default `vmaf_v0.6.1` model, for the
+    /// frames compared from now on. Call [`QualityAnalyzer::finish`] after
+    /// the last frame to score it.
+    #[cfg(feature = "vmaf")]
+    pub fn enable_vmaf(mut self) -> Result<Self, Error> {
+        let vmaf = vmaf::Vmaf::new(
+            self.width,
+            self.height,
+            self.subsampling_format,
+            self.bit_depth,
+        )?;
+
+        self.vmaf = Some((vmaf, self.frames.len()));
+        Ok(self)
+    }
+
+    /// Finishes VMAF scoring, which fills in the score of the last frame
+    /// compared. Frames compared afterwards aren't scored. This does nothing
+    /// if VMAF isn't enabled.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        #[cfg(feature = "vmaf")]
+        if let Some((vmaf, start)) = self.vmaf.take() {
+            if let Some((index, score)) = vmaf.flush()? {
+                self.frames[start + index].vmaf = Some(score);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Stores a copy of a source picture, to be compared against the
+    /// reconstructed frame with the same timestamp.
+    pub fn push_source(&mut self, picture: &impl Picture, pts: i64) {
+        let planes = [Plane::Y, Plane::U, Plane::V];
+        self.sources.insert(
+            pts,
+            SourcePicture {
+                planes: planes.map(|p| picture.as_slice(p).to_vec()),
+                strides: planes.map(|p| picture.stride(p)),
+            },
+        );
+    }
+
+    /// Compares a reconstructed frame with the source picture submitted with
+    /// the same timestamp. Returns `None` if there is no such source picture.
+    pub fn compare(
+        &mut self,
+        recon: &impl Picture,
+        pts: i64,
+    ) -> Result<Option<FrameQuality>, Error> {
+        let Some(source) = self.sources.remove(&pts) else {
+            return Ok(None);
+        };
+
+        let (uv_width, uv_height) =
+            chroma_dimensions(self.width, self.height, self.subsampling_format);
+        let bytes_per_sample = recon.bytes_per_sample();
+        let mut psnr = [0.0; 3];
+        for (i, plane) in [Plane::Y, Plane::U, Plane::V].into_iter().enumerate() {
+            let (w, h) = if plane == Plane::Y {
+                (self.width, self.height)
+            } else {
+                (uv_width, uv_height)
+            };
+
+            if w == 0 || h == 0 {
+                continue;
+            }
+
+            let a = PlaneView::new(&source.planes[i], source.strides[i], bytes_per_sample, w, h);
+            let b = PlaneView::new(
+                recon.as_slice(plane),
+                recon.stride(plane),
+                bytes_per_sample,
+                w,
+                h,
+            );
+            psnr[i] = plane_psnr(&a, &b, self.bit_depth);
+        }
+
+        let ssim = plane_ssim(
+            &PlaneView::new(
+                &source.planes[0],
+                source.strides[0],
+                bytes_per_sample,
+                self.width,
+                self.height,
+            ),
+            &PlaneView::new(
+                recon.as_slice(Plane::Y),
+                recon.stride(Plane::Y),
+                bytes_per_sample,
+                self.width,
+                self.height,
+            ),
+            self.bit_depth,
+        );
+
+        // The previous frame's VMAF score is available once this one is read.
+        #[cfg(feature = "vmaf")]
+        if let Some((vmaf, start)) = &mut self.vmaf {
+            if let Some((index, score)) = vmaf.read(&source, recon)? {
+                self.frames[*start + index].vmaf = Some(score);
+            }
+        }
+
+        let quality = FrameQuality {
+            pts,
+            psnr,
+            ssim,
+            vmaf: None,
+        };
+
+        self.frames.push(quality);
+        Ok(Some(quality))
+    }
+
+    /// Retrieves all available reconstructed frames from an AV1 encoder, and
+    /// compares them with the corresponding source pictures. The encoder must
+    /// have been configured with
+    /// [`Av1EncoderConfig::enable_recon`](crate::av1::Av1EncoderConfig::enable_recon).
+    #[cfg(feature = "av1")]
+    pub fn analyze_recon(
+        &mut self,
+        encoder: &crate::av1::Av1Encoder,
+    ) -> Result<Vec<FrameQuality>, Error> {
+        let mut frames = Vec::new();
+        while let Some(recon) = encoder.get_recon()? {
+            if let Some(quality) = self.compare(&recon, recon.pts())? {
+                frames.push(quality);
+            }
+        }
+
+        Ok(frames)
+    }
+
+    /// The metrics for each frame compared so far, in the order they were
+    /// compared.
+    pub fn frames(&self) -> &[FrameQuality] {
+        &self.frames
+    }
+
+    /// The metrics averaged over all frames compared so far. The VMAF score
+    /// is averaged over the frames which have one; call
+    /// [`QualityAnalyzer::finish`] first to include the last frame.
+    pub fn summary(&self) -> SessionQuality {
+        let n = self.frames.len();
+        if n == 0 {
+            return SessionQuality::default();
+        }
+
+        let mean =
+            |f: &dyn Fn(&FrameQuality) -> f64| self.frames.iter().map(f).sum::<f64>() / n as f64;
+
+        let scores: Vec<f64> = self.frames.iter().filter_map(|q| q.vmaf).collect();
+        let vmaf = (!scores.is_empty()).then(|| scores.iter().sum::<f64>() / scores.len() as f64);
+
+        SessionQuality {
+            frames: n,
+            psnr: [
+                mean(&|q| q.psnr[0]),
+                mean(&|q| q.psnr[1]),
+                mean(&|q| q.psnr[2]),
+            ],
+            ssim: mean(&|q| q.ssim),
+            vmaf,
+        }
+    }
+}
+
+/// A view of the samples in a single plane.
+struct PlaneView<'a> {
+    data: &'a [u8],
+    stride: usize,
+    bytes_per_sample: usize,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> PlaneView<'a> {
+    fn new(data: &'a [u8], stride: u32, bytes_per_sample: u32, width: u32, height: u32) -> Self {
+        Self {
+            data,
+            stride: stride as usize,
+            bytes_per_sample: bytes_per_sample as usize,
+            width: width as usize,
+            height: height as usize,
+        }
+    }
+
+    fn sample(&self, x: usize, y: usize) -> f64 {
+        let off = y * self.stride + x * self.bytes_per_sample;
+        if self.bytes_per_sample == 2 {
+            u16::from_ne_bytes([self.data[off], self.data[off + 1]]) as f64
+        } else {
+            self.data[off] as f64
+        }
+    }
+}
+
+fn plane_psnr(a: &PlaneView<'_>, b: &PlaneView<'_>, bit_depth: u32) -> f64 {
+    let mut sse = 0.0;
+    for y in 0..a.height {
+        for x in 0..a.width {
+            let d = a.sample(x, y) - b.sample(x, y);
+            sse += d * d;
+        }
+    }
+
+    if sse == 0.0 {
+        return MAX_PSNR;
+    }
+
+    let peak = ((1u64 << bit_depth) - 1) as f64;
+    let samples = (a.width * a.height) as f64;
+    (10.0 * (peak * peak * samples / sse).log10()).min(MAX_PSNR)
+}
+
+/// Computes SSIM over 8x8 windows, spaced 4 samples apart.
+fn plane_ssim(a: &PlaneView<'_>, b: &PlaneView<'_>, bit_depth: u32) -> f64 {
+    const WINDOW: usize = 8;
+    const STEP: usize = 4;
+
+    let peak = ((1u64 << bit_depth) - 1) as f64;
+    let c1 = (0.01 * peak) * (0.01 * peak);
+    let c2 = (0.03 * peak) * (0.03 * peak);
+
+    if a.width < WINDOW || a.height < WINDOW {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for wy in (0..=a.height - WINDOW).step_by(STEP) {
+        for wx in (0..=a.width - WINDOW).step_by(STEP) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in wy..wy + WINDOW {
+                for x in wx..wx + WINDOW {
+                    let (va, vb) = (a.sample(x, y), b.sample(x, y));
+                    sa += va;
+                    sb += vb;
+                    saa += va * va;
+                    sbb += vb * vb;
+                    sab += va * vb;
+                }
+            }
+
+            let n = (WINDOW * WINDOW) as f64;
+            let (ma, mb) = (sa / n, sb / n);
+            let var_a = saa / n - ma * ma;
+            let var_b = sbb / n - mb * mb;
+            let cov = sab / n - ma * mb;
+
+            total += ((2.0 * ma * mb + c1) * (2.0 * cov + c2))
+                / ((ma * ma + mb * mb + c1) * (var_a + var_b + c2));
+            windows += 1;
+        }
+    }
+
+    total / windows as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::YUVBuffer;
+
+    #[test]
+    fn identical_pictures() {
+        let mut source = YUVBuffer::new(64, 64, SubsamplingFormat::Yuv420);
+        for (i, v) in source.as_mut_slice(Plane::Y).iter_mut().enumerate() {
+            *v = (i % 251) as u8;
+        }
+
+        let mut analyzer = QualityAnalyzer::new(64, 64, SubsamplingFormat::Yuv420, 8);
+        analyzer.push_source(&source, 7);
+
+        let quality = analyzer.compare(&source, 7).unwrap().unwrap();
+        assert_eq!(quality.psnr, [MAX_PSNR; 3]);
+        assert!((quality.ssim - 1.0).abs() < 1e-9);
+
+        // The source is released after comparison.
+        assert_eq!(analyzer.compare(&source, 7).unwrap(), None);
+    }
+
+    #[test]
+    fn distorted_picture() {
+        let source = YUVBuffer::new(64, 64, SubsamplingFormat::Yuv420);
+        let mut recon = YUVBuffer::new(64, 64, SubsamplingFormat::Yuv420);
+        recon.as_mut_slice(Plane::Y).fill(10);
+
+        let mut analyzer = QualityAnalyzer::new(64, 64, SubsamplingFormat::Yuv420, 8);
+        analyzer.push_source(&source, 0);
+
+        let quality = analyzer.compare(&recon, 0).unwrap().unwrap();
+
+        // An error of 10 everywhere gives 20 * log10(255 / 10).
+        assert!((quality.psnr[0] - 28.13).abs() < 0.01);
+        assert_eq!(quality.psnr[1], MAX_PSNR);
+        assert_eq!(analyzer.summary().frames, 1);
+        assert_eq!(analyzer.summary().vmaf, None);
+    }
+
+    #[cfg(feature = "vmaf")]
+    #[test]
+    fn vmaf() {
+        let mut source = YUVBuffer::new(64, 64, SubsamplingFormat::Yuv420);
+        for (i, v) in source.as_mut_slice(Plane::Y).iter_mut().enumerate() {
+            *v = (i % 251) as u8;
+        }
+
+        let mut analyzer = QualityAnalyzer::new(64, 64, SubsamplingFormat::Yuv420, 8)
+            .enable_vmaf()
+            .unwrap();
+        for pts in 0..3 {
+            analyzer.push_source(&source, pts);
+            let quality = analyzer.compare(&source, pts).unwrap().unwrap();
+            assert_eq!(quality.vmaf, None);
+        }
+
+        // Each frame is scored once the next one has been read.
+        let scores: Vec<_> = analyzer.frames().iter().map(|q| q.vmaf).collect();
+        assert!(scores[..2].iter().all(Option::is_some), "{:?}", scores);
+        assert_eq!(scores[2], None);
+
+        analyzer.finish().unwrap();
+        let vmaf = analyzer.summary().vmaf.unwrap();
+        assert!(analyzer.frames().iter().all(|q| q.vmaf.is_some()));
+        assert!(vmaf > 90.0, "{}", vmaf);
+
+        // Finishing again does nothing, and later frames aren't scored.
+        analyzer.finish().unwrap();
+        analyzer.push_source(&source, 3);
+        analyzer.compare(&source, 3).unwrap();
+        assert_eq!(analyzer.frames()[3].vmaf, None);
+        assert_eq!(analyzer.summary().vmaf, Some(vmaf));
+    }
+}
@@ -0,0 +1,298 @@
+//! A minimal binding to libvmaf, which must be installed on the system.
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr};
+
+use crate::buffer::chroma_dimensions;
+use crate::{Error, LibraryError, Picture, Plane, SubsamplingFormat};
+
+use super::SourcePicture;
+
+const VMAF_LOG_LEVEL_NONE: c_int = 0;
+const VMAF_PIX_FMT_YUV420P: c_int = 1;
+const VMAF_PIX_FMT_YUV422P: c_int = 2;
+const VMAF_PIX_FMT_YUV444P: c_int = 3;
+const VMAF_PIX_FMT_YUV400P: c_int = 4;
+const VMAF_MODEL_FLAGS_DEFAULT: u64 = 0;
+
+const DEFAULT_MODEL: &CStr = c"vmaf_v0.6.1";
+
+#[repr(C)]
+struct VmafConfiguration {
+    log_level: c_int,
+    n_threads: c_uint,
+    n_subsample: c_uint,
+    cpumask: u64,
+    gpumask: u64,
+}
+
+#[repr(C)]
+struct VmafModelConfig {
+    name: *const c_char,
+    flags: u64,
+}
+
+#[repr(C)]
+struct VmafPicture {
+    pix_fmt: c_int,
+    bpc: c_uint,
+    w: [c_uint; 3],
+    h: [c_uint; 3],
+    stride: [isize; 3],
+    data: [*mut c_void; 3],
+    ref_: *mut c_void,
+    priv_: *mut c_void,
+}
+
+#[repr(C)]
+struct VmafContext {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct VmafModel {
+    _private: [u8; 0],
+}
+
+#[link(name = "vmaf")]
+extern "C" {
+    fn vmaf_init(vmaf: *mut *mut VmafContext, cfg: VmafConfiguration) -> c_int;
+    fn vmaf_close(vmaf: *mut VmafContext) -> c_int;
+    fn vmaf_model_load(
+        model: *mut *mut VmafModel,
+        cfg: *mut VmafModelConfig,
+        version: *const c_char,
+    ) -> c_int;
+    fn vmaf_model_destroy(model: *mut VmafModel);
+    fn vmaf_use_features_from_model(vmaf: *mut VmafContext, model: *mut VmafModel) -> c_int;
+    fn vmaf_picture_alloc(
+        pic: *mut VmafPicture,
+        pix_fmt: c_int,
+        bpc: c_uint,
+        w: c_uint,
+        h: c_uint,
+    ) -> c_int;
+    fn vmaf_picture_unref(pic: *mut VmafPicture) -> c_int;
+    fn vmaf_read_pictures(
+        vmaf: *mut VmafContext,
+        reference: *mut VmafPicture,
+        distorted: *mut VmafPicture,
+        index: c_uint,
+    ) -> c_int;
+    fn vmaf_score_at_index(
+        vmaf: *mut VmafContext,
+        model: *mut VmafModel,
+        score: *mut f64,
+        index: c_uint,
+    ) -> c_int;
+}
+
+fn check(code: c_int) -> Result<(), Error> {
+    match code {
+        0 => Ok(()),
+        code => Err(LibraryError::Unknown(code).into()),
+    }
+}
+
+/// A picture allocated by libvmaf, which is released on drop unless it was
+/// handed over to the library.
+struct PictureGuard(VmafPicture);
+
+impl Drop for PictureGuard {
+    fn drop(&mut self) {
+        // The library clears the picture if it released it itself.
+        if !self.0.ref_.is_null() {
+            unsafe {
+                vmaf_picture_unref(&mut self.0);
+            }
+        }
+    }
+}
+
+/// Scores frames with libvmaf.
+///
+/// The motion features of the default model compare each frame with the
+/// next, so the score for a frame is only available once the next frame
+/// has been read, or the context has been flushed at the end of the stream.
+pub(super) struct Vmaf {
+    ctx: *mut VmafContext,
+    model: *mut VmafModel,
+    index: c_uint,
+    width: u32,
+    height: u32,
+    subsampling_format: SubsamplingFormat,
+    bit_depth: u32,
+}
+
+unsafe impl Send for Vmaf {}
+
+impl Vmaf {
+    pub(super) fn new(
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+        bit_depth: u32,
+    ) -> Result<Self, Error> {
+        let cfg = VmafConfiguration {
+            log_level: VMAF_LOG_LEVEL_NONE,
+            n_threads: 0,
+            n_subsample: 1,
+            cpumask: 0,
+            gpumask: 0,
+        };
+
+        let mut model_cfg = VmafModelConfig {
+            name: DEFAULT_MODEL.as_ptr(),
+            flags: VMAF_MODEL_FLAGS_DEFAULT,
+        };
+
+        let mut vmaf = Vmaf {
+            ctx: std::ptr::null_mut(),
+            model: std::ptr::null_mut(),
+            index: 0,
+            width,
+            height,
+            subsampling_format,
+            bit_depth,
+        };
+
+        unsafe {
+            check(vmaf_init(&mut vmaf.ctx, cfg))?;
+            check(vmaf_model_load(
+                &mut vmaf.model,
+                &mut model_cfg,
+                DEFAULT_MODEL.as_ptr(),
+            ))?;
+            check(vmaf_use_features_from_model(vmaf.ctx, vmaf.model))?;
+        }
+
+        Ok(vmaf)
+    }
+
+    /// Reads a pair of pictures, and returns the index and score of the
+    /// previous frame, which only becomes available now. Indices start at
+    /// zero, with the first frame read.
+    pub(super) fn read(
+        &mut self,
+        source: &SourcePicture,
+        recon: &impl Picture,
+    ) -> Result<Option<(usize, f64)>, Error> {
+        let planes = [Plane::Y, Plane::U, Plane::V];
+        let mut reference = self.alloc_picture(|i| (&source.planes[i][..], source.strides[i]))?;
+        let mut distorted =
+            self.alloc_picture(|i| (recon.as_slice(planes[i]), recon.stride(planes[i])))?;
+
+        unsafe {
+            check(vmaf_read_pictures(
+                self.ctx,
+                &mut reference.0,
+                &mut distorted.0,
+                self.index,
+            ))?;
+        }
+
+        // The library releases the pictures once it's done with them.
+        std::mem::forget(reference);
+        std::mem::forget(distorted);
+
+        self.index += 1;
+        match self.index {
+            1 => Ok(None),
+            n => self.score_at(n - 2).map(Some),
+        }
+    }
+
+    /// Flushes the context at the end of the stream, and returns the index
+    /// and score of the last frame read, if any. No more pictures can be
+    /// read afterwards, so this consumes the context.
+    pub(super) fn flush(self) -> Result<Option<(usize, f64)>, Error> {
+        unsafe {
+            check(vmaf_read_pictures(
+                self.ctx,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                0,
+            ))?;
+        }
+
+        match self.index {
+            0 => Ok(None),
+            n => self.score_at(n - 1).map(Some),
+        }
+    }
+
+    fn score_at(&self, index: c_uint) -> Result<(usize, f64), Error> {
+        let mut score = 0.0;
+        unsafe {
+            check(vmaf_score_at_index(self.ctx, self.model, &mut score, index))?;
+        }
+
+        Ok((index as usize, score))
+    }
+
+    fn alloc_picture<'a>(
+        &self,
+        plane: impl Fn(usize) -> (&'a [u8], u32),
+    ) -> Result<PictureGuard, Error> {
+        let pix_fmt = match self.subsampling_format {
+            SubsamplingFormat::Yuv400 => VMAF_PIX_FMT_YUV400P,
+            SubsamplingFormat::Yuv420 => VMAF_PIX_FMT_YUV420P,
+            SubsamplingFormat::Yuv422 => VMAF_PIX_FMT_YUV422P,
+            SubsamplingFormat::Yuv444 => VMAF_PIX_FMT_YUV444P,
+        };
+
+        let bytes_per_sample = if self.bit_depth > 8 { 2 } else { 1 };
+        let (uv_width, uv_height) =
+            chroma_dimensions(self.width, self.height, self.subsampling_format);
+
+        let mut pic = PictureGuard(unsafe { std::mem::zeroed() });
+        unsafe {
+            check(vmaf_picture_alloc(
+                &mut pic.0,
+                pix_fmt,
+                self.bit_depth,
+                self.width,
+                self.height,
+            ))?;
+        }
+
+        let planes = if self.subsampling_format == SubsamplingFormat::Yuv400 {
+            1
+        } else {
+            3
+        };
+
+        for i in 0..planes {
+            let (src, src_stride) = plane(i);
+            let (w, h) = if i == 0 {
+                (self.width, self.height)
+            } else {
+                (uv_width, uv_height)
+            };
+
+            let row_len = (w * bytes_per_sample) as usize;
+            for row in 0..h as usize {
+                let src_row = &src[row * src_stride as usize..][..row_len];
+                unsafe {
+                    let dst = (pic.0.data[i] as *mut u8).offset(row as isize * pic.0.stride[i]);
+                    std::ptr::copy_nonoverlapping(src_row.as_ptr(), dst, row_len);
+                }
+            }
+        }
+
+        Ok(pic)
+    }
+}
+
+impl Drop for Vmaf {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.model.is_null() {
+                vmaf_model_destroy(self.model);
+            }
+
+            if !self.ctx.is_null() {
+                vmaf_close(self.ctx);
+            }
+        }
+    }
+}
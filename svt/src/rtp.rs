@@ -0,0 +1,51 @@
+//! Packetization of encoded streams for RTP.
+//!
+//! Each submodule implements the RTP payload format for one codec, splitting
+//! the output of the encoder into payloads no larger than a configured size,
+//! which is the MTU less the size of the IP, UDP, and RTP headers and any RTP
+//! header extensions. Building the RTP headers themselves is left to the
+//! caller.
+
+pub mod av1;
+pub mod hevc;
+
+use crate::nal::NalError;
+use crate::obu::ObuError;
+
+/// An error packetizing an encoded frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RtpError {
+    /// The data isn't a valid sequence of OBUs.
+    InvalidObu(String),
+    /// The data isn't a valid sequence of NAL units in the Annex B format.
+    InvalidNal(String),
+    /// The maximum payload size is too small to hold the payload headers
+    /// and part of an OBU or NAL unit.
+    PayloadSizeTooSmall(usize),
+}
+
+impl std::error::Error for RtpError {}
+
+impl From<NalError> for RtpError {
+    fn from(e: NalError) -> Self {
+        RtpError::InvalidNal(e.0)
+    }
+}
+
+impl From<ObuError> for RtpError {
+    fn from(e: ObuError) -> Self {
+        RtpError::InvalidObu(e.0)
+    }
+}
+
+impl std::fmt::Display for RtpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RtpError::InvalidObu(detail) => write!(f, "invalid OBU: {}", detail),
+            RtpError::InvalidNal(detail) => write!(f, "invalid NAL unit: {}", detail),
+            RtpError::PayloadSizeTooSmall(size) => {
+                write!(f, "maximum payload size too small: {}", size)
+            }
+        }
+    }
+}
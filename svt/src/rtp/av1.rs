@@ -0,0 +1,352 @@
+//! The [AV1 RTP payload format](https://aomediacodec.github.io/av1-rtp-spec/).
+//!
+//! A [`Payloader`] splits each temporal unit output by the encoder (one
+//! [`Av1Packet`](crate::av1::Av1Packet) with
+//! [`OutputMode::TemporalUnit`](crate::av1::OutputMode::TemporalUnit), or any
+//! other buffer of OBUs in the low overhead bitstream format) into RTP
+//! payloads no larger than the configured size. Each payload starts with an [`AggregationHeader`], and
+//! OBUs which don't fit are fragmented across payloads.
+//!
+//! ```
+//! # use svt::rtp::av1::Payloader;
+//! # fn send_rtp(_: &[u8], _: bool) {}
+//! # fn example(temporal_unit: &[u8]) -> Result<(), svt::rtp::RtpError> {
+//! let payloader = Payloader::new(1200);
+//!
+//! let payloads = payloader.payload(temporal_unit)?;
+//! let count = payloads.len();
+//! for (i, payload) in payloads.iter().enumerate() {
+//!     // The marker bit is set on the last packet of the temporal unit.
+//!     send_rtp(payload, i == count - 1);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::obu::{obus, write_leb128, Obu, ObuType};
+
+use super::RtpError;
+
+/// The smallest usable payload size: an aggregation header and one byte of
+/// an OBU.
+const MIN_PAYLOAD_SIZE: usize = 2;
+
+/// An OBU as it's carried in an RTP payload: the header, with
+/// `obu_has_size_field` cleared, followed by the payload.
+fn rtp_element(obu: &Obu<'_>) -> Vec<u8> {
+    let mut element = Vec::with_capacity(obu.header().len() + obu.size());
+    element.extend_from_slice(obu.header());
+    element[0] &= !0x2;
+    element.extend_from_slice(obu.payload());
+    element
+}
+
+fn leb128_size(value: usize) -> usize {
+    let bits = usize::BITS - value.leading_zeros();
+    (bits as usize).div_ceil(7).max(1)
+}
+
+/// The header at the start of each AV1 RTP payload.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct AggregationHeader {
+    /// Whether the first OBU element continues a fragment from the previous
+    /// payload (Z).
+    pub continues_fragment: bool,
+    /// Whether the last OBU element is continued in the next payload (Y).
+    pub fragment_continues: bool,
+    /// The number of OBU elements, if it's between 1 and 3; the last element
+    /// then has no length field. If `0`, every element has one (W).
+    pub obu_count: u8,
+    /// Whether the payload is the first of a coded video sequence (N).
+    pub new_sequence: bool,
+}
+
+impl AggregationHeader {
+    /// Parses an aggregation header.
+    pub fn from_byte(b: u8) -> Self {
+        Self {
+            continues_fragment: b & 0x80 != 0,
+            fragment_continues: b & 0x40 != 0,
+            obu_count: (b >> 4) & 0x3,
+            new_sequence: b & 0x8 != 0,
+        }
+    }
+
+    /// Serializes the header.
+    pub fn to_byte(self) -> u8 {
+        let mut b = (self.obu_count.min(3)) << 4;
+        if self.continues_fragment {
+            b |= 0x80;
+        }
+
+        if self.fragment_continues {
+            b |= 0x40;
+        }
+
+        if self.new_sequence {
+            b |= 0x8;
+        }
+
+        b
+    }
+}
+
+/// Splits temporal units into RTP payloads.
+#[derive(Debug, Copy, Clone)]
+pub struct Payloader {
+    max_payload_size: usize,
+}
+
+impl Payloader {
+    /// Creates a payloader producing payloads of at most `max_payload_size`
+    /// bytes.
+    pub fn new(max_payload_size: usize) -> Self {
+        Self { max_payload_size }
+    }
+
+    /// Splits a temporal unit into RTP payloads, in order. The RTP marker bit
+    /// should be set on the packet carrying the last payload.
+    ///
+    /// Temporal delimiters, tile lists, and padding are dropped, as the
+    /// payload format requires. If the temporal unit contains a sequence
+    /// header, the first payload is marked as the start of a new coded video
+    /// sequence.
+    pub fn payload(&self, temporal_unit: &[u8]) -> Result<Vec<Vec<u8>>, RtpError> {
+        if self.max_payload_size < MIN_PAYLOAD_SIZE {
+            return Err(RtpError::PayloadSizeTooSmall(self.max_payload_size));
+        }
+
+        let mut new_sequence = false;
+        let mut elements = Vec::new();
+        for obu in obus(temporal_unit) {
+            let obu = obu?;
+            match obu.obu_type() {
+                ObuType::TemporalDelimiter | ObuType::TileList | ObuType::Padding => continue,
+                ObuType::SequenceHeader => new_sequence = true,
+                _ => (),
+            }
+
+            elements.push(rtp_element(&obu));
+        }
+
+        let mut builder = PayloadBuilder::new(self.max_payload_size, new_sequence);
+        for element in &elements {
+            let mut rest = &element[..];
+            loop {
+                let available = builder.available();
+                if leb128_size(rest.len()) + rest.len() <= available {
+                    builder.push(rest);
+                    break;
+                }
+
+                // Otherwise, the element is the last in the payload, which
+                // has no length field if there are at most three.
+                let len = if builder.elements.len() < 3 {
+                    available
+                } else {
+                    available.saturating_sub(leb128_size(available))
+                };
+
+                if len == 0 {
+                    builder.flush(false);
+                } else if rest.len() <= len {
+                    builder.push(rest);
+                    builder.flush(false);
+                    break;
+                } else {
+                    builder.push(&rest[..len]);
+                    builder.flush(true);
+                    rest = &rest[len..];
+                }
+            }
+        }
+
+        if !builder.elements.is_empty() {
+            builder.flush(false);
+        }
+
+        Ok(builder.payloads)
+    }
+}
+
+struct PayloadBuilder<'a> {
+    max_size: usize,
+    elements: Vec<&'a [u8]>,
+    size: usize,
+    continues_fragment: bool,
+    new_sequence: bool,
+    payloads: Vec<Vec<u8>>,
+}
+
+impl<'a> PayloadBuilder<'a> {
+    fn new(max_size: usize, new_sequence: bool) -> Self {
+        Self {
+            max_size,
+            elements: Vec::new(),
+            size: 1,
+            continues_fragment: false,
+            new_sequence,
+            payloads: Vec::new(),
+        }
+    }
+
+    fn available(&self) -> usize {
+        self.max_size - self.size
+    }
+
+    /// Adds an element, counting the size of its length field even if it
+    /// ends up omitted.
+    fn push(&mut self, element: &'a [u8]) {
+        self.size += leb128_size(element.len()) + element.len();
+        self.elements.push(element);
+    }
+
+    fn flush(&mut self, fragment_continues: bool) {
+        let count = self.elements.len();
+        let header = AggregationHeader {
+            continues_fragment: self.continues_fragment,
+            fragment_continues,
+            obu_count: if count <= 3 { count as u8 } else { 0 },
+            new_sequence: self.new_sequence && self.payloads.is_empty(),
+        };
+
+        let mut payload = Vec::with_capacity(self.size);
+        payload.push(header.to_byte());
+        for (i, element) in self.elements.drain(..).enumerate() {
+            if header.obu_count == 0 || i + 1 < count {
+                write_leb128(&mut payload, element.len());
+            }
+
+            payload.extend_from_slice(element);
+        }
+
+        self.payloads.push(payload);
+        self.size = 1;
+        self.continues_fragment = fragment_continues;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obu::read_leb128;
+
+    /// Builds an OBU with a size field.
+    fn obu(obu_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![(obu_type << 3) | 0x2];
+        write_leb128(&mut data, payload.len());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn temporal_unit(obus: &[Vec<u8>]) -> Vec<u8> {
+        obus.concat()
+    }
+
+    /// Reassembles the OBU elements from a sequence of payloads.
+    fn depayload(payloads: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let mut elements = Vec::new();
+        let mut fragment = Vec::new();
+        for payload in payloads {
+            let header = AggregationHeader::from_byte(payload[0]);
+            let mut data = &payload[1..];
+            let mut i = 0;
+            while !data.is_empty() {
+                i += 1;
+                let len = if header.obu_count == 0 || i < header.obu_count {
+                    let (len, n) = read_leb128(data).unwrap();
+                    data = &data[n..];
+                    len
+                } else {
+                    data.len()
+                };
+
+                fragment.extend_from_slice(&data[..len]);
+                data = &data[len..];
+                if !(data.is_empty() && header.fragment_continues) {
+                    elements.push(std::mem::take(&mut fragment));
+                }
+            }
+        }
+
+        elements
+    }
+
+    #[test]
+    fn aggregation_header() {
+        let header = AggregationHeader {
+            continues_fragment: true,
+            fragment_continues: false,
+            obu_count: 2,
+            new_sequence: true,
+        };
+
+        assert_eq!(header.to_byte(), 0b1010_1000);
+        assert_eq!(AggregationHeader::from_byte(header.to_byte()), header);
+    }
+
+    #[test]
+    fn aggregate() {
+        let tu = temporal_unit(&[obu(2, &[]), obu(1, &[1, 2, 3]), obu(6, &[4; 10])]);
+        let payloads = Payloader::new(1200).payload(&tu).unwrap();
+        assert_eq!(payloads.len(), 1);
+
+        // The temporal delimiter is dropped, and the last element has no
+        // length field.
+        let header = AggregationHeader::from_byte(payloads[0][0]);
+        assert_eq!(header.obu_count, 2);
+        assert!(header.new_sequence);
+        assert_eq!(payloads[0].len(), 1 + 1 + 4 + 11);
+        assert_eq!(
+            depayload(&payloads),
+            vec![vec![1 << 3, 1, 2, 3], [vec![6 << 3], vec![4; 10]].concat()]
+        );
+    }
+
+    #[test]
+    fn fragment() {
+        let frame = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+        let tu = temporal_unit(&[obu(2, &[]), obu(5, &[9; 4]), obu(6, &frame)]);
+        let payloads = Payloader::new(100).payload(&tu).unwrap();
+
+        assert!(payloads.iter().all(|p| p.len() <= 100));
+        assert!(!AggregationHeader::from_byte(payloads[0][0]).new_sequence);
+        assert!(!AggregationHeader::from_byte(payloads[0][0]).continues_fragment);
+
+        let last = AggregationHeader::from_byte(payloads.last().unwrap()[0]);
+        assert!(last.continues_fragment);
+        assert!(!last.fragment_continues);
+
+        assert_eq!(
+            depayload(&payloads),
+            vec![vec![5 << 3, 9, 9, 9, 9], [vec![6 << 3], frame].concat()]
+        );
+    }
+
+    #[test]
+    fn payload_size_too_small() {
+        let tu = temporal_unit(&[obu(6, &[0; 10])]);
+        assert_eq!(
+            Payloader::new(1).payload(&tu),
+            Err(RtpError::PayloadSizeTooSmall(1))
+        );
+
+        let payloads = Payloader::new(2).payload(&tu).unwrap();
+        assert_eq!(payloads.len(), 11);
+        assert!(payloads.iter().all(|p| p.len() == 2));
+        assert_eq!(
+            depayload(&payloads),
+            vec![[vec![6 << 3], vec![0; 10]].concat()]
+        );
+    }
+
+    #[test]
+    fn leb128() {
+        for value in [0, 1, 127, 128, 16383, 16384, 1 << 30] {
+            let mut buf = Vec::new();
+            write_leb128(&mut buf, value);
+            assert_eq!(buf.len(), leb128_size(value));
+            assert_eq!(read_leb128(&buf), Some((value, buf.len())));
+        }
+    }
+}
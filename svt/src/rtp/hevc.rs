@@ -0,0 +1,250 @@
+//! The HEVC RTP payload format, from [RFC 7798](https://www.rfc-editor.org/rfc/rfc7798).
+//!
+//! A [`Payloader`] splits each access unit output by the encoder (one
+//! [`HevcPacket`](crate::hevc::HevcPacket), or any other buffer of NAL units
+//! in the Annex B format) into RTP payloads no larger than the configured
+//! size. Consecutive NAL units which fit together are combined into
+//! aggregation packets, and NAL units which don't fit on their own are split
+//! into fragmentation units.
+//!
+//! The payloads don't include a decoding order number, so the stream must be
+//! signaled with `sprop-max-don-diff=0` (the default).
+//!
+//! ```
+//! # use svt::rtp::hevc::Payloader;
+//! # fn send_rtp(_: &[u8], _: bool) {}
+//! # fn example(access_unit: &[u8]) -> Result<(), svt::rtp::RtpError> {
+//! let payloader = Payloader::new(1200);
+//!
+//! let payloads = payloader.payload(access_unit)?;
+//! let count = payloads.len();
+//! for (i, payload) in payloads.iter().enumerate() {
+//!     // The marker bit is set on the last packet of the access unit.
+//!     send_rtp(payload, i == count - 1);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use super::RtpError;
+
+pub use crate::nal::{nal_units, NalError, NalUnit, NalUnits};
+
+/// The NAL unit type of an aggregation packet.
+const NAL_UNIT_AP: u8 = 48;
+
+/// The NAL unit type of a fragmentation unit.
+const NAL_UNIT_FU: u8 = 49;
+
+/// The smallest usable payload size: a payload header, a fragmentation unit
+/// header, and one byte of a NAL unit.
+const MIN_PAYLOAD_SIZE: usize = 4;
+
+/// Splits access units into RTP payloads.
+#[derive(Debug, Copy, Clone)]
+pub struct Payloader {
+    max_payload_size: usize,
+}
+
+impl Payloader {
+    /// Creates a payloader producing payloads of at most `max_payload_size`
+    /// bytes.
+    pub fn new(max_payload_size: usize) -> Self {
+        Self { max_payload_size }
+    }
+
+    /// Splits an access unit into RTP payloads, in order. The RTP marker bit
+    /// should be set on the packet carrying the last payload.
+    ///
+    /// Each payload is a single NAL unit, an aggregation packet (AP) holding
+    /// several NAL units, or a fragmentation unit (FU) holding part of one.
+    pub fn payload(&self, access_unit: &[u8]) -> Result<Vec<Vec<u8>>, RtpError> {
+        if self.max_payload_size < MIN_PAYLOAD_SIZE {
+            return Err(RtpError::PayloadSizeTooSmall(self.max_payload_size));
+        }
+
+        let mut builder = PayloadBuilder::new(self.max_payload_size);
+        for nal in nal_units(access_unit) {
+            let nal = nal?;
+            let len = nal.as_bytes().len();
+            if len > self.max_payload_size {
+                builder.flush();
+                builder.fragment(nal);
+                continue;
+            }
+
+            // Aggregation packets store the size of each NAL unit in 16 bits.
+            if builder.size + 2 + len > self.max_payload_size || len > u16::MAX as usize {
+                builder.flush();
+            }
+
+            builder.push(nal);
+            if len > u16::MAX as usize {
+                builder.flush();
+            }
+        }
+
+        builder.flush();
+        Ok(builder.payloads)
+    }
+}
+
+struct PayloadBuilder<'a> {
+    max_size: usize,
+    nals: Vec<NalUnit<'a>>,
+    size: usize,
+    payloads: Vec<Vec<u8>>,
+}
+
+impl<'a> PayloadBuilder<'a> {
+    fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            nals: Vec::new(),
+            size: 2,
+            payloads: Vec::new(),
+        }
+    }
+
+    /// Adds a NAL unit to the next aggregation packet, counting its size
+    /// field.
+    fn push(&mut self, nal: NalUnit<'a>) {
+        self.size += 2 + nal.as_bytes().len();
+        self.nals.push(nal);
+    }
+
+    /// Writes out the pending NAL units, as an aggregation packet if there
+    /// are more than one.
+    fn flush(&mut self) {
+        match self.nals.len() {
+            0 => return,
+            1 => self.payloads.push(self.nals[0].as_bytes().to_vec()),
+            _ => {
+                let layer_id = self.nals.iter().map(|n| n.layer_id()).min().unwrap();
+                let tid = self
+                    .nals
+                    .iter()
+                    .map(|n| n.temporal_id_plus1())
+                    .min()
+                    .unwrap();
+
+                let mut payload = Vec::with_capacity(self.size);
+                payload.push((NAL_UNIT_AP << 1) | (layer_id >> 5));
+                payload.push((layer_id << 3) | tid);
+                for nal in &self.nals {
+                    let data = nal.as_bytes();
+                    payload.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                    payload.extend_from_slice(data);
+                }
+
+                self.payloads.push(payload);
+            }
+        }
+
+        self.nals.clear();
+        self.size = 2;
+    }
+
+    /// Splits a NAL unit into fragmentation units.
+    fn fragment(&mut self, nal: NalUnit<'a>) {
+        let data = nal.as_bytes();
+        let header = [(data[0] & 0x81) | (NAL_UNIT_FU << 1), data[1]];
+        let chunks = data[2..].chunks(self.max_size - 3);
+        let count = chunks.len();
+        for (i, chunk) in chunks.enumerate() {
+            let mut fu_header = nal.nal_type();
+            if i == 0 {
+                fu_header |= 0x80;
+            }
+
+            if i + 1 == count {
+                fu_header |= 0x40;
+            }
+
+            let mut payload = Vec::with_capacity(3 + chunk.len());
+            payload.extend_from_slice(&header);
+            payload.push(fu_header);
+            payload.extend_from_slice(chunk);
+            self.payloads.push(payload);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VPS: [u8; 4] = [0x40, 0x01, 0x0c, 0x01];
+    const SPS: [u8; 4] = [0x42, 0x01, 0x01, 0x02];
+    const PPS: [u8; 4] = [0x44, 0x01, 0xc0, 0xf7];
+
+    /// An IDR_W_RADL slice.
+    const IDR: [u8; 12] = [
+        0x26, 0x01, 0xaf, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09,
+    ];
+
+    fn access_unit(nals: &[&[u8]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (i, nal) in nals.iter().enumerate() {
+            // Mix three and four byte start codes.
+            if i % 2 == 0 {
+                data.push(0);
+            }
+
+            data.extend_from_slice(&[0, 0, 1]);
+            data.extend_from_slice(nal);
+        }
+
+        data
+    }
+
+    #[test]
+    fn aggregate() {
+        let au = access_unit(&[&VPS, &SPS, &PPS]);
+        let payloads = Payloader::new(1200).payload(&au).unwrap();
+        assert_eq!(
+            payloads,
+            vec![vec![
+                0x60, 0x01, // AP
+                0x00, 0x04, 0x40, 0x01, 0x0c, 0x01, // VPS
+                0x00, 0x04, 0x42, 0x01, 0x01, 0x02, // SPS
+                0x00, 0x04, 0x44, 0x01, 0xc0, 0xf7, // PPS
+            ]]
+        );
+    }
+
+    #[test]
+    fn single_nal_units() {
+        let au = access_unit(&[&VPS, &IDR]);
+        let payloads = Payloader::new(12).payload(&au).unwrap();
+        assert_eq!(payloads, vec![VPS.to_vec(), IDR.to_vec()]);
+    }
+
+    #[test]
+    fn fragment() {
+        let au = access_unit(&[&VPS, &IDR]);
+        let payloads = Payloader::new(8).payload(&au).unwrap();
+        assert_eq!(
+            payloads,
+            vec![
+                VPS.to_vec(),
+                vec![0x62, 0x01, 0x93, 0xaf, 0x01, 0x02, 0x03, 0x04],
+                vec![0x62, 0x01, 0x53, 0x05, 0x06, 0x07, 0x08, 0x09],
+            ]
+        );
+    }
+
+    #[test]
+    fn payload_size_too_small() {
+        let au = access_unit(&[&IDR]);
+        assert_eq!(
+            Payloader::new(3).payload(&au),
+            Err(RtpError::PayloadSizeTooSmall(3))
+        );
+
+        let payloads = Payloader::new(4).payload(&au).unwrap();
+        assert_eq!(payloads.len(), 10);
+        assert_eq!(payloads[0], vec![0x62, 0x01, 0x93, 0xaf]);
+        assert_eq!(payloads[9], vec![0x62, 0x01, 0x53, 0x09]);
+    }
+}
@@ -0,0 +1,249 @@
+//! A simple scaler, for encoding pictures at a different size than they were
+//! captured or decoded at.
+//!
+//! This is meant for the common cases of an ABR ladder or a screen capture
+//! which doesn't match the encode size, not as a replacement for a dedicated
+//! scaling library. Each plane is scaled separately, in the same subsampling
+//! format, and only 8-bit pictures are supported.
+//!
+//! ```
+//! # use svt::{Picture, SubsamplingFormat, YUVBuffer};
+//! # use svt::scale::{scale, Filter};
+//! # fn main() -> Result<(), svt::Error> {
+//! let capture = YUVBuffer::new(2560, 1440, SubsamplingFormat::Yuv420);
+//! let scaled = scale(&capture, SubsamplingFormat::Yuv420, 1920, 1080, Filter::Box)?;
+//! assert_eq!((scaled.width(), scaled.height()), (1920, 1080));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::buffer::chroma_dimensions;
+use crate::{Error, Picture, PictureRequirements, Plane, SubsamplingFormat, YUVBuffer};
+
+/// The filter used to compute each output sample.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Averages the input samples each output sample covers. This avoids
+    /// aliasing when downscaling by large factors, and is the default. When
+    /// upscaling, it repeats the nearest sample.
+    #[default]
+    Box,
+    /// Interpolates between the four nearest input samples. This is smoother
+    /// for upscaling and small downscales, but aliases when downscaling by
+    /// more than a factor of two.
+    Bilinear,
+}
+
+/// Scales a picture to the given size, returning a new buffer. See
+/// [`scale_into`].
+pub fn scale(
+    picture: &impl Picture,
+    format: SubsamplingFormat,
+    width: u32,
+    height: u32,
+    filter: Filter,
+) -> Result<YUVBuffer, Error> {
+    let mut buffer = YUVBuffer::new(width, height, format);
+    scale_into(picture, format, &mut buffer, filter)?;
+    Ok(buffer)
+}
+
+/// Scales a picture to the size of an existing buffer, which must be in the
+/// same subsampling format, so that the buffer can be reused for each frame.
+///
+/// The picture must be 8-bit, with planes of the size its format implies, or
+/// [`Error::InvalidPicture`] is returned.
+pub fn scale_into(
+    picture: &impl Picture,
+    format: SubsamplingFormat,
+    buffer: &mut YUVBuffer,
+    filter: Filter,
+) -> Result<(), Error> {
+    let requirements = PictureRequirements {
+        width: picture.width(),
+        height: picture.height(),
+        subsampling_format: format,
+        bytes_per_sample: 1,
+    };
+
+    requirements.check(picture)?;
+
+    let src_size = (picture.width(), picture.height());
+    let dst_size = (buffer.width(), buffer.height());
+    for plane in [Plane::Y, Plane::U, Plane::V] {
+        let (src_size, dst_size) = match plane {
+            Plane::Y => (src_size, dst_size),
+            _ => (
+                chroma_dimensions(src_size.0, src_size.1, format),
+                chroma_dimensions(dst_size.0, dst_size.1, format),
+            ),
+        };
+
+        let src = picture.as_slice(plane);
+        let src_stride = picture.stride(plane) as usize;
+        let dst_stride = buffer.stride(plane) as usize;
+        let dst = buffer.as_mut_slice(plane);
+        match filter {
+            Filter::Box => scale_box(src, src_stride, src_size, dst, dst_stride, dst_size),
+            Filter::Bilinear => {
+                scale_bilinear(src, src_stride, src_size, dst, dst_stride, dst_size)
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn scale_box(
+    src: &[u8],
+    src_stride: usize,
+    (src_width, src_height): (u32, u32),
+    dst: &mut [u8],
+    dst_stride: usize,
+    (dst_width, dst_height): (u32, u32),
+) {
+    if src_width == 0 || src_height == 0 {
+        return;
+    }
+
+    // The range of input samples covered by output sample `i`. When
+    // upscaling, this is a single sample.
+    let span = |i: u32, src_len: u32, dst_len: u32| {
+        let start = (i as u64 * src_len as u64 / dst_len as u64) as usize;
+        let end = ((i as u64 + 1) * src_len as u64 / dst_len as u64) as usize;
+        start..end.max(start + 1)
+    };
+
+    let columns: Vec<_> = (0..dst_width)
+        .map(|x| span(x, src_width, dst_width))
+        .collect();
+
+    let mut sums = vec![0u32; src_width as usize];
+    for y in 0..dst_height {
+        let rows = span(y, src_height, dst_height);
+
+        // Sum the rows first, so that each input sample is only read once.
+        sums.fill(0);
+        for row in rows.clone() {
+            let row = &src[row * src_stride..][..src_width as usize];
+            for (sum, &v) in sums.iter_mut().zip(row) {
+                *sum += v as u32;
+            }
+        }
+
+        let out = &mut dst[y as usize * dst_stride..][..dst_width as usize];
+        for (v, cols) in out.iter_mut().zip(&columns) {
+            let count = (rows.len() * cols.len()) as u32;
+            let sum: u32 = sums[cols.clone()].iter().sum();
+            *v = ((sum + count / 2) / count) as u8;
+        }
+    }
+}
+
+/// The number of fractional bits in the bilinear weights.
+const BILINEAR_BITS: u32 = 8;
+
+fn scale_bilinear(
+    src: &[u8],
+    src_stride: usize,
+    (src_width, src_height): (u32, u32),
+    dst: &mut [u8],
+    dst_stride: usize,
+    (dst_width, dst_height): (u32, u32),
+) {
+    if src_width == 0 || src_height == 0 {
+        return;
+    }
+
+    // The two input samples on either side of the center of output sample
+    // `i`, and the weight of the second, with the centers of the first and
+    // last samples aligned.
+    let taps = |i: u32, src_len: u32, dst_len: u32| {
+        let one = 1i64 << BILINEAR_BITS;
+        let pos = ((2 * i as i64 + 1) * src_len as i64 * one / dst_len as i64 - one) / 2;
+        let pos = pos.clamp(0, (src_len as i64 - 1) * one);
+        let first = (pos >> BILINEAR_BITS) as usize;
+        let second = (first + 1).min(src_len as usize - 1);
+        (first, second, (pos & (one - 1)) as u32)
+    };
+
+    let columns: Vec<_> = (0..dst_width)
+        .map(|x| taps(x, src_width, dst_width))
+        .collect();
+
+    let one = 1 << BILINEAR_BITS;
+    let round = 1 << (2 * BILINEAR_BITS - 1);
+    for y in 0..dst_height {
+        let (top, bottom, wy) = taps(y, src_height, dst_height);
+        let top = &src[top * src_stride..][..src_width as usize];
+        let bottom = &src[bottom * src_stride..][..src_width as usize];
+
+        let out = &mut dst[y as usize * dst_stride..][..dst_width as usize];
+        for (v, &(left, right, wx)) in out.iter_mut().zip(&columns) {
+            let upper = top[left] as u32 * (one - wx) + top[right] as u32 * wx;
+            let lower = bottom[left] as u32 * (one - wx) + bottom[right] as u32 * wx;
+            *v = ((upper * (one - wy) + lower * wy + round) >> (2 * BILINEAR_BITS)) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rustfmt::skip]
+    const PLANE: [u8; 16] = [
+        0, 2, 10, 10,
+        4, 6, 20, 20,
+        1, 1, 1, 1,
+        1, 1, 255, 255,
+    ];
+
+    #[test]
+    fn box_filter() {
+        let mut down = [0; 4];
+        scale_box(&PLANE, 4, (4, 4), &mut down, 2, (2, 2));
+        assert_eq!(down, [3, 15, 1, 128]);
+
+        let mut up = [0; 16];
+        scale_box(&down, 2, (2, 2), &mut up, 4, (4, 4));
+        assert_eq!(&up[..4], &[3, 3, 15, 15]);
+        assert_eq!(&up[12..], &[1, 1, 128, 128]);
+    }
+
+    #[test]
+    fn bilinear_filter() {
+        // A 2:1 downscale samples exactly between each pair.
+        let mut down = [0; 4];
+        scale_bilinear(&PLANE, 4, (4, 4), &mut down, 2, (2, 2));
+        assert_eq!(down, [3, 15, 1, 128]);
+
+        // Upscaling a ramp keeps it linear in the middle, and clamps at the
+        // edges.
+        let ramp = [0, 64, 128, 192];
+        let mut up = [0; 8];
+        scale_bilinear(&ramp, 4, (4, 1), &mut up, 8, (8, 1));
+        assert_eq!(up, [0, 16, 48, 80, 112, 144, 176, 192]);
+    }
+
+    #[test]
+    fn scale_picture() {
+        let mut src = YUVBuffer::new(64, 48, SubsamplingFormat::Yuv420);
+        src.as_mut_slice(Plane::Y).fill(100);
+        src.as_mut_slice(Plane::U).fill(50);
+        src.as_mut_slice(Plane::V).fill(200);
+
+        for filter in [Filter::Box, Filter::Bilinear] {
+            let dst = scale(&src, SubsamplingFormat::Yuv420, 21, 15, filter).unwrap();
+            assert!(dst.as_slice(Plane::Y).iter().all(|&v| v == 100));
+            assert!(dst.as_slice(Plane::U).iter().all(|&v| v == 50));
+            assert!(dst.as_slice(Plane::V).iter().all(|&v| v == 200));
+        }
+
+        // The planes don't match the format.
+        assert!(matches!(
+            scale(&src, SubsamplingFormat::Yuv444, 32, 24, Filter::Box),
+            Err(Error::InvalidPicture { .. })
+        ));
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::sink::{PacketSink, Pipe};
+use crate::{Encoder, EncoderConfig, Error, FrameTypeRequest, Packet, Picture, SubsamplingFormat};
+
+/// An encoded packet copied out of the encoder, so that it can outlive it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedPacket {
+    data: Vec<u8>,
+    pts: i64,
+    keyframe: bool,
+    timestamp: Option<Instant>,
+}
+
+impl OwnedPacket {
+    pub(crate) fn new(data: Vec<u8>, pts: i64, keyframe: bool) -> Self {
+        Self {
+            data,
+            pts,
+            keyframe,
+            timestamp: None,
+        }
+    }
+
+    /// Shifts the pts by `offset`, and drops the capture time, for packets
+    /// from an encoder which only saw part of the stream.
+    pub(crate) fn rebase(mut self, offset: i64) -> Self {
+        self.pts += offset;
+        self.timestamp = None;
+        self
+    }
+
+    /// Appends the data of a later packet.
+    pub(crate) fn extend(&mut self, data: &[u8]) {
+        self.data.extend_from_slice(data);
+    }
+
+    /// The presentation timestamp of the frame.
+    pub fn pts(&self) -> i64 {
+        self.pts
+    }
+
+    /// Whether the packet contains a keyframe.
+    pub fn is_keyframe(&self) -> bool {
+        self.keyframe
+    }
+
+    /// For packets output by a [`Session`], the time passed to
+    /// [`Session::encode`] with the picture.
+    pub fn timestamp(&self) -> Option<Instant> {
+        self.timestamp
+    }
+
+    /// Returns the encoded bytes.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Packet for OwnedPacket {
+    fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn is_eos(&self) -> bool {
+        false
+    }
+}
+
+impl AsRef<[u8]> for OwnedPacket {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// A simple encoding session, in the style of the WebCodecs `VideoEncoder`,
+/// for applications which don't need to manage the
+/// [`Encoder::send_picture`], [`Encoder::get_packet`] and
+/// [`Encoder::finish`] loop themselves.
+///
+/// The encoder is created when the first picture is passed to
+/// [`Session::encode`], at the size of that picture. Packets are drained as
+/// pictures are sent, and returned with the time each picture was captured.
+/// There is one packet for each picture, in decode order; data which doesn't
+/// complete a picture, like an AV1 alt-ref frame, is output together with the
+/// next packet which does.
+///
+/// # Example
+///
+/// ```no_run
+/// # #[cfg(feature = "av1")]
+/// # fn main() -> Result<(), svt::Error> {
+/// use std::time::Instant;
+///
+/// use svt::av1::Av1EncoderConfig;
+/// use svt::{Packet, Session, SubsamplingFormat, YUVBuffer};
+///
+/// let mut session = Session::new(Av1EncoderConfig::default().preset(10));
+/// let picture = YUVBuffer::new(1280, 720, SubsamplingFormat::Yuv420);
+///
+/// for _ in 0..30 {
+///     session.encode(&picture, Instant::now())?;
+///     for packet in session.take_packets() {
+///         // Send the packet over the network...
+///     }
+/// }
+///
+/// for packet in session.flush()? {
+///     // Handle the final packets the same way.
+/// }
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "av1"))]
+/// # fn main() {}
+/// ```
+pub struct Session<C: EncoderConfig> {
+    config: Option<C>,
+    subsampling_format: SubsamplingFormat,
+    encoder: Option<C::Encoder>,
+    next_pts: i64,
+    timestamps: HashMap<i64, Instant>,
+    pending: Vec<u8>,
+    output: Vec<OwnedPacket>,
+}
+
+impl<C: EncoderConfig> std::fmt::Debug for Session<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("subsampling_format", &self.subsampling_format)
+            .field("started", &self.encoder.is_some())
+            .field("pictures", &self.next_pts)
+            .field("packets", &self.output.len())
+            .finish()
+    }
+}
+
+impl<C: EncoderConfig> Session<C> {
+    /// Creates a session, which encodes 4:2:0 pictures with the given
+    /// configuration.
+    pub fn new(config: C) -> Self {
+        Self {
+            config: Some(config),
+            subsampling_format: SubsamplingFormat::Yuv420,
+            encoder: None,
+            next_pts: 0,
+            timestamps: HashMap::new(),
+            pending: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Sets the chroma subsampling format of the pictures. This must be set
+    /// before the first picture is encoded.
+    pub fn subsampling_format(mut self, format: SubsamplingFormat) -> Self {
+        self.subsampling_format = format;
+        self
+    }
+
+    /// The underlying encoder, once the first picture has been encoded.
+    pub fn encoder(&self) -> Option<&C::Encoder> {
+        self.encoder.as_ref()
+    }
+
+    /// Sends a picture to the encoder, along with the time it was captured.
+    /// Any packets which are ready are collected, to be retrieved with
+    /// [`Session::take_packets`].
+    ///
+    /// Pictures are numbered in the order they're sent, and the numbers are
+    /// used as the timestamps passed to the encoder. If the encoder can't be
+    /// created for the first picture, the error is returned, and the session
+    /// can't be used again.
+    pub fn encode(&mut self, picture: &impl Picture, timestamp: Instant) -> Result<(), Error> {
+        self.encode_with(picture, timestamp, FrameTypeRequest::Auto)
+    }
+
+    /// Sends a picture to the encoder, like [`Session::encode`], and forces
+    /// it to be coded as a keyframe.
+    pub fn encode_keyframe(
+        &mut self,
+        picture: &impl Picture,
+        timestamp: Instant,
+    ) -> Result<(), Error> {
+        self.encode_with(picture, timestamp, FrameTypeRequest::Key)
+    }
+
+    /// Attaches a [`PacketSink`], which the packets are written to as
+    /// they're output, instead of being collected.
+    pub fn pipe_to<S: PacketSink>(self, sink: S) -> Pipe<C, S> {
+        Pipe::new(self, sink)
+    }
+
+    fn encode_with(
+        &mut self,
+        picture: &impl Picture,
+        timestamp: Instant,
+        frame_type: FrameTypeRequest,
+    ) -> Result<(), Error> {
+        let encoder = match (&mut self.encoder, self.config.take()) {
+            (Some(encoder), _) => encoder,
+            (encoder, Some(config)) => encoder.insert(config.create_encoder(
+                picture.width(),
+                picture.height(),
+                self.subsampling_format,
+            )?),
+            // Creating the encoder failed on an earlier call.
+            (None, None) => {
+                return Err(Error::BadParameter {
+                    detail: Some("the encoder could not be created".to_owned()),
+                })
+            }
+        };
+
+        let pts = self.next_pts;
+        encoder.send_picture(picture, pts, frame_type)?;
+        self.next_pts += 1;
+        self.timestamps.insert(pts, timestamp);
+
+        self.drain(false)
+    }
+
+    /// Returns the packets output so far, in decode order.
+    pub fn take_packets(&mut self) -> Vec<OwnedPacket> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Finishes the stream, waits for the encoder to output the remaining
+    /// packets, and returns them along with any which weren't retrieved with
+    /// [`Session::take_packets`]. Afterwards, encoding another picture returns
+    /// [`Error::InvalidState`].
+    pub fn flush(&mut self) -> Result<Vec<OwnedPacket>, Error> {
+        if let Some(encoder) = &self.encoder {
+            encoder.finish()?;
+            self.drain(true)?;
+        }
+
+        Ok(self.take_packets())
+    }
+
+    fn drain(&mut self, wait: bool) -> Result<(), Error> {
+        let Some(encoder) = &self.encoder else {
+            return Ok(());
+        };
+
+        while let Some(packet) = encoder.get_packet(wait)? {
+            self.pending.extend_from_slice(packet.as_bytes());
+            if let Some(pts) = packet.completed_pts() {
+                let data = std::mem::take(&mut self.pending);
+                let keyframe = packet.keyframe_reason().is_some();
+                let mut output = OwnedPacket::new(data, pts, keyframe);
+                output.timestamp = self.timestamps.remove(&pts);
+                self.output.push(output);
+            }
+
+            if packet.is_eos() {
+                // Anything left over belongs with the last picture, unless
+                // it was already taken.
+                if let Some(last) = self.output.last_mut() {
+                    last.extend(&std::mem::take(&mut self.pending));
+                }
+
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::MockEncoderConfig;
+    use crate::YUVBuffer;
+
+    #[test]
+    fn encode() {
+        let mut session = Session::new(MockEncoderConfig::default().delay(2));
+        let picture = YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420);
+        let start = Instant::now();
+
+        for i in 0..4 {
+            session
+                .encode(&picture, start + Duration::from_millis(i))
+                .unwrap();
+        }
+
+        session
+            .encode_keyframe(&picture, start + Duration::from_millis(4))
+            .unwrap();
+
+        let mut packets = session.take_packets();
+        assert_eq!(packets.len(), 3);
+        packets.extend(session.flush().unwrap());
+
+        let summary: Vec<_> = packets
+            .iter()
+            .map(|p| (p.pts(), p.is_keyframe(), p.timestamp()))
+            .collect();
+        assert_eq!(
+            summary,
+            (0..5)
+                .map(|i| (
+                    i,
+                    i == 0 || i == 4,
+                    Some(start + Duration::from_millis(i as u64))
+                ))
+                .collect::<Vec<_>>()
+        );
+        assert!(packets.iter().all(|p| p.as_bytes().len() == 16));
+
+        assert!(matches!(
+            session.encode(&picture, start),
+            Err(Error::InvalidState { .. })
+        ));
+    }
+
+    #[test]
+    fn create_error() {
+        let mut session = Session::new(MockEncoderConfig::default());
+        let picture = YUVBuffer::new(0, 0, SubsamplingFormat::Yuv420);
+        assert!(session.encode(&picture, Instant::now()).is_err());
+        assert!(matches!(
+            session.encode(&picture, Instant::now()),
+            Err(Error::BadParameter { .. })
+        ));
+        assert!(session.encoder().is_none());
+    }
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use crate::{Encoder, EncoderState, Error};
+
+/// How long dropping an encoder waits for the stream to finish, unless
+/// configured otherwise.
+pub(crate) const DEFAULT_DROP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Finishes the stream and discards the remaining packets, until the EOS
+/// packet is retrieved or `timeout` elapses.
+///
+/// The libraries can hang if they're deinitialized while pictures are still
+/// being encoded, so the encoders do this before shutting down.
+pub(crate) fn drain(encoder: &impl Encoder, timeout: Duration) -> Result<(), Error> {
+    encoder.finish()?;
+
+    let deadline = std::time::Instant::now() + timeout;
+    while encoder.state() != EncoderState::Finished {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if encoder.get_packet_timeout(remaining)?.is_none() {
+            return Err(Error::TimedOut);
+        }
+    }
+
+    Ok(())
+}
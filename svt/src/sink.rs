@@ -0,0 +1,450 @@
+//! Destinations for encoded packets, which write the bitstream to any
+//! [`std::io::Write`].
+//!
+//! A [`PacketSink`] can be fed by hand, or attached to a [`Session`] with
+//! [`Session::pipe_to`], so that a whole encode writes straight to a file:
+//!
+//! ```no_run
+//! # #[cfg(feature = "av1")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use std::fs::File;
+//! use std::io::BufWriter;
+//! use std::time::Instant;
+//!
+//! use svt::av1::Av1EncoderConfig;
+//! use svt::sink::IvfSink;
+//! use svt::{Session, SubsamplingFormat, YUVBuffer};
+//!
+//! let file = BufWriter::new(File::create("output.ivf")?);
+//! let config = Av1EncoderConfig::default().framerate(30, 1);
+//! let mut pipe = Session::new(config).pipe_to(IvfSink::new(file).framerate(30, 1));
+//!
+//! let picture = YUVBuffer::new(1280, 720, SubsamplingFormat::Yuv420);
+//! for _ in 0..30 {
+//!     pipe.encode(&picture, Instant::now())?;
+//! }
+//!
+//! pipe.finish()?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "av1"))]
+//! # fn main() {}
+//! ```
+
+use std::io::{self, Write};
+use std::time::Instant;
+
+use crate::{EncoderConfig, Error, Packet, Picture, Rational, Session};
+
+/// A destination for encoded packets.
+pub trait PacketSink {
+    /// Called once, before the first packet, with the dimensions of the
+    /// pictures.
+    fn start(&mut self, width: u32, height: u32) -> io::Result<()> {
+        let _ = (width, height);
+        Ok(())
+    }
+
+    /// Writes the data of a packet, and the pts of the picture it completes.
+    fn write_packet(&mut self, data: &[u8], pts: i64) -> io::Result<()>;
+
+    /// Called once the stream has ended, to write any buffered data.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Writes packets back to back, without any framing: an OBU stream for AV1,
+/// or an Annex B byte stream for HEVC.
+#[derive(Debug)]
+pub struct RawSink<W> {
+    writer: W,
+}
+
+impl<W: Write> RawSink<W> {
+    /// Creates a sink which writes to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Consumes the sink, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> PacketSink for RawSink<W> {
+    fn write_packet(&mut self, data: &[u8], _pts: i64) -> io::Result<()> {
+        self.writer.write_all(data)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes packets to an IVF file, the simple container used by the libaom
+/// and libvpx tools.
+///
+/// The header is written before the first packet. Since the writer may not be
+/// seekable, its frame count is left at zero, which players ignore.
+#[derive(Debug)]
+pub struct IvfSink<W> {
+    writer: W,
+    fourcc: [u8; 4],
+    timebase: Rational,
+}
+
+impl<W: Write> IvfSink<W> {
+    /// Creates a sink which writes AV1 packets to `writer`, with a timebase
+    /// of 1/30.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            fourcc: *b"AV01",
+            timebase: Rational::new(1, 30),
+        }
+    }
+
+    /// Sets the codec's fourcc, for example `*b"VP90"` for VP9.
+    pub fn fourcc(mut self, fourcc: [u8; 4]) -> Self {
+        self.fourcc = fourcc;
+        self
+    }
+
+    /// Sets the timebase from the framerate, for pts which count pictures,
+    /// like those assigned by a [`Session`].
+    pub fn framerate(self, numerator: u32, denominator: u32) -> Self {
+        self.timebase(Rational::from_framerate(numerator, denominator))
+    }
+
+    /// Sets the timebase of the pts.
+    pub fn timebase(mut self, timebase: Rational) -> Self {
+        self.timebase = timebase;
+        self
+    }
+
+    /// Consumes the sink, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> PacketSink for IvfSink<W> {
+    fn start(&mut self, width: u32, height: u32) -> io::Result<()> {
+        let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}x{} is too large for IVF", width, height),
+            ));
+        };
+
+        let mut header = [0; 32];
+        header[0..4].copy_from_slice(b"DKIF");
+        header[6..8].copy_from_slice(&32_u16.to_le_bytes());
+        header[8..12].copy_from_slice(&self.fourcc);
+        header[12..14].copy_from_slice(&width.to_le_bytes());
+        header[14..16].copy_from_slice(&height.to_le_bytes());
+        header[16..20].copy_from_slice(&self.timebase.den.to_le_bytes());
+        header[20..24].copy_from_slice(&self.timebase.num.to_le_bytes());
+        self.writer.write_all(&header)
+    }
+
+    fn write_packet(&mut self, data: &[u8], pts: i64) -> io::Result<()> {
+        let size = u32::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "packet is too large"))?;
+
+        self.writer.write_all(&size.to_le_bytes())?;
+        self.writer.write_all(&pts.to_le_bytes())?;
+        self.writer.write_all(data)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes AV1 packets in the length-delimited format from Annex B of the AV1
+/// specification, in which every temporal unit, frame, and OBU is prefixed
+/// with its size. HEVC packets are already in the Annex B byte stream format,
+/// and can be written with a [`RawSink`].
+#[cfg(feature = "av1")]
+#[derive(Debug)]
+pub struct AnnexBSink<W> {
+    writer: W,
+    frames: Vec<Vec<u8>>,
+    has_frame_header: bool,
+}
+
+#[cfg(feature = "av1")]
+impl<W: Write> AnnexBSink<W> {
+    /// Creates a sink which writes to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            frames: Vec::new(),
+            has_frame_header: false,
+        }
+    }
+
+    /// Consumes the sink, returning the underlying writer. Call
+    /// [`PacketSink::finish`] first to write the last temporal unit.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes the buffered temporal unit.
+    fn write_temporal_unit(&mut self) -> io::Result<()> {
+        use crate::obu::write_leb128;
+
+        if self.frames.is_empty() {
+            return Ok(());
+        }
+
+        let mut buf = Vec::new();
+        for frame in &self.frames {
+            write_leb128(&mut buf, frame.len());
+            buf.extend_from_slice(frame);
+        }
+
+        let mut size = Vec::new();
+        write_leb128(&mut size, buf.len());
+        self.writer.write_all(&size)?;
+        self.writer.write_all(&buf)?;
+
+        self.frames.clear();
+        self.has_frame_header = false;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "av1")]
+impl<W: Write> PacketSink for AnnexBSink<W> {
+    fn write_packet(&mut self, data: &[u8], _pts: i64) -> io::Result<()> {
+        use crate::obu::{obus, write_leb128, ObuType};
+
+        for obu in obus(data) {
+            let obu = obu.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+            // A temporal delimiter starts a temporal unit, and a frame header
+            // starts a frame, except that the OBUs before the first frame
+            // header belong to the first frame.
+            let frame_header = matches!(obu.obu_type(), ObuType::FrameHeader | ObuType::Frame);
+            if obu.obu_type() == ObuType::TemporalDelimiter {
+                self.write_temporal_unit()?;
+            } else if frame_header && self.has_frame_header {
+                self.frames.push(Vec::new());
+            }
+
+            self.has_frame_header |= frame_header;
+
+            if self.frames.is_empty() {
+                self.frames.push(Vec::new());
+            }
+
+            let frame = self.frames.last_mut().unwrap();
+            let header = obu.header();
+            write_leb128(frame, header.len() + obu.size());
+
+            // The size is given by obu_length instead.
+            frame.push(header[0] & !0x02);
+            frame.extend_from_slice(&header[1..]);
+            frame.extend_from_slice(obu.payload());
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.write_temporal_unit()?;
+        self.writer.flush()
+    }
+}
+
+/// An error from a [`Pipe`]: either encoding failed, or writing to the sink
+/// did.
+#[derive(Debug)]
+pub enum PipeError {
+    /// The encoder failed.
+    Encode(Error),
+    /// Writing to the sink failed.
+    Io(io::Error),
+}
+
+impl std::error::Error for PipeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipeError::Encode(e) => Some(e),
+            PipeError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl std::fmt::Display for PipeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipeError::Encode(e) => write!(f, "failed to encode: {}", e),
+            PipeError::Io(e) => write!(f, "failed to write packet: {}", e),
+        }
+    }
+}
+
+impl From<Error> for PipeError {
+    fn from(e: Error) -> Self {
+        PipeError::Encode(e)
+    }
+}
+
+impl From<io::Error> for PipeError {
+    fn from(e: io::Error) -> Self {
+        PipeError::Io(e)
+    }
+}
+
+/// A [`Session`] which writes its packets to a [`PacketSink`] as they're
+/// output. Created with [`Session::pipe_to`].
+pub struct Pipe<C: EncoderConfig, S> {
+    session: Session<C>,
+    sink: S,
+    started: bool,
+}
+
+impl<C: EncoderConfig, S> std::fmt::Debug for Pipe<C, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pipe")
+            .field("session", &self.session)
+            .field("started", &self.started)
+            .finish()
+    }
+}
+
+impl<C: EncoderConfig, S: PacketSink> Pipe<C, S> {
+    pub(crate) fn new(session: Session<C>, sink: S) -> Self {
+        Self {
+            session,
+            sink,
+            started: false,
+        }
+    }
+
+    /// Encodes a picture, like [`Session::encode`], and writes any packets
+    /// which are ready to the sink.
+    pub fn encode(&mut self, picture: &impl Picture, timestamp: Instant) -> Result<(), PipeError> {
+        self.session.encode(picture, timestamp)?;
+        self.write(picture)
+    }
+
+    /// Encodes a picture as a keyframe, like [`Session::encode_keyframe`],
+    /// and writes any packets which are ready to the sink.
+    pub fn encode_keyframe(
+        &mut self,
+        picture: &impl Picture,
+        timestamp: Instant,
+    ) -> Result<(), PipeError> {
+        self.session.encode_keyframe(picture, timestamp)?;
+        self.write(picture)
+    }
+
+    /// Finishes the stream, writes the remaining packets, and returns the
+    /// sink.
+    pub fn finish(mut self) -> Result<S, PipeError> {
+        for packet in self.session.flush()? {
+            self.sink.write_packet(packet.as_bytes(), packet.pts())?;
+        }
+
+        self.sink.finish()?;
+        Ok(self.sink)
+    }
+
+    /// The underlying session.
+    pub fn session(&self) -> &Session<C> {
+        &self.session
+    }
+
+    fn write(&mut self, picture: &impl Picture) -> Result<(), PipeError> {
+        if !self.started {
+            self.sink.start(picture.width(), picture.height())?;
+            self.started = true;
+        }
+
+        for packet in self.session.take_packets() {
+            self.sink.write_packet(packet.as_bytes(), packet.pts())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ivf() {
+        let mut sink = IvfSink::new(Vec::new()).framerate(25, 1);
+        sink.start(320, 240).unwrap();
+        sink.write_packet(&[1, 2, 3], 7).unwrap();
+        sink.finish().unwrap();
+
+        let data = sink.into_inner();
+        assert_eq!(data.len(), 32 + 12 + 3);
+        assert_eq!(&data[0..4], b"DKIF");
+        assert_eq!(&data[8..12], b"AV01");
+        assert_eq!(&data[12..16], &[0x40, 0x01, 0xf0, 0x00]);
+        assert_eq!(&data[16..24], &[25, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(&data[32..36], &[3, 0, 0, 0]);
+        assert_eq!(&data[36..44], &7_i64.to_le_bytes());
+        assert_eq!(&data[44..], &[1, 2, 3]);
+
+        let mut sink = IvfSink::new(Vec::new());
+        assert!(sink.start(70_000, 240).is_err());
+    }
+
+    #[cfg(feature = "av1")]
+    #[test]
+    fn annexb() {
+        let mut sink = AnnexBSink::new(Vec::new());
+        sink.write_packet(
+            &[
+                0x12, 0x00, // Temporal delimiter.
+                0x0a, 0x01, 0xaa, // Sequence header.
+                0x1a, 0x01, 0xbb, // Frame header.
+                0x22, 0x01, 0xcc, // Tile group.
+                0x32, 0x02, 0xdd, 0xee, // Frame.
+            ],
+            0,
+        )
+        .unwrap();
+        sink.write_packet(&[0x12, 0x00, 0x32, 0x01, 0xff], 1)
+            .unwrap();
+        sink.finish().unwrap();
+
+        assert_eq!(
+            sink.into_inner(),
+            [
+                // The first temporal unit, with two frames.
+                0x11, //
+                0x0b, 0x01, 0x10, 0x02, 0x08, 0xaa, 0x02, 0x18, 0xbb, 0x02, 0x20, 0xcc, 0x04, 0x03,
+                0x30, 0xdd, 0xee, //
+                // The second temporal unit.
+                0x06, //
+                0x05, 0x01, 0x10, 0x02, 0x30, 0xff,
+            ]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn pipe() {
+        use crate::mock::MockEncoderConfig;
+        use crate::{SubsamplingFormat, YUVBuffer};
+
+        let config = MockEncoderConfig::default().delay(2).packet_size(12);
+        let mut pipe = Session::new(config).pipe_to(RawSink::new(Vec::new()));
+        let picture = YUVBuffer::new(16, 16, SubsamplingFormat::Yuv420);
+        for _ in 0..3 {
+            pipe.encode(&picture, Instant::now()).unwrap();
+        }
+
+        let data = pipe.finish().unwrap().into_inner();
+        assert_eq!(data.len(), 36);
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{Encoder, EncoderState, Error, FrameTypeRequest, Picture};
+
+/// The sending half of an encoder, created with [`Encoder::split`].
+///
+/// If the sender is dropped before the stream is finished, for example
+/// because the thread that owns it panicked, the stream is finished
+/// automatically, so that the receiver sees an EOS packet rather than
+/// waiting forever.
+#[derive(Debug)]
+pub struct EncoderSender<E: Encoder> {
+    encoder: Arc<E>,
+}
+
+impl<E: Encoder> EncoderSender<E> {
+    /// Sends an input picture to the encoder. See [`Encoder::send_picture`].
+    ///
+    /// Once the stream has been finished, either with
+    /// [`EncoderSender::finish`] or a [`ShutdownHandle`], this returns
+    /// [`Error::InvalidState`].
+    pub fn send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        self.encoder.send_picture(picture, pts, frame_type)
+    }
+
+    /// Requests that the encoder finish encoding. See [`Encoder::finish`].
+    pub fn finish(&self) -> Result<(), Error> {
+        self.encoder.finish()
+    }
+
+    /// The number of pictures sent but not yet fully encoded. See
+    /// [`Encoder::in_flight`].
+    pub fn in_flight(&self) -> usize {
+        self.encoder.in_flight()
+    }
+
+    /// Returns a handle which can finish the stream from any thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle<E> {
+        ShutdownHandle {
+            encoder: self.encoder.clone(),
+        }
+    }
+}
+
+impl<E: Encoder> Drop for EncoderSender<E> {
+    fn drop(&mut self) {
+        let _ = self.encoder.finish();
+    }
+}
+
+/// The receiving half of an encoder, created with [`Encoder::split`].
+#[derive(Debug)]
+pub struct PacketReceiver<E> {
+    encoder: Arc<E>,
+}
+
+impl<E: Encoder> PacketReceiver<E> {
+    /// Retrieves an encoded packet from the encoder. See
+    /// [`Encoder::get_packet`].
+    pub fn get_packet(&self, wait: bool) -> Result<Option<E::Packet>, Error> {
+        self.encoder.get_packet(wait)
+    }
+
+    /// Retrieves an encoded packet from the encoder, waiting at most
+    /// `timeout`. See [`Encoder::get_packet_timeout`].
+    pub fn get_packet_timeout(&self, timeout: Duration) -> Result<Option<E::Packet>, Error> {
+        self.encoder.get_packet_timeout(timeout)
+    }
+
+    /// The state of the encoder. See [`Encoder::state`].
+    pub fn state(&self) -> EncoderState {
+        self.encoder.state()
+    }
+}
+
+/// Finishes the stream of a split encoder, from any thread. Created with
+/// [`EncoderSender::shutdown_handle`].
+///
+/// A receiver blocked in [`PacketReceiver::get_packet`] can't be interrupted
+/// directly, since the libraries have no way to cancel a blocking call.
+/// Instead, shutting down flushes the encoder, and the receiver wakes up once
+/// the remaining packets and the EOS packet have been produced. Any pictures
+/// sent afterwards are rejected.
+#[derive(Debug)]
+pub struct ShutdownHandle<E: Encoder> {
+    encoder: Arc<E>,
+}
+
+impl<E: Encoder> Clone for ShutdownHandle<E> {
+    fn clone(&self) -> Self {
+        Self {
+            encoder: self.encoder.clone(),
+        }
+    }
+}
+
+impl<E: Encoder> ShutdownHandle<E> {
+    /// Finishes the stream, unless it has already been finished.
+    pub fn shutdown(&self) -> Result<(), Error> {
+        self.encoder.finish()
+    }
+
+    /// Whether the stream has been finished.
+    pub fn is_shutdown(&self) -> bool {
+        self.encoder.state() != EncoderState::Running
+    }
+}
+
+pub(crate) fn split<E: Encoder>(encoder: E) -> (EncoderSender<E>, PacketReceiver<E>) {
+    let encoder = Arc::new(encoder);
+    (
+        EncoderSender {
+            encoder: encoder.clone(),
+        },
+        PacketReceiver { encoder },
+    )
+}
@@ -0,0 +1,191 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::Error;
+
+/// The lifecycle of an encoder, as reported by [`Encoder::state`].
+///
+/// An encoder starts out [`Running`](EncoderState::Running). Calling
+/// [`Encoder::finish`] moves it to [`Draining`](EncoderState::Draining), and
+/// retrieving the EOS packet moves it to [`Finished`](EncoderState::Finished).
+/// The libraries misbehave if pictures are sent after the stream has ended,
+/// so the wrapper tracks the state and rejects them with
+/// [`Error::InvalidState`].
+///
+/// If a panic is caught at the boundary with the library, for example in a
+/// log sink, or unwinds out of a call while the encoder is locked, the
+/// library's state can no longer be trusted. The encoder moves to
+/// [`Poisoned`](EncoderState::Poisoned) from any state, and every call
+/// except dropping it fails with [`Error::Poisoned`].
+///
+/// | Call                       | Running    | Draining                 | Finished       |
+/// |----------------------------|------------|--------------------------|----------------|
+/// | [`Encoder::send_picture`]  | Ok         | `InvalidState`           | `InvalidState` |
+/// | [`Encoder::finish`]        | → Draining | no-op                    | no-op          |
+/// | [`Encoder::get_packet`]    | Ok         | Ok; → Finished after EOS | `None`         |
+///
+/// [`Encoder::state`]: crate::Encoder::state
+/// [`Encoder::finish`]: crate::Encoder::finish
+/// [`Encoder::send_picture`]: crate::Encoder::send_picture
+/// [`Encoder::get_packet`]: crate::Encoder::get_packet
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EncoderState {
+    /// The encoder accepts pictures.
+    Running,
+    /// The stream has been finished, and the remaining packets are being
+    /// retrieved.
+    Draining,
+    /// The EOS packet has been retrieved.
+    Finished,
+    /// A panic interrupted a call into the library, and the encoder can't be
+    /// used any further.
+    Poisoned,
+}
+
+/// Tracks the state of an encoder. Transitions on the sending side must be
+/// made while holding the encoder's send lock, and on the receiving side
+/// while holding its receive lock. Once poisoned, the state never changes.
+#[derive(Debug)]
+pub(crate) struct StateTracker(AtomicU8);
+
+#[cfg_attr(
+    not(any(feature = "av1", feature = "hevc", feature = "vp9", feature = "mock")),
+    allow(dead_code)
+)]
+impl StateTracker {
+    pub(crate) fn new() -> Self {
+        StateTracker(AtomicU8::new(EncoderState::Running as u8))
+    }
+
+    pub(crate) fn get(&self) -> EncoderState {
+        match self.0.load(Ordering::Acquire) {
+            0 => EncoderState::Running,
+            1 => EncoderState::Draining,
+            2 => EncoderState::Finished,
+            _ => EncoderState::Poisoned,
+        }
+    }
+
+    /// Returns an error if the encoder is poisoned.
+    pub(crate) fn check_poisoned(&self) -> Result<(), Error> {
+        match self.get() {
+            EncoderState::Poisoned => Err(Error::Poisoned),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an error unless the encoder accepts pictures.
+    pub(crate) fn check_running(&self) -> Result<(), Error> {
+        match self.get() {
+            EncoderState::Running => Ok(()),
+            EncoderState::Poisoned => Err(Error::Poisoned),
+            state => Err(Error::InvalidState { state }),
+        }
+    }
+
+    /// Whether [`Encoder::finish`](crate::Encoder::finish) should signal the
+    /// end of the stream to the library.
+    pub(crate) fn should_finish(&self) -> Result<bool, Error> {
+        self.check_poisoned()?;
+        Ok(self.get() == EncoderState::Running)
+    }
+
+    /// Locks one of the encoder's mutexes. If a panic unwound while it was
+    /// held, the encoder is poisoned instead.
+    pub(crate) fn lock<'a, T>(&self, mutex: &'a Mutex<T>) -> Result<MutexGuard<'a, T>, Error> {
+        match mutex.lock() {
+            Ok(guard) => Ok(guard),
+            Err(_) => {
+                self.set_poisoned();
+                Err(Error::Poisoned)
+            }
+        }
+    }
+
+    pub(crate) fn set_draining(&self) {
+        self.transition(EncoderState::Draining);
+    }
+
+    pub(crate) fn set_finished(&self) {
+        self.transition(EncoderState::Finished);
+    }
+
+    /// Returns to running, when the encoder starts a new stream.
+    #[cfg_attr(not(feature = "av1"), allow(dead_code))]
+    pub(crate) fn restart(&self) {
+        self.transition(EncoderState::Running);
+    }
+
+    pub(crate) fn set_poisoned(&self) {
+        self.0
+            .store(EncoderState::Poisoned as u8, Ordering::Release);
+    }
+
+    fn transition(&self, to: EncoderState) {
+        let poisoned = EncoderState::Poisoned as u8;
+        let _ = self
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |state| {
+                (state != poisoned).then_some(to as u8)
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions() {
+        let state = StateTracker::new();
+        assert_eq!(state.get(), EncoderState::Running);
+        assert!(state.check_running().is_ok());
+        assert!(state.should_finish().unwrap());
+
+        state.set_draining();
+        assert_eq!(state.get(), EncoderState::Draining);
+        assert!(!state.should_finish().unwrap());
+        assert!(matches!(
+            state.check_running(),
+            Err(Error::InvalidState {
+                state: EncoderState::Draining
+            })
+        ));
+
+        state.set_finished();
+        assert_eq!(state.get(), EncoderState::Finished);
+        assert!(!state.should_finish().unwrap());
+        assert!(state.check_running().is_err());
+    }
+
+    #[test]
+    fn poisoned() {
+        let state = StateTracker::new();
+        state.set_poisoned();
+        assert_eq!(state.get(), EncoderState::Poisoned);
+        assert!(matches!(state.check_running(), Err(Error::Poisoned)));
+        assert!(matches!(state.should_finish(), Err(Error::Poisoned)));
+
+        // Poisoning is permanent.
+        state.set_draining();
+        state.set_finished();
+        assert_eq!(state.get(), EncoderState::Poisoned);
+    }
+
+    #[test]
+    fn poisoned_lock() {
+        let state = StateTracker::new();
+        let mutex = std::sync::Arc::new(Mutex::new(()));
+        assert!(state.lock(&mutex).is_ok());
+
+        let m = mutex.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = m.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+
+        assert!(matches!(state.lock(&mutex), Err(Error::Poisoned)));
+        assert_eq!(state.get(), EncoderState::Poisoned);
+    }
+}
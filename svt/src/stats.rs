@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+
+/// Encoder statistics, aggregated over the lifetime of the encoder.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// The number of pictures sent to the encoder.
+    pub frames_submitted: u64,
+    /// The number of encoded frames output by the encoder.
+    pub frames_output: u64,
+    /// The number of submitted pictures which have been fully encoded. This
+    /// can lag behind `frames_output`, because an alt-ref frame is coded from
+    /// the same picture as a later overlay frame, and only the latter
+    /// completes it.
+    pub pictures_completed: u64,
+    /// The total size of the encoded frames, in bytes.
+    pub bytes_output: u64,
+    /// The number of intra frames (keyframes, intra-only, I and IDR frames)
+    /// output by the encoder.
+    pub intra_frames_output: u64,
+    /// The number of all other frames output by the encoder.
+    pub inter_frames_output: u64,
+    /// The average QP of intra frames, if any have been output.
+    pub average_intra_qp: Option<f64>,
+    /// The average QP of all other frames, if any have been output.
+    pub average_inter_qp: Option<f64>,
+    /// The achieved bitrate in bits per second, measured over the most recent
+    /// second of output according to the configured framerate. This is `None`
+    /// until at least one frame has been output, or if the framerate is
+    /// unknown.
+    pub bitrate: Option<f64>,
+}
+
+impl Stats {
+    /// The number of pictures submitted to the encoder which haven't been
+    /// fully encoded yet.
+    pub fn pending_frames(&self) -> u64 {
+        self.frames_submitted
+            .saturating_sub(self.pictures_completed)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+pub(crate) struct StatsTracker {
+    stats: Stats,
+    intra_qp_sum: u64,
+    inter_qp_sum: u64,
+    framerate: Option<f64>,
+    window: VecDeque<u64>,
+    window_len: usize,
+}
+
+#[cfg_attr(not(any(feature = "av1", feature = "hevc")), allow(dead_code))]
+impl StatsTracker {
+    pub(crate) fn new(framerate_numerator: u32, framerate_denominator: u32) -> Self {
+        let framerate = if framerate_numerator > 0 && framerate_denominator > 0 {
+            Some(framerate_numerator as f64 / framerate_denominator as f64)
+        } else {
+            None
+        };
+
+        // Keep one second's worth of frame sizes.
+        let window_len = framerate.map_or(1, |fps| fps.ceil().max(1.0) as usize);
+
+        Self {
+            stats: Stats::default(),
+            intra_qp_sum: 0,
+            inter_qp_sum: 0,
+            framerate,
+            window: VecDeque::with_capacity(window_len),
+            window_len,
+        }
+    }
+
+    pub(crate) fn record_submitted(&mut self) {
+        self.stats.frames_submitted += 1;
+    }
+
+    pub(crate) fn record_output(&mut self, size: usize, qp: u32, intra: bool) {
+        self.stats.frames_output += 1;
+        self.stats.bytes_output += size as u64;
+
+        if intra {
+            self.intra_qp_sum += qp as u64;
+            self.stats.intra_frames_output += 1;
+        } else {
+            self.inter_qp_sum += qp as u64;
+            self.stats.inter_frames_output += 1;
+        }
+
+        if self.window.len() == self.window_len {
+            self.window.pop_front();
+        }
+
+        self.window.push_back(size as u64);
+    }
+
+    /// Records that an output frame completed a submitted picture. Called
+    /// once per picture, in addition to [`StatsTracker::record_output`].
+    pub(crate) fn record_completed(&mut self) {
+        self.stats.pictures_completed += 1;
+    }
+
+    /// The number of pictures submitted but not yet fully encoded.
+    pub(crate) fn pending(&self) -> u64 {
+        self.stats.pending_frames()
+    }
+
+    /// Forgets the pictures which haven't been fully encoded, because the
+    /// stream they were sent to was discarded. They no longer count as
+    /// submitted.
+    #[cfg_attr(not(feature = "av1"), allow(dead_code))]
+    pub(crate) fn discard_pending(&mut self) {
+        self.stats.frames_submitted -= self.pending();
+    }
+
+    pub(crate) fn snapshot(&self) -> Stats {
+        let average = |sum: u64, n: u64| (n > 0).then(|| sum as f64 / n as f64);
+
+        let bitrate = self.framerate.and_then(|fps| {
+            if self.window.is_empty() {
+                return None;
+            }
+
+            let bits = self.window.iter().sum::<u64>() as f64 * 8.0;
+            Some(bits * fps / self.window.len() as f64)
+        });
+
+        Stats {
+            average_intra_qp: average(self.intra_qp_sum, self.stats.intra_frames_output),
+            average_inter_qp: average(self.inter_qp_sum, self.stats.inter_frames_output),
+            bitrate,
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitrate_window() {
+        let mut tracker = StatsTracker::new(2, 1);
+        assert_eq!(tracker.snapshot().bitrate, None);
+
+        tracker.record_submitted();
+        tracker.record_output(1000, 20, true);
+        tracker.record_output(500, 30, false);
+        tracker.record_output(100, 40, false);
+
+        let stats = tracker.snapshot();
+        assert_eq!(stats.frames_submitted, 1);
+        assert_eq!(stats.frames_output, 3);
+        assert_eq!(stats.bytes_output, 1600);
+        assert_eq!(stats.intra_frames_output, 1);
+        assert_eq!(stats.inter_frames_output, 2);
+        assert_eq!(stats.average_intra_qp, Some(20.0));
+        assert_eq!(stats.average_inter_qp, Some(35.0));
+
+        // Only the last two frames fall within the window.
+        assert_eq!(stats.bitrate, Some(600.0 * 8.0));
+    }
+
+    #[test]
+    fn pending_excludes_alt_ref() {
+        let mut tracker = StatsTracker::new(30, 1);
+        for _ in 0..3 {
+            tracker.record_submitted();
+        }
+
+        // A keyframe, then an alt-ref frame which doesn't complete a picture.
+        tracker.record_output(1000, 20, true);
+        tracker.record_completed();
+        tracker.record_output(500, 30, false);
+        assert_eq!(tracker.pending(), 2);
+
+        // The overlay frame completes the alt-ref's source picture.
+        tracker.record_output(10, 30, false);
+        tracker.record_completed();
+        assert_eq!(tracker.pending(), 1);
+        assert_eq!(tracker.snapshot().frames_output, 3);
+        assert_eq!(tracker.snapshot().pictures_completed, 2);
+
+        tracker.discard_pending();
+        assert_eq!(tracker.pending(), 0);
+        assert_eq!(tracker.snapshot().frames_submitted, 2);
+    }
+
+    #[test]
+    fn unknown_framerate() {
+        let mut tracker = StatsTracker::new(0, 0);
+        tracker.record_output(1000, 20, true);
+        assert_eq!(tracker.snapshot().bitrate, None);
+    }
+}
@@ -0,0 +1,262 @@
+//! Deterministic test patterns, for examples, benchmarks and tests that need
+//! input pictures without shipping video fixtures.
+//!
+//! Patterns are defined in RGB and converted to limited-range BT.709 YUV, at
+//! any resolution, subsampling format and bit depth. The same pattern and
+//! frame number always produce the same picture.
+//!
+//! ```
+//! # use svt::{SubsamplingFormat, YUVBuffer};
+//! # use svt::testsrc::Pattern;
+//! let mut buffer = YUVBuffer::new(640, 360, SubsamplingFormat::Yuv420);
+//! for frame in 0..30 {
+//!     Pattern::MovingBox.fill(&mut buffer, frame);
+//!     // Send the picture to an encoder.
+//! }
+//! ```
+
+use std::f64::consts::PI;
+
+use crate::{Picture, Picture16, Plane, YUVBuffer, YUVBuffer16};
+
+/// A test pattern.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// SMPTE color bars: seven 75% bars, a row of reversed castellations, and
+    /// a bottom row with -I, 100% white, +Q and black. The pattern is static.
+    Bars,
+    /// A smooth color gradient, which scrolls left by one pixel per frame.
+    Gradient,
+    /// A circular zone plate, with spatial frequency increasing from the
+    /// center to the corners. The rings move outward over time.
+    ZonePlate,
+    /// A white box, moving across a grey background by four pixels per
+    /// frame.
+    MovingBox,
+}
+
+type Rgb = [f64; 3];
+
+const BARS: [Rgb; 7] = [
+    [0.75, 0.75, 0.75],
+    [0.75, 0.75, 0.0],
+    [0.0, 0.75, 0.75],
+    [0.0, 0.75, 0.0],
+    [0.75, 0.0, 0.75],
+    [0.75, 0.0, 0.0],
+    [0.0, 0.0, 0.75],
+];
+
+const CASTELLATIONS: [Rgb; 7] = [
+    [0.0, 0.0, 0.75],
+    [0.0, 0.0, 0.0],
+    [0.75, 0.0, 0.75],
+    [0.0, 0.0, 0.0],
+    [0.0, 0.75, 0.75],
+    [0.0, 0.0, 0.0],
+    [0.75, 0.75, 0.75],
+];
+
+const MINUS_I: Rgb = [0.0, 0.2456, 0.4125];
+const PLUS_Q: Rgb = [0.2536, 0.0, 0.4703];
+
+impl Pattern {
+    /// Fills an 8-bit picture with the given frame of the pattern.
+    pub fn fill(self, buf: &mut YUVBuffer, frame: u64) {
+        let (width, height) = (buf.width(), buf.height());
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            let stride = Picture::stride(buf, plane);
+            self.render(
+                plane,
+                width,
+                height,
+                stride,
+                8,
+                frame,
+                buf.as_mut_slice(plane),
+            );
+        }
+    }
+
+    /// Fills a high bit depth picture with the given frame of the pattern,
+    /// using samples of the given bit depth.
+    pub fn fill16(self, buf: &mut YUVBuffer16, bit_depth: u32, frame: u64) {
+        let (width, height) = (Picture16::width(buf), Picture16::height(buf));
+        for plane in [Plane::Y, Plane::U, Plane::V] {
+            let stride = Picture16::stride(buf, plane);
+            self.render(
+                plane,
+                width,
+                height,
+                stride,
+                bit_depth,
+                frame,
+                buf.as_mut_slice(plane),
+            );
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render<T>(
+        self,
+        plane: Plane,
+        width: u32,
+        height: u32,
+        stride: u32,
+        bit_depth: u32,
+        frame: u64,
+        samples: &mut [T],
+    ) where
+        T: TryFrom<u32>,
+        T::Error: std::fmt::Debug,
+    {
+        if samples.is_empty() {
+            return;
+        }
+
+        // Chroma samples are taken from the top-left pixel they cover.
+        let rows = samples.len() as u32 / stride;
+        let step_x = if stride < width { 2 } else { 1 };
+        let step_y = if rows < height { 2 } else { 1 };
+
+        for (y, row) in samples.chunks_mut(stride as usize).enumerate() {
+            for (x, sample) in row.iter_mut().enumerate() {
+                let rgb = self.rgb(x as u32 * step_x, y as u32 * step_y, width, height, frame);
+                let v = quantize(plane, rgb, bit_depth);
+                *sample = T::try_from(v).expect("sample out of range");
+            }
+        }
+    }
+
+    fn rgb(self, x: u32, y: u32, width: u32, height: u32, frame: u64) -> Rgb {
+        match self {
+            Pattern::Bars => {
+                let bar = (x * 7 / width) as usize;
+                if y < height * 2 / 3 {
+                    BARS[bar]
+                } else if y < height * 3 / 4 {
+                    CASTELLATIONS[bar]
+                } else {
+                    // The bottom row is split into -I, white, +Q, and black,
+                    // each the width of five quarter bars.
+                    match x * 28 / width {
+                        0..=4 => MINUS_I,
+                        5..=9 => [1.0; 3],
+                        10..=14 => PLUS_Q,
+                        _ => [0.0; 3],
+                    }
+                }
+            }
+            Pattern::Gradient => {
+                let u = ((x as u64 + frame) % width as u64) as f64 / width as f64;
+                let v = y as f64 / height as f64;
+                [u, v, 1.0 - u]
+            }
+            Pattern::ZonePlate => {
+                let dx = x as f64 - width as f64 / 2.0;
+                let dy = y as f64 - height as f64 / 2.0;
+                let r_max = (width as f64).hypot(height as f64) / 2.0;
+
+                // The frequency reaches the Nyquist limit at the corners.
+                let phase = PI / (2.0 * r_max) * (dx * dx + dy * dy) - frame as f64 * 0.2;
+                [0.5 + 0.5 * phase.cos(); 3]
+            }
+            Pattern::MovingBox => {
+                let size = width.min(height) / 4;
+                let left = (frame * 4 % width as u64) as u32;
+                let top = (height - size) / 2;
+
+                // The box wraps around the right edge.
+                let inside_x = (x + width - left) % width < size;
+                let inside_y = (top..top + size).contains(&y);
+                if inside_x && inside_y {
+                    [1.0; 3]
+                } else {
+                    [0.5; 3]
+                }
+            }
+        }
+    }
+}
+
+/// Converts an RGB value to a limited-range BT.709 sample for the given plane.
+fn quantize(plane: Plane, [r, g, b]: Rgb, bit_depth: u32) -> u32 {
+    let luma = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let v = match plane {
+        Plane::Y => 16.0 + 219.0 * luma,
+        Plane::U => 128.0 + 224.0 * (b - luma) / 1.8556,
+        Plane::V => 128.0 + 224.0 * (r - luma) / 1.5748,
+    };
+
+    (v * (1 << (bit_depth - 8)) as f64).round() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SubsamplingFormat;
+
+    #[test]
+    fn bars() {
+        let mut buf = YUVBuffer::new(70, 40, SubsamplingFormat::Yuv420);
+        Pattern::Bars.fill(&mut buf, 0);
+
+        // 75% white, then 75% yellow.
+        assert_eq!(buf.as_slice(Plane::Y)[0], 180);
+        assert_eq!(buf.as_slice(Plane::U)[0], 128);
+        assert_eq!(buf.as_slice(Plane::V)[0], 128);
+        assert_eq!(buf.as_slice(Plane::Y)[10], 168);
+        assert!(buf.as_slice(Plane::U)[5] < 128);
+    }
+
+    #[test]
+    fn bars_high_bit_depth() {
+        let mut buf = YUVBuffer16::new(70, 40, SubsamplingFormat::Yuv422);
+        Pattern::Bars.fill16(&mut buf, 10, 0);
+
+        assert_eq!(Picture16::as_slice(&buf, Plane::Y)[0], 721);
+        assert_eq!(Picture16::as_slice(&buf, Plane::U)[0], 512);
+    }
+
+    #[test]
+    fn zone_plate_center() {
+        let mut buf = YUVBuffer::new(64, 64, SubsamplingFormat::Yuv444);
+        Pattern::ZonePlate.fill(&mut buf, 0);
+
+        assert_eq!(buf.as_slice(Plane::Y)[32 * 64 + 32], 235);
+        assert!(buf.as_slice(Plane::U).iter().all(|&v| v == 128));
+    }
+
+    #[test]
+    fn monochrome() {
+        let mut buf = YUVBuffer::new(64, 64, SubsamplingFormat::Yuv400);
+        Pattern::Gradient.fill(&mut buf, 0);
+
+        // Pure blue.
+        assert_eq!(buf.as_slice(Plane::Y)[0], 32);
+    }
+
+    #[test]
+    fn deterministic() {
+        for pattern in [
+            Pattern::Bars,
+            Pattern::Gradient,
+            Pattern::ZonePlate,
+            Pattern::MovingBox,
+        ] {
+            let mut a = YUVBuffer::new(96, 54, SubsamplingFormat::Yuv420);
+            let mut b = YUVBuffer::new(96, 54, SubsamplingFormat::Yuv420);
+            pattern.fill(&mut a, 3);
+            pattern.fill(&mut b, 3);
+            assert_eq!(a.as_slice(Plane::Y), b.as_slice(Plane::Y));
+            assert_eq!(a.as_slice(Plane::U), b.as_slice(Plane::U));
+
+            // Only the bars are static.
+            pattern.fill(&mut b, 4);
+            assert_eq!(
+                a.as_slice(Plane::Y) == b.as_slice(Plane::Y),
+                pattern == Pattern::Bars
+            );
+        }
+    }
+}
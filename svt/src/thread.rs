@@ -0,0 +1,240 @@
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+use crate::{Error, LibraryError};
+
+/// The scheduling priority of a thread.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ThreadPriority {
+    /// A nice value, from -20 (highest priority) to 19 (lowest). Raising the
+    /// priority above the default of 0 requires `CAP_SYS_NICE`.
+    Nice(i32),
+    /// A real-time (`SCHED_FIFO`) priority, from 1 to 99. This requires
+    /// `CAP_SYS_NICE`, or an `RLIMIT_RTPRIO` limit at least as high.
+    Realtime(u32),
+}
+
+/// Scheduling options for threads the crate spawns itself, like the packet
+/// thread of a [`PipelinedEncoder`](crate::PipelinedEncoder).
+///
+/// The worker threads of the encoder libraries are configured separately, on
+/// the encoder configs. Setting the affinity or priority is only supported
+/// on Linux; elsewhere, spawning a thread with either set fails with
+/// [`Error::BadParameter`].
+///
+/// # Example
+///
+/// ```no_run
+/// # use svt::{Encoder, PipelinedEncoder, ThreadOptions, ThreadPriority};
+/// # fn example<E>(encoder: E) -> Result<(), svt::Error>
+/// # where
+/// #     E: Encoder + Send + Sync + 'static,
+/// #     E::Packet: Send + 'static,
+/// # {
+/// let options = ThreadOptions::default()
+///     .name("svt-packets")
+///     .affinity([3])
+///     .priority(ThreadPriority::Realtime(10));
+///
+/// let pipeline = PipelinedEncoder::with_thread_options(encoder, &options)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadOptions {
+    name: Option<String>,
+    affinity: Option<Vec<usize>>,
+    priority: Option<ThreadPriority>,
+}
+
+impl ThreadOptions {
+    /// Sets the name of the thread.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Restricts the thread to the given logical processors, numbered from 0.
+    pub fn affinity(mut self, cores: impl IntoIterator<Item = usize>) -> Self {
+        self.affinity = Some(cores.into_iter().collect());
+        self
+    }
+
+    /// Sets the scheduling priority of the thread.
+    pub fn priority(mut self, priority: ThreadPriority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Spawns a thread with these options. If the affinity or priority can't
+    /// be set, the error is returned, and `f` isn't run.
+    ///
+    /// This can also be used to place the worker threads of an encoder
+    /// library: unless pinned execution or a target socket is configured,
+    /// the libraries don't set the affinity of their threads, so they inherit
+    /// the affinity of the thread which creates the encoder.
+    pub fn spawn<T, F>(&self, f: F) -> Result<JoinHandle<T>, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        self.validate()?;
+
+        let mut builder = std::thread::Builder::new();
+        if let Some(name) = &self.name {
+            builder = builder.name(name.clone());
+        }
+
+        let options = self.clone();
+        let (tx, rx) = mpsc::sync_channel(1);
+        let handle = builder
+            .spawn(move || {
+                let res = options.apply();
+                let failed = res.is_err();
+                let _ = tx.send(res);
+
+                if failed {
+                    // Exit without running `f`, and without invoking the
+                    // panic hook.
+                    std::panic::resume_unwind(Box::new(()));
+                }
+
+                f()
+            })
+            .map_err(|_| LibraryError::InsufficientResources)?;
+
+        match rx.recv() {
+            Ok(Ok(())) => Ok(handle),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(LibraryError::Undefined.into()),
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        let bad = |detail: String| {
+            Err(Error::BadParameter {
+                detail: Some(detail),
+            })
+        };
+
+        match self.priority {
+            Some(ThreadPriority::Nice(nice)) if !(-20..=19).contains(&nice) => {
+                bad(format!("nice value {} is out of range", nice))
+            }
+            Some(ThreadPriority::Realtime(priority)) if !(1..=99).contains(&priority) => {
+                bad(format!("real-time priority {} is out of range", priority))
+            }
+            _ if self.affinity.as_ref().is_some_and(|cores| cores.is_empty()) => {
+                bad("thread affinity must include at least one core".to_owned())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies the affinity and priority to the current thread.
+    #[cfg(target_os = "linux")]
+    fn apply(&self) -> Result<(), Error> {
+        let os_error = |what: &str, err: std::io::Error| Error::BadParameter {
+            detail: Some(format!("failed to set thread {}: {}", what, err)),
+        };
+
+        if let Some(cores) = &self.affinity {
+            unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                for &core in cores {
+                    if core >= libc::CPU_SETSIZE as usize {
+                        return Err(Error::BadParameter {
+                            detail: Some(format!("core {} is out of range", core)),
+                        });
+                    }
+
+                    libc::CPU_SET(core, &mut set);
+                }
+
+                if libc::sched_setaffinity(0, size_of_val(&set), &set) != 0 {
+                    return Err(os_error("affinity", std::io::Error::last_os_error()));
+                }
+            }
+        }
+
+        match self.priority {
+            // On Linux, nice values apply to individual threads.
+            Some(ThreadPriority::Nice(nice)) => unsafe {
+                let tid = libc::syscall(libc::SYS_gettid) as libc::id_t;
+                if libc::setpriority(libc::PRIO_PROCESS, tid, nice) != 0 {
+                    return Err(os_error("priority", std::io::Error::last_os_error()));
+                }
+            },
+            Some(ThreadPriority::Realtime(priority)) => unsafe {
+                let mut param: libc::sched_param = std::mem::zeroed();
+                param.sched_priority = priority as libc::c_int;
+                let err =
+                    libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param);
+                if err != 0 {
+                    return Err(os_error("priority", std::io::Error::from_raw_os_error(err)));
+                }
+            },
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn apply(&self) -> Result<(), Error> {
+        if self.affinity.is_some() || self.priority.is_some() {
+            return Err(Error::BadParameter {
+                detail: Some("thread affinity and priority are only supported on Linux".to_owned()),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_named() {
+        let name = ThreadOptions::default()
+            .name("svt-test")
+            .spawn(|| std::thread::current().name().map(str::to_owned))
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(name.as_deref(), Some("svt-test"));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn spawn_with_affinity() {
+        let cores = ThreadOptions::default()
+            .affinity([0])
+            .spawn(|| unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                libc::sched_getaffinity(0, size_of_val(&set), &mut set);
+                libc::CPU_COUNT(&set)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(cores, 1);
+    }
+
+    #[test]
+    fn invalid_options() {
+        for options in [
+            ThreadOptions::default().priority(ThreadPriority::Realtime(0)),
+            ThreadOptions::default().priority(ThreadPriority::Nice(20)),
+            ThreadOptions::default().affinity([]),
+            ThreadOptions::default().affinity([usize::MAX]),
+        ] {
+            let res = options.spawn(|| panic!("thread should not run"));
+            assert!(matches!(res, Err(Error::BadParameter { .. })));
+        }
+    }
+}
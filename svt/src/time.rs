@@ -0,0 +1,101 @@
+/// A rational number, used as a timebase. For example, a timebase of
+/// `1/90000` means each tick is 1/90000th of a second.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    /// The numerator.
+    pub num: u32,
+    /// The denominator.
+    pub den: u32,
+}
+
+impl Rational {
+    /// Creates a new rational number. Panics if the denominator is zero.
+    pub fn new(num: u32, den: u32) -> Self {
+        assert!(den != 0, "denominator must be non-zero");
+        Self { num, den }
+    }
+
+    /// The timebase for timestamps counted in frames, at the given framerate.
+    pub fn from_framerate(numerator: u32, denominator: u32) -> Self {
+        Self::new(denominator, numerator)
+    }
+}
+
+impl std::fmt::Display for Rational {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+/// A timestamp in a given timebase.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Timestamp {
+    /// The timestamp, in ticks of the timebase.
+    pub value: i64,
+    /// The length of a tick, in seconds.
+    pub timebase: Rational,
+}
+
+impl Timestamp {
+    /// Creates a new timestamp.
+    pub fn new(value: i64, timebase: Rational) -> Self {
+        Self { value, timebase }
+    }
+
+    /// Converts the timestamp to another timebase, rounding to the nearest
+    /// tick.
+    pub fn rescale(self, timebase: Rational) -> Self {
+        if timebase == self.timebase {
+            return self;
+        }
+
+        let num = self.value as i128 * self.timebase.num as i128 * timebase.den as i128;
+        let den = self.timebase.den as i128 * timebase.num as i128;
+        assert!(den != 0, "cannot rescale to a zero timebase");
+
+        // Round half away from zero.
+        let value = (2 * num + num.signum() * den) / (2 * den);
+        Self {
+            value: value as i64,
+            timebase,
+        }
+    }
+
+    /// The timestamp in seconds.
+    pub fn as_secs_f64(&self) -> f64 {
+        self.value as f64 * self.timebase.num as f64 / self.timebase.den as f64
+    }
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.value, self.timebase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescale() {
+        let frames = Rational::from_framerate(30000, 1001);
+        let mpegts = Rational::new(1, 90000);
+
+        let ts = Timestamp::new(30, frames);
+        assert_eq!(ts.rescale(mpegts), Timestamp::new(90090, mpegts));
+        assert_eq!(ts.rescale(mpegts).rescale(frames), ts);
+        assert!((ts.as_secs_f64() - 1.001).abs() < 1e-9);
+
+        // Rounds to the nearest tick.
+        let ms = Rational::new(1, 1000);
+        assert_eq!(
+            Timestamp::new(1, frames).rescale(ms),
+            Timestamp::new(33, ms)
+        );
+        assert_eq!(
+            Timestamp::new(-1, frames).rescale(ms),
+            Timestamp::new(-33, ms)
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Derives decode timestamps for packets output in decode order.
+///
+/// With a random access prediction structure, the encoder reorders frames,
+/// so packets are output in decode order while their `pts` remains in
+/// presentation order. Muxers generally require a monotonically increasing
+/// DTS, which is never greater than the PTS of the same packet.
+///
+/// The reorderer assigns each packet the next smallest submitted
+/// presentation timestamp, shifted back by the encoder's reorder depth. Feed
+/// it every timestamp passed to [`Encoder::send_picture`], and call
+/// [`TimestampReorderer::next_dts`] for each frame packet, in the order the
+/// packets are retrieved.
+///
+/// # Example
+///
+/// ```no_run
+/// # #[cfg(feature = "av1")]
+/// # fn main() -> Result<(), svt::Error> {
+/// use svt::av1::Av1EncoderConfig;
+/// use svt::{Encoder, Packet, SubsamplingFormat, TimestampReorderer, YUVBuffer};
+///
+/// let encoder = Av1EncoderConfig::default()
+///     .framerate(30, 1)
+///     .create_encoder(1920, 1080, SubsamplingFormat::Yuv420)?;
+///
+/// // Timestamps are in frames.
+/// let mut reorderer = TimestampReorderer::new(encoder.reorder_depth(), 1);
+/// let picture = YUVBuffer::new(1920, 1080, SubsamplingFormat::Yuv420);
+///
+/// for pts in 0..30 {
+///     encoder.send_picture(&picture, pts, false)?;
+///     reorderer.push_pts(pts);
+///
+///     while let Some(packet) = encoder.get_packet(false)? {
+///         let dts = reorderer.next_dts();
+///         // Write the packet with its pts and dts...
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "av1"))]
+/// # fn main() {}
+/// ```
+///
+/// [`Encoder::send_picture`]: crate::Encoder::send_picture
+#[derive(Debug, Clone)]
+pub struct TimestampReorderer {
+    reorder_depth: u32,
+    frame_duration: i64,
+    pending: BinaryHeap<Reverse<i64>>,
+    last_dts: Option<i64>,
+}
+
+impl TimestampReorderer {
+    /// Creates a reorderer for an encoder which delays output by up to
+    /// `reorder_depth` frames, like the value returned by
+    /// `Av1Encoder::reorder_depth` or `HevcEncoder::reorder_depth`. The frame
+    /// duration is in the same units as the timestamps.
+    pub fn new(reorder_depth: u32, frame_duration: i64) -> Self {
+        Self {
+            reorder_depth,
+            frame_duration,
+            pending: BinaryHeap::new(),
+            last_dts: None,
+        }
+    }
+
+    /// Records the presentation timestamp of a picture sent to the encoder.
+    pub fn push_pts(&mut self, pts: i64) {
+        self.pending.push(Reverse(pts));
+    }
+
+    /// Returns the decode timestamp for the next packet in decode order.
+    /// Returns `None` if every recorded picture has already been assigned a
+    /// decode timestamp.
+    pub fn next_dts(&mut self) -> Option<i64> {
+        let Reverse(pts) = self.pending.pop()?;
+        let mut dts = pts - self.reorder_depth as i64 * self.frame_duration;
+
+        // Keep the DTS strictly increasing, even if the timestamps are
+        // irregular.
+        if let Some(last) = self.last_dts {
+            dts = dts.max(last + 1);
+        }
+
+        self.last_dts = Some(dts);
+        Some(dts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hierarchical_reordering() {
+        // Display order 0-8, with a three level mini-GOP of eight frames.
+        let decode_order = [0, 8, 4, 2, 1, 3, 6, 5, 7];
+
+        let mut reorderer = TimestampReorderer::new(3, 10);
+        for pts in 0..9 {
+            reorderer.push_pts(pts * 10);
+        }
+
+        let mut last = None;
+        for frame in decode_order {
+            let pts = frame * 10;
+            let dts = reorderer.next_dts().unwrap();
+
+            assert!(dts <= pts, "dts {} > pts {}", dts, pts);
+            assert!(last.is_none_or(|last| dts > last));
+            last = Some(dts);
+        }
+
+        assert_eq!(reorderer.next_dts(), None);
+    }
+
+    #[test]
+    fn no_reordering() {
+        let mut reorderer = TimestampReorderer::new(0, 1);
+        reorderer.push_pts(5);
+        reorderer.push_pts(6);
+
+        assert_eq!(reorderer.next_dts(), Some(5));
+        assert_eq!(reorderer.next_dts(), Some(6));
+    }
+}
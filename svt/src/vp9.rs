@@ -0,0 +1,354 @@
+//! A rust wrapper for Intel's Scalable Video Technology for VP9 (SVT-VP9)
+//! video encoder.
+//!
+//! SVT-VP9 only supports 8-bit 4:2:0 input.
+//!
+//! # Example
+//! ```
+//! # use svt::{Encoder, Packet, YUVBuffer, SubsamplingFormat};
+//! # use svt::vp9::{Vp9EncoderConfig, RateControlMode};
+//! # fn copy_frame(_: &mut YUVBuffer)
+//! #     -> Result<i64, Box<dyn std::error::Error>> { Ok(0) }
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let width = 800;
+//! # let height = 600;
+//! let encoder = Vp9EncoderConfig::default()
+//!     .preset(8)
+//!     .rate_control_mode(RateControlMode::ConstantBitrate)
+//!     .target_bitrate(1_000_000)
+//!     .create_encoder(width, height, SubsamplingFormat::Yuv420)?;
+//!
+//! let mut buffer = YUVBuffer::new(width, height, SubsamplingFormat::Yuv420);
+//!
+//! loop {
+//!     // Copy the YUV data into the buffer from a file, network stream, etc.
+//!     // The source will also provide the PTS (presentation timestamp).
+//!     let pts = copy_frame(&mut buffer)?;
+//!
+//!     // Submit the input picture.
+//!     encoder.send_picture(&buffer, pts, false)?;
+//!     while let Some(packet) = encoder.get_packet(false)? {
+//!         // Write the packet to a file or send it over the network.
+//!     }
+//!
+//! #   break
+//! }
+//!
+//! // Once all frames have been submitted, flush the encoder.
+//! encoder.finish()?;
+//!
+//! while let Some(packet) = encoder.get_packet(true)? {
+//!     // Handle the final packets the same way, but check `is_eos` to see if
+//!     // the stream is finished.
+//!     if packet.is_eos() {
+//!         break;
+//!     }
+//! }
+//!
+//! # Ok(())
+//! # }
+
+use svt_vp9_sys::*;
+
+mod config;
+mod packet;
+
+pub use config::*;
+pub use packet::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::buffer::PlaneLayout;
+use crate::instrument::queue_wait;
+use crate::shutdown;
+use crate::state::StateTracker;
+use crate::{Encoder, EncoderCall, EncoderState, Error, FrameTypeRequest, Packet, Picture};
+use crate::{PictureRequirements, SubsamplingFormat};
+
+struct LibraryHandle(*mut EbComponentType);
+
+unsafe impl Send for LibraryHandle {}
+
+// The library allows pictures to be sent and packets to be retrieved
+// concurrently. The encoder serializes calls of each kind.
+unsafe impl Sync for LibraryHandle {}
+
+impl LibraryHandle {
+    fn as_ptr(&self) -> *mut EbComponentType {
+        self.0
+    }
+}
+
+impl Drop for LibraryHandle {
+    fn drop(&mut self) {
+        unsafe {
+            eb_vp9_deinit_handle(self.0);
+        }
+    }
+}
+
+/// An encoder instance.
+pub struct Vp9Encoder {
+    handle: LibraryHandle,
+    width: u32,
+    height: u32,
+    state: StateTracker,
+    in_flight: AtomicUsize,
+    drop_timeout: Duration,
+    send_lock: Mutex<()>,
+    recv_lock: Mutex<()>,
+}
+
+impl std::fmt::Debug for Vp9Encoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Encoder")
+            .field(&self.handle.as_ptr())
+            .finish()
+    }
+}
+
+impl Encoder for Vp9Encoder {
+    type Packet = Vp9Packet;
+
+    fn send_picture(
+        &self,
+        picture: &impl Picture,
+        pts: i64,
+        frame_type: impl Into<FrameTypeRequest>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("send_picture", codec = "vp9", pts).entered();
+
+        let layout = PlaneLayout::new(picture, &self.picture_requirements())?;
+
+        let slice_type = match frame_type.into() {
+            FrameTypeRequest::Auto => EB_INVALID_PICTURE,
+            FrameTypeRequest::Key => EB_IDR_PICTURE,
+            v => {
+                return Err(Error::BadParameter {
+                    detail: Some(format!("unsupported frame type for VP9: {:?}", v)),
+                })
+            }
+        };
+
+        let mut input_pic = EbSvtEncInput {
+            luma: layout.luma as *mut _,
+            cb: layout.cb as *mut _,
+            cr: layout.cr as *mut _,
+            y_stride: layout.y_stride,
+            cb_stride: layout.cb_stride,
+            cr_stride: layout.cr_stride,
+        };
+
+        let mut input = EbBufferHeaderType {
+            size: size_of::<EbBufferHeaderType>() as u32,
+            p_buffer: &mut input_pic as *mut _ as *mut u8,
+            n_filled_len: layout.len as u32,
+            pts,
+            slice_type,
+            ..Default::default()
+        };
+
+        let _guard = self.state.lock(&self.send_lock)?;
+        self.state.check_running()?;
+
+        unsafe {
+            result(queue_wait(|| {
+                eb_vp9_svt_enc_send_picture(self.handle.as_ptr(), &mut input)
+            }))
+            .map_err(|e| e.in_call(EncoderCall::SendPicture, Some(pts), None))?
+        }
+
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn get_packet(&self, done: bool) -> Result<Option<Vp9Packet>, Error> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "get_packet",
+            codec = "vp9",
+            wait = done,
+            pts = tracing::field::Empty,
+            size = tracing::field::Empty,
+        )
+        .entered();
+
+        let _guard = self.state.lock(&self.recv_lock)?;
+        self.state.check_poisoned()?;
+        if self.state.get() == EncoderState::Finished {
+            return Ok(None);
+        }
+
+        let mut p = std::ptr::null_mut();
+        unsafe {
+            #[allow(non_upper_case_globals)]
+            match queue_wait(|| eb_vp9_svt_get_packet(self.handle.as_ptr(), &mut p, done as u8)) {
+                EbErrorType_EB_NoErrorEmptyQueue => return Ok(None),
+                code => result(code).map_err(|e| e.in_call(EncoderCall::GetPacket, None, None))?,
+            }
+
+            let packet = Vp9Packet::new(p);
+            if packet.is_eos() {
+                self.state.set_finished();
+            }
+
+            if packet.completed_pts().is_some() {
+                let _ = self
+                    .in_flight
+                    .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+            }
+
+            #[cfg(feature = "tracing")]
+            {
+                span.record("pts", packet.pts());
+                span.record("size", packet.as_ref().len());
+            }
+
+            Ok(Some(packet))
+        }
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        let mut input = EbBufferHeaderType {
+            flags: EB_BUFFERFLAG_EOS,
+            ..Default::default()
+        };
+
+        let _guard = self.state.lock(&self.send_lock)?;
+        if !self.state.should_finish()? {
+            return Ok(());
+        }
+
+        unsafe {
+            result(eb_vp9_svt_enc_send_picture(
+                self.handle.as_ptr(),
+                &mut input,
+            ))
+            .map_err(|e| e.in_call(EncoderCall::Finish, None, None))?
+        }
+
+        self.state.set_draining();
+        Ok(())
+    }
+
+    fn state(&self) -> EncoderState {
+        self.state.get()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Acquire)
+    }
+}
+
+impl Vp9Encoder {
+    /// Constructs an encoder from an existing pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that both pointers are valid, and the encoder
+    /// has been initialized with `eb_vp9_svt_init_handle` and
+    /// `eb_vp9_init_encoder`.
+    pub unsafe fn from_raw(
+        handle: *mut EbComponentType,
+        cfg: *mut EbSvtVp9EncConfiguration,
+    ) -> Self {
+        Self::new(LibraryHandle(handle), &*cfg)
+    }
+
+    fn new(handle: LibraryHandle, cfg: &EbSvtVp9EncConfiguration) -> Self {
+        Self {
+            handle,
+            width: cfg.source_width,
+            height: cfg.source_height,
+            state: StateTracker::new(),
+            in_flight: AtomicUsize::new(0),
+            drop_timeout: shutdown::DEFAULT_DROP_TIMEOUT,
+            send_lock: Mutex::new(()),
+            recv_lock: Mutex::new(()),
+        }
+    }
+
+    /// Finishes the stream, waits at most `timeout` for the remaining
+    /// packets, and shuts the encoder down. Any packets not yet retrieved are
+    /// discarded. Returns [`Error::TimedOut`] if the stream didn't finish in
+    /// time, in which case the encoder is deinitialized anyway.
+    ///
+    /// Dropping the encoder does the same, with the timeout set by
+    /// [`Vp9EncoderConfig::drop_timeout`], but ignores any errors.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<(), Error> {
+        let res = shutdown::drain(&self, timeout);
+        self.drop_timeout = Duration::ZERO;
+        res
+    }
+
+    /// The dimensions and format of the pictures the encoder accepts, which
+    /// are always 8-bit 4:2:0.
+    pub fn picture_requirements(&self) -> PictureRequirements {
+        PictureRequirements {
+            width: self.width,
+            height: self.height,
+            subsampling_format: SubsamplingFormat::Yuv420,
+            bytes_per_sample: 1,
+        }
+    }
+}
+
+impl Drop for Vp9Encoder {
+    fn drop(&mut self) {
+        // Deinitializing the library while pictures are in flight can hang.
+        // Drains can't be attempted while unwinding, since the locks may be
+        // poisoned.
+        if !std::thread::panicking() {
+            let _ = shutdown::drain(&*self, self.drop_timeout);
+        }
+
+        unsafe {
+            eb_vp9_deinit_encoder(self.handle.as_ptr());
+        }
+    }
+}
+
+pub(crate) fn result(code: EbErrorType) -> Result<(), Error> {
+    Error::check(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Packet, SubsamplingFormat, YUVBuffer};
+
+    use super::*;
+
+    #[test]
+    fn encode_frame() {
+        simple_logger::init_with_env().ok();
+
+        let enc = Vp9EncoderConfig::default()
+            .preset(9)
+            .create_encoder(800, 600, SubsamplingFormat::Yuv420)
+            .expect("failed to create encoder");
+
+        let buf = YUVBuffer::new(800, 600, SubsamplingFormat::Yuv420);
+        enc.send_picture(&buf, 0, true)
+            .expect("failed to send picture");
+        enc.finish().expect("failed to finish");
+
+        let packet = enc
+            .get_packet(true)
+            .expect("failed to get packet")
+            .expect("no packet");
+        assert!(packet.is_keyframe());
+        assert_eq!(packet.pts(), 0);
+        assert!(!packet.as_bytes().is_empty());
+    }
+
+    #[test]
+    fn unsupported_format() {
+        simple_logger::init_with_env().ok();
+
+        let res = Vp9EncoderConfig::default().create_encoder(800, 600, SubsamplingFormat::Yuv444);
+        assert!(matches!(res, Err(Error::BadParameter { .. })));
+    }
+}
@@ -0,0 +1,329 @@
+use svt_vp9_sys::*;
+
+use std::time::Duration;
+
+use crate::{EncoderConfig, Error, RateControl, SubsamplingFormat};
+
+use super::{result, LibraryHandle, Vp9Encoder};
+
+/// How often (in frames) to insert a keyframe.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IntraPeriod {
+    /// Automatically determine the intra period.
+    Auto,
+    /// Only insert a keyframe at the start of the stream.
+    None,
+    /// Use a fixed intra period.
+    Fixed(u32),
+}
+
+/// The prediction structure for each GOP.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PredictionStructure {
+    /// Forward prediction only, using P frames.
+    LowDelayP,
+    /// Bidirectional prediction, using alternate reference frames.
+    RandomAccess,
+}
+
+/// The rate control mode to use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RateControlMode {
+    /// Use a constant quantization parameter.
+    ConstantQp,
+    /// Use variable bitrate.
+    VariableBitrate,
+    /// Use constant bitrate, for real-time applications.
+    ConstantBitrate,
+}
+
+/// What the encoder should optimize for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tune {
+    /// Visual quality.
+    VisualQuality,
+    /// Objective metrics, like PSNR and SSIM.
+    Psnr,
+    /// VMAF.
+    Vmaf,
+}
+
+/// Whether to use ASM optimizations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AsmType {
+    /// Do not use ASM optimizations.
+    None,
+    /// Auto-select the highest assembly instruction set supported.
+    Auto,
+}
+
+/// Which socket(s) to use for encoding, on dual-socket systems.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TargetSocket {
+    /// Use the first socket.
+    First,
+    /// Use the second socket.
+    Second,
+    /// Use both sockets.
+    Both,
+}
+
+/// A helper for building an encode configuration.
+///
+/// For configuration options, see the upstream docs:
+///
+/// <https://github.com/OpenVisualCloud/SVT-VP9/blob/master/Docs/svt-vp9_encoder_user_guide.md>
+pub struct Vp9EncoderConfig {
+    handle: LibraryHandle,
+    drop_timeout: Option<Duration>,
+    cfg: EbSvtVp9EncConfiguration,
+}
+
+impl Default for Vp9EncoderConfig {
+    fn default() -> Self {
+        unsafe {
+            let mut handle = std::ptr::null_mut();
+            let mut cfg = std::mem::zeroed();
+
+            let res = eb_vp9_svt_init_handle(&mut handle, std::ptr::null_mut(), &mut cfg);
+            assert_eq!(0, res);
+
+            Vp9EncoderConfig {
+                handle: LibraryHandle(handle),
+                drop_timeout: None,
+                cfg,
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Vp9EncoderConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("EncoderConfig")
+            .field(&self.handle.as_ptr())
+            .finish()
+    }
+}
+
+impl Vp9EncoderConfig {
+    /// Creates a new encoder from the config. Only 4:2:0 input is supported.
+    pub fn create_encoder(
+        mut self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<Vp9Encoder, Error> {
+        if subsampling_format != SubsamplingFormat::Yuv420 {
+            return Err(Error::BadParameter {
+                detail: Some(format!(
+                    "unsupported subsampling format for VP9: {:?}",
+                    subsampling_format
+                )),
+            });
+        }
+
+        // Set the frame size.
+        self.cfg.source_width = width;
+        self.cfg.source_height = height;
+
+        // Copy config parameters onto the encoder handle.
+        unsafe {
+            result(eb_vp9_svt_enc_set_parameter(
+                self.handle.as_ptr(),
+                &mut self.cfg,
+            ))?
+        }
+
+        // Create the encoder.
+        unsafe { result(eb_vp9_init_encoder(self.handle.as_ptr()))? }
+
+        let mut encoder = Vp9Encoder::new(self.handle, &self.cfg);
+        if let Some(timeout) = self.drop_timeout {
+            encoder.drop_timeout = timeout;
+        }
+
+        Ok(encoder)
+    }
+
+    /// Sets how long dropping the encoder waits for the stream to finish
+    /// before deinitializing the library, which can hang if pictures are
+    /// still being encoded. The default is five seconds. See
+    /// [`Vp9Encoder::shutdown`].
+    pub fn drop_timeout(mut self, timeout: Duration) -> Self {
+        self.drop_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the encoder preset, from 0-9, with 0 being the highest quality and
+    /// 9 the highest density.
+    pub fn preset(mut self, preset: u8) -> Self {
+        self.cfg.enc_mode = preset;
+        self
+    }
+
+    /// Sets what the encoder should optimize for.
+    pub fn tune(mut self, tune: Tune) -> Self {
+        self.cfg.tune = match tune {
+            Tune::VisualQuality => 0,
+            Tune::Psnr => 1,
+            Tune::Vmaf => 2,
+        };
+
+        self
+    }
+
+    /// Sets the intra refresh period.
+    pub fn intra_period_length(mut self, intra_period_length: IntraPeriod) -> Self {
+        self.cfg.intra_period = match intra_period_length {
+            IntraPeriod::Auto => -2,
+            IntraPeriod::None => -1,
+            IntraPeriod::Fixed(frames) => frames as i32,
+        };
+
+        self
+    }
+
+    /// Sets the prediction structure.
+    pub fn prediction_structure(mut self, pred_structure: PredictionStructure) -> Self {
+        self.cfg.pred_structure = match pred_structure {
+            PredictionStructure::LowDelayP => 0,
+            PredictionStructure::RandomAccess => 2,
+        };
+
+        self
+    }
+
+    /// Sets the input framerate.
+    pub fn framerate(mut self, numerator: u32, denominator: u32) -> Self {
+        self.cfg.frame_rate_numerator = numerator as i32;
+        self.cfg.frame_rate_denominator = denominator as i32;
+        self
+    }
+
+    /// Sets the rate control mode.
+    pub fn rate_control_mode(mut self, rate_control_mode: RateControlMode) -> Self {
+        self.cfg.rate_control_mode = match rate_control_mode {
+            RateControlMode::ConstantQp => 0,
+            RateControlMode::VariableBitrate => 1,
+            RateControlMode::ConstantBitrate => 2,
+        };
+
+        self
+    }
+
+    /// Sets the target QP for [RateControlMode::ConstantQp].
+    pub fn qp(mut self, qp: u32) -> Self {
+        self.cfg.qp = qp;
+        self
+    }
+
+    /// Sets the target bitrate, in bits/second, for the
+    /// [RateControlMode::VariableBitrate] and [RateControlMode::ConstantBitrate]
+    /// modes.
+    pub fn target_bitrate(mut self, bitrate: u32) -> Self {
+        self.cfg.target_bit_rate = bitrate;
+        self
+    }
+
+    /// Sets the maximum QP for the bitrate-based rate control modes.
+    pub fn max_qp_allowed(mut self, qp: u32) -> Self {
+        self.cfg.max_qp_allowed = qp;
+        self
+    }
+
+    /// Sets the minimum QP for the bitrate-based rate control modes.
+    pub fn min_qp_allowed(mut self, qp: u32) -> Self {
+        self.cfg.min_qp_allowed = qp;
+        self
+    }
+
+    /// Sets the VBV maximum rate in bits/second.
+    pub fn vbv_max_rate(mut self, rate: u32) -> Self {
+        self.cfg.vbv_max_rate = rate;
+        self
+    }
+
+    /// Sets the VBV buffer size in bits.
+    pub fn vbv_buf_size(mut self, size: u32) -> Self {
+        self.cfg.vbv_buf_size = size;
+        self
+    }
+
+    /// Sets the look-ahead distance.
+    pub fn look_ahead_distance(mut self, distance: u32) -> Self {
+        self.cfg.look_ahead_distance = distance;
+        self
+    }
+
+    /// Enables the deblocking loop filter.
+    pub fn enable_loop_filter(mut self, v: bool) -> Self {
+        self.cfg.loop_filter = v as u8;
+        self
+    }
+
+    /// Configures the number of logical processors to use.
+    pub fn logical_processors(mut self, count: u32) -> Self {
+        self.cfg.logical_processors = count;
+        self
+    }
+
+    /// Configures the target socket to use, for dual-socket systems.
+    pub fn target_socket(mut self, socket: TargetSocket) -> Self {
+        self.cfg.target_socket = match socket {
+            TargetSocket::Both => -1,
+            TargetSocket::First => 0,
+            TargetSocket::Second => 1,
+        };
+
+        self
+    }
+
+    /// Configures which assembly instruction set to use.
+    pub fn asm_type(mut self, asm_type: AsmType) -> Self {
+        self.cfg.asm_type = match asm_type {
+            AsmType::None => 0,
+            AsmType::Auto => 1,
+        };
+
+        self
+    }
+}
+
+impl EncoderConfig for Vp9EncoderConfig {
+    type Encoder = Vp9Encoder;
+
+    fn preset(self, preset: u8) -> Self {
+        Vp9EncoderConfig::preset(self, preset)
+    }
+
+    fn framerate(self, numerator: u32, denominator: u32) -> Self {
+        Vp9EncoderConfig::framerate(self, numerator, denominator)
+    }
+
+    fn rate_control(self, rate_control: RateControl) -> Self {
+        match rate_control {
+            RateControl::ConstantQp(qp) => {
+                self.rate_control_mode(RateControlMode::ConstantQp).qp(qp)
+            }
+            RateControl::VariableBitrate(bitrate) => self
+                .rate_control_mode(RateControlMode::VariableBitrate)
+                .target_bitrate(bitrate),
+        }
+    }
+
+    fn gop(self, intra_period: Option<u32>) -> Self {
+        self.intra_period_length(match intra_period {
+            Some(frames) => IntraPeriod::Fixed(frames),
+            None => IntraPeriod::Auto,
+        })
+    }
+
+    fn create_encoder(
+        self,
+        width: u32,
+        height: u32,
+        subsampling_format: SubsamplingFormat,
+    ) -> Result<Vp9Encoder, Error> {
+        Vp9EncoderConfig::create_encoder(self, width, height, subsampling_format)
+    }
+}
@@ -0,0 +1,91 @@
+use svt_vp9_sys::*;
+
+use crate::Packet;
+
+/// A packet of encoded data output by the encoder. The buffer is reference
+/// counted, and will be reused by the encoder once dropped.
+pub struct Vp9Packet {
+    handle: *mut EbBufferHeaderType,
+}
+
+impl std::fmt::Debug for Vp9Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Packet")
+            .field("slice_type", &unsafe { (*self.handle).slice_type })
+            .field("size", &unsafe { (*self.handle).n_filled_len })
+            .finish()
+    }
+}
+
+impl Packet for Vp9Packet {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                (*self.handle).p_buffer,
+                (*self.handle).n_filled_len as usize,
+            )
+        }
+    }
+
+    fn is_eos(&self) -> bool {
+        unsafe { (*self.handle).flags & EB_BUFFERFLAG_EOS != 0 }
+    }
+
+    // Hidden alt-ref frames complete their source picture, since a later
+    // show-existing frame only refers back to them.
+    fn completed_pts(&self) -> Option<i64> {
+        let complete =
+            !self.is_eos() && !self.as_bytes().is_empty() && !self.is_show_existing_frame();
+
+        complete.then(|| self.pts())
+    }
+}
+
+impl AsRef<[u8]> for Vp9Packet {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Vp9Packet {
+    /// Whether the packet contains a keyframe.
+    pub fn is_keyframe(&self) -> bool {
+        unsafe { matches!((*self.handle).slice_type, EB_I_PICTURE | EB_IDR_PICTURE) }
+    }
+
+    /// Whether the packet only signals that a previously decoded frame should
+    /// be shown, rather than containing a new frame.
+    pub fn is_show_existing_frame(&self) -> bool {
+        unsafe { (*self.handle).flags & EB_BUFFERFLAG_SHOW_EXT != 0 }
+    }
+
+    /// The QP used to encode the frame.
+    pub fn qp(&self) -> u32 {
+        unsafe { (*self.handle).qp }
+    }
+
+    /// The presentation timestamp of the frame.
+    pub fn pts(&self) -> i64 {
+        unsafe { (*self.handle).pts }
+    }
+
+    /// The decode timestamp of the frame.
+    pub fn dts(&self) -> i64 {
+        unsafe { (*self.handle).dts }
+    }
+
+    pub(crate) fn new(p: *mut EbBufferHeaderType) -> Self {
+        Self { handle: p }
+    }
+}
+
+// Releasing the buffer is thread-safe.
+unsafe impl Send for Vp9Packet {}
+
+impl Drop for Vp9Packet {
+    fn drop(&mut self) {
+        unsafe {
+            eb_vp9_svt_release_out_buffer(&mut self.handle);
+        }
+    }
+}
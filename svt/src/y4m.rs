@@ -0,0 +1,607 @@
+//! A reader and writer for YUV4MPEG2 (y4m) streams, as produced by `ffmpeg -f
+//! yuv4mpegpipe`.
+//!
+//! 8, 10 and 12-bit streams are supported, in any of the four subsampling
+//! formats. High bit depth samples are stored as 16-bit little-endian values.
+//!
+//! # Example
+//! ```no_run
+//! # use svt::y4m::Y4mReader;
+//! # use svt::YUVBuffer;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let stdin = std::io::BufReader::new(std::io::stdin());
+//! let mut reader = Y4mReader::new(stdin)?;
+//!
+//! let header = reader.header();
+//! let mut buffer = YUVBuffer::new(header.width, header.height, header.subsampling_format);
+//! while reader.read_frame(&mut buffer)? {
+//!     // Send the picture to an encoder.
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io::{self, Read, Write};
+
+use crate::buffer::chroma_dimensions;
+use crate::{Picture, Picture16, Plane, Rational, SubsamplingFormat, YUVBuffer, YUVBuffer16};
+
+/// The maximum length of a stream or frame header.
+const MAX_HEADER_LEN: usize = 1024;
+
+const PLANES: [Plane; 3] = [Plane::Y, Plane::U, Plane::V];
+
+/// An error reading or writing a y4m stream.
+#[derive(Debug)]
+pub enum Y4mError {
+    /// Reading from or writing to the underlying stream failed.
+    Io(io::Error),
+    /// The stream is malformed, uses an unsupported format, or doesn't match
+    /// the picture passed in.
+    Invalid(String),
+}
+
+impl std::error::Error for Y4mError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Y4mError::Io(e) => Some(e),
+            Y4mError::Invalid(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Y4mError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Y4mError::Io(e) => write!(f, "y4m I/O error: {}", e),
+            Y4mError::Invalid(detail) => write!(f, "invalid y4m stream: {}", detail),
+        }
+    }
+}
+
+impl From<io::Error> for Y4mError {
+    fn from(e: io::Error) -> Self {
+        Y4mError::Io(e)
+    }
+}
+
+fn invalid(detail: impl Into<String>) -> Y4mError {
+    Y4mError::Invalid(detail.into())
+}
+
+/// The position of the chroma samples relative to the luma samples, for 4:2:0
+/// streams.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChromaSiting {
+    /// Centered between the four luma samples (`C420jpeg`). This is the
+    /// default for 8-bit 4:2:0 streams.
+    Center,
+    /// Co-sited horizontally with the left luma samples, and centered
+    /// vertically (`C420mpeg2`).
+    Left,
+    /// Co-sited with the top-left luma sample (`C420paldv`).
+    TopLeft,
+    /// Not specified by the stream. This is always the case for high bit
+    /// depth or non-4:2:0 streams.
+    Unspecified,
+}
+
+impl From<ChromaSiting> for crate::ChromaSamplePosition {
+    fn from(siting: ChromaSiting) -> Self {
+        match siting {
+            ChromaSiting::Left => Self::Vertical,
+            ChromaSiting::TopLeft => Self::Colocated,
+            ChromaSiting::Center | ChromaSiting::Unspecified => Self::Unknown,
+        }
+    }
+}
+
+/// The parameters of a y4m stream.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Y4mHeader {
+    /// The width of each picture, in pixels.
+    pub width: u32,
+    /// The height of each picture, in pixels.
+    pub height: u32,
+    /// The framerate, in frames per second, as a fraction.
+    pub framerate: Rational,
+    /// The chroma subsampling format.
+    pub subsampling_format: SubsamplingFormat,
+    /// The bit depth: 8, 10 or 12.
+    pub bit_depth: u32,
+    /// The chroma siting. Only 8-bit 4:2:0 streams can specify the siting;
+    /// for others, it's ignored when writing.
+    pub chroma_siting: ChromaSiting,
+}
+
+impl Y4mHeader {
+    fn parse(line: &str) -> Result<Self, Y4mError> {
+        let mut params = line.split(' ');
+        if params.next() != Some("YUV4MPEG2") {
+            return Err(invalid("missing YUV4MPEG2 signature"));
+        }
+
+        let mut width = None;
+        let mut height = None;
+        let mut framerate = None;
+        let mut colorspace = "420jpeg";
+
+        for param in params {
+            let mut chars = param.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            match tag {
+                Some('W') => width = value.parse().ok(),
+                Some('H') => height = value.parse().ok(),
+                Some('F') => {
+                    framerate = value
+                        .split_once(':')
+                        .and_then(|(n, d)| Some((n.parse().ok()?, d.parse().ok()?)))
+                        .filter(|&(_, d): &(u32, u32)| d != 0)
+                        .map(|(n, d)| Rational::new(n, d))
+                }
+                Some('C') => colorspace = value,
+                // Interlacing, aspect ratio and extensions are ignored.
+                _ => (),
+            }
+        }
+
+        let (subsampling_format, bit_depth, chroma_siting) = match colorspace {
+            "420" | "420jpeg" => (SubsamplingFormat::Yuv420, 8, ChromaSiting::Center),
+            "420mpeg2" => (SubsamplingFormat::Yuv420, 8, ChromaSiting::Left),
+            "420paldv" => (SubsamplingFormat::Yuv420, 8, ChromaSiting::TopLeft),
+            "422" => (SubsamplingFormat::Yuv422, 8, ChromaSiting::Unspecified),
+            "444" => (SubsamplingFormat::Yuv444, 8, ChromaSiting::Unspecified),
+            "mono" => (SubsamplingFormat::Yuv400, 8, ChromaSiting::Unspecified),
+            "420p10" => (SubsamplingFormat::Yuv420, 10, ChromaSiting::Unspecified),
+            "422p10" => (SubsamplingFormat::Yuv422, 10, ChromaSiting::Unspecified),
+            "444p10" => (SubsamplingFormat::Yuv444, 10, ChromaSiting::Unspecified),
+            "mono10" => (SubsamplingFormat::Yuv400, 10, ChromaSiting::Unspecified),
+            "420p12" => (SubsamplingFormat::Yuv420, 12, ChromaSiting::Unspecified),
+            "422p12" => (SubsamplingFormat::Yuv422, 12, ChromaSiting::Unspecified),
+            "444p12" => (SubsamplingFormat::Yuv444, 12, ChromaSiting::Unspecified),
+            "mono12" => (SubsamplingFormat::Yuv400, 12, ChromaSiting::Unspecified),
+            c => return Err(invalid(format!("unsupported colorspace: {}", c))),
+        };
+
+        let header = Y4mHeader {
+            width: width.ok_or_else(|| invalid("missing or invalid width"))?,
+            height: height.ok_or_else(|| invalid("missing or invalid height"))?,
+            framerate: framerate.ok_or_else(|| invalid("missing or invalid framerate"))?,
+            subsampling_format,
+            bit_depth,
+            chroma_siting,
+        };
+
+        header.validate()?;
+        Ok(header)
+    }
+
+    fn colorspace(&self) -> Result<String, Y4mError> {
+        let base = match self.subsampling_format {
+            SubsamplingFormat::Yuv400 => "mono",
+            SubsamplingFormat::Yuv420 => "420",
+            SubsamplingFormat::Yuv422 => "422",
+            SubsamplingFormat::Yuv444 => "444",
+        };
+
+        Ok(match (self.subsampling_format, self.bit_depth) {
+            (SubsamplingFormat::Yuv420, 8) => match self.chroma_siting {
+                ChromaSiting::Left => "420mpeg2".to_owned(),
+                ChromaSiting::TopLeft => "420paldv".to_owned(),
+                ChromaSiting::Center | ChromaSiting::Unspecified => "420jpeg".to_owned(),
+            },
+            (_, 8) => base.to_owned(),
+            (SubsamplingFormat::Yuv400, depth @ (10 | 12)) => format!("{}{}", base, depth),
+            (_, depth @ (10 | 12)) => format!("{}p{}", base, depth),
+            (_, depth) => return Err(invalid(format!("unsupported bit depth: {}", depth))),
+        })
+    }
+
+    fn validate(&self) -> Result<(), Y4mError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(invalid("empty picture dimensions"));
+        }
+
+        Ok(())
+    }
+
+    /// The width and height of each plane, in samples. The chroma planes of
+    /// odd-sized pictures are rounded up, in y4m and in [`YUVBuffer`] alike.
+    fn plane_dimensions(&self) -> [(usize, usize); 3] {
+        let (uv_width, uv_height) =
+            chroma_dimensions(self.width, self.height, self.subsampling_format);
+        let luma = (self.width as usize, self.height as usize);
+        let chroma = (uv_width as usize, uv_height as usize);
+        [luma, chroma, chroma]
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        if self.bit_depth > 8 {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+/// Reads pictures from a y4m stream. The reader reads the headers a byte at a
+/// time, so it's best to wrap unbuffered sources in a [`std::io::BufReader`].
+#[derive(Debug)]
+pub struct Y4mReader<R> {
+    reader: R,
+    header: Y4mHeader,
+    scratch: Vec<u8>,
+}
+
+impl<R: Read> Y4mReader<R> {
+    /// Creates a reader, reading the stream header from `reader`.
+    pub fn new(mut reader: R) -> Result<Self, Y4mError> {
+        let line = read_line(&mut reader)?.ok_or_else(|| invalid("empty stream"))?;
+        let header = Y4mHeader::parse(&line)?;
+
+        Ok(Self {
+            reader,
+            header,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// The parameters of the stream.
+    pub fn header(&self) -> Y4mHeader {
+        self.header
+    }
+
+    /// Reads the next picture from an 8-bit stream into `buf`, which must have
+    /// the same dimensions and subsampling format as the stream. Returns
+    /// `false` at the end of the stream.
+    pub fn read_frame(&mut self, buf: &mut YUVBuffer) -> Result<bool, Y4mError> {
+        if self.header.bit_depth != 8 {
+            return Err(invalid(format!(
+                "{}-bit stream can't be read into an 8-bit buffer",
+                self.header.bit_depth
+            )));
+        }
+
+        let lens = PLANES.map(|p| Picture::as_slice(buf, p).len());
+        self.check_buffer(buf.width(), buf.height(), lens)?;
+        if !self.read_frame_header()? {
+            return Ok(false);
+        }
+
+        for plane in PLANES {
+            self.reader.read_exact(buf.as_mut_slice(plane))?;
+        }
+
+        Ok(true)
+    }
+
+    /// Reads the next picture from a high bit depth stream into `buf`, which
+    /// must have the same dimensions and subsampling format as the stream.
+    /// Returns `false` at the end of the stream.
+    pub fn read_frame16(&mut self, buf: &mut YUVBuffer16) -> Result<bool, Y4mError> {
+        if self.header.bit_depth == 8 {
+            return Err(invalid("8-bit stream can't be read into a 16-bit buffer"));
+        }
+
+        let lens = PLANES.map(|p| Picture16::as_slice(buf, p).len());
+        self.check_buffer(Picture16::width(buf), Picture16::height(buf), lens)?;
+        if !self.read_frame_header()? {
+            return Ok(false);
+        }
+
+        for plane in PLANES {
+            let samples = buf.as_mut_slice(plane);
+            self.scratch.resize(samples.len() * 2, 0);
+            self.reader.read_exact(&mut self.scratch)?;
+
+            for (sample, bytes) in samples.iter_mut().zip(self.scratch.chunks_exact(2)) {
+                *sample = u16::from_le_bytes([bytes[0], bytes[1]]);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Consumes the reader, returning the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn check_buffer(&self, width: u32, height: u32, lens: [usize; 3]) -> Result<(), Y4mError> {
+        let expected = self.header.plane_dimensions().map(|(w, h)| w * h);
+        if width != self.header.width || height != self.header.height || lens != expected {
+            return Err(invalid(format!(
+                "buffer doesn't match the stream: {}x{} {:?}",
+                self.header.width, self.header.height, self.header.subsampling_format
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Reads a frame header. Returns `false` at the end of the stream.
+    fn read_frame_header(&mut self) -> Result<bool, Y4mError> {
+        match read_line(&mut self.reader)? {
+            None => Ok(false),
+            Some(line) if line == "FRAME" || line.starts_with("FRAME ") => Ok(true),
+            Some(_) => Err(invalid("missing FRAME marker")),
+        }
+    }
+}
+
+/// Writes pictures to a y4m stream.
+#[derive(Debug)]
+pub struct Y4mWriter<W> {
+    writer: W,
+    header: Y4mHeader,
+    scratch: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Creates a writer, writing the stream header to `writer`.
+    pub fn new(mut writer: W, header: Y4mHeader) -> Result<Self, Y4mError> {
+        header.validate()?;
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C{}",
+            header.width,
+            header.height,
+            header.framerate.num,
+            header.framerate.den,
+            header.colorspace()?,
+        )?;
+
+        Ok(Self {
+            writer,
+            header,
+            scratch: Vec::new(),
+        })
+    }
+
+    /// Writes a picture, which must have the same dimensions, subsampling
+    /// format and sample size as the stream. Use [`YUVBuffer16`] or another
+    /// [`Picture16`] for high bit depth streams.
+    pub fn write_frame(&mut self, picture: &impl Picture) -> Result<(), Y4mError> {
+        let bytes_per_sample = self.header.bytes_per_sample();
+        if picture.width() != self.header.width
+            || picture.height() != self.header.height
+            || picture.bytes_per_sample() as usize != bytes_per_sample
+        {
+            return Err(invalid(format!(
+                "picture doesn't match the stream: {}x{}, {}-bit",
+                self.header.width, self.header.height, self.header.bit_depth
+            )));
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        for (plane, (width, height)) in PLANES.into_iter().zip(self.header.plane_dimensions()) {
+            if height == 0 {
+                continue;
+            }
+
+            let data = picture.as_slice(plane);
+            let stride = picture.stride(plane) as usize;
+            let row_len = width * bytes_per_sample;
+            if stride < row_len || data.len() < stride * (height - 1) + row_len {
+                return Err(invalid(format!("{:?} plane is too small", plane)));
+            }
+
+            for row in data.chunks(stride).take(height) {
+                let row = &row[..row_len];
+                if bytes_per_sample == 2 && cfg!(target_endian = "big") {
+                    self.scratch.clear();
+                    self.scratch
+                        .extend(row.chunks_exact(2).flat_map(|b| [b[1], b[0]]));
+                    self.writer.write_all(&self.scratch)?;
+                } else {
+                    self.writer.write_all(row)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Consumes the writer, returning the underlying stream.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a header line, without the trailing newline. Returns `None` if the
+/// stream ends before the first byte.
+fn read_line(reader: &mut impl Read) -> Result<Option<String>, Y4mError> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    loop {
+        match reader.read(&mut byte) {
+            Ok(0) if line.is_empty() => return Ok(None),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) if line.len() >= MAX_HEADER_LEN => return Err(invalid("header too long")),
+            Ok(_) => line.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| invalid("header isn't valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsrc::Pattern;
+
+    fn header(format: SubsamplingFormat, bit_depth: u32) -> Y4mHeader {
+        Y4mHeader {
+            width: 64,
+            height: 48,
+            framerate: Rational::new(30000, 1001),
+            subsampling_format: format,
+            bit_depth,
+            chroma_siting: ChromaSiting::Unspecified,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for format in [
+            SubsamplingFormat::Yuv400,
+            SubsamplingFormat::Yuv420,
+            SubsamplingFormat::Yuv422,
+            SubsamplingFormat::Yuv444,
+        ] {
+            let mut writer = Y4mWriter::new(Vec::new(), header(format, 8)).unwrap();
+            let mut source = YUVBuffer::new(64, 48, format);
+            for frame in 0..3 {
+                Pattern::MovingBox.fill(&mut source, frame);
+                writer.write_frame(&source).unwrap();
+            }
+
+            let stream = writer.into_inner();
+            let mut reader = Y4mReader::new(stream.as_slice()).unwrap();
+            assert_eq!(reader.header().subsampling_format, format);
+
+            let mut buf = YUVBuffer::new(64, 48, format);
+            for frame in 0..3 {
+                Pattern::MovingBox.fill(&mut source, frame);
+                assert!(reader.read_frame(&mut buf).unwrap());
+                for plane in PLANES {
+                    assert_eq!(buf.as_slice(plane), source.as_slice(plane));
+                }
+            }
+
+            assert!(!reader.read_frame(&mut buf).unwrap());
+        }
+    }
+
+    #[test]
+    fn round_trip_high_bit_depth() {
+        let mut source = YUVBuffer16::new(64, 48, SubsamplingFormat::Yuv422);
+        Pattern::Bars.fill16(&mut source, 10, 0);
+
+        let mut writer = Y4mWriter::new(Vec::new(), header(SubsamplingFormat::Yuv422, 10)).unwrap();
+        writer.write_frame(&source).unwrap();
+
+        let stream = writer.into_inner();
+        assert!(stream.starts_with(b"YUV4MPEG2 W64 H48 F30000:1001 Ip A1:1 C422p10\n"));
+
+        let mut reader = Y4mReader::new(stream.as_slice()).unwrap();
+        assert_eq!(reader.header().bit_depth, 10);
+
+        let mut buf = YUVBuffer16::new(64, 48, SubsamplingFormat::Yuv422);
+        assert!(reader.read_frame16(&mut buf).unwrap());
+        for plane in PLANES {
+            assert_eq!(
+                Picture16::as_slice(&buf, plane),
+                Picture16::as_slice(&source, plane)
+            );
+        }
+
+        let mut buf = YUVBuffer::new(64, 48, SubsamplingFormat::Yuv422);
+        assert!(reader.read_frame(&mut buf).is_err());
+    }
+
+    #[test]
+    fn round_trip_odd_size() {
+        for format in [
+            SubsamplingFormat::Yuv400,
+            SubsamplingFormat::Yuv420,
+            SubsamplingFormat::Yuv422,
+            SubsamplingFormat::Yuv444,
+        ] {
+            let header = Y4mHeader {
+                width: 63,
+                height: 47,
+                ..header(format, 8)
+            };
+
+            let mut source = YUVBuffer::new(63, 47, format);
+            Pattern::MovingBox.fill(&mut source, 0);
+
+            let mut writer = Y4mWriter::new(Vec::new(), header).unwrap();
+            writer.write_frame(&source).unwrap();
+
+            // 4:2:0 chroma planes are 32x24, and 4:2:2 chroma planes are 32x47.
+            let stream = writer.into_inner();
+            let header_len = stream.iter().position(|&b| b == b'\n').unwrap() + 1;
+            let chroma_len = match format {
+                SubsamplingFormat::Yuv400 => 0,
+                SubsamplingFormat::Yuv420 => 32 * 24,
+                SubsamplingFormat::Yuv422 => 32 * 47,
+                SubsamplingFormat::Yuv444 => 63 * 47,
+            };
+            assert_eq!(
+                stream.len(),
+                header_len + "FRAME\n".len() + 63 * 47 + chroma_len * 2
+            );
+
+            let mut reader = Y4mReader::new(stream.as_slice()).unwrap();
+            assert_eq!(reader.header().width, 63);
+            assert_eq!(reader.header().height, 47);
+
+            let mut buf = YUVBuffer::new(63, 47, format);
+            assert!(reader.read_frame(&mut buf).unwrap());
+            for plane in PLANES {
+                assert_eq!(buf.as_slice(plane), source.as_slice(plane));
+            }
+
+            assert!(!reader.read_frame(&mut buf).unwrap());
+        }
+    }
+
+    #[test]
+    fn chroma_siting() {
+        for (colorspace, siting) in [
+            ("", ChromaSiting::Center),
+            (" C420", ChromaSiting::Center),
+            (" C420jpeg", ChromaSiting::Center),
+            (" C420mpeg2", ChromaSiting::Left),
+            (" C420paldv", ChromaSiting::TopLeft),
+            (" C420p10", ChromaSiting::Unspecified),
+        ] {
+            let line = format!("YUV4MPEG2 W64 H48 F25:1 Ip A0:0{}", colorspace);
+            let header = Y4mHeader::parse(&line).unwrap();
+            assert_eq!(header.subsampling_format, SubsamplingFormat::Yuv420);
+            assert_eq!(header.chroma_siting, siting);
+
+            // The siting survives a round trip, as far as it can be expressed.
+            let mut stream = Vec::new();
+            Y4mWriter::new(&mut stream, header).unwrap();
+            let reader = Y4mReader::new(stream.as_slice()).unwrap();
+            assert_eq!(reader.header(), header);
+        }
+    }
+
+    #[test]
+    fn invalid_headers() {
+        for line in [
+            "",
+            "YUV4MPEG W64 H48 F25:1",
+            "YUV4MPEG2 H48 F25:1",
+            "YUV4MPEG2 W64 H48 F25:0",
+            "YUV4MPEG2 W64 H48 F25:1 C411",
+            "YUV4MPEG2 W0 H48 F25:1 C420",
+        ] {
+            let stream = format!("{}\n", line);
+            assert!(matches!(
+                Y4mReader::new(stream.as_bytes()),
+                Err(Y4mError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let mut stream = b"YUV4MPEG2 W4 H4 F25:1 C444\nFRAME\n".to_vec();
+        stream.extend([0; 20]);
+
+        let mut reader = Y4mReader::new(stream.as_slice()).unwrap();
+        let mut buf = YUVBuffer::new(4, 4, SubsamplingFormat::Yuv444);
+        assert!(matches!(reader.read_frame(&mut buf), Err(Y4mError::Io(_))));
+    }
+}
@@ -0,0 +1,74 @@
+//! Measures the end-to-end latency of the AV1 encoder in low-delay mode, as
+//! the number of pictures sent before the corresponding packet comes out.
+
+use std::time::{Duration, Instant};
+
+use svt::av1::Av1EncoderConfig;
+use svt::testsrc::Pattern;
+use svt::{Encoder, Packet, SubsamplingFormat, YUVBuffer};
+
+const FRAMES: i64 = 30;
+
+fn max_lag(config: Av1EncoderConfig) -> i64 {
+    let enc = config
+        .create_encoder(320, 240, SubsamplingFormat::Yuv420)
+        .expect("failed to create encoder");
+
+    let mut buf = YUVBuffer::new(320, 240, SubsamplingFormat::Yuv420);
+    let mut packets_out = 0;
+    let mut max_lag = 0;
+
+    for pts in 0..FRAMES {
+        Pattern::MovingBox.fill(&mut buf, pts as u64);
+        enc.send_picture(&buf, pts, pts == 0)
+            .expect("failed to send picture");
+
+        // The encoder runs asynchronously, so give it a moment to produce the
+        // packet for the picture we just sent.
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while packets_out <= pts && Instant::now() < deadline {
+            match enc.get_packet(false).expect("failed to get packet") {
+                Some(packet) if !packet.as_bytes().is_empty() => packets_out += 1,
+                Some(_) => (),
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+
+        max_lag = max_lag.max(pts + 1 - packets_out);
+    }
+
+    enc.finish().expect("failed to finish");
+    while !enc
+        .get_packet(true)
+        .expect("failed to get packet")
+        .expect("no packet")
+        .is_eos()
+    {}
+
+    max_lag
+}
+
+#[test]
+fn low_delay_latency() {
+    simple_logger::init_with_env().ok();
+
+    let config = Av1EncoderConfig::default()
+        .preset(12)
+        .framerate(30, 1)
+        .max_frame_latency(0);
+
+    assert_eq!(max_lag(config), 0);
+}
+
+#[test]
+fn bounded_latency() {
+    simple_logger::init_with_env().ok();
+
+    let config = Av1EncoderConfig::default()
+        .preset(12)
+        .framerate(30, 1)
+        .max_frame_latency(3);
+
+    // Once a mini-GOP is complete, its packets are output together.
+    assert!(max_lag(config) <= 4);
+}